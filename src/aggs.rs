@@ -0,0 +1,1339 @@
+use crate::errors::FqError;
+use crate::expr::{Val, ValKey};
+use crate::funcs::{AggregateFunc, Arity, Signature, Type};
+use crate::unicode;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// aggragate functions
+pub const SUM_FUNC: &str = "sum";
+pub const COUNT_FUNC: &str = "count";
+pub const MAX_FUNC: &str = "max";
+pub const MIN_FUNC: &str = "min";
+pub const AVG_FUNC: &str = "avg";
+pub const STDDEV_SAMP_FUNC: &str = "stddev_samp";
+pub const STDDEV_POP_FUNC: &str = "stddev_pop";
+pub const VAR_SAMP_FUNC: &str = "var_samp";
+pub const VAR_POP_FUNC: &str = "var_pop";
+pub const MEDIAN_FUNC: &str = "median";
+pub const PERCENTILE_CONT_FUNC: &str = "percentile_cont";
+pub const PERCENTILE_DISC_FUNC: &str = "percentile_disc";
+pub const MODE_FUNC: &str = "mode";
+pub const CORR_FUNC: &str = "corr";
+pub const COVAR_SAMP_FUNC: &str = "covar_samp";
+pub const COVAR_POP_FUNC: &str = "covar_pop";
+pub const REGR_SLOPE_FUNC: &str = "regr_slope";
+pub const REGR_INTERCEPT_FUNC: &str = "regr_intercept";
+pub const REGR_R2_FUNC: &str = "regr_r2";
+pub const STRING_AGG_FUNC: &str = "string_agg";
+pub const ARG_MAX_FUNC: &str = "arg_max";
+pub const ARG_MIN_FUNC: &str = "arg_min";
+pub const FIRST_FUNC: &str = "first";
+pub const LAST_FUNC: &str = "last";
+pub const ANY_VALUE_FUNC: &str = "any_value";
+
+/// State of an aggregate function call. The state is updated with
+/// one value at a time, and the final value can be obtained at any
+/// point (including when no value was given).
+pub trait Accumulator {
+    /// Updates the state with the values of the arguments (one value
+    /// per argument) for a row. Null values are ignored.
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError>;
+
+    /// Returns the final value for all the values seen so far.
+    fn finish(&self) -> Result<Val, FqError>;
+}
+
+/// Creates the state of a call of a built-in aggregate function from
+/// its parameters.
+type AccumulatorFn = fn(&[Val]) -> Result<Box<dyn Accumulator>, FqError>;
+
+/// A built-in aggregate function.
+struct Builtin {
+    name: &'static str,
+    arity: usize,
+    params: Arity,
+    signature: Signature,
+    new: AccumulatorFn,
+}
+
+impl AggregateFunc for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn params(&self) -> Arity {
+        self.params
+    }
+
+    fn signature(&self) -> Signature {
+        self.signature.clone()
+    }
+
+    fn accumulator(&self, params: &[Val]) -> Result<Box<dyn Accumulator>, FqError> {
+        if !self.params.accepts(params.len()) {
+            return Err(FqError::exe(format!(
+                "Wrong number of arguments for {}()",
+                self.name
+            )));
+        }
+        (self.new)(params)
+    }
+}
+
+fn builtin(
+    name: &'static str,
+    arity: usize,
+    params: Arity,
+    signature: Signature,
+    new: AccumulatorFn,
+) -> Rc<dyn AggregateFunc> {
+    Rc::new(Builtin {
+        name,
+        arity,
+        params,
+        signature,
+        new,
+    })
+}
+
+/// Returns all built-in aggregate functions.
+pub fn builtin_aggregates() -> Vec<Rc<dyn AggregateFunc>> {
+    let none = Arity::exact(0);
+    let sig = |args: &[Type], ret: Type| Signature::new(args.to_vec(), ret);
+    let num = [Type::NUMBER];
+    let pair = [Type::NUMBER, Type::NUMBER];
+    let any = [Type::ANY];
+    vec![
+        builtin(SUM_FUNC, 1, none, sig(&num, Type::NUMBER), |_| {
+            Ok(Box::new(Sum::new()))
+        }),
+        builtin(COUNT_FUNC, 1, none, sig(&any, Type::INT), |_| {
+            Ok(Box::new(Count::new()))
+        }),
+        builtin(MAX_FUNC, 1, none, sig(&num, Type::NUMBER), |_| {
+            Ok(Box::new(Max::new()))
+        }),
+        builtin(MIN_FUNC, 1, none, sig(&num, Type::NUMBER), |_| {
+            Ok(Box::new(Min::new()))
+        }),
+        builtin(AVG_FUNC, 1, none, sig(&num, Type::FLOAT), |_| {
+            Ok(Box::new(Avg::new()))
+        }),
+        builtin(STDDEV_SAMP_FUNC, 1, none, sig(&num, Type::FLOAT), |_| {
+            Ok(Box::new(Variance::new(STDDEV_SAMP_FUNC, true, true)))
+        }),
+        builtin(STDDEV_POP_FUNC, 1, none, sig(&num, Type::FLOAT), |_| {
+            Ok(Box::new(Variance::new(STDDEV_POP_FUNC, false, true)))
+        }),
+        builtin(VAR_SAMP_FUNC, 1, none, sig(&num, Type::FLOAT), |_| {
+            Ok(Box::new(Variance::new(VAR_SAMP_FUNC, true, false)))
+        }),
+        builtin(VAR_POP_FUNC, 1, none, sig(&num, Type::FLOAT), |_| {
+            Ok(Box::new(Variance::new(VAR_POP_FUNC, false, false)))
+        }),
+        builtin(MEDIAN_FUNC, 1, none, sig(&num, Type::NUMBER), |_| {
+            Ok(Box::new(Percentile::new(MEDIAN_FUNC, 0.5, true)))
+        }),
+        builtin(
+            PERCENTILE_CONT_FUNC,
+            1,
+            Arity::exact(1),
+            sig(&pair, Type::NUMBER),
+            |params| {
+                let fraction = check_fraction(PERCENTILE_CONT_FUNC, &params[0])?;
+                Ok(Box::new(Percentile::new(
+                    PERCENTILE_CONT_FUNC,
+                    fraction,
+                    true,
+                )))
+            },
+        ),
+        builtin(
+            PERCENTILE_DISC_FUNC,
+            1,
+            Arity::exact(1),
+            sig(&pair, Type::NUMBER),
+            |params| {
+                let fraction = check_fraction(PERCENTILE_DISC_FUNC, &params[0])?;
+                Ok(Box::new(Percentile::new(
+                    PERCENTILE_DISC_FUNC,
+                    fraction,
+                    false,
+                )))
+            },
+        ),
+        builtin(MODE_FUNC, 1, none, sig(&any, Type::ANY), |_| {
+            Ok(Box::new(Mode::new()))
+        }),
+        builtin(CORR_FUNC, 2, none, sig(&pair, Type::FLOAT), |_| {
+            Ok(Box::new(Bivariate::new(CORR_FUNC)))
+        }),
+        builtin(COVAR_SAMP_FUNC, 2, none, sig(&pair, Type::FLOAT), |_| {
+            Ok(Box::new(Bivariate::new(COVAR_SAMP_FUNC)))
+        }),
+        builtin(COVAR_POP_FUNC, 2, none, sig(&pair, Type::FLOAT), |_| {
+            Ok(Box::new(Bivariate::new(COVAR_POP_FUNC)))
+        }),
+        builtin(REGR_SLOPE_FUNC, 2, none, sig(&pair, Type::FLOAT), |_| {
+            Ok(Box::new(Bivariate::new(REGR_SLOPE_FUNC)))
+        }),
+        builtin(
+            REGR_INTERCEPT_FUNC,
+            2,
+            none,
+            sig(&pair, Type::FLOAT),
+            |_| Ok(Box::new(Bivariate::new(REGR_INTERCEPT_FUNC))),
+        ),
+        builtin(REGR_R2_FUNC, 2, none, sig(&pair, Type::FLOAT), |_| {
+            Ok(Box::new(Bivariate::new(REGR_R2_FUNC)))
+        }),
+        builtin(
+            STRING_AGG_FUNC,
+            1,
+            Arity::range(1, 2),
+            sig(&[Type::ANY, Type::STR, Type::INT], Type::STR),
+            |params| {
+                let cap = params.get(1).unwrap_or(&Val::NULL);
+                Ok(Box::new(StringAgg::new(&params[0], cap)?))
+            },
+        ),
+        builtin(
+            ARG_MAX_FUNC,
+            2,
+            none,
+            sig(&[Type::ANY, Type::ANY], Type::ANY),
+            |_| Ok(Box::new(ArgExtreme::new(ARG_MAX_FUNC, true))),
+        ),
+        builtin(
+            ARG_MIN_FUNC,
+            2,
+            none,
+            sig(&[Type::ANY, Type::ANY], Type::ANY),
+            |_| Ok(Box::new(ArgExtreme::new(ARG_MIN_FUNC, false))),
+        ),
+        builtin(FIRST_FUNC, 1, none, sig(&any, Type::ANY), |_| {
+            Ok(Box::new(First::new()))
+        }),
+        builtin(LAST_FUNC, 1, none, sig(&any, Type::ANY), |_| {
+            Ok(Box::new(Last::new()))
+        }),
+        builtin(ANY_VALUE_FUNC, 1, none, sig(&any, Type::ANY), |_| {
+            Ok(Box::new(First::new()))
+        }),
+    ]
+}
+
+fn check_fraction(name: &str, val: &Val) -> Result<f64, FqError> {
+    match val {
+        Val::INT(val) if (0..=1).contains(val) => Ok(*val as f64),
+        Val::FLOAT(val) if (0.0..=1.0).contains(val) => Ok(*val as f64),
+        _ => Err(FqError::exe(format!(
+            "{}() requires a fraction between 0 and 1",
+            name
+        ))),
+    }
+}
+
+/// Returns the only value given to an accumulator of a function with
+/// one argument.
+fn single(vals: Vec<Val>) -> Val {
+    vals.into_iter().next().unwrap_or(Val::NULL)
+}
+
+fn check_number(name: &str, val: &Val) -> Result<(), FqError> {
+    match val {
+        Val::INT(_) | Val::FLOAT(_) => Ok(()),
+        _ => Err(FqError::exe(format!(
+            "{}() only works for number types",
+            name
+        ))),
+    }
+}
+
+/// Sum of non-null values; null if there are no such values.
+pub struct Sum {
+    sum: Option<Val>,
+}
+
+impl Sum {
+    pub fn new() -> Sum {
+        Sum { sum: None }
+    }
+}
+
+impl Accumulator for Sum {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(SUM_FUNC, &val)?;
+
+        self.sum = match (&self.sum, &val) {
+            (None, _) => Some(val),
+            (Some(Val::INT(sum)), Val::INT(val)) => match sum.checked_add(*val) {
+                Some(sum) => Some(Val::INT(sum)),
+                None => return Err(FqError::exe("sum() of int values overflows")),
+            },
+            (Some(sum), _) => Some(sum.plus(&val)?),
+        };
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.sum {
+            Some(sum) => Ok(sum.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Number of non-null values.
+pub struct Count {
+    count: i32,
+}
+
+impl Count {
+    pub fn new() -> Count {
+        Count { count: 0 }
+    }
+}
+
+impl Accumulator for Count {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if !matches!(val, Val::NULL) {
+            self.count = match self.count.checked_add(1) {
+                Some(count) => count,
+                None => return Err(FqError::exe("count() overflows int")),
+            };
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        Ok(Val::INT(self.count))
+    }
+}
+
+/// Max of non-null values; null if there are no such values.
+pub struct Max {
+    max: Option<Val>,
+}
+
+impl Max {
+    pub fn new() -> Max {
+        Max { max: None }
+    }
+}
+
+impl Accumulator for Max {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(MAX_FUNC, &val)?;
+
+        let replace = match &self.max {
+            None => true,
+            Some(max) => matches!(max.lt(&val)?, Val::BOOL(true)),
+        };
+        if replace {
+            self.max = Some(val);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.max {
+            Some(max) => Ok(max.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Min of non-null values; null if there are no such values.
+pub struct Min {
+    min: Option<Val>,
+}
+
+impl Min {
+    pub fn new() -> Min {
+        Min { min: None }
+    }
+}
+
+impl Accumulator for Min {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(MIN_FUNC, &val)?;
+
+        let replace = match &self.min {
+            None => true,
+            Some(min) => matches!(val.lt(min)?, Val::BOOL(true)),
+        };
+        if replace {
+            self.min = Some(val);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.min {
+            Some(min) => Ok(min.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Average of non-null values; null if there are no such values.
+pub struct Avg {
+    // The mean of ints is not an int in general (and their sum may
+    // not fit an int), so the sum is kept as a float.
+    sum: f64,
+    count: usize,
+}
+
+impl Avg {
+    pub fn new() -> Avg {
+        Avg { sum: 0.0, count: 0 }
+    }
+}
+
+impl Accumulator for Avg {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(AVG_FUNC, &val)?;
+
+        self.sum += to_f64(&val);
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        if self.count == 0 {
+            return Ok(Val::NULL);
+        }
+        Ok(Val::FLOAT((self.sum / self.count as f64) as f32))
+    }
+}
+
+/// Returns the number in the given value as a float.
+fn to_f64(val: &Val) -> f64 {
+    match val {
+        Val::INT(val) => *val as f64,
+        Val::FLOAT(val) => *val as f64,
+        _ => f64::NAN,
+    }
+}
+
+/// Variance (or standard deviation) of non-null values; null if
+/// there are not enough values. The state is updated with Welford's
+/// algorithm, which is numerically stable and needs constant memory.
+pub struct Variance {
+    name: &'static str,
+    // true for sample, false for population variance
+    sample: bool,
+    // true for standard deviation, false for variance
+    sqrt: bool,
+    count: u64,
+    mean: f64,
+    // sum of squared differences from the mean
+    m2: f64,
+}
+
+impl Variance {
+    pub fn new(name: &'static str, sample: bool, sqrt: bool) -> Variance {
+        Variance {
+            name,
+            sample,
+            sqrt,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+}
+
+impl Accumulator for Variance {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(self.name, &val)?;
+
+        let val = to_f64(&val);
+        self.count += 1;
+        let delta = val - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (val - self.mean);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        let count = if self.sample {
+            self.count.saturating_sub(1)
+        } else {
+            self.count
+        };
+        if count == 0 {
+            return Ok(Val::NULL);
+        }
+
+        let variance = self.m2 / count as f64;
+        if self.sqrt {
+            Ok(Val::FLOAT(variance.sqrt() as f32))
+        } else {
+            Ok(Val::FLOAT(variance as f32))
+        }
+    }
+}
+
+/// Percentile of non-null values; null if there are no such values.
+/// A continuous percentile interpolates between the two nearest
+/// values, and a discrete percentile is the first value whose
+/// position in the sorted values is at or above the fraction.
+///
+/// All values are kept (as floats), but they are not sorted; the
+/// percentile is found by selection in linear time.
+pub struct Percentile {
+    name: &'static str,
+    fraction: f64,
+    continuous: bool,
+    // reordered in place when the percentile is selected
+    vals: RefCell<Vec<f64>>,
+    // true if all values are ints, so a discrete percentile is an int
+    ints: bool,
+}
+
+impl Percentile {
+    pub fn new(name: &'static str, fraction: f64, continuous: bool) -> Percentile {
+        Percentile {
+            name,
+            fraction,
+            continuous,
+            vals: RefCell::new(Vec::new()),
+            ints: true,
+        }
+    }
+}
+
+impl Accumulator for Percentile {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        check_number(self.name, &val)?;
+
+        self.ints &= matches!(val, Val::INT(_));
+        self.vals.get_mut().push(to_f64(&val));
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        let mut vals = self.vals.borrow_mut();
+        if vals.is_empty() {
+            return Ok(Val::NULL);
+        }
+
+        let n = vals.len();
+        if !self.continuous {
+            let ix = discrete_rank(self.fraction, n).clamp(1, n) - 1;
+            let (_, val, _) = vals.select_nth_unstable_by(ix, f64::total_cmp);
+            return if self.ints {
+                Ok(Val::INT(*val as i32))
+            } else {
+                Ok(Val::FLOAT(*val as f32))
+            };
+        }
+
+        let pos = self.fraction * (n - 1) as f64;
+        let ix = pos.floor() as usize;
+        let (_, lower, upper) = vals.select_nth_unstable_by(ix, f64::total_cmp);
+        let lower = *lower;
+        // The next value in the sorted order is the smallest value
+        // after the selected one.
+        let upper = upper.iter().copied().min_by(f64::total_cmp);
+        let val = match upper {
+            Some(upper) => lower + (pos - ix as f64) * (upper - lower),
+            None => lower,
+        };
+        Ok(Val::FLOAT(val as f32))
+    }
+}
+
+/// Returns the 1-based rank of the discrete percentile at the given
+/// fraction of `n` values, i.e., the ceiling of fraction * n. The
+/// fraction comes from a float (e.g., 0.4 is 0.4000000059604645), so
+/// it is taken as the shortest decimal that gives the same float, and
+/// the rank is computed exactly with integers.
+fn discrete_rank(fraction: f64, n: usize) -> usize {
+    let text = (fraction as f32).to_string();
+    let (int, frac) = text.split_once('.').unwrap_or((&text, ""));
+    // Longer decimals would overflow; such fractions are tiny, so
+    // fraction * n is not close to an integer anyway.
+    if frac.len() <= 18 {
+        let den = 10u128.pow(frac.len() as u32);
+        let int = int.parse::<u128>().ok();
+        let frac = if frac.is_empty() {
+            Some(0)
+        } else {
+            frac.parse::<u128>().ok()
+        };
+        if let (Some(int), Some(frac)) = (int, frac) {
+            return ((int * den + frac) * n as u128).div_ceil(den) as usize;
+        }
+    }
+    (fraction * n as f64).ceil() as usize
+}
+
+/// Most frequent non-null value; the smallest of such values if there
+/// are several (or the first one seen among values that cannot be
+/// compared). Null if there are no non-null values.
+pub struct Mode {
+    // distinct values with the number of their occurrences, in the
+    // order of their first occurrence
+    counts: Vec<(Val, u64)>,
+    // index of each value in `counts`
+    index: HashMap<ValKey, usize>,
+}
+
+impl Mode {
+    pub fn new() -> Mode {
+        Mode {
+            counts: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl Accumulator for Mode {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        let ix = *self.index.entry(val.key()).or_insert_with(|| {
+            self.counts.push((val, 0));
+            self.counts.len() - 1
+        });
+        self.counts[ix].1 += 1;
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        // Values are visited in a fixed order, so ties between values
+        // that cannot be compared are always resolved the same way.
+        let mut mode: Option<&(Val, u64)> = None;
+        for entry in &self.counts {
+            let replace = match mode {
+                None => true,
+                Some((val, count)) => {
+                    entry.1 > *count
+                        || (entry.1 == *count && matches!(entry.0.lt(val), Ok(Val::BOOL(true))))
+                }
+            };
+            if replace {
+                mode = Some(entry);
+            }
+        }
+        match mode {
+            Some((val, _)) => Ok(val.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Statistics of pairs of non-null values: correlation, covariance,
+/// and linear regression. Rows where either value is null are
+/// ignored, and the result is null if there are not enough pairs (or
+/// a value does not vary). The state is updated with a variant of
+/// Welford's algorithm for co-moments.
+///
+/// Regression functions take the dependent variable first, e.g.,
+/// `regr_slope(y, x)`, as in SQL.
+pub struct Bivariate {
+    name: &'static str,
+    count: u64,
+    // a and b are the values of the first and the second argument
+    mean_a: f64,
+    mean_b: f64,
+    // sums of squared differences from the means
+    m2_a: f64,
+    m2_b: f64,
+    // sum of products of differences from the means
+    c_ab: f64,
+}
+
+impl Bivariate {
+    pub fn new(name: &'static str) -> Bivariate {
+        Bivariate {
+            name,
+            count: 0,
+            mean_a: 0.0,
+            mean_b: 0.0,
+            m2_a: 0.0,
+            m2_b: 0.0,
+            c_ab: 0.0,
+        }
+    }
+}
+
+impl Accumulator for Bivariate {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let (a, b) = match vals.as_slice() {
+            [Val::NULL, _] | [_, Val::NULL] => return Ok(()),
+            [a, b] => (a, b),
+            _ => {
+                return Err(FqError::exe(format!(
+                    "Wrong number of arguments for {}()",
+                    self.name
+                )))
+            }
+        };
+        check_number(self.name, a)?;
+        check_number(self.name, b)?;
+
+        let (a, b) = (to_f64(a), to_f64(b));
+        self.count += 1;
+        let n = self.count as f64;
+        let delta_a = a - self.mean_a;
+        let delta_b = b - self.mean_b;
+        self.mean_a += delta_a / n;
+        self.mean_b += delta_b / n;
+        self.m2_a += delta_a * (a - self.mean_a);
+        self.m2_b += delta_b * (b - self.mean_b);
+        self.c_ab += delta_a * (b - self.mean_b);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        let n = self.count as f64;
+        let val = match self.name {
+            COVAR_SAMP_FUNC if self.count > 1 => Some(self.c_ab / (n - 1.0)),
+            COVAR_POP_FUNC if self.count > 0 => Some(self.c_ab / n),
+            CORR_FUNC if self.m2_a > 0.0 && self.m2_b > 0.0 => {
+                Some(self.c_ab / (self.m2_a * self.m2_b).sqrt())
+            }
+            // b is the independent variable
+            REGR_SLOPE_FUNC if self.m2_b > 0.0 => Some(self.c_ab / self.m2_b),
+            REGR_INTERCEPT_FUNC if self.m2_b > 0.0 => {
+                Some(self.mean_a - self.c_ab / self.m2_b * self.mean_b)
+            }
+            REGR_R2_FUNC if self.m2_b > 0.0 && self.m2_a == 0.0 => Some(1.0),
+            REGR_R2_FUNC if self.m2_b > 0.0 => {
+                Some(self.c_ab * self.c_ab / (self.m2_a * self.m2_b))
+            }
+            _ => None,
+        };
+        match val {
+            Some(val) => Ok(Val::FLOAT(val as f32)),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Non-null values (of any type) joined with a separator in the
+/// input order; null if there are no such values. The result can be
+/// capped to a number of characters (grapheme clusters), after which
+/// values are ignored.
+pub struct StringAgg {
+    sep: String,
+    cap: Option<usize>,
+    result: Option<String>,
+    // number of characters in the result
+    len: usize,
+}
+
+impl StringAgg {
+    pub fn new(sep: &Val, cap: &Val) -> Result<StringAgg, FqError> {
+        let sep = match sep {
+            Val::STR(sep) => sep.to_string(),
+            _ => return Err(FqError::exe("string_agg() requires a string separator")),
+        };
+        let cap = match cap {
+            Val::INT(cap) if *cap >= 0 => Some(*cap as usize),
+            Val::NULL => None,
+            _ => {
+                return Err(FqError::exe(
+                    "string_agg() requires a non-negative length cap",
+                ))
+            }
+        };
+        Ok(StringAgg {
+            sep,
+            cap,
+            result: None,
+            len: 0,
+        })
+    }
+}
+
+impl Accumulator for StringAgg {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if let Val::NULL = val {
+            return Ok(());
+        }
+        if matches!(self.cap, Some(cap) if self.len >= cap) {
+            return Ok(());
+        }
+
+        let text = val.text().unwrap_or_default();
+        let piece = match self.result {
+            None => text,
+            Some(_) => format!("{}{}", self.sep, text),
+        };
+        self.len += unicode::graphemes(&piece).len();
+        let result = self.result.get_or_insert_with(String::new);
+        result.push_str(&piece);
+
+        if let Some(cap) = self.cap {
+            if self.len > cap {
+                *result = unicode::graphemes(result).into_iter().take(cap).collect();
+                self.len = cap;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.result {
+            Some(result) => Ok(Val::STR(result.to_string())),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Value (first argument) on the row with the max (or min) key
+/// (second argument); the first such row in the input order if there
+/// are several. Rows with a null key are ignored, and the result is
+/// null if there are no other rows.
+pub struct ArgExtreme {
+    name: &'static str,
+    max: bool,
+    // the best key so far and its value
+    best: Option<(Val, Val)>,
+}
+
+impl ArgExtreme {
+    pub fn new(name: &'static str, max: bool) -> ArgExtreme {
+        ArgExtreme {
+            name,
+            max,
+            best: None,
+        }
+    }
+}
+
+impl Accumulator for ArgExtreme {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let mut vals = vals.into_iter();
+        let (Some(val), Some(key)) = (vals.next(), vals.next()) else {
+            return Err(FqError::exe(format!(
+                "Wrong number of arguments for {}()",
+                self.name
+            )));
+        };
+        if let Val::NULL = key {
+            return Ok(());
+        }
+
+        let replace = match &self.best {
+            None => true,
+            Some((best, _)) if self.max => matches!(best.lt(&key)?, Val::BOOL(true)),
+            Some((best, _)) => matches!(key.lt(best)?, Val::BOOL(true)),
+        };
+        if replace {
+            self.best = Some((key, val));
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.best {
+            Some((_, val)) => Ok(val.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// First non-null value in the input order; null if there are no
+/// such values. It is also used for `any_value`.
+pub struct First {
+    first: Option<Val>,
+}
+
+impl First {
+    pub fn new() -> First {
+        First { first: None }
+    }
+}
+
+impl Accumulator for First {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if self.first.is_none() && !matches!(val, Val::NULL) {
+            self.first = Some(val);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.first {
+            Some(first) => Ok(first.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Last non-null value in the input order; null if there are no such
+/// values.
+pub struct Last {
+    last: Option<Val>,
+}
+
+impl Last {
+    pub fn new() -> Last {
+        Last { last: None }
+    }
+}
+
+impl Accumulator for Last {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        let val = single(vals);
+        if !matches!(val, Val::NULL) {
+            self.last = Some(val);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        match &self.last {
+            Some(last) => Ok(last.clone()),
+            None => Ok(Val::NULL),
+        }
+    }
+}
+
+/// Accumulator for a `distinct` call of an aggregate function, which
+/// passes only the first occurrence of each (non-null) value to the
+/// wrapped accumulator.
+pub struct Distinct {
+    seen: HashSet<Vec<ValKey>>,
+    acc: Box<dyn Accumulator>,
+}
+
+impl Distinct {
+    pub fn new(acc: Box<dyn Accumulator>) -> Distinct {
+        Distinct {
+            seen: HashSet::new(),
+            acc,
+        }
+    }
+}
+
+impl Accumulator for Distinct {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        // Null values are ignored by accumulators anyway.
+        if !vals.iter().any(|val| matches!(val, Val::NULL)) {
+            let key = vals.iter().map(Val::key).collect();
+            if !self.seen.insert(key) {
+                return Ok(());
+            }
+        }
+        self.acc.update(vals)
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        self.acc.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an accumulator for the built-in aggregate function with
+    /// the given name.
+    fn new_accumulator(name: &str, params: &[Val]) -> Result<Box<dyn Accumulator>, FqError> {
+        let func = builtin_aggregates()
+            .into_iter()
+            .find(|func| func.name() == name)
+            .unwrap();
+        func.accumulator(params)
+    }
+
+    fn aggregate(name: &str, vals: Vec<Val>) -> Val {
+        aggregate_with(name, &[], vals)
+    }
+
+    fn aggregate_with(name: &str, params: &[Val], vals: Vec<Val>) -> Val {
+        let mut acc = new_accumulator(name, params).unwrap();
+        for val in vals {
+            acc.update(vec![val]).unwrap();
+        }
+        acc.finish().unwrap()
+    }
+
+    #[test]
+    fn sum_mixed_numbers() {
+        match aggregate(SUM_FUNC, vec![Val::INT(1), Val::FLOAT(2.5), Val::NULL]) {
+            Val::FLOAT(val) => assert!((val - 3.5).abs() < f32::EPSILON),
+            _ => panic!("sum() errors"),
+        }
+    }
+
+    #[test]
+    fn avg_ignores_nulls() {
+        match aggregate(AVG_FUNC, vec![Val::INT(4), Val::NULL, Val::INT(8)]) {
+            Val::FLOAT(val) => assert!((val - 6.0).abs() < f32::EPSILON),
+            _ => panic!("avg() errors"),
+        }
+    }
+
+    #[test]
+    fn avg_of_ints_is_float() {
+        match aggregate(AVG_FUNC, vec![Val::INT(1), Val::INT(2)]) {
+            Val::FLOAT(val) => assert!((val - 1.5).abs() < f32::EPSILON),
+            _ => panic!("avg() errors"),
+        }
+    }
+
+    #[test]
+    fn aggregate_int_limits() {
+        let big = vec![Val::INT(2_000_000_000), Val::INT(2_000_000_000)];
+        match aggregate(AVG_FUNC, big.clone()) {
+            Val::FLOAT(val) => assert!((val - 2e9).abs() < 1e3),
+            _ => panic!("avg() errors"),
+        }
+
+        let mut acc = new_accumulator(SUM_FUNC, &[]).unwrap();
+        acc.update(vec![big[0].clone()]).unwrap();
+        assert!(acc.update(vec![big[1].clone()]).is_err());
+        assert!(matches!(
+            aggregate(SUM_FUNC, vec![Val::INT(i32::MAX), Val::INT(-1)]),
+            Val::INT(2_147_483_646)
+        ));
+
+        let mut acc = Count { count: i32::MAX };
+        assert!(acc.update(vec![Val::INT(1)]).is_err());
+    }
+
+    #[test]
+    fn aggregate_no_values() {
+        assert!(matches!(aggregate(COUNT_FUNC, vec![]), Val::INT(0)));
+        assert!(matches!(
+            aggregate(COUNT_FUNC, vec![Val::NULL]),
+            Val::INT(0)
+        ));
+        assert!(matches!(aggregate(SUM_FUNC, vec![]), Val::NULL));
+        assert!(matches!(aggregate(MAX_FUNC, vec![Val::NULL]), Val::NULL));
+        assert!(matches!(aggregate(MIN_FUNC, vec![]), Val::NULL));
+        assert!(matches!(aggregate(AVG_FUNC, vec![Val::NULL]), Val::NULL));
+    }
+
+    #[test]
+    #[should_panic(expected = "Execution error: max() only works for number types")]
+    fn max_of_strings() {
+        let mut acc = new_accumulator(MAX_FUNC, &[]).unwrap();
+        acc.update(vec![Val::STR(String::from("abc"))])
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    fn assert_float(expected: f64, val: Val) {
+        match val {
+            Val::FLOAT(val) => assert!(
+                (expected - val as f64).abs() < 1e-4,
+                "{} != {}",
+                expected,
+                val
+            ),
+            _ => panic!("Expected float {}", expected),
+        }
+    }
+
+    fn ints(vals: &[i32]) -> Vec<Val> {
+        vals.iter().map(|val| Val::INT(*val)).collect()
+    }
+
+    #[test]
+    fn variance_and_stddev() {
+        let vals = || ints(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_float(4.0, aggregate(VAR_POP_FUNC, vals()));
+        assert_float(2.0, aggregate(STDDEV_POP_FUNC, vals()));
+        assert_float(32.0 / 7.0, aggregate(VAR_SAMP_FUNC, vals()));
+        assert_float((32.0f64 / 7.0).sqrt(), aggregate(STDDEV_SAMP_FUNC, vals()));
+
+        assert!(matches!(aggregate(VAR_SAMP_FUNC, ints(&[1])), Val::NULL));
+        assert_float(0.0, aggregate(VAR_POP_FUNC, ints(&[1])));
+        assert!(matches!(
+            aggregate(STDDEV_POP_FUNC, vec![Val::NULL]),
+            Val::NULL
+        ));
+    }
+
+    #[test]
+    fn variance_is_stable() {
+        // Naive sum of squares loses all precision for these values.
+        let vals = vec![
+            Val::FLOAT(1e7 + 4.0),
+            Val::FLOAT(1e7 + 7.0),
+            Val::FLOAT(1e7 + 13.0),
+            Val::FLOAT(1e7 + 16.0),
+        ];
+        assert_float(30.0, aggregate(VAR_SAMP_FUNC, vals));
+    }
+
+    #[test]
+    fn median_and_percentiles() {
+        assert_float(3.0, aggregate(MEDIAN_FUNC, ints(&[5, 1, 3])));
+        assert_float(2.5, aggregate(MEDIAN_FUNC, ints(&[4, 1, 3, 2])));
+
+        let vals = || ints(&[15, 20, 35, 40, 50]);
+        let cont = |p: f32| aggregate_with(PERCENTILE_CONT_FUNC, &[Val::FLOAT(p)], vals());
+        let disc = |p: f32| aggregate_with(PERCENTILE_DISC_FUNC, &[Val::FLOAT(p)], vals());
+        assert_float(15.0, cont(0.0));
+        assert_float(29.0, cont(0.4));
+        assert_float(50.0, cont(1.0));
+        assert!(matches!(disc(0.0), Val::INT(15)));
+        assert!(matches!(disc(0.4), Val::INT(20)));
+        assert!(matches!(disc(0.95), Val::INT(50)));
+        assert!(matches!(disc(0.6), Val::INT(35)));
+        // ranks k/n fall exactly on the k-th value
+        assert!(matches!(disc(0.2), Val::INT(15)));
+        assert!(matches!(disc(0.8), Val::INT(40)));
+        assert!(matches!(disc(1.0), Val::INT(50)));
+        assert!(matches!(disc(0.2001), Val::INT(20)));
+        assert_eq!(0, discrete_rank(0.0, 5));
+        assert_eq!(5, discrete_rank(1.0, 5));
+        assert_eq!(7, discrete_rank(0.7f32 as f64, 10));
+        assert_eq!(1, discrete_rank(0.333_333_3f32 as f64, 3));
+        assert_eq!(1, discrete_rank(1e-30f32 as f64, 1000));
+        assert_eq!(3, discrete_rank(0.3f32 as f64, 10));
+
+        // The values are reordered in place, so finishing again (as
+        // for window frames) gives the same result.
+        let mut acc = new_accumulator(PERCENTILE_DISC_FUNC, &[Val::FLOAT(0.4)]).unwrap();
+        for val in ints(&[40, 15, 50, 20, 35]) {
+            acc.update(vec![val]).unwrap();
+        }
+        assert!(matches!(acc.finish().unwrap(), Val::INT(20)));
+        assert!(matches!(acc.finish().unwrap(), Val::INT(20)));
+
+        assert!(matches!(
+            aggregate_with(PERCENTILE_CONT_FUNC, &[Val::FLOAT(0.5)], vec![]),
+            Val::NULL
+        ));
+        assert!(new_accumulator(PERCENTILE_CONT_FUNC, &[Val::FLOAT(1.5)]).is_err());
+        assert!(new_accumulator(PERCENTILE_DISC_FUNC, &[]).is_err());
+        assert!(new_accumulator(MEDIAN_FUNC, &[Val::FLOAT(0.5)]).is_err());
+    }
+
+    #[test]
+    fn mode_of_values() {
+        assert!(matches!(
+            aggregate(MODE_FUNC, ints(&[3, 1, 3, 2, 1])),
+            Val::INT(1)
+        ));
+        match aggregate(
+            MODE_FUNC,
+            vec![
+                Val::STR(String::from("b")),
+                Val::NULL,
+                Val::STR(String::from("b")),
+                Val::STR(String::from("a")),
+            ],
+        ) {
+            Val::STR(val) => assert_eq!("b", val),
+            _ => panic!("mode() errors"),
+        }
+        assert!(matches!(aggregate(MODE_FUNC, vec![Val::NULL]), Val::NULL));
+
+        // A tie between values that cannot be compared goes to the
+        // first one seen.
+        let mixed = || {
+            vec![
+                Val::STR(String::from("a")),
+                Val::INT(1),
+                Val::INT(1),
+                Val::STR(String::from("a")),
+                Val::BOOL(true),
+                Val::BOOL(true),
+            ]
+        };
+        for _ in 0..10 {
+            match aggregate(MODE_FUNC, mixed()) {
+                Val::STR(val) => assert_eq!("a", val),
+                _ => panic!("mode() errors"),
+            }
+        }
+    }
+
+    fn aggregate_pairs(name: &str, pairs: &[(Val, Val)]) -> Val {
+        let mut acc = new_accumulator(name, &[]).unwrap();
+        for (a, b) in pairs {
+            acc.update(vec![a.clone(), b.clone()]).unwrap();
+        }
+        acc.finish().unwrap()
+    }
+
+    #[test]
+    fn bivariate_stats() {
+        // y = 2x + 1, with a row that has a null value
+        let pairs = vec![
+            (Val::INT(3), Val::INT(1)),
+            (Val::INT(5), Val::INT(2)),
+            (Val::NULL, Val::INT(10)),
+            (Val::INT(7), Val::INT(3)),
+            (Val::INT(9), Val::INT(4)),
+        ];
+        assert_float(2.0, aggregate_pairs(REGR_SLOPE_FUNC, &pairs));
+        assert_float(1.0, aggregate_pairs(REGR_INTERCEPT_FUNC, &pairs));
+        assert_float(1.0, aggregate_pairs(REGR_R2_FUNC, &pairs));
+        assert_float(1.0, aggregate_pairs(CORR_FUNC, &pairs));
+        assert_float(10.0 / 3.0, aggregate_pairs(COVAR_SAMP_FUNC, &pairs));
+        assert_float(2.5, aggregate_pairs(COVAR_POP_FUNC, &pairs));
+
+        let pairs = vec![
+            (Val::INT(1), Val::INT(3)),
+            (Val::INT(2), Val::INT(1)),
+            (Val::INT(3), Val::INT(2)),
+        ];
+        assert_float(-0.5, aggregate_pairs(CORR_FUNC, &pairs));
+        assert_float(0.25, aggregate_pairs(REGR_R2_FUNC, &pairs));
+    }
+
+    #[test]
+    fn bivariate_not_enough_values() {
+        let one = vec![(Val::INT(1), Val::INT(2))];
+        assert!(matches!(aggregate_pairs(COVAR_SAMP_FUNC, &one), Val::NULL));
+        assert!(matches!(aggregate_pairs(CORR_FUNC, &one), Val::NULL));
+        assert!(matches!(aggregate_pairs(REGR_SLOPE_FUNC, &[]), Val::NULL));
+
+        // x does not vary
+        let vertical = vec![(Val::INT(1), Val::INT(2)), (Val::INT(3), Val::INT(2))];
+        assert!(matches!(
+            aggregate_pairs(REGR_SLOPE_FUNC, &vertical),
+            Val::NULL
+        ));
+    }
+
+    fn strs(vals: &[&str]) -> Vec<Val> {
+        vals.iter()
+            .map(|val| match *val {
+                "" => Val::NULL,
+                val => Val::STR(val.to_string()),
+            })
+            .collect()
+    }
+
+    fn assert_str(expected: &str, val: Val) {
+        match val {
+            Val::STR(val) => assert_eq!(expected, val),
+            _ => panic!("Expected string {}", expected),
+        }
+    }
+
+    #[test]
+    fn string_agg_values() {
+        let sep = [Val::STR(String::from(", "))];
+        assert_str(
+            "b, a, b",
+            aggregate_with(STRING_AGG_FUNC, &sep, strs(&["b", "", "a", "b"])),
+        );
+        assert_str("1, 2", aggregate_with(STRING_AGG_FUNC, &sep, ints(&[1, 2])));
+        assert!(matches!(
+            aggregate_with(STRING_AGG_FUNC, &sep, strs(&[""])),
+            Val::NULL
+        ));
+        assert!(new_accumulator(STRING_AGG_FUNC, &[]).is_err());
+        assert!(new_accumulator(STRING_AGG_FUNC, &[Val::INT(1)]).is_err());
+    }
+
+    #[test]
+    fn string_agg_with_cap() {
+        let params = [Val::STR(String::from(",")), Val::INT(5)];
+        assert_str(
+            "abc,d",
+            aggregate_with(STRING_AGG_FUNC, &params, strs(&["abc", "def", "ghi"])),
+        );
+        assert!(new_accumulator(STRING_AGG_FUNC, &[params[0].clone(), Val::INT(-1)]).is_err());
+    }
+
+    #[test]
+    fn distinct_values() {
+        let mut acc = Distinct::new(
+            new_accumulator(STRING_AGG_FUNC, &[Val::STR(String::from(","))]).unwrap(),
+        );
+        for val in strs(&["b", "a", "", "b", "a", "c"]) {
+            acc.update(vec![val]).unwrap();
+        }
+        assert_str("b,a,c", acc.finish().unwrap());
+
+        let mut acc = Distinct::new(new_accumulator(COUNT_FUNC, &[]).unwrap());
+        for val in ints(&[1, 2, 1, 1]) {
+            acc.update(vec![val]).unwrap();
+        }
+        assert!(matches!(acc.finish().unwrap(), Val::INT(2)));
+
+        // Values of different types are different even if they have
+        // the same text.
+        let mut acc = Distinct::new(new_accumulator(COUNT_FUNC, &[]).unwrap());
+        let vals = vec![
+            Val::INT(1),
+            Val::FLOAT(1.0),
+            Val::STR(String::from("1")),
+            Val::BOOL(true),
+            Val::STR(String::from("true")),
+            Val::INT(1),
+        ];
+        for val in vals {
+            acc.update(vec![val]).unwrap();
+        }
+        assert!(matches!(acc.finish().unwrap(), Val::INT(5)));
+    }
+
+    #[test]
+    fn arg_max_and_arg_min() {
+        let pairs = vec![
+            (Val::STR(String::from("a")), Val::INT(3)),
+            (Val::STR(String::from("b")), Val::INT(7)),
+            (Val::STR(String::from("c")), Val::NULL),
+            (Val::STR(String::from("d")), Val::INT(7)),
+            (Val::NULL, Val::INT(1)),
+        ];
+        assert_str("b", aggregate_pairs(ARG_MAX_FUNC, &pairs));
+        assert!(matches!(aggregate_pairs(ARG_MIN_FUNC, &pairs), Val::NULL));
+        assert_str("a", aggregate_pairs(ARG_MIN_FUNC, &pairs[..4]));
+        assert!(matches!(
+            aggregate_pairs(ARG_MAX_FUNC, &pairs[2..3]),
+            Val::NULL
+        ));
+    }
+
+    #[test]
+    fn first_and_last() {
+        let vals = || strs(&["", "a", "b", "c", ""]);
+        assert_str("a", aggregate(FIRST_FUNC, vals()));
+        assert_str("a", aggregate(ANY_VALUE_FUNC, vals()));
+        assert_str("c", aggregate(LAST_FUNC, vals()));
+        assert!(matches!(aggregate(FIRST_FUNC, strs(&[""])), Val::NULL));
+        assert!(matches!(aggregate(LAST_FUNC, vec![]), Val::NULL));
+    }
+}
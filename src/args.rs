@@ -1,27 +1,340 @@
+use crate::funcs::{AggregateFunc, Func, Registry, ScalarFunc};
 use std::env;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Args {
     query: String,
+    lenient: bool,
+    thousands_sep: Option<char>,
+    decimal_sep: char,
+    true_tokens: Vec<String>,
+    false_tokens: Vec<String>,
+    null_tokens: Vec<String>,
+    null_output: String,
+    null_on_decode_error: bool,
+    delimiter: Option<String>,
+    whitespace: bool,
+    format: Option<String>,
+    header: bool,
+    funcs: Registry,
 }
 
 impl Args {
     pub fn new(query: String) -> Args {
-        Args { query }
+        Args {
+            query,
+            lenient: false,
+            thousands_sep: None,
+            decimal_sep: '.',
+            true_tokens: vec![String::from("true")],
+            false_tokens: vec![String::from("false")],
+            null_tokens: Vec::new(),
+            null_output: String::from(" "),
+            null_on_decode_error: false,
+            delimiter: None,
+            whitespace: false,
+            format: None,
+            header: false,
+            funcs: Registry::new(),
+        }
     }
 
     pub fn query(&self) -> String {
         self.query.to_string()
     }
 
+    /// Enables (or disables) lenient casts, which ignore whitespace
+    /// around values and accept the configured separators.
+    pub fn with_lenient(mut self, lenient: bool) -> Args {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets the thousands separator accepted by casts to numbers. It
+    /// also enables lenient casts.
+    pub fn with_thousands_sep(mut self, sep: char) -> Args {
+        self.thousands_sep = Some(sep);
+        self.lenient = true;
+        self
+    }
+
+    /// Sets the decimal separator accepted by casts to numbers. It
+    /// also enables lenient casts.
+    pub fn with_decimal_sep(mut self, sep: char) -> Args {
+        self.decimal_sep = sep;
+        self.lenient = true;
+        self
+    }
+
+    /// Sets the values that are cast to `true`.
+    pub fn with_true_tokens(mut self, tokens: Vec<String>) -> Args {
+        self.true_tokens = tokens;
+        self
+    }
+
+    /// Sets the values that are cast to `false`.
+    pub fn with_false_tokens(mut self, tokens: Vec<String>) -> Args {
+        self.false_tokens = tokens;
+        self
+    }
+
+    /// Sets the values that are cast to null. Empty values are always
+    /// cast to null.
+    pub fn with_null_tokens(mut self, tokens: Vec<String>) -> Args {
+        self.null_tokens = tokens;
+        self
+    }
+
+    /// Sets the text that is shown for null values.
+    pub fn with_null_output(mut self, output: String) -> Args {
+        self.null_output = output;
+        self
+    }
+
+    /// Makes decoding functions return null (instead of reporting an
+    /// error) for values that cannot be decoded.
+    pub fn with_null_on_decode_error(mut self, null: bool) -> Args {
+        self.null_on_decode_error = null;
+        self
+    }
+
+    /// Sets the text that separates fields in the input file, unless
+    /// the query gives one. It can contain escape sequences, e.g.,
+    /// `\t`. With the `csv` format, it separates CSV fields instead of
+    /// commas.
+    pub fn with_delimiter(mut self, delimiter: String) -> Args {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Makes runs of whitespace separate fields in the input file
+    /// (ignoring whitespace at the start and the end of a line),
+    /// unless the query gives a format. It takes precedence over the
+    /// delimiter.
+    pub fn with_whitespace(mut self, whitespace: bool) -> Args {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Sets the format of the input file (`csv`), unless the query
+    /// gives one. It takes precedence over the whitespace and the
+    /// delimiter options.
+    pub fn with_format(mut self, format: String) -> Args {
+        self.format = Some(format);
+        self
+    }
+
+    /// Makes the first row of the input file a header, which is
+    /// skipped.
+    pub fn with_header(mut self, header: bool) -> Args {
+        self.header = header;
+        self
+    }
+
+    /// Adds a scalar function that can be called in the query; it
+    /// replaces a built-in function with the same name.
+    pub fn with_scalar_func(mut self, func: Rc<dyn ScalarFunc>) -> Args {
+        self.funcs.register(Func::SCALAR(func));
+        self
+    }
+
+    /// Adds an aggregate function that can be called in the query; it
+    /// replaces a built-in function with the same name.
+    pub fn with_aggregate_func(mut self, func: Rc<dyn AggregateFunc>) -> Args {
+        self.funcs.register(Func::AGG(func));
+        self
+    }
+
+    pub fn lenient(&self) -> bool {
+        self.lenient
+    }
+
+    pub fn thousands_sep(&self) -> Option<char> {
+        self.thousands_sep
+    }
+
+    pub fn decimal_sep(&self) -> char {
+        self.decimal_sep
+    }
+
+    pub fn true_tokens(&self) -> Vec<String> {
+        self.true_tokens.clone()
+    }
+
+    pub fn false_tokens(&self) -> Vec<String> {
+        self.false_tokens.clone()
+    }
+
+    pub fn null_tokens(&self) -> Vec<String> {
+        self.null_tokens.clone()
+    }
+
+    pub fn null_output(&self) -> String {
+        self.null_output.to_string()
+    }
+
+    pub fn null_on_decode_error(&self) -> bool {
+        self.null_on_decode_error
+    }
+
+    pub fn delimiter(&self) -> Option<String> {
+        self.delimiter.clone()
+    }
+
+    pub fn whitespace(&self) -> bool {
+        self.whitespace
+    }
+
+    pub fn format(&self) -> Option<String> {
+        self.format.clone()
+    }
+
+    pub fn header(&self) -> bool {
+        self.header
+    }
+
+    /// Returns the functions that can be called in the query.
+    pub fn funcs(&self) -> &Registry {
+        &self.funcs
+    }
+
     pub fn parse() -> Result<Args, &'static str> {
-        let args: Vec<String> = env::args().collect();
-        if args.len() != 2 {
+        let args: Vec<String> = env::args().skip(1).collect();
+        Args::from(&args)
+    }
+
+    /// Creates arguments from the given command line arguments
+    /// (without the program name). Options have to be given before
+    /// the query.
+    pub fn from(args: &[String]) -> Result<Args, &'static str> {
+        let mut iter = args.iter();
+        let mut options: Vec<&String> = Vec::new();
+        let mut query: Option<&String> = None;
+        for arg in iter.by_ref() {
+            if arg.starts_with("--") {
+                options.push(arg);
+            } else {
+                query = Some(arg);
+                break;
+            }
+        }
+
+        if iter.next().is_some() {
             return Err("Requires exactly one query argument");
         }
+        let mut result = match query {
+            Some(query) => Args::new(query.to_string()),
+            None => return Err("Requires exactly one query argument"),
+        };
+
+        for option in options {
+            result = match option.split_once('=') {
+                None if option == "--lenient" => result.with_lenient(true),
+                None if option == "--whitespace" => result.with_whitespace(true),
+                None if option == "--header" => result.with_header(true),
+                None if option == "--null-on-decode-error" => {
+                    result.with_null_on_decode_error(true)
+                }
+                Some(("--thousands-sep", sep)) => result.with_thousands_sep(parse_char(sep)?),
+                Some(("--decimal-sep", sep)) => result.with_decimal_sep(parse_char(sep)?),
+                Some(("--true", tokens)) => result.with_true_tokens(parse_list(tokens)),
+                Some(("--false", tokens)) => result.with_false_tokens(parse_list(tokens)),
+                Some(("--null", tokens)) => result.with_null_tokens(parse_list(tokens)),
+                Some(("--null-output", output)) => result.with_null_output(output.to_string()),
+                Some(("--delimiter", delimiter)) => result.with_delimiter(delimiter.to_string()),
+                Some(("--format", format)) => result.with_format(format.to_string()),
+                _ => return Err("Unknown option"),
+            };
+        }
+
+        Ok(result)
+    }
+}
+
+fn parse_char(val: &str) -> Result<char, &'static str> {
+    let mut chars = val.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err("Separator has to be a single character"),
+    }
+}
+
+/// Splits a comma separated list of values.
+fn parse_list(val: &str) -> Vec<String> {
+    val.split(',').map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn from_query() {
+        let args = Args::from(&strings(&["select 1 from f"])).unwrap();
+        assert_eq!("select 1 from f", args.query());
+        assert!(!args.lenient());
+    }
+
+    #[test]
+    fn from_options() {
+        let args = Args::from(&strings(&[
+            "--lenient",
+            "--thousands-sep=,",
+            "--decimal-sep=.",
+            "select 1 from f",
+        ]))
+        .unwrap();
+        assert!(args.lenient());
+        assert_eq!(Some(','), args.thousands_sep());
+        assert_eq!('.', args.decimal_sep());
+    }
+
+    #[test]
+    fn from_token_options() {
+        let args = Args::from(&strings(&[
+            "--true=yes,y",
+            "--false=no",
+            "--null=NA,-",
+            "--null-output=NULL",
+            "select 1 from f",
+        ]))
+        .unwrap();
+        assert_eq!(strings(&["yes", "y"]), args.true_tokens());
+        assert_eq!(strings(&["no"]), args.false_tokens());
+        assert_eq!(strings(&["NA", "-"]), args.null_tokens());
+        assert_eq!("NULL", args.null_output());
+        assert_eq!(None, args.delimiter());
+        assert!(!args.whitespace());
+        assert_eq!(None, args.format());
+        assert!(!args.header());
+        assert!(!args.null_on_decode_error());
+
+        let args = Args::from(&strings(&[
+            "--null-on-decode-error",
+            "--delimiter=\\t",
+            "--whitespace",
+            "--format=csv",
+            "--header",
+            "select 1 from f",
+        ]))
+        .unwrap();
+        assert!(args.null_on_decode_error());
+        assert_eq!(Some("\\t".to_string()), args.delimiter());
+        assert!(args.whitespace());
+        assert_eq!(Some("csv".to_string()), args.format());
+        assert!(args.header());
+    }
 
-        Ok(Args {
-            query: String::from(&args[1]),
-        })
+    #[test]
+    fn from_invalid() {
+        assert!(Args::from(&strings(&[])).is_err());
+        assert!(Args::from(&strings(&["q1", "q2"])).is_err());
+        assert!(Args::from(&strings(&["--unknown", "q"])).is_err());
+        assert!(Args::from(&strings(&["--thousands-sep=ab", "q"])).is_err());
     }
 }
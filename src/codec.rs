@@ -0,0 +1,234 @@
+// Encodings used by the scalar functions. Decoders return `None` if
+// the given text is not a valid encoding.
+
+use std::fmt::Write;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the given bytes with the standard base64 alphabet (RFC
+/// 4648), with padding.
+pub fn to_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let ix = (bits >> (18 - 6 * i)) & 0x3f;
+                out.push(BASE64_ALPHABET[ix as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes base64 text (the standard alphabet); padding is optional.
+pub fn from_base64(val: &str) -> Option<Vec<u8>> {
+    let trimmed = val.trim_end_matches('=');
+    let padding = val.len() - trimmed.len();
+    if padding > 2 || (padding > 0 && !val.len().is_multiple_of(4)) || trimmed.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(trimmed.len() / 4 * 3 + 2);
+    let mut bits: u32 = 0;
+    let mut nbits = 0;
+    for c in trimmed.bytes() {
+        let ix = BASE64_ALPHABET.iter().position(|a| *a == c)?;
+        bits = bits << 6 | ix as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push((bits >> nbits) as u8);
+            bits &= (1 << nbits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Formats the given bytes as lower-case hex digits.
+pub fn to_hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(2 * data.len());
+    for byte in data {
+        write!(out, "{:02x}", byte).expect("writing to a string cannot fail");
+    }
+    out
+}
+
+/// Decodes hex digits (in any case).
+pub fn from_hex(val: &str) -> Option<Vec<u8>> {
+    if !val.len().is_multiple_of(2) {
+        return None;
+    }
+    val.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((hex_digit(pair[0])? << 4) | hex_digit(pair[1])?))
+        .collect()
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Percent-encodes all bytes of the UTF-8 encoding except the
+/// unreserved characters (RFC 3986).
+pub fn url_encode(val: &str) -> String {
+    let mut out = String::with_capacity(val.len());
+    for byte in val.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => write!(out, "%{:02X}", byte).expect("writing to a string cannot fail"),
+        }
+    }
+    out
+}
+
+/// Decodes percent-encoded bytes; other characters (including `+`)
+/// are kept.
+pub fn url_decode(val: &str) -> Option<Vec<u8>> {
+    let bytes = val.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut ix = 0;
+    while ix < bytes.len() {
+        if bytes[ix] == b'%' {
+            let high = hex_digit(*bytes.get(ix + 1)?)?;
+            let low = hex_digit(*bytes.get(ix + 2)?)?;
+            out.push(high << 4 | low);
+            ix += 3;
+        } else {
+            out.push(bytes[ix]);
+            ix += 1;
+        }
+    }
+    Some(out)
+}
+
+/// Replaces the characters that are special in HTML with entities.
+pub fn html_escape(val: &str) -> String {
+    let mut out = String::with_capacity(val.len());
+    for c in val.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replaces the common named entities and all numeric character
+/// references with the characters they stand for. A `&` that does not
+/// start a reference (a name or number followed by `;`) is kept, but
+/// an unknown entity or an invalid character reference cannot be
+/// decoded.
+pub fn html_unescape(val: &str) -> Option<String> {
+    let mut out = String::with_capacity(val.len());
+    let mut rest = val;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+            .unwrap_or(rest.len());
+        if len > 0 && rest[len..].starts_with(';') {
+            out.push(entity_char(&rest[..len])?);
+            rest = &rest[len + 1..];
+        } else {
+            out.push('&');
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Returns the character for the given entity name (without `&` and
+/// `;`), e.g., `amp` or `#x41`.
+fn entity_char(name: &str) -> Option<char> {
+    let code = match name {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some('\u{a0}'),
+        _ => name.strip_prefix('#')?,
+    };
+    let code = match code.strip_prefix(['x', 'X']) {
+        Some(hex) if !hex.is_empty() && hex.len() <= 6 => u32::from_str_radix(hex, 16).ok()?,
+        None if !code.is_empty() && code.len() <= 7 => code.parse::<u32>().ok()?,
+        _ => return None,
+    };
+    char::from_u32(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_roundtrip() {
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encoded, to_base64(data.as_bytes()));
+            assert_eq!(Some(data.as_bytes().to_vec()), from_base64(encoded));
+        }
+        assert_eq!(Some(b"fo".to_vec()), from_base64("Zm8"));
+        assert_eq!(None, from_base64("Zm9v!"));
+        assert_eq!(None, from_base64("Z"));
+        assert_eq!(None, from_base64("Zm8=="));
+        assert_eq!(None, from_base64("Zg="));
+    }
+
+    #[test]
+    fn hex_roundtrip() {
+        assert_eq!("00ff10", to_hex(&[0, 255, 16]));
+        assert_eq!(Some(vec![0, 255, 16]), from_hex("00FF10"));
+        assert_eq!(None, from_hex("abc"));
+        assert_eq!(None, from_hex("zz"));
+    }
+
+    #[test]
+    fn url_roundtrip() {
+        assert_eq!("a%20b%2Fc~%C3%A9", url_encode("a b/c~\u{e9}"));
+        assert_eq!(
+            Some("a b/c+\u{e9}".as_bytes().to_vec()),
+            url_decode("a%20b%2fc+%C3%A9")
+        );
+        assert_eq!(None, url_decode("100%"));
+        assert_eq!(None, url_decode("%zz"));
+    }
+
+    #[test]
+    fn html_roundtrip() {
+        let text = "<a href=\"x\">Tom & Jerry's</a>";
+        let escaped = "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;";
+        assert_eq!(escaped, html_escape(text));
+        assert_eq!(Some(text.to_string()), html_unescape(escaped));
+        assert_eq!(
+            Some("A\u{e9} & &amp".to_string()),
+            html_unescape("&#x41;&#233; & &amp")
+        );
+        assert_eq!(None, html_unescape("&unknown;"));
+        assert_eq!(None, html_unescape("&#xZZ;"));
+        assert_eq!(None, html_unescape("&#xD800;"));
+    }
+}
@@ -1,49 +1,136 @@
+use crate::aggs::{Accumulator, Distinct};
+use crate::codec;
 use crate::errors::FqError;
-use std::fmt;
+use crate::funcs::{Arity, Func, ScalarFunc, Signature, Type};
+use crate::hash;
+use crate::json::{Json, Step};
+use crate::table::{RowMeta, Table};
+use crate::unicode;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Val {
     INT(i32),
     FLOAT(f32),
     STR(String),
     BOOL(bool),
-    ACC(Box<Val>),
+    NULL,
+}
+
+/// Typed representation of a value that can be used as a key, e.g.,
+/// to group values; values of different types are different keys.
+#[derive(Debug, Eq, Hash, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum ValKey {
+    INT(i32),
+    // bits of the float (with -0.0 as 0.0)
+    FLOAT(u32),
+    STR(String),
+    BOOL(bool),
     NULL,
 }
 
 // string functions
-const UPPER_FUNC: &'static str = "upper";
-const LOWER_FUNC: &'static str = "lower";
-const LENGTH_FUNC: &'static str = "length";
-const REV_FUNC: &'static str = "rev";
+const UPPER_FUNC: &str = "upper";
+const LOWER_FUNC: &str = "lower";
+const LENGTH_FUNC: &str = "length";
+const REV_FUNC: &str = "rev";
+const OCTET_LENGTH_FUNC: &str = "octet_length";
+const SUBSTR_FUNC: &str = "substr";
+const CASEFOLD_FUNC: &str = "casefold";
+const NFC_FUNC: &str = "nfc";
+const NFD_FUNC: &str = "nfd";
+const TRIM_FUNC: &str = "trim";
+const LTRIM_FUNC: &str = "ltrim";
+const RTRIM_FUNC: &str = "rtrim";
+const REPLACE_FUNC: &str = "replace";
+const SPLIT_PART_FUNC: &str = "split_part";
+const LPAD_FUNC: &str = "lpad";
+const RPAD_FUNC: &str = "rpad";
+const STARTS_WITH_FUNC: &str = "starts_with";
+const ENDS_WITH_FUNC: &str = "ends_with";
+const CONTAINS_FUNC: &str = "contains";
+const POSITION_FUNC: &str = "position";
+const REPEAT_FUNC: &str = "repeat";
+const CONCAT_WS_FUNC: &str = "concat_ws";
+const MD5_FUNC: &str = "md5";
+const SHA1_FUNC: &str = "sha1";
+const SHA256_FUNC: &str = "sha256";
+const CRC32_FUNC: &str = "crc32";
+const HASH64_FUNC: &str = "hash64";
+const TO_BASE64_FUNC: &str = "to_base64";
+const FROM_BASE64_FUNC: &str = "from_base64";
+const TO_HEX_FUNC: &str = "to_hex";
+const FROM_HEX_FUNC: &str = "from_hex";
+const URL_ENCODE_FUNC: &str = "url_encode";
+const URL_DECODE_FUNC: &str = "url_decode";
+const HTML_ESCAPE_FUNC: &str = "html_escape";
+const HTML_UNESCAPE_FUNC: &str = "html_unescape";
 // math functions
-const ABS_FUNC: &'static str = "abs";
-const SIGN_FUNC: &'static str = "sign";
-const CEIL_FUNC: &'static str = "ceil";
-const FLOOR_FUNC: &'static str = "floor";
-const ROUND_FUNC: &'static str = "round";
-const COS_FUNC: &'static str = "cos";
-const SIN_FUNC: &'static str = "sin";
-// aggragate functions
-const SUM_FUNC: &'static str = "sum";
-const COUNT_FUNC: &'static str = "count";
-const MAX_FUNC: &'static str = "max";
-const MIN_FUNC: &'static str = "min";
-const AVG_FUNC: &'static str = "avg";
-
-pub static SCALAR_FUNCS: [&str; 11] = [
-    UPPER_FUNC,
-    LOWER_FUNC,
-    LENGTH_FUNC,
-    REV_FUNC,
-    ABS_FUNC,
-    SIGN_FUNC,
-    CEIL_FUNC,
-    FLOOR_FUNC,
-    ROUND_FUNC,
-    COS_FUNC,
-    SIN_FUNC,
-];
+const ABS_FUNC: &str = "abs";
+const SIGN_FUNC: &str = "sign";
+const CEIL_FUNC: &str = "ceil";
+const FLOOR_FUNC: &str = "floor";
+const ROUND_FUNC: &str = "round";
+const COS_FUNC: &str = "cos";
+const SIN_FUNC: &str = "sin";
+const SQRT_FUNC: &str = "sqrt";
+const POW_FUNC: &str = "pow";
+const EXP_FUNC: &str = "exp";
+const LN_FUNC: &str = "ln";
+const LOG10_FUNC: &str = "log10";
+const LOG2_FUNC: &str = "log2";
+const TAN_FUNC: &str = "tan";
+const ASIN_FUNC: &str = "asin";
+const ACOS_FUNC: &str = "acos";
+const ATAN_FUNC: &str = "atan";
+const ATAN2_FUNC: &str = "atan2";
+const PI_FUNC: &str = "pi";
+const MOD_FUNC: &str = "mod";
+const GREATEST_FUNC: &str = "greatest";
+const LEAST_FUNC: &str = "least";
+const TRUNC_FUNC: &str = "trunc";
+
+/// Describes how the value of an expression relates to the rows of a
+/// table, which is used to check if columns can be used together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum ExprKind {
+    /// Value does not depend on rows, e.g., `1 + 2`.
+    CONST,
+    /// Value is computed for each row, e.g., `int(@0) * 2`.
+    SCALAR,
+    /// Value is computed over all rows, e.g., `sum(int(@0))`.
+    AGG,
+    /// Per-row and aggregate values in one expression, e.g.,
+    /// `int(@0) + sum(int(@0))`.
+    MIXED,
+    /// Aggregate function used inside an aggregate function (or any
+    /// function call inside a window function call).
+    NESTED,
+    /// Value is computed for each row over a window of rows, e.g.,
+    /// `row_number() over (order by int(@0))`.
+    WINDOW,
+}
+
+impl ExprKind {
+    /// Returns the kind of an expression that combines two
+    /// subexpressions of the given kinds.
+    pub fn combine(self, other: ExprKind) -> ExprKind {
+        match (self, other) {
+            (ExprKind::NESTED, _) | (_, ExprKind::NESTED) => ExprKind::NESTED,
+            (ExprKind::MIXED, _) | (_, ExprKind::MIXED) => ExprKind::MIXED,
+            (ExprKind::AGG, ExprKind::SCALAR | ExprKind::WINDOW)
+            | (ExprKind::SCALAR | ExprKind::WINDOW, ExprKind::AGG) => ExprKind::MIXED,
+            (ExprKind::WINDOW, _) | (_, ExprKind::WINDOW) => ExprKind::WINDOW,
+            (ExprKind::CONST, kind) | (kind, ExprKind::CONST) => kind,
+            (kind, _) => kind,
+        }
+    }
+}
 
 pub trait Sign {
     fn sign(&self) -> i32;
@@ -69,22 +156,73 @@ impl Sign for f32 {
     }
 }
 
-fn max<T: PartialOrd>(first: T, second: T) -> T {
-    if first < second {
-        second
-    } else {
-        first
+/// Returns the string in the given argument of the given function,
+/// or `None` if the argument is null.
+fn str_arg<'a>(val: &'a Val, func: &str) -> Result<Option<&'a str>, FqError> {
+    match val {
+        Val::STR(val) => Ok(Some(val)),
+        Val::NULL => Ok(None),
+        _ => Err(FqError::exe(format!(
+            "{}() only works for string types",
+            func
+        ))),
+    }
+}
+
+/// Returns the int in the given argument of the given function, or
+/// `None` if the argument is null.
+fn int_arg(val: &Val, func: &str) -> Result<Option<i32>, FqError> {
+    match val {
+        Val::INT(val) => Ok(Some(*val)),
+        Val::NULL => Ok(None),
+        _ => Err(FqError::exe(format!("{}() requires an int argument", func))),
     }
 }
 
-fn min<T: PartialOrd>(first: T, second: T) -> T {
-    if first < second {
-        first
+/// Returns the number in the given argument of the given function as
+/// a float, or `None` if the argument is null.
+fn num_arg(val: &Val, func: &str) -> Result<Option<f32>, FqError> {
+    match val {
+        Val::INT(val) => Ok(Some(*val as f32)),
+        Val::FLOAT(val) => Ok(Some(*val)),
+        Val::NULL => Ok(None),
+        _ => Err(FqError::exe(format!(
+            "{}() only works for number types",
+            func
+        ))),
+    }
+}
+
+/// Check of valid arguments of a math function and their description
+/// (for error messages).
+type Domain = (fn(f32) -> bool, &'static str);
+
+const NON_NEGATIVE: Domain = (|x| x >= 0.0, "a non-negative argument");
+const POSITIVE: Domain = (|x| x > 0.0, "a positive argument");
+const UNIT_RANGE: Domain = (|x| x.abs() <= 1.0, "an argument in [-1, 1]");
+
+/// Returns the given result of the given math function, or an error
+/// if the result is not a finite number, e.g., for `pow(-8, 0.5)`.
+fn finite(val: f32, func: &str) -> Result<Val, FqError> {
+    if val.is_finite() {
+        Ok(Val::FLOAT(val))
     } else {
-        second
+        Err(FqError::exe(format!(
+            "{}() result is not a finite number",
+            func
+        )))
     }
 }
 
+/// Which end(s) of a string `trim` functions work on.
+#[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+enum Side {
+    LEFT,
+    RIGHT,
+    BOTH,
+}
+
 // todo: PartialOrd and PartialEq
 impl Val {
     // scalar functions
@@ -114,22 +252,325 @@ impl Val {
         }
     }
 
+    /// Returns the number of characters (grapheme clusters), e.g.,
+    /// `e` followed by a combining accent is one character.
     fn length(&self) -> Result<Val, FqError> {
         match self {
-            Val::STR(val) => Ok(Val::INT(val.bytes().len() as i32)),
+            Val::STR(val) => Ok(Val::INT(unicode::graphemes(val).len() as i32)),
             Val::NULL => Ok(Val::NULL),
             _ => Err(FqError::exe("length() only works for string types")),
         }
     }
 
+    /// Returns the number of bytes in the UTF-8 encoding.
+    fn octet_length(&self) -> Result<Val, FqError> {
+        match self {
+            Val::STR(val) => Ok(Val::INT(val.len() as i32)),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("octet_length() only works for string types")),
+        }
+    }
+
+    /// Reverses the characters (grapheme clusters), so combining
+    /// marks stay with their base characters.
     fn rev(&self) -> Result<Val, FqError> {
         match self {
-            Val::STR(val) => Ok(Val::STR(val.chars().rev().collect())),
+            Val::STR(val) => Ok(Val::STR(
+                unicode::graphemes(val).into_iter().rev().collect(),
+            )),
             Val::NULL => Ok(Val::NULL),
             _ => Err(FqError::exe("rev() only works for string types")),
         }
     }
 
+    /// Returns `len` characters (grapheme clusters) starting at the
+    /// 1-based position `start`; all the remaining characters if
+    /// `len` is not given. Positions before the first character
+    /// count towards `len`, as in SQL.
+    fn substr(&self, start: &Val, len: Option<&Val>) -> Result<Val, FqError> {
+        let val = str_arg(self, SUBSTR_FUNC)?;
+        let start = int_arg(start, SUBSTR_FUNC)?;
+        let len = match len {
+            Some(len) => int_arg(len, SUBSTR_FUNC)?.map(Some),
+            None => Some(None),
+        };
+        let (Some(val), Some(start), Some(len)) = (val, start, len) else {
+            return Ok(Val::NULL);
+        };
+
+        let start = start as i64;
+        let end = match len {
+            Some(len) if len < 0 => {
+                return Err(FqError::exe("substr() requires a non-negative length"));
+            }
+            Some(len) => start + len as i64,
+            None => i64::MAX,
+        };
+
+        Ok(Val::STR(
+            unicode::graphemes(val)
+                .into_iter()
+                .enumerate()
+                .filter(|(ix, _)| {
+                    let pos = *ix as i64 + 1;
+                    pos >= start && pos < end
+                })
+                .map(|(_, g)| g)
+                .collect(),
+        ))
+    }
+
+    /// Applies full Unicode case folding, which is meant for
+    /// case-insensitive comparison, e.g., `Straße` becomes `strasse`.
+    fn casefold(&self) -> Result<Val, FqError> {
+        match self {
+            Val::STR(val) => Ok(Val::STR(unicode::case_fold(val))),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("casefold() only works for string types")),
+        }
+    }
+
+    /// Converts to the Unicode normalization form C (composed).
+    fn nfc(&self) -> Result<Val, FqError> {
+        match self {
+            Val::STR(val) => Ok(Val::STR(unicode::nfc(val))),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("nfc() only works for string types")),
+        }
+    }
+
+    /// Converts to the Unicode normalization form D (decomposed).
+    fn nfd(&self) -> Result<Val, FqError> {
+        match self {
+            Val::STR(val) => Ok(Val::STR(unicode::nfd(val))),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("nfd() only works for string types")),
+        }
+    }
+
+    /// Removes the given characters (whitespace if not given) from
+    /// the given side(s) of the string.
+    fn trim(&self, chars: Option<&Val>, side: Side) -> Result<Val, FqError> {
+        let func = match side {
+            Side::LEFT => LTRIM_FUNC,
+            Side::RIGHT => RTRIM_FUNC,
+            Side::BOTH => TRIM_FUNC,
+        };
+        let val = str_arg(self, func)?;
+        let chars = match chars {
+            Some(chars) => str_arg(chars, func)?.map(Some),
+            None => Some(None),
+        };
+        let (Some(val), Some(chars)) = (val, chars) else {
+            return Ok(Val::NULL);
+        };
+
+        let matches = |c: char| match chars {
+            Some(chars) => chars.contains(c),
+            None => c.is_whitespace(),
+        };
+        let val = match side {
+            Side::LEFT => val.trim_start_matches(matches),
+            Side::RIGHT => val.trim_end_matches(matches),
+            Side::BOTH => val.trim_matches(matches),
+        };
+        Ok(Val::STR(val.to_string()))
+    }
+
+    /// Replaces all occurrences of `from` with `to`. The string is
+    /// unchanged if `from` is empty.
+    fn replace(&self, from: &Val, to: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(from), Some(to)) = (
+            str_arg(self, REPLACE_FUNC)?,
+            str_arg(from, REPLACE_FUNC)?,
+            str_arg(to, REPLACE_FUNC)?,
+        ) else {
+            return Ok(Val::NULL);
+        };
+
+        if from.is_empty() {
+            return Ok(Val::STR(val.to_string()));
+        }
+        Ok(Val::STR(val.replace(from, to)))
+    }
+
+    /// Splits the string on the delimiter and returns the `n`th part
+    /// (1-based); a negative `n` counts from the end. Returns an
+    /// empty string if there is no such part.
+    fn split_part(&self, delim: &Val, n: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(delim), Some(n)) = (
+            str_arg(self, SPLIT_PART_FUNC)?,
+            str_arg(delim, SPLIT_PART_FUNC)?,
+            int_arg(n, SPLIT_PART_FUNC)?,
+        ) else {
+            return Ok(Val::NULL);
+        };
+
+        let parts: Vec<&str> = if delim.is_empty() {
+            vec![val]
+        } else {
+            val.split(delim).collect()
+        };
+        let ix = match n {
+            0 => return Err(FqError::exe("split_part() requires a non-zero part")),
+            n if n > 0 => n as usize - 1,
+            n if (n.unsigned_abs() as usize) <= parts.len() => {
+                parts.len() - n.unsigned_abs() as usize
+            }
+            _ => return Ok(Val::STR(String::new())),
+        };
+        Ok(Val::STR(parts.get(ix).unwrap_or(&"").to_string()))
+    }
+
+    /// Pads the string to `len` characters (grapheme clusters) with
+    /// the fill string (a space if not given) on the left or right.
+    /// The string is truncated if it is longer than `len`.
+    fn pad(&self, len: &Val, fill: Option<&Val>, left: bool) -> Result<Val, FqError> {
+        let func = if left { LPAD_FUNC } else { RPAD_FUNC };
+        let val = str_arg(self, func)?;
+        let len = int_arg(len, func)?;
+        let fill = match fill {
+            Some(fill) => str_arg(fill, func)?,
+            None => Some(" "),
+        };
+        let (Some(val), Some(len), Some(fill)) = (val, len, fill) else {
+            return Ok(Val::NULL);
+        };
+
+        let len = len.max(0) as usize;
+        let chars: Vec<&str> = unicode::graphemes(val);
+        if chars.len() >= len || fill.is_empty() {
+            return Ok(Val::STR(chars.iter().take(len).copied().collect()));
+        }
+
+        let padding: String = unicode::graphemes(fill)
+            .into_iter()
+            .cycle()
+            .take(len - chars.len())
+            .collect();
+        if left {
+            Ok(Val::STR(padding + val))
+        } else {
+            Ok(Val::STR(val.to_string() + &padding))
+        }
+    }
+
+    fn starts_with(&self, prefix: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(prefix)) = (
+            str_arg(self, STARTS_WITH_FUNC)?,
+            str_arg(prefix, STARTS_WITH_FUNC)?,
+        ) else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::BOOL(val.starts_with(prefix)))
+    }
+
+    fn ends_with(&self, suffix: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(suffix)) = (
+            str_arg(self, ENDS_WITH_FUNC)?,
+            str_arg(suffix, ENDS_WITH_FUNC)?,
+        ) else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::BOOL(val.ends_with(suffix)))
+    }
+
+    fn contains(&self, sub: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(sub)) = (str_arg(self, CONTAINS_FUNC)?, str_arg(sub, CONTAINS_FUNC)?)
+        else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::BOOL(val.contains(sub)))
+    }
+
+    /// Returns the 1-based position (in characters) of the first
+    /// occurrence of `sub`, or 0 if there is no occurrence.
+    fn position(&self, sub: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(sub)) = (str_arg(self, POSITION_FUNC)?, str_arg(sub, POSITION_FUNC)?)
+        else {
+            return Ok(Val::NULL);
+        };
+        match val.find(sub) {
+            Some(ix) => Ok(Val::INT(unicode::graphemes(&val[..ix]).len() as i32 + 1)),
+            None => Ok(Val::INT(0)),
+        }
+    }
+
+    /// Repeats the string `n` times; an empty string if `n` is not
+    /// positive.
+    fn repeat(&self, n: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(n)) = (str_arg(self, REPEAT_FUNC)?, int_arg(n, REPEAT_FUNC)?) else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::STR(val.repeat(n.max(0) as usize)))
+    }
+
+    /// Joins the given values with the separator (`self`). Null
+    /// values are skipped.
+    fn concat_ws(&self, vals: &[Val]) -> Result<Val, FqError> {
+        let Some(sep) = str_arg(self, CONCAT_WS_FUNC)? else {
+            return Ok(Val::NULL);
+        };
+        let parts: Vec<String> = vals.iter().filter_map(Val::text).collect();
+        Ok(Val::STR(parts.join(sep)))
+    }
+
+    /// Returns the digest (as hex digits) of the UTF-8 encoding of
+    /// the string.
+    fn digest(&self, func: &str, hash: fn(&[u8]) -> String) -> Result<Val, FqError> {
+        let Some(val) = str_arg(self, func)? else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::STR(hash(val.as_bytes())))
+    }
+
+    /// Encodes the string (its UTF-8 encoding for binary encodings)
+    /// with the given encoder.
+    fn encode(&self, func: &str, encode: fn(&str) -> String) -> Result<Val, FqError> {
+        let Some(val) = str_arg(self, func)? else {
+            return Ok(Val::NULL);
+        };
+        Ok(Val::STR(encode(val)))
+    }
+
+    /// Decodes the string with the given decoder. A value that cannot
+    /// be decoded, or that is not valid UTF-8 once decoded, is null
+    /// or an error depending on the format.
+    fn decode(
+        &self,
+        func: &str,
+        decode: fn(&str) -> Option<Vec<u8>>,
+        format: &ValFormat,
+    ) -> Result<Val, FqError> {
+        let Some(val) = str_arg(self, func)? else {
+            return Ok(Val::NULL);
+        };
+        match decode(val).and_then(|bytes| String::from_utf8(bytes).ok()) {
+            Some(decoded) => Ok(Val::STR(decoded)),
+            None if format.null_on_decode_error => Ok(Val::NULL),
+            None => Err(FqError::exe(format!("{}() cannot decode {}", func, val))),
+        }
+    }
+
+    /// Returns the 64-bit hash of the string as hex digits, or the
+    /// bucket (from 0 to `buckets` - 1) that the hash falls into if
+    /// the number of buckets is given.
+    fn hash64(&self, buckets: Option<&Val>) -> Result<Val, FqError> {
+        let Some(val) = str_arg(self, HASH64_FUNC)? else {
+            return Ok(Val::NULL);
+        };
+        let hash = hash::fnv1a64(val.as_bytes());
+        match buckets.map(|buckets| int_arg(buckets, HASH64_FUNC)) {
+            None => Ok(Val::STR(format!("{:016x}", hash))),
+            Some(Ok(Some(buckets))) if buckets > 0 => Ok(Val::INT((hash % buckets as u64) as i32)),
+            Some(Ok(None)) => Ok(Val::NULL),
+            Some(Err(err)) => Err(err),
+            Some(_) => Err(FqError::exe(
+                "hash64() requires a positive int number of buckets",
+            )),
+        }
+    }
+
     fn sign(&self) -> Result<Val, FqError> {
         match self {
             Val::INT(val) => Ok(Val::INT(val.sign())),
@@ -189,115 +630,110 @@ impl Val {
         Ok(Val::FLOAT(val))
     }
 
-    // aggragate functions
-
-    fn sum(&self, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        match self {
-            Val::INT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::INT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::INT(*val + other)))),
-                    Val::FLOAT(other) => Ok(Val::ACC(Box::new(Val::FLOAT((*val as f32) + other)))),
-                    _ => Err(FqError::exe("sum() only works for number types")),
-                },
-            },
-            Val::FLOAT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::FLOAT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(*val + (*other as f32))))),
-                    Val::FLOAT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(*val + other)))),
-                    _ => Err(FqError::exe("sum() only works for number types")),
-                },
-            },
-            // value to be processed is null
-            Val::NULL => match acc {
-                None => Ok(Val::ACC(Box::new(Val::NULL))),
-                Some(acc) => Ok(Val::ACC(Box::new(*acc.clone()))),
-            },
-            _ => Err(FqError::exe("sum() only works for number types")),
+    /// Applies a math function (that returns a float) to the number.
+    /// The optional domain decides if the number is a valid argument
+    /// and describes valid arguments for the error message.
+    fn math(&self, func: &str, domain: Option<Domain>, op: fn(f32) -> f32) -> Result<Val, FqError> {
+        let Some(val) = num_arg(self, func)? else {
+            return Ok(Val::NULL);
+        };
+        if let Some((check, requirement)) = domain {
+            if !check(val) {
+                return Err(FqError::exe(format!("{}() requires {}", func, requirement)));
+            }
         }
+        finite(op(val), func)
     }
 
-    fn count(&self, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        // Do not count `null` values.
-        if let Val::NULL = self {
-            match acc {
-                None => {
-                    return Ok(Val::ACC(Box::new(Val::NULL)));
-                }
-                Some(acc) => {
-                    return Ok(Val::ACC(Box::new(*acc.clone())));
-                }
-            }
+    fn pow(&self, exp: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(exp)) = (num_arg(self, POW_FUNC)?, num_arg(exp, POW_FUNC)?) else {
+            return Ok(Val::NULL);
+        };
+        finite(val.powf(exp), POW_FUNC)
+    }
+
+    fn atan2(&self, x: &Val) -> Result<Val, FqError> {
+        let (Some(y), Some(x)) = (num_arg(self, ATAN2_FUNC)?, num_arg(x, ATAN2_FUNC)?) else {
+            return Ok(Val::NULL);
+        };
+        finite(y.atan2(x), ATAN2_FUNC)
+    }
+
+    /// Remainder of the division; its sign is the sign of the
+    /// dividend, as for `%` in Rust.
+    fn rem(&self, other: &Val) -> Result<Val, FqError> {
+        let (Some(val), Some(divisor)) = (num_arg(self, MOD_FUNC)?, num_arg(other, MOD_FUNC)?)
+        else {
+            return Ok(Val::NULL);
+        };
+        if divisor == 0.0 {
+            return Err(FqError::exe("mod() division by 0"));
         }
-        match acc {
-            None => Ok(Val::ACC(Box::new(Val::INT(1)))),
-            Some(acc) => match acc.as_ref() {
-                Val::INT(val) => Ok(Val::ACC(Box::new(Val::INT(*val + 1)))),
-                _ => Err(FqError::exe("Internal error")),
-            },
+        match (self, other) {
+            (Val::INT(val), Val::INT(other)) => Ok(Val::INT(val.wrapping_rem(*other))),
+            _ => Ok(Val::FLOAT(val % divisor)),
+        }
+    }
+
+    /// Returns the largest (or smallest if `greatest` is false) of
+    /// the given numbers or strings. Null values are skipped; the
+    /// result is null if all values are null.
+    fn extreme(vals: &[Val], greatest: bool) -> Result<Val, FqError> {
+        let func = if greatest { GREATEST_FUNC } else { LEAST_FUNC };
+        let mut result = Val::NULL;
+        for val in vals {
+            if !matches!(val, Val::INT(_) | Val::FLOAT(_) | Val::STR(_) | Val::NULL) {
+                return Err(FqError::exe(format!(
+                    "{}() only works for number and string types",
+                    func
+                )));
+            }
+            let replace = match (&result, val) {
+                (_, Val::NULL) => false,
+                (Val::NULL, _) => true,
+                (current, val) if greatest => matches!(current.lt(val)?, Val::BOOL(true)),
+                (current, val) => matches!(val.lt(current)?, Val::BOOL(true)),
+            };
+            if replace {
+                result = val.clone();
+            }
         }
+        Ok(result)
     }
 
-    fn max(&self, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn trunc(&self) -> Result<Val, FqError> {
         match self {
-            Val::INT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::INT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::INT(max(*val, *other))))),
-                    Val::FLOAT(other) => {
-                        Ok(Val::ACC(Box::new(Val::FLOAT(max(*val as f32, *other)))))
-                    }
-                    _ => Err(FqError::exe("max() only works for number types")),
-                },
-            },
-            Val::FLOAT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::FLOAT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(max(*val, *other as f32))))),
-                    Val::FLOAT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(max(*val, *other))))),
-                    _ => Err(FqError::exe("max() only works for number types")),
-                },
-            },
-            Val::NULL => match acc {
-                None => Ok(Val::ACC(Box::new(Val::NULL))),
-                Some(acc) => Ok(Val::ACC(Box::new(*acc.clone()))),
-            },
-            _ => Err(FqError::exe("max() only works for number types")),
+            Val::INT(val) => Ok(Val::INT(*val)),
+            Val::FLOAT(val) => Ok(Val::FLOAT(val.trunc())),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("trunc() only works for number types")),
         }
     }
 
-    fn min(&self, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    /// Rounds to the given number of digits after the decimal point;
+    /// a negative number of digits rounds to tens, hundreds, etc.
+    fn round_to(&self, digits: &Val) -> Result<Val, FqError> {
+        let Some(digits) = int_arg(digits, ROUND_FUNC)? else {
+            return Ok(Val::NULL);
+        };
         match self {
-            Val::INT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::INT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::INT(min(*val, *other))))),
-                    Val::FLOAT(other) => {
-                        Ok(Val::ACC(Box::new(Val::FLOAT(min(*val as f32, *other)))))
-                    }
-                    _ => Err(FqError::exe("min() only works for number types")),
-                },
-            },
-            Val::FLOAT(val) => match acc {
-                None => Ok(Val::ACC(Box::new(Val::FLOAT(*val)))),
-                Some(acc) => match acc.as_ref() {
-                    Val::INT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(min(*val, *other as f32))))),
-                    Val::FLOAT(other) => Ok(Val::ACC(Box::new(Val::FLOAT(min(*val, *other))))),
-                    _ => Err(FqError::exe("min() only works for number types")),
-                },
-            },
-            Val::NULL => match acc {
-                None => Ok(Val::ACC(Box::new(Val::NULL))),
-                Some(acc) => Ok(Val::ACC(Box::new(*acc.clone()))),
-            },
-            _ => Err(FqError::exe("min() only works for number types")),
+            Val::INT(val) if digits >= 0 => Ok(Val::INT(*val)),
+            Val::INT(val) => {
+                let scale = 10f64.powi(-digits);
+                Ok(Val::INT(((*val as f64 / scale).round() * scale) as i32))
+            }
+            Val::FLOAT(val) => {
+                let scale = 10f64.powi(digits);
+                finite(((*val as f64 * scale).round() / scale) as f32, ROUND_FUNC)
+            }
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("round() only works for number types")),
         }
     }
 
     // expressions
 
-    fn plus(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn plus(&self, other: &Val) -> Result<Val, FqError> {
         match (self, other) {
             (Val::INT(val), Val::INT(other)) => Ok(Val::INT(*val + *other)),
             (Val::INT(val), Val::FLOAT(other)) => Ok(Val::FLOAT((*val as f32) + *other)),
@@ -308,7 +744,7 @@ impl Val {
         }
     }
 
-    fn minus(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn minus(&self, other: &Val) -> Result<Val, FqError> {
         match (self, other) {
             (Val::INT(val), Val::INT(other)) => Ok(Val::INT(*val - *other)),
             (Val::INT(val), Val::FLOAT(other)) => Ok(Val::FLOAT((*val as f32) - *other)),
@@ -319,7 +755,7 @@ impl Val {
         }
     }
 
-    fn mul(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn mul(&self, other: &Val) -> Result<Val, FqError> {
         match (self, other) {
             (Val::INT(val), Val::INT(other)) => Ok(Val::INT(*val * *other)),
             (Val::INT(val), Val::FLOAT(other)) => Ok(Val::FLOAT((*val as f32) * *other)),
@@ -330,7 +766,7 @@ impl Val {
         }
     }
 
-    fn div(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn div(&self, other: &Val) -> Result<Val, FqError> {
         if let Val::INT(0) | Val::FLOAT(0.0) = other {
             return Err(FqError::exe("division by 0"));
         }
@@ -344,7 +780,7 @@ impl Val {
         }
     }
 
-    fn lt(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn lt(&self, other: &Val) -> Result<Val, FqError> {
         match (self, other) {
             (Val::INT(val), Val::INT(other)) => Ok(Val::BOOL(*val < *other)),
             (Val::INT(val), Val::FLOAT(other)) => Ok(Val::BOOL((*val as f32) < *other)),
@@ -355,7 +791,7 @@ impl Val {
         }
     }
 
-    fn eq(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn eq(&self, other: &Val) -> Result<Val, FqError> {
         match (self, other) {
             (Val::INT(val), Val::INT(other)) => Ok(Val::BOOL(*val == *other)),
             (Val::INT(val), Val::FLOAT(other)) => Ok(Val::BOOL((*val as f32) == *other)),
@@ -367,11 +803,11 @@ impl Val {
         }
     }
 
-    fn gt(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn gt(&self, other: &Val) -> Result<Val, FqError> {
         other.lt(self)
     }
 
-    fn le(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn le(&self, other: &Val) -> Result<Val, FqError> {
         let result = self.lt(other)?;
         if let Val::BOOL(false) = result {
             return self.eq(other);
@@ -379,7 +815,7 @@ impl Val {
         Ok(result)
     }
 
-    fn ge(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn ge(&self, other: &Val) -> Result<Val, FqError> {
         let result = self.gt(other)?;
         if let Val::BOOL(false) = result {
             return self.eq(other);
@@ -387,24 +823,35 @@ impl Val {
         Ok(result)
     }
 
-    fn ne(&self, other: &Val) -> Result<Val, FqError> {
+    pub fn ne(&self, other: &Val) -> Result<Val, FqError> {
         if let Val::BOOL(false) = self.eq(other)? {
-            return Ok(Val::BOOL(true));
+            Ok(Val::BOOL(true))
         } else {
-            return Ok(Val::BOOL(false));
+            Ok(Val::BOOL(false))
+        }
+    }
+
+    /// Returns the text of the value, or `None` for null, which is
+    /// shown as configured (see `ValFormat::show`).
+    pub fn text(&self) -> Option<String> {
+        match self {
+            Val::INT(val) => Some(val.to_string()),
+            Val::FLOAT(val) => Some(val.to_string()),
+            Val::BOOL(val) => Some(val.to_string()),
+            Val::STR(val) => Some(val.to_string()),
+            Val::NULL => None,
         }
     }
-}
 
-impl fmt::Display for Val {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    /// Returns the key of the value (see `ValKey`).
+    pub fn key(&self) -> ValKey {
         match self {
-            Val::INT(val) => write!(f, "{}", val.to_string()),
-            Val::FLOAT(val) => write!(f, "{}", val.to_string()),
-            Val::BOOL(val) => write!(f, "{}", val.to_string()),
-            Val::STR(val) => write!(f, "{}", val.to_string()),
-            Val::ACC(val) => write!(f, "{}", val.to_string()),
-            Val::NULL => write!(f, " "),
+            Val::INT(val) => ValKey::INT(*val),
+            Val::FLOAT(val) if *val == 0.0 => ValKey::FLOAT(0.0f32.to_bits()),
+            Val::FLOAT(val) => ValKey::FLOAT(val.to_bits()),
+            Val::STR(val) => ValKey::STR(val.to_string()),
+            Val::BOOL(val) => ValKey::BOOL(*val),
+            Val::NULL => ValKey::NULL,
         }
     }
 }
@@ -416,23 +863,231 @@ impl Clone for Val {
             Val::FLOAT(val) => Val::FLOAT(*val),
             Val::BOOL(val) => Val::BOOL(*val),
             Val::STR(val) => Val::STR(String::from(val)),
-            Val::ACC(val) => Val::ACC(Box::new(*val.clone())),
             Val::NULL => Val::NULL,
         }
     }
 }
 
+/// State of aggregate function calls (while rows are being
+/// processed) indexed by the call id.
+pub type Accs = HashMap<usize, Box<dyn Accumulator>>;
+
+/// Options that control how column values (strings) are cast to
+/// other types and how values are shown in the output.
+pub struct ValFormat {
+    /// If true, whitespace around a value is ignored, and separators
+    /// (below) are accepted in numbers.
+    pub lenient: bool,
+    /// Separator of thousands, e.g., `,` in `1,000`.
+    pub thousands_sep: Option<char>,
+    /// Separator of the fractional part of a number.
+    pub decimal_sep: char,
+    /// Values that are cast to `true`.
+    pub true_tokens: Vec<String>,
+    /// Values that are cast to `false`.
+    pub false_tokens: Vec<String>,
+    /// Values that are cast to null (in addition to empty values).
+    pub null_tokens: Vec<String>,
+    /// Text that is shown for null values.
+    pub null_output: String,
+    /// If true, decoding functions return null for values that
+    /// cannot be decoded; otherwise, they report an error.
+    pub null_on_decode_error: bool,
+}
+
+impl Default for ValFormat {
+    fn default() -> ValFormat {
+        ValFormat::new()
+    }
+}
+
+impl ValFormat {
+    pub fn new() -> ValFormat {
+        ValFormat {
+            lenient: false,
+            thousands_sep: None,
+            decimal_sep: '.',
+            true_tokens: vec![String::from("true")],
+            false_tokens: vec![String::from("false")],
+            null_tokens: Vec::new(),
+            null_output: String::from(" "),
+            null_on_decode_error: false,
+        }
+    }
+
+    /// Returns true if the given column value should be cast to null.
+    fn is_null(&self, val: &str) -> bool {
+        let val = self.prepare(val);
+        val.is_empty() || self.null_tokens.iter().any(|token| token == val)
+    }
+
+    /// Returns the bool for the given column value, or `None` if the
+    /// value is neither a true nor a false token.
+    fn parse_bool(&self, val: &str) -> Option<bool> {
+        let val = self.prepare(val);
+        if self.true_tokens.iter().any(|token| token == val) {
+            Some(true)
+        } else if self.false_tokens.iter().any(|token| token == val) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the text that is shown for the given value.
+    pub fn show(&self, val: &Val) -> String {
+        val.text().unwrap_or_else(|| self.null_output.to_string())
+    }
+
+    /// Returns the given column value prepared for parsing.
+    fn prepare<'a>(&self, val: &'a str) -> &'a str {
+        if self.lenient {
+            val.trim()
+        } else {
+            val
+        }
+    }
+
+    /// Returns the given column value prepared for parsing as a
+    /// number, i.e., without thousands separators and with `.` as the
+    /// decimal separator.
+    fn prepare_number(&self, val: &str) -> String {
+        let val = self.prepare(val);
+        if !self.lenient {
+            return val.to_string();
+        }
+
+        let mut number = String::new();
+        for c in val.chars() {
+            if Some(c) == self.thousands_sep {
+                continue;
+            }
+            if c == self.decimal_sep {
+                number.push('.');
+            } else {
+                number.push(c);
+            }
+        }
+        number
+    }
+}
+
+/// Context for evaluating expressions that holds values that are not
+/// part of a row, i.e., final values of aggregate function calls and
+/// options for casts.
+pub struct Ctx<'a> {
+    aggs: HashMap<usize, Val>,
+    // values of window function calls for all rows
+    windows: HashMap<usize, Vec<Val>>,
+    // index of the row that is evaluated
+    row: usize,
+    // metadata of all rows (see `Table::meta`)
+    meta: &'a [Rc<RowMeta>],
+    format: Rc<ValFormat>,
+}
+
+impl<'a> Ctx<'a> {
+    #[cfg(test)]
+    pub fn new() -> Ctx<'a> {
+        Ctx::with_format(Rc::new(ValFormat::new()))
+    }
+
+    pub fn with_format(format: Rc<ValFormat>) -> Ctx<'a> {
+        Ctx {
+            aggs: HashMap::new(),
+            windows: HashMap::new(),
+            row: 0,
+            meta: &[],
+            format,
+        }
+    }
+
+    /// Sets the index of the row that is evaluated next, which is
+    /// needed to find values of window function calls and
+    /// pseudo-columns.
+    pub fn set_row(&mut self, row: usize) {
+        self.row = row;
+    }
+
+    /// Refers to the metadata of the rows of the given table, which
+    /// is needed to evaluate pseudo-columns and JSON paths.
+    pub fn set_meta(&mut self, table: &'a Table) {
+        self.meta = table.meta();
+    }
+
+    /// Returns the metadata of the row that is evaluated.
+    fn meta(&self) -> Result<&RowMeta, FqError> {
+        match self.meta.get(self.row) {
+            Some(meta) => Ok(meta),
+            None => Err(FqError::exe(
+                "Pseudo-columns and JSON paths can only be evaluated over rows read from a file",
+            )),
+        }
+    }
+
+    /// Returns the final value of the aggregate function call with
+    /// the given id.
+    fn agg(&self, id: usize) -> Result<Val, FqError> {
+        match self.aggs.get(&id) {
+            Some(val) => Ok(val.clone()),
+            None => Err(FqError::exe(
+                "Aggregate function can only be evaluated over all rows",
+            )),
+        }
+    }
+
+    /// Returns the value of the window function call with the given
+    /// id for the current row.
+    pub fn window(&self, id: usize) -> Result<Val, FqError> {
+        match self.windows.get(&id).and_then(|vals| vals.get(self.row)) {
+            Some(val) => Ok(val.clone()),
+            None => Err(FqError::exe(
+                "Window function can only be evaluated over all rows",
+            )),
+        }
+    }
+
+    /// Stores the values (one per row) of the window function call
+    /// with the given id.
+    pub fn set_window(&mut self, id: usize, vals: Vec<Val>) {
+        self.windows.insert(id, vals);
+    }
+}
+
 pub trait Expr {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError>;
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError>;
 
     // todo: design to be improved
     fn is_star(&self) -> bool {
         false
     }
 
-    // oh well
-    fn finish(&self, acc: Val, _nrows: i32) -> Result<Val, FqError> {
-        Ok(acc)
+    /// Returns the kind of this expression (see `ExprKind`).
+    fn kind(&self) -> ExprKind {
+        ExprKind::CONST
+    }
+
+    /// Returns the type of the values of this expression, or a
+    /// message if an argument of a function call in it has an
+    /// incorrect type.
+    fn ty(&self) -> Result<Type, String>;
+
+    /// Updates the state of each aggregate function call in this
+    /// expression with the values from the given row.
+    fn accumulate(&self, _row: &[String], _ctx: &Ctx, _accs: &mut Accs) -> Result<(), FqError> {
+        Ok(())
+    }
+
+    /// Stores into the context the final value of each aggregate
+    /// function call in this expression.
+    fn finish(&self, _accs: &Accs, _ctx: &mut Ctx) -> Result<(), FqError> {
+        Ok(())
+    }
+
+    /// Stores into the context the values of each window function
+    /// call in this expression for all rows of the given table.
+    fn window(&self, _table: &Table, _ctx: &mut Ctx) -> Result<(), FqError> {
+        Ok(())
     }
 }
 
@@ -448,9 +1103,13 @@ impl IntConst {
 }
 
 impl Expr for IntConst {
-    fn eval(&self, _row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, _row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
         Ok(Val::INT(self.val))
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::INT)
+    }
 }
 
 pub struct BoolConst {
@@ -464,12 +1123,17 @@ impl BoolConst {
 }
 
 impl Expr for BoolConst {
-    fn eval(&self, _row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, _row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
         Ok(Val::BOOL(self.val))
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::BOOL)
+    }
 }
 
-/// Represents a column expression, e.g., @1
+/// Represents a column expression, e.g., @1. A field that is missing
+/// on a line evaluates to null.
 pub struct ColRef {
     val: u32,
 }
@@ -481,15 +1145,125 @@ impl ColRef {
 }
 
 impl Expr for ColRef {
-    fn eval(&self, row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        if (self.val as usize) >= row.len() {
-            return Err(FqError::exe(format!(
-                "index out of bounds: number of columns is {} but the column reference is {}",
-                row.len(),
-                self.val
-            )));
+    fn eval(&self, row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
+        match row.get(self.val as usize) {
+            Some(val) => Ok(Val::STR(val.to_string())),
+            None => Ok(Val::NULL),
+        }
+    }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::STR)
+    }
+
+    fn kind(&self) -> ExprKind {
+        ExprKind::SCALAR
+    }
+}
+
+/// Kinds of values that `Scan` records about each line (see
+/// `RowMeta`).
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pseudo {
+    // line number
+    NR,
+    // number of fields
+    NF,
+    // whole line
+    LINE,
+    // byte offset of the line
+    OFFSET,
+    // file name
+    FILE,
+}
+
+impl Pseudo {
+    /// Returns the pseudo-column with the given name (without the
+    /// column prefix), e.g., nr.
+    pub fn from_name(name: &str) -> Option<Pseudo> {
+        match name {
+            "nr" => Some(Pseudo::NR),
+            "nf" => Some(Pseudo::NF),
+            "line" => Some(Pseudo::LINE),
+            "offset" => Some(Pseudo::OFFSET),
+            "file" => Some(Pseudo::FILE),
+            _ => None,
+        }
+    }
+}
+
+/// Represents a pseudo-column expression, e.g., @nr. Like a column
+/// reference, it evaluates to a string that has to be cast.
+pub struct PseudoCol {
+    val: Pseudo,
+}
+
+impl PseudoCol {
+    pub fn new(val: Pseudo) -> PseudoCol {
+        PseudoCol { val }
+    }
+}
+
+impl Expr for PseudoCol {
+    fn eval(&self, _row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        let meta = ctx.meta()?;
+        Ok(Val::STR(match self.val {
+            Pseudo::NR => meta.line.to_string(),
+            Pseudo::NF => meta.fields.to_string(),
+            Pseudo::LINE => meta.text.to_string(),
+            Pseudo::OFFSET => meta.offset.to_string(),
+            Pseudo::FILE => meta.file.to_string(),
+        }))
+    }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::STR)
+    }
+
+    fn kind(&self) -> ExprKind {
+        ExprKind::SCALAR
+    }
+}
+
+/// Represents a path into the line parsed as JSON, e.g.,
+/// `$.request.method`. Like a column reference, it evaluates to a
+/// string that has to be cast; a missing value (or JSON null)
+/// evaluates to null.
+pub struct JsonCol {
+    path: Vec<Step>,
+}
+
+impl JsonCol {
+    pub fn new(path: Vec<Step>) -> JsonCol {
+        JsonCol { path }
+    }
+}
+
+impl Expr for JsonCol {
+    fn eval(&self, _row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        let meta = ctx.meta()?;
+        let json = match meta.json() {
+            Some(json) => json,
+            None => {
+                return Err(FqError::exe(format!(
+                    "Line {} is not valid JSON",
+                    meta.line
+                )))
+            }
+        };
+        match json.get(&self.path).and_then(Json::to_field) {
+            Some(val) => Ok(Val::STR(val)),
+            None => Ok(Val::NULL),
         }
-        Ok(Val::STR(row[self.val as usize].to_string()))
+    }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::STR)
+    }
+
+    fn kind(&self) -> ExprKind {
+        ExprKind::SCALAR
     }
 }
 
@@ -502,13 +1276,21 @@ impl StarConst {
 }
 
 impl Expr for StarConst {
-    fn eval(&self, _row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, _row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
         Err(FqError::exe("Never eval *"))
     }
 
     fn is_star(&self) -> bool {
         true
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::ANY)
+    }
+
+    fn kind(&self) -> ExprKind {
+        ExprKind::SCALAR
+    }
 }
 
 pub struct StrConst {
@@ -522,9 +1304,13 @@ impl StrConst {
 }
 
 impl Expr for StrConst {
-    fn eval(&self, _row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, _row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
         Ok(Val::STR(String::from(&self.val)))
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::STR)
+    }
 }
 
 pub struct FloatConst {
@@ -538,11 +1324,16 @@ impl FloatConst {
 }
 
 impl Expr for FloatConst {
-    fn eval(&self, _row: &Vec<String>, _acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, _row: &[String], _ctx: &Ctx) -> Result<Val, FqError> {
         Ok(Val::FLOAT(self.val))
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        Ok(Type::FLOAT)
+    }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Bop {
     GT,
     LT,
@@ -569,9 +1360,9 @@ impl BinExpr {
 }
 
 impl Expr for BinExpr {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        let left_val = self.left.eval(row, acc)?;
-        let right_val = self.right.eval(row, acc)?;
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        let left_val = self.left.eval(row, ctx)?;
+        let right_val = self.right.eval(row, ctx)?;
         match self.op {
             Bop::GT => left_val.gt(&right_val),
             Bop::LT => left_val.lt(&right_val),
@@ -585,52 +1376,459 @@ impl Expr for BinExpr {
             Bop::DIV => left_val.div(&right_val),
         }
     }
-}
 
-pub struct FuncCall {
-    name: String,
-    // todo: support multiple arguments
-    args: Rc<dyn Expr>,
-}
+    fn ty(&self) -> Result<Type, String> {
+        let left = self.left.ty()?;
+        let right = self.right.ty()?;
+        Ok(match self.op {
+            Bop::PLUS | Bop::MINUS | Bop::MUL | Bop::DIV => match (left, right) {
+                (Type::INT, Type::INT) => Type::INT,
+                (Type::FLOAT, _) | (_, Type::FLOAT) => Type::FLOAT,
+                _ => Type::NUMBER,
+            },
+            _ => Type::BOOL,
+        })
+    }
 
-impl FuncCall {
-    pub fn new(name: &str, args: Rc<dyn Expr>) -> FuncCall {
+    fn kind(&self) -> ExprKind {
+        self.left.kind().combine(self.right.kind())
+    }
+
+    fn accumulate(&self, row: &[String], ctx: &Ctx, accs: &mut Accs) -> Result<(), FqError> {
+        self.left.accumulate(row, ctx, accs)?;
+        self.right.accumulate(row, ctx, accs)
+    }
+
+    fn finish(&self, accs: &Accs, ctx: &mut Ctx) -> Result<(), FqError> {
+        self.left.finish(accs, ctx)?;
+        self.right.finish(accs, ctx)
+    }
+
+    fn window(&self, table: &Table, ctx: &mut Ctx) -> Result<(), FqError> {
+        self.left.window(table, ctx)?;
+        self.right.window(table, ctx)
+    }
+}
+
+/// Computes the value of a built-in scalar function call.
+type ScalarFn = fn(&[Val], &ValFormat) -> Result<Val, FqError>;
+
+/// A built-in scalar function.
+struct Builtin {
+    name: &'static str,
+    arity: Arity,
+    signature: Signature,
+    eval: ScalarFn,
+}
+
+impl ScalarFunc for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn signature(&self) -> Signature {
+        self.signature.clone()
+    }
+
+    fn eval(&self, args: &[Val], format: &ValFormat) -> Result<Val, FqError> {
+        (self.eval)(args, format)
+    }
+}
+
+fn builtin(
+    name: &'static str,
+    arity: Arity,
+    signature: Signature,
+    eval: ScalarFn,
+) -> Rc<dyn ScalarFunc> {
+    Rc::new(Builtin {
+        name,
+        arity,
+        signature,
+        eval,
+    })
+}
+
+/// Returns all built-in scalar functions.
+pub fn builtin_scalars() -> Vec<Rc<dyn ScalarFunc>> {
+    use Type::{ANY, BOOL, FLOAT, INT, NUMBER, STR};
+    let one = Arity::exact(1);
+    let two = Arity::exact(2);
+    let sig = |args: &[Type], ret: Type| Signature::new(args.to_vec(), ret);
+    vec![
+        builtin(ABS_FUNC, one, sig(&[NUMBER], NUMBER), |args, _| {
+            args[0].abs()
+        }),
+        builtin(UPPER_FUNC, one, sig(&[STR], STR), |args, _| args[0].upper()),
+        builtin(LOWER_FUNC, one, sig(&[STR], STR), |args, _| args[0].lower()),
+        builtin(LENGTH_FUNC, one, sig(&[STR], INT), |args, _| {
+            args[0].length()
+        }),
+        builtin(OCTET_LENGTH_FUNC, one, sig(&[STR], INT), |args, _| {
+            args[0].octet_length()
+        }),
+        builtin(REV_FUNC, one, sig(&[STR], STR), |args, _| args[0].rev()),
+        builtin(
+            SUBSTR_FUNC,
+            Arity::range(2, 3),
+            sig(&[STR, INT], STR),
+            |args, _| args[0].substr(&args[1], args.get(2)),
+        ),
+        builtin(CASEFOLD_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].casefold()
+        }),
+        builtin(NFC_FUNC, one, sig(&[STR], STR), |args, _| args[0].nfc()),
+        builtin(NFD_FUNC, one, sig(&[STR], STR), |args, _| args[0].nfd()),
+        builtin(
+            TRIM_FUNC,
+            Arity::range(1, 2),
+            sig(&[STR], STR),
+            |args, _| args[0].trim(args.get(1), Side::BOTH),
+        ),
+        builtin(
+            LTRIM_FUNC,
+            Arity::range(1, 2),
+            sig(&[STR], STR),
+            |args, _| args[0].trim(args.get(1), Side::LEFT),
+        ),
+        builtin(
+            RTRIM_FUNC,
+            Arity::range(1, 2),
+            sig(&[STR], STR),
+            |args, _| args[0].trim(args.get(1), Side::RIGHT),
+        ),
+        builtin(
+            REPLACE_FUNC,
+            Arity::exact(3),
+            sig(&[STR], STR),
+            |args, _| args[0].replace(&args[1], &args[2]),
+        ),
+        builtin(
+            SPLIT_PART_FUNC,
+            Arity::exact(3),
+            sig(&[STR, STR, INT], STR),
+            |args, _| args[0].split_part(&args[1], &args[2]),
+        ),
+        builtin(
+            LPAD_FUNC,
+            Arity::range(2, 3),
+            sig(&[STR, INT, STR], STR),
+            |args, _| args[0].pad(&args[1], args.get(2), true),
+        ),
+        builtin(
+            RPAD_FUNC,
+            Arity::range(2, 3),
+            sig(&[STR, INT, STR], STR),
+            |args, _| args[0].pad(&args[1], args.get(2), false),
+        ),
+        builtin(STARTS_WITH_FUNC, two, sig(&[STR], BOOL), |args, _| {
+            args[0].starts_with(&args[1])
+        }),
+        builtin(ENDS_WITH_FUNC, two, sig(&[STR], BOOL), |args, _| {
+            args[0].ends_with(&args[1])
+        }),
+        builtin(CONTAINS_FUNC, two, sig(&[STR], BOOL), |args, _| {
+            args[0].contains(&args[1])
+        }),
+        builtin(POSITION_FUNC, two, sig(&[STR], INT), |args, _| {
+            args[0].position(&args[1])
+        }),
+        builtin(REPEAT_FUNC, two, sig(&[STR, INT], STR), |args, _| {
+            args[0].repeat(&args[1])
+        }),
+        builtin(
+            CONCAT_WS_FUNC,
+            Arity::at_least(1),
+            sig(&[STR, ANY], STR),
+            |args, _| args[0].concat_ws(&args[1..]),
+        ),
+        builtin(MD5_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].digest(MD5_FUNC, |data| codec::to_hex(&hash::md5(data)))
+        }),
+        builtin(SHA1_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].digest(SHA1_FUNC, |data| codec::to_hex(&hash::sha1(data)))
+        }),
+        builtin(SHA256_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].digest(SHA256_FUNC, |data| codec::to_hex(&hash::sha256(data)))
+        }),
+        builtin(CRC32_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].digest(CRC32_FUNC, |data| format!("{:08x}", hash::crc32(data)))
+        }),
+        builtin(
+            HASH64_FUNC,
+            Arity::range(1, 2),
+            sig(&[STR, INT], ANY),
+            |args, _| args[0].hash64(args.get(1)),
+        ),
+        builtin(TO_BASE64_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].encode(TO_BASE64_FUNC, |val| codec::to_base64(val.as_bytes()))
+        }),
+        builtin(FROM_BASE64_FUNC, one, sig(&[STR], STR), |args, format| {
+            args[0].decode(FROM_BASE64_FUNC, codec::from_base64, format)
+        }),
+        builtin(TO_HEX_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].encode(TO_HEX_FUNC, |val| codec::to_hex(val.as_bytes()))
+        }),
+        builtin(FROM_HEX_FUNC, one, sig(&[STR], STR), |args, format| {
+            args[0].decode(FROM_HEX_FUNC, codec::from_hex, format)
+        }),
+        builtin(URL_ENCODE_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].encode(URL_ENCODE_FUNC, codec::url_encode)
+        }),
+        builtin(URL_DECODE_FUNC, one, sig(&[STR], STR), |args, format| {
+            args[0].decode(URL_DECODE_FUNC, codec::url_decode, format)
+        }),
+        builtin(HTML_ESCAPE_FUNC, one, sig(&[STR], STR), |args, _| {
+            args[0].encode(HTML_ESCAPE_FUNC, codec::html_escape)
+        }),
+        builtin(HTML_UNESCAPE_FUNC, one, sig(&[STR], STR), |args, format| {
+            args[0].decode(
+                HTML_UNESCAPE_FUNC,
+                |val| codec::html_unescape(val).map(String::into_bytes),
+                format,
+            )
+        }),
+        builtin(SIGN_FUNC, one, sig(&[NUMBER], INT), |args, _| {
+            args[0].sign()
+        }),
+        builtin(CEIL_FUNC, one, sig(&[NUMBER], NUMBER), |args, _| {
+            args[0].ceil()
+        }),
+        builtin(FLOOR_FUNC, one, sig(&[NUMBER], NUMBER), |args, _| {
+            args[0].floor()
+        }),
+        builtin(
+            ROUND_FUNC,
+            Arity::range(1, 2),
+            sig(&[NUMBER, INT], NUMBER),
+            |args, _| match args.get(1) {
+                Some(digits) => args[0].round_to(digits),
+                None => args[0].round(),
+            },
+        ),
+        builtin(COS_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].cos()
+        }),
+        builtin(SIN_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].sin()
+        }),
+        builtin(SQRT_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(SQRT_FUNC, Some(NON_NEGATIVE), f32::sqrt)
+        }),
+        builtin(POW_FUNC, two, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].pow(&args[1])
+        }),
+        builtin(EXP_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(EXP_FUNC, None, f32::exp)
+        }),
+        builtin(LN_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(LN_FUNC, Some(POSITIVE), f32::ln)
+        }),
+        builtin(LOG10_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(LOG10_FUNC, Some(POSITIVE), f32::log10)
+        }),
+        builtin(LOG2_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(LOG2_FUNC, Some(POSITIVE), f32::log2)
+        }),
+        builtin(TAN_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(TAN_FUNC, None, f32::tan)
+        }),
+        builtin(ASIN_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(ASIN_FUNC, Some(UNIT_RANGE), f32::asin)
+        }),
+        builtin(ACOS_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(ACOS_FUNC, Some(UNIT_RANGE), f32::acos)
+        }),
+        builtin(ATAN_FUNC, one, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].math(ATAN_FUNC, None, f32::atan)
+        }),
+        builtin(ATAN2_FUNC, two, sig(&[NUMBER], FLOAT), |args, _| {
+            args[0].atan2(&args[1])
+        }),
+        builtin(PI_FUNC, Arity::exact(0), sig(&[], FLOAT), |_, _| {
+            Ok(Val::FLOAT(std::f32::consts::PI))
+        }),
+        builtin(MOD_FUNC, two, sig(&[NUMBER], NUMBER), |args, _| {
+            args[0].rem(&args[1])
+        }),
+        builtin(
+            GREATEST_FUNC,
+            Arity::at_least(1),
+            sig(&[ANY], ANY),
+            |args, _| Val::extreme(args, true),
+        ),
+        builtin(
+            LEAST_FUNC,
+            Arity::at_least(1),
+            sig(&[ANY], ANY),
+            |args, _| Val::extreme(args, false),
+        ),
+        builtin(TRUNC_FUNC, one, sig(&[NUMBER], NUMBER), |args, _| {
+            args[0].trunc()
+        }),
+    ]
+}
+
+// Source of unique ids for function calls.
+static NEXT_CALL_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct FuncCall {
+    // Identifies the state of an aggregate function call.
+    id: usize,
+    func: Func,
+    args: Vec<Rc<dyn Expr>>,
+    // If true, an aggregate function skips repeated values.
+    distinct: bool,
+}
+
+impl FuncCall {
+    pub fn new(func: Func, args: Vec<Rc<dyn Expr>>) -> FuncCall {
         FuncCall {
-            name: name.to_string(),
+            id: NEXT_CALL_ID.fetch_add(1, Ordering::Relaxed),
+            func,
             args,
+            distinct: false,
         }
     }
+
+    pub fn with_distinct(mut self, distinct: bool) -> FuncCall {
+        self.distinct = distinct;
+        self
+    }
+
+    fn is_agg(&self) -> bool {
+        matches!(self.func, Func::AGG(_))
+    }
+
+    fn eval_args(&self, row: &[String], ctx: &Ctx) -> Result<Vec<Val>, FqError> {
+        let mut vals = Vec::new();
+        for arg in &self.args {
+            vals.push(arg.eval(row, ctx)?);
+        }
+        Ok(vals)
+    }
+
+    /// Creates the state of an aggregate function call. Arguments
+    /// after the aggregated ones are parameters of the function,
+    /// which have to be constant.
+    fn new_accumulator(&self, ctx: &Ctx) -> Result<Box<dyn Accumulator>, FqError> {
+        let Func::AGG(func) = &self.func else {
+            return Err(FqError::internal("Not an aggregate function call"));
+        };
+        let mut params = Vec::new();
+        for arg in self.args.iter().skip(func.arity()) {
+            if arg.kind() != ExprKind::CONST {
+                return Err(FqError::exe(format!(
+                    "{}() requires constant parameters",
+                    func.name()
+                )));
+            }
+            params.push(arg.eval(&[], ctx)?);
+        }
+        let acc = func.accumulator(&params)?;
+        if self.distinct {
+            return Ok(Box::new(Distinct::new(acc)));
+        }
+        Ok(acc)
+    }
+
+    fn err_args(&self) -> FqError {
+        FqError::exe(format!(
+            "Wrong number of arguments for {}()",
+            self.func.name()
+        ))
+    }
 }
 
 impl Expr for FuncCall {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        match self.name.as_str() {
-            ABS_FUNC => self.args.eval(row, acc)?.abs(),
-            UPPER_FUNC => self.args.eval(row, acc)?.upper(),
-            LOWER_FUNC => self.args.eval(row, acc)?.lower(),
-            LENGTH_FUNC => self.args.eval(row, acc)?.length(),
-            REV_FUNC => self.args.eval(row, acc)?.rev(),
-            SIGN_FUNC => self.args.eval(row, acc)?.sign(),
-            CEIL_FUNC => self.args.eval(row, acc)?.ceil(),
-            FLOOR_FUNC => self.args.eval(row, acc)?.floor(),
-            ROUND_FUNC => self.args.eval(row, acc)?.round(),
-            COS_FUNC => self.args.eval(row, acc)?.cos(),
-            SIN_FUNC => self.args.eval(row, acc)?.sin(),
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        let func = match &self.func {
+            Func::SCALAR(func) => func,
             // aggragate
-            SUM_FUNC => self.args.eval(row, acc)?.sum(acc),
-            COUNT_FUNC => self.args.eval(row, acc)?.count(acc),
-            MAX_FUNC => self.args.eval(row, acc)?.max(acc),
-            MIN_FUNC => self.args.eval(row, acc)?.min(acc),
-            AVG_FUNC => self.args.eval(row, acc)?.sum(acc),
-            _ => Err(FqError::exe(format!("Unsupported function {}", self.name))),
+            Func::AGG(_) => return ctx.agg(self.id),
+        };
+
+        let args = self.eval_args(row, ctx)?;
+        if !func.arity().accepts(args.len()) {
+            return Err(self.err_args());
+        }
+        func.eval(&args, &ctx.format)
+    }
+
+    fn ty(&self) -> Result<Type, String> {
+        let mut args = Vec::new();
+        for arg in &self.args {
+            args.push(arg.ty()?);
         }
+        let signature = self.func.signature();
+        signature.check(self.func.name(), &args)?;
+        Ok(signature.ret)
+    }
+
+    fn kind(&self) -> ExprKind {
+        let kind = self
+            .args
+            .iter()
+            .fold(ExprKind::CONST, |kind, arg| kind.combine(arg.kind()));
+        if !self.is_agg() {
+            return kind;
+        }
+        match kind {
+            ExprKind::CONST | ExprKind::SCALAR => ExprKind::AGG,
+            _ => ExprKind::NESTED,
+        }
+    }
+
+    fn accumulate(&self, row: &[String], ctx: &Ctx, accs: &mut Accs) -> Result<(), FqError> {
+        let arity = match &self.func {
+            Func::AGG(func) => func.arity(),
+            Func::SCALAR(_) => {
+                for arg in &self.args {
+                    arg.accumulate(row, ctx, accs)?;
+                }
+                return Ok(());
+            }
+        };
+        if self.args.len() < arity {
+            return Err(self.err_args());
+        }
+        let mut vals = Vec::new();
+        for arg in &self.args[..arity] {
+            vals.push(arg.eval(row, ctx)?);
+        }
+        let acc = match accs.entry(self.id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.new_accumulator(ctx)?),
+        };
+        acc.update(vals)
+    }
+
+    fn finish(&self, accs: &Accs, ctx: &mut Ctx) -> Result<(), FqError> {
+        if !self.is_agg() {
+            for arg in &self.args {
+                arg.finish(accs, ctx)?;
+            }
+            return Ok(());
+        }
+
+        // There is no state if there were no rows.
+        let val = match accs.get(&self.id) {
+            Some(acc) => acc.finish()?,
+            None => self.new_accumulator(ctx)?.finish()?,
+        };
+        ctx.aggs.insert(self.id, val);
+        Ok(())
     }
 
-    fn finish(&self, acc: Val, nrows: i32) -> Result<Val, FqError> {
-        match self.name.as_str() {
-            AVG_FUNC => acc.div(&Val::INT(nrows)),
-            _ => Ok(acc),
+    fn window(&self, table: &Table, ctx: &mut Ctx) -> Result<(), FqError> {
+        for arg in &self.args {
+            arg.window(table, ctx)?;
         }
+        Ok(())
     }
 }
 
@@ -645,18 +1843,28 @@ impl IntCast {
 }
 
 impl Expr for IntCast {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
         // Cast should only be used on column references, so we always
-        // expect a string.
-        match self.exp.eval(row, acc)? {
-            Val::STR(val) if val == "" => Ok(Val::NULL),
-            Val::STR(val) => match val.parse::<i32>() {
+        // expect a string (or null for a missing JSON value).
+        match self.exp.eval(row, ctx)? {
+            Val::STR(val) if ctx.format.is_null(&val) => Ok(Val::NULL),
+            Val::NULL => Ok(Val::NULL),
+            Val::STR(val) => match ctx.format.prepare_number(&val).parse::<i32>() {
                 Ok(num) => Ok(Val::INT(num)),
                 Err(_) => Err(FqError::exe(format!("Cannot cast {} to int", val))),
             },
             _ => Err(FqError::exe("Cast can be used only on column references")),
         }
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        self.exp.ty()?;
+        Ok(Type::INT)
+    }
+
+    fn kind(&self) -> ExprKind {
+        self.exp.kind()
+    }
 }
 
 pub struct FloatCast {
@@ -670,16 +1878,26 @@ impl FloatCast {
 }
 
 impl Expr for FloatCast {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        match self.exp.eval(row, acc)? {
-            Val::STR(val) if val == "" => Ok(Val::NULL),
-            Val::STR(val) => match val.parse::<f32>() {
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        match self.exp.eval(row, ctx)? {
+            Val::STR(val) if ctx.format.is_null(&val) => Ok(Val::NULL),
+            Val::NULL => Ok(Val::NULL),
+            Val::STR(val) => match ctx.format.prepare_number(&val).parse::<f32>() {
                 Ok(num) => Ok(Val::FLOAT(num)),
                 Err(_) => Err(FqError::exe(format!("Cannot cast {} to float", val))),
             },
             _ => Err(FqError::exe("Cast can be used only on column references")),
         }
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        self.exp.ty()?;
+        Ok(Type::FLOAT)
+    }
+
+    fn kind(&self) -> ExprKind {
+        self.exp.kind()
+    }
 }
 
 pub struct BoolCast {
@@ -693,16 +1911,26 @@ impl BoolCast {
 }
 
 impl Expr for BoolCast {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        match self.exp.eval(row, acc)? {
-            Val::STR(val) if val == "" => Ok(Val::NULL),
-            Val::STR(val) => match val.parse::<bool>() {
-                Ok(val) => Ok(Val::BOOL(val)),
-                Err(_) => Err(FqError::exe(format!("Cannot cast {} to bool", val))),
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        match self.exp.eval(row, ctx)? {
+            Val::STR(val) if ctx.format.is_null(&val) => Ok(Val::NULL),
+            Val::NULL => Ok(Val::NULL),
+            Val::STR(val) => match ctx.format.parse_bool(&val) {
+                Some(val) => Ok(Val::BOOL(val)),
+                None => Err(FqError::exe(format!("Cannot cast {} to bool", val))),
             },
             _ => Err(FqError::exe("Cast can be used only on column references")),
         }
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        self.exp.ty()?;
+        Ok(Type::BOOL)
+    }
+
+    fn kind(&self) -> ExprKind {
+        self.exp.kind()
+    }
 }
 
 pub struct StrCast {
@@ -716,23 +1944,40 @@ impl StrCast {
 }
 
 impl Expr for StrCast {
-    fn eval(&self, row: &Vec<String>, acc: &Option<Box<Val>>) -> Result<Val, FqError> {
-        match self.exp.eval(row, acc)? {
-            Val::STR(val) if val == "" => Ok(Val::NULL),
+    fn eval(&self, row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        match self.exp.eval(row, ctx)? {
+            Val::STR(val) if ctx.format.is_null(&val) => Ok(Val::NULL),
+            Val::NULL => Ok(Val::NULL),
             Val::STR(val) => Ok(Val::STR(val)),
             _ => Err(FqError::exe("Cast can be used only on column references")),
         }
     }
+
+    fn ty(&self) -> Result<Type, String> {
+        self.exp.ty()?;
+        Ok(Type::STR)
+    }
+
+    fn kind(&self) -> ExprKind {
+        self.exp.kind()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::aggs::{COUNT_FUNC, MAX_FUNC, MIN_FUNC, SUM_FUNC};
+    use crate::funcs::Registry;
+
+    /// Returns a call of the built-in function with the given name.
+    fn call(name: &str, args: Vec<Rc<dyn Expr>>) -> FuncCall {
+        FuncCall::new(Registry::new().get(name).unwrap().clone(), args)
+    }
 
     #[test]
     fn eval_bool() {
         let exp = BoolConst::new(true);
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::BOOL(val)) => assert!(val),
             _ => panic!("Incorrect bool eval"),
         }
@@ -741,7 +1986,7 @@ mod tests {
     #[test]
     fn eval_int() {
         let exp = IntConst::new(33);
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::INT(val)) => assert_eq!(33, val),
             _ => panic!("Incorrect int eval"),
         }
@@ -750,7 +1995,7 @@ mod tests {
     #[test]
     fn eval_string() {
         let exp = StrConst::new(String::from("best string"));
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::STR(val)) => assert_eq!("best string", val),
             _ => panic!("Incorrect string eval"),
         }
@@ -764,7 +2009,7 @@ mod tests {
             Rc::new(FloatConst::new(60.0)),
         );
 
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::BOOL(val)) => assert!(!val),
             _ => panic!("Incorrect relation eval"),
         }
@@ -775,16 +2020,35 @@ mod tests {
             Rc::new(FloatConst::new(60.0)),
         );
 
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::BOOL(val)) => assert!(val),
             _ => panic!("Incorrect relation eval"),
         }
     }
 
+    #[test]
+    fn kind_of_exprs() {
+        let col = || -> Rc<dyn Expr> { Rc::new(IntCast::new(Rc::new(ColRef::new(0)))) };
+        let sum = || -> Rc<dyn Expr> { Rc::new(call(SUM_FUNC, vec![col()])) };
+
+        assert_eq!(ExprKind::CONST, IntConst::new(1).kind());
+        assert_eq!(ExprKind::SCALAR, call(ABS_FUNC, vec![col()]).kind());
+        assert_eq!(ExprKind::AGG, sum().kind());
+        assert_eq!(
+            ExprKind::AGG,
+            BinExpr::new(Bop::MUL, sum(), Rc::new(IntConst::new(2))).kind()
+        );
+        assert_eq!(
+            ExprKind::MIXED,
+            BinExpr::new(Bop::PLUS, sum(), col()).kind()
+        );
+        assert_eq!(ExprKind::NESTED, call(MAX_FUNC, vec![sum()]).kind());
+    }
+
     #[test]
     fn eval_int_cast() {
         let exp = IntCast::new(Rc::new(ColRef::new(1)));
-        match exp.eval(&vec![String::from("one"), String::from("123")], &None) {
+        match exp.eval(&[String::from("one"), String::from("123")], &Ctx::new()) {
             Ok(Val::INT(val)) => assert_eq!(123, val),
             _ => panic!("Incorrect int cast eval"),
         }
@@ -793,12 +2057,95 @@ mod tests {
     #[test]
     fn eval_float_cast() {
         let exp = FloatCast::new(Rc::new(ColRef::new(0)));
-        match exp.eval(&vec![String::from("123.00"), String::from("543.0")], &None) {
+        match exp.eval(
+            &[String::from("123.00"), String::from("543.0")],
+            &Ctx::new(),
+        ) {
             Ok(Val::FLOAT(val)) => assert!((123.00 - val).abs() < f32::EPSILON),
             _ => panic!("Incorrect float cast eval"),
         }
     }
 
+    #[test]
+    fn eval_lenient_casts() {
+        let ctx = Ctx::with_format(Rc::new(ValFormat {
+            lenient: true,
+            thousands_sep: Some('.'),
+            decimal_sep: ',',
+            ..ValFormat::new()
+        }));
+        let row = vec![
+            String::from(" +1.234 "),
+            String::from("1.234,5"),
+            String::from(" true"),
+            String::from("  "),
+        ];
+
+        let exp = IntCast::new(Rc::new(ColRef::new(0)));
+        assert!(matches!(exp.eval(&row, &ctx), Ok(Val::INT(1234))));
+
+        let exp = FloatCast::new(Rc::new(ColRef::new(1)));
+        match exp.eval(&row, &ctx) {
+            Ok(Val::FLOAT(val)) => assert!((val - 1234.5).abs() < f32::EPSILON),
+            _ => panic!("Incorrect lenient float cast"),
+        }
+
+        let exp = BoolCast::new(Rc::new(ColRef::new(2)));
+        assert!(matches!(exp.eval(&row, &ctx), Ok(Val::BOOL(true))));
+
+        let exp = IntCast::new(Rc::new(ColRef::new(3)));
+        assert!(matches!(exp.eval(&row, &ctx), Ok(Val::NULL)));
+
+        // Not lenient by default.
+        let exp = IntCast::new(Rc::new(ColRef::new(0)));
+        assert!(exp.eval(&row, &Ctx::new()).is_err());
+    }
+
+    #[test]
+    fn val_text_and_key() {
+        assert_eq!(None, Val::NULL.text());
+        assert_eq!(Some(String::from("1")), Val::FLOAT(1.0).text());
+        assert_ne!(Val::INT(1).key(), Val::FLOAT(1.0).key());
+        assert_ne!(Val::INT(1).key(), Val::STR(String::from("1")).key());
+        assert_ne!(Val::NULL.key(), Val::STR(String::from(" ")).key());
+        assert_eq!(Val::FLOAT(0.0).key(), Val::FLOAT(-0.0).key());
+    }
+
+    #[test]
+    fn eval_casts_with_tokens() {
+        let format = ValFormat {
+            true_tokens: vec![String::from("yes"), String::from("y")],
+            false_tokens: vec![String::from("no")],
+            null_tokens: vec![String::from("NA")],
+            null_output: String::from("NULL"),
+            ..ValFormat::new()
+        };
+        assert_eq!("NULL", format.show(&Val::NULL));
+        assert_eq!("1", format.show(&Val::INT(1)));
+
+        let ctx = Ctx::with_format(Rc::new(format));
+        let row = vec![String::from("y"), String::from("no"), String::from("NA")];
+
+        let exp = BoolCast::new(Rc::new(ColRef::new(0)));
+        assert!(matches!(exp.eval(&row, &ctx), Ok(Val::BOOL(true))));
+
+        let exp = BoolCast::new(Rc::new(ColRef::new(1)));
+        assert!(matches!(exp.eval(&row, &ctx), Ok(Val::BOOL(false))));
+
+        for cast in [
+            Rc::new(IntCast::new(Rc::new(ColRef::new(2)))) as Rc<dyn Expr>,
+            Rc::new(FloatCast::new(Rc::new(ColRef::new(2)))),
+            Rc::new(BoolCast::new(Rc::new(ColRef::new(2)))),
+            Rc::new(StrCast::new(Rc::new(ColRef::new(2)))),
+        ] {
+            assert!(matches!(cast.eval(&row, &ctx), Ok(Val::NULL)));
+        }
+
+        // The default tokens are not accepted anymore.
+        let exp = BoolCast::new(Rc::new(ColRef::new(0)));
+        assert!(exp.eval(&[String::from("true")], &ctx).is_err());
+    }
+
     #[test]
     #[should_panic(expected = "Execution error: division by 0")]
     fn eval_div_by_zero() {
@@ -807,23 +2154,21 @@ mod tests {
             Rc::new(IntConst::new(55)),
             Rc::new(IntConst::new(0)),
         );
-        exp.eval(&vec![], &None)
+        exp.eval(&[], &Ctx::new())
             .unwrap_or_else(|err| panic!("{err}"));
     }
 
     #[test]
-    #[should_panic(expected = "Execution error: index out of bounds")]
     fn eval_column_ref_out_of_bounds() {
         let exp = ColRef::new(100);
-        exp.eval(&vec![], &None)
-            .unwrap_or_else(|err| panic!("{err}"));
+        assert!(matches!(exp.eval(&[], &Ctx::new()), Ok(Val::NULL)));
     }
 
     #[test]
     #[should_panic(expected = "Execution error: Cannot cast abc to int")]
     fn eval_int_cast_error() {
         let exp = IntCast::new(Rc::new(ColRef::new(0)));
-        exp.eval(&vec![String::from("abc")], &None)
+        exp.eval(&[String::from("abc")], &Ctx::new())
             .unwrap_or_else(|err| panic!("{err}"));
     }
 
@@ -831,7 +2176,7 @@ mod tests {
     #[should_panic(expected = "Execution error: Cannot cast abc to float")]
     fn eval_float_cast_error() {
         let exp = FloatCast::new(Rc::new(ColRef::new(0)));
-        exp.eval(&vec![String::from("abc")], &None)
+        exp.eval(&[String::from("abc")], &Ctx::new())
             .unwrap_or_else(|err| panic!("{err}"));
     }
 
@@ -839,14 +2184,17 @@ mod tests {
     #[should_panic(expected = "Execution error: Cannot cast abc to bool")]
     fn eval_bool_cast_error() {
         let exp = BoolCast::new(Rc::new(ColRef::new(0)));
-        exp.eval(&vec![String::from("abc")], &None)
+        exp.eval(&[String::from("abc")], &Ctx::new())
             .unwrap_or_else(|err| panic!("{err}"));
     }
 
     #[test]
     fn eval_abs_func() {
-        let exp = FuncCall::new(ABS_FUNC, Rc::new(IntCast::new(Rc::new(ColRef::new(0)))));
-        match exp.eval(&vec![String::from("-3"), String::from("abc")], &None) {
+        let exp = call(
+            ABS_FUNC,
+            vec![Rc::new(IntCast::new(Rc::new(ColRef::new(0))))],
+        );
+        match exp.eval(&[String::from("-3"), String::from("abc")], &Ctx::new()) {
             Ok(Val::INT(val)) => assert_eq!(3, val),
             _ => panic!("abs() errors"),
         }
@@ -854,11 +2202,11 @@ mod tests {
 
     #[test]
     fn eval_upper_func() {
-        let exp = FuncCall::new(
+        let exp = call(
             UPPER_FUNC,
-            Rc::new(StrConst::new(String::from("something"))),
+            vec![Rc::new(StrConst::new(String::from("something")))],
         );
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::STR(val)) => assert_eq!("SOMETHING", val),
             _ => panic!("upper() errors"),
         }
@@ -866,11 +2214,11 @@ mod tests {
 
     #[test]
     fn eval_lower_func() {
-        let exp = FuncCall::new(
+        let exp = call(
             LOWER_FUNC,
-            Rc::new(StrConst::new(String::from("Something"))),
+            vec![Rc::new(StrConst::new(String::from("Something")))],
         );
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::STR(val)) => assert_eq!("something", val),
             _ => panic!("lower() errors"),
         }
@@ -878,11 +2226,11 @@ mod tests {
 
     #[test]
     fn eval_length_func() {
-        let exp = FuncCall::new(
+        let exp = call(
             LENGTH_FUNC,
-            Rc::new(StrConst::new(String::from("FQL Tutorial"))),
+            vec![Rc::new(StrConst::new(String::from("FQL Tutorial")))],
         );
-        match exp.eval(&vec![], &None) {
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::INT(val)) => assert_eq!(12, val),
             _ => panic!("length() errors"),
         }
@@ -890,29 +2238,517 @@ mod tests {
 
     #[test]
     fn eval_rev_func() {
-        let exp = FuncCall::new(REV_FUNC, Rc::new(StrConst::new(String::from("something"))));
-        match exp.eval(&vec![], &None) {
+        let exp = call(
+            REV_FUNC,
+            vec![Rc::new(StrConst::new(String::from("something")))],
+        );
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::STR(val)) => assert_eq!("gnihtemos", val),
             _ => panic!("rev() errors"),
         }
     }
 
+    fn str_const(val: &str) -> Rc<dyn Expr> {
+        Rc::new(StrConst::new(String::from(val)))
+    }
+
+    #[test]
+    fn eval_length_func_unicode() {
+        // `e` followed by a combining acute accent.
+        let exp = call(LENGTH_FUNC, vec![str_const("he\u{301}llo")]);
+        assert!(matches!(exp.eval(&[], &Ctx::new()), Ok(Val::INT(5))));
+
+        let exp = call(LENGTH_FUNC, vec![str_const("h\u{e9}llo")]);
+        assert!(matches!(exp.eval(&[], &Ctx::new()), Ok(Val::INT(5))));
+    }
+
+    #[test]
+    fn eval_octet_length_func() {
+        let exp = call(OCTET_LENGTH_FUNC, vec![str_const("h\u{e9}llo")]);
+        assert!(matches!(exp.eval(&[], &Ctx::new()), Ok(Val::INT(6))));
+    }
+
+    #[test]
+    fn eval_rev_func_unicode() {
+        let exp = call(REV_FUNC, vec![str_const("he\u{301}y")]);
+        match exp.eval(&[], &Ctx::new()) {
+            Ok(Val::STR(val)) => assert_eq!("ye\u{301}h", val),
+            _ => panic!("rev() errors"),
+        }
+    }
+
+    #[test]
+    fn eval_substr_func() {
+        let substr = |args: Vec<Rc<dyn Expr>>| call(SUBSTR_FUNC, args).eval(&[], &Ctx::new());
+
+        match substr(vec![
+            str_const("he\u{301}llo"),
+            Rc::new(IntConst::new(2)),
+            Rc::new(IntConst::new(3)),
+        ]) {
+            Ok(Val::STR(val)) => assert_eq!("e\u{301}ll", val),
+            _ => panic!("substr() errors"),
+        }
+        match substr(vec![str_const("hello"), Rc::new(IntConst::new(4))]) {
+            Ok(Val::STR(val)) => assert_eq!("lo", val),
+            _ => panic!("substr() errors"),
+        }
+        match substr(vec![
+            str_const("hello"),
+            Rc::new(IntConst::new(0)),
+            Rc::new(IntConst::new(2)),
+        ]) {
+            Ok(Val::STR(val)) => assert_eq!("h", val),
+            _ => panic!("substr() errors"),
+        }
+        assert!(substr(vec![
+            str_const("hello"),
+            Rc::new(IntConst::new(1)),
+            Rc::new(IntConst::new(-1)),
+        ])
+        .is_err());
+        assert!(substr(vec![str_const("hello")]).is_err());
+    }
+
+    fn eval_func(name: &str, args: Vec<Val>) -> Result<Val, FqError> {
+        let args: Vec<Rc<dyn Expr>> = args
+            .into_iter()
+            .map(|val| -> Rc<dyn Expr> {
+                match val {
+                    Val::INT(val) => Rc::new(IntConst::new(val)),
+                    Val::STR(val) => Rc::new(StrConst::new(val)),
+                    Val::NULL => Rc::new(StrCast::new(Rc::new(ColRef::new(0)))),
+                    _ => panic!("Unsupported argument"),
+                }
+            })
+            .collect();
+        // Null arguments are read from an empty column.
+        call(name, args).eval(&[String::new()], &Ctx::new())
+    }
+
+    fn str_val(val: &str) -> Val {
+        Val::STR(String::from(val))
+    }
+
+    fn assert_str(expected: &str, actual: Result<Val, FqError>) {
+        match actual {
+            Ok(Val::STR(val)) => assert_eq!(expected, val),
+            _ => panic!("Expected string {}", expected),
+        }
+    }
+
+    #[test]
+    fn eval_trim_funcs() {
+        assert_str("a b", eval_func(TRIM_FUNC, vec![str_val(" \ta b ")]));
+        assert_str("a b ", eval_func(LTRIM_FUNC, vec![str_val("  a b ")]));
+        assert_str(" a b", eval_func(RTRIM_FUNC, vec![str_val(" a b  ")]));
+        assert_str(
+            "abc",
+            eval_func(TRIM_FUNC, vec![str_val("xyabcyx"), str_val("xy")]),
+        );
+        assert_str(
+            "abcyx",
+            eval_func(LTRIM_FUNC, vec![str_val("xyabcyx"), str_val("xy")]),
+        );
+        assert!(matches!(
+            eval_func(TRIM_FUNC, vec![Val::NULL]),
+            Ok(Val::NULL)
+        ));
+        assert!(eval_func(TRIM_FUNC, vec![Val::INT(1)]).is_err());
+    }
+
+    #[test]
+    fn eval_replace_func() {
+        assert_str(
+            "a-b-c",
+            eval_func(
+                REPLACE_FUNC,
+                vec![str_val("a b c"), str_val(" "), str_val("-")],
+            ),
+        );
+        assert_str(
+            "abc",
+            eval_func(
+                REPLACE_FUNC,
+                vec![str_val("abc"), str_val(""), str_val("-")],
+            ),
+        );
+        assert!(matches!(
+            eval_func(REPLACE_FUNC, vec![str_val("abc"), Val::NULL, str_val("-")]),
+            Ok(Val::NULL)
+        ));
+    }
+
+    #[test]
+    fn eval_split_part_func() {
+        let split_part = |n| {
+            eval_func(
+                SPLIT_PART_FUNC,
+                vec![str_val("a:b:c"), str_val(":"), Val::INT(n)],
+            )
+        };
+        assert_str("a", split_part(1));
+        assert_str("c", split_part(3));
+        assert_str("", split_part(4));
+        assert_str("b", split_part(-2));
+        assert_str("", split_part(-4));
+        assert!(split_part(0).is_err());
+    }
+
+    #[test]
+    fn eval_pad_funcs() {
+        assert_str(
+            "  ab",
+            eval_func(LPAD_FUNC, vec![str_val("ab"), Val::INT(4)]),
+        );
+        assert_str(
+            "ab..",
+            eval_func(RPAD_FUNC, vec![str_val("ab"), Val::INT(4), str_val(".")]),
+        );
+        assert_str(
+            "xyxab",
+            eval_func(LPAD_FUNC, vec![str_val("ab"), Val::INT(5), str_val("xy")]),
+        );
+        assert_str(
+            "abc",
+            eval_func(LPAD_FUNC, vec![str_val("abcd"), Val::INT(3)]),
+        );
+        assert!(matches!(
+            eval_func(RPAD_FUNC, vec![str_val("ab"), Val::NULL]),
+            Ok(Val::NULL)
+        ));
+    }
+
+    #[test]
+    fn eval_match_funcs() {
+        let abc = || str_val("abc");
+        assert!(matches!(
+            eval_func(STARTS_WITH_FUNC, vec![abc(), str_val("ab")]),
+            Ok(Val::BOOL(true))
+        ));
+        assert!(matches!(
+            eval_func(ENDS_WITH_FUNC, vec![abc(), str_val("ab")]),
+            Ok(Val::BOOL(false))
+        ));
+        assert!(matches!(
+            eval_func(CONTAINS_FUNC, vec![abc(), str_val("b")]),
+            Ok(Val::BOOL(true))
+        ));
+        assert!(matches!(
+            eval_func(CONTAINS_FUNC, vec![abc(), Val::NULL]),
+            Ok(Val::NULL)
+        ));
+    }
+
+    #[test]
+    fn eval_position_func() {
+        assert!(matches!(
+            eval_func(POSITION_FUNC, vec![str_val("abc"), str_val("c")]),
+            Ok(Val::INT(3))
+        ));
+        assert!(matches!(
+            eval_func(POSITION_FUNC, vec![str_val("e\u{301}bc"), str_val("b")]),
+            Ok(Val::INT(2))
+        ));
+        assert!(matches!(
+            eval_func(POSITION_FUNC, vec![str_val("abc"), str_val("x")]),
+            Ok(Val::INT(0))
+        ));
+    }
+
+    #[test]
+    fn eval_repeat_func() {
+        assert_str(
+            "ababab",
+            eval_func(REPEAT_FUNC, vec![str_val("ab"), Val::INT(3)]),
+        );
+        assert_str(
+            "",
+            eval_func(REPEAT_FUNC, vec![str_val("ab"), Val::INT(-1)]),
+        );
+        assert!(eval_func(REPEAT_FUNC, vec![str_val("ab"), str_val("3")]).is_err());
+    }
+
+    #[test]
+    fn eval_concat_ws_func() {
+        assert_str(
+            "a,1,b",
+            eval_func(
+                CONCAT_WS_FUNC,
+                vec![
+                    str_val(","),
+                    str_val("a"),
+                    Val::INT(1),
+                    Val::NULL,
+                    str_val("b"),
+                ],
+            ),
+        );
+        assert!(matches!(
+            eval_func(CONCAT_WS_FUNC, vec![Val::NULL, str_val("a")]),
+            Ok(Val::NULL)
+        ));
+    }
+
+    #[test]
+    fn eval_hash_funcs() {
+        assert_str(
+            "900150983cd24fb0d6963f7d28e17f72",
+            eval_func(MD5_FUNC, vec![str_val("abc")]),
+        );
+        assert_str(
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            eval_func(SHA1_FUNC, vec![str_val("abc")]),
+        );
+        assert_str(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            eval_func(SHA256_FUNC, vec![str_val("abc")]),
+        );
+        assert_str(
+            "cbf43926",
+            eval_func(CRC32_FUNC, vec![str_val("123456789")]),
+        );
+        assert_str(
+            "85944171f73967e8",
+            eval_func(HASH64_FUNC, vec![str_val("foobar")]),
+        );
+        assert!(matches!(
+            eval_func(HASH64_FUNC, vec![str_val("foobar"), Val::INT(10)]),
+            Ok(Val::INT(val)) if val == (0x85944171f73967e8u64 % 10) as i32
+        ));
+        assert!(matches!(
+            eval_func(MD5_FUNC, vec![Val::NULL]),
+            Ok(Val::NULL)
+        ));
+        assert!(eval_func(SHA1_FUNC, vec![Val::INT(1)]).is_err());
+        assert!(eval_func(HASH64_FUNC, vec![str_val("a"), Val::INT(0)]).is_err());
+    }
+
+    #[test]
+    fn eval_encoding_funcs() {
+        assert_str("w6lh", eval_func(TO_BASE64_FUNC, vec![str_val("\u{e9}a")]));
+        assert_str(
+            "\u{e9}a",
+            eval_func(FROM_BASE64_FUNC, vec![str_val("w6lh")]),
+        );
+        assert_str("c3a961", eval_func(TO_HEX_FUNC, vec![str_val("\u{e9}a")]));
+        assert_str("\u{e9}a", eval_func(FROM_HEX_FUNC, vec![str_val("C3A961")]));
+        assert_str(
+            "a%2Fb%20c",
+            eval_func(URL_ENCODE_FUNC, vec![str_val("a/b c")]),
+        );
+        assert_str(
+            "a/b c",
+            eval_func(URL_DECODE_FUNC, vec![str_val("a%2Fb%20c")]),
+        );
+        assert_str(
+            "&lt;b&gt;",
+            eval_func(HTML_ESCAPE_FUNC, vec![str_val("<b>")]),
+        );
+        assert_str(
+            "<b>",
+            eval_func(HTML_UNESCAPE_FUNC, vec![str_val("&lt;b&gt;")]),
+        );
+        assert!(matches!(
+            eval_func(FROM_BASE64_FUNC, vec![Val::NULL]),
+            Ok(Val::NULL)
+        ));
+        assert!(eval_func(TO_HEX_FUNC, vec![Val::INT(1)]).is_err());
+    }
+
+    #[test]
+    fn eval_decode_errors() {
+        // Invalid encodings and bytes that are not valid UTF-8.
+        let invalid = [
+            (FROM_BASE64_FUNC, "w6l!"),
+            (FROM_HEX_FUNC, "c3"),
+            (URL_DECODE_FUNC, "100%"),
+            (HTML_UNESCAPE_FUNC, "&unknown;"),
+            (HTML_UNESCAPE_FUNC, "&#xZZ;"),
+        ];
+        for (func, val) in invalid {
+            assert!(eval_func(func, vec![str_val(val)]).is_err());
+
+            let format = ValFormat {
+                null_on_decode_error: true,
+                ..ValFormat::new()
+            };
+            let exp = call(func, vec![str_const(val)]);
+            let ctx = Ctx::with_format(Rc::new(format));
+            assert!(matches!(exp.eval(&[], &ctx), Ok(Val::NULL)));
+        }
+    }
+
+    fn assert_float(expected: f32, actual: Result<Val, FqError>) {
+        match actual {
+            Ok(Val::FLOAT(val)) => {
+                assert!((expected - val).abs() < 1e-5, "{} != {}", expected, val)
+            }
+            _ => panic!("Expected float {}", expected),
+        }
+    }
+
+    fn eval_math(name: &str, args: Vec<Val>) -> Result<Val, FqError> {
+        let args: Vec<Rc<dyn Expr>> = args
+            .into_iter()
+            .map(|val| -> Rc<dyn Expr> {
+                match val {
+                    Val::FLOAT(val) => Rc::new(FloatConst::new(val)),
+                    Val::INT(val) => Rc::new(IntConst::new(val)),
+                    _ => panic!("Unsupported argument"),
+                }
+            })
+            .collect();
+        call(name, args).eval(&[], &Ctx::new())
+    }
+
+    #[test]
+    fn eval_math_funcs() {
+        assert_float(3.0, eval_math(SQRT_FUNC, vec![Val::INT(9)]));
+        assert_float(8.0, eval_math(POW_FUNC, vec![Val::INT(2), Val::INT(3)]));
+        assert_float(1.0, eval_math(EXP_FUNC, vec![Val::INT(0)]));
+        assert_float(
+            1.0,
+            eval_math(LN_FUNC, vec![Val::FLOAT(std::f32::consts::E)]),
+        );
+        assert_float(2.0, eval_math(LOG10_FUNC, vec![Val::INT(100)]));
+        assert_float(3.0, eval_math(LOG2_FUNC, vec![Val::INT(8)]));
+        assert_float(0.0, eval_math(TAN_FUNC, vec![Val::INT(0)]));
+        assert_float(0.0, eval_math(ASIN_FUNC, vec![Val::INT(0)]));
+        assert_float(0.0, eval_math(ACOS_FUNC, vec![Val::INT(1)]));
+        assert_float(0.0, eval_math(ATAN_FUNC, vec![Val::INT(0)]));
+        assert_float(
+            std::f32::consts::FRAC_PI_2,
+            eval_math(ATAN2_FUNC, vec![Val::INT(1), Val::INT(0)]),
+        );
+        assert_float(std::f32::consts::PI, eval_math(PI_FUNC, vec![]));
+    }
+
+    #[test]
+    fn eval_math_funcs_domain_errors() {
+        for (name, args) in [
+            (SQRT_FUNC, vec![Val::INT(-1)]),
+            (LN_FUNC, vec![Val::INT(0)]),
+            (LOG10_FUNC, vec![Val::FLOAT(-2.0)]),
+            (LOG2_FUNC, vec![Val::INT(0)]),
+            (ASIN_FUNC, vec![Val::INT(2)]),
+            (ACOS_FUNC, vec![Val::FLOAT(-1.5)]),
+            (POW_FUNC, vec![Val::INT(-8), Val::FLOAT(0.5)]),
+            (POW_FUNC, vec![Val::INT(0), Val::INT(-1)]),
+            (MOD_FUNC, vec![Val::INT(1), Val::INT(0)]),
+        ] {
+            assert!(eval_math(name, args).is_err(), "{}() should fail", name);
+        }
+
+        match eval_math(SQRT_FUNC, vec![Val::INT(-1)]) {
+            Err(err) => assert_eq!(
+                "Execution error: sqrt() requires a non-negative argument",
+                err.to_string()
+            ),
+            _ => panic!("sqrt() should fail"),
+        }
+    }
+
+    #[test]
+    fn eval_mod_func() {
+        assert!(matches!(
+            eval_math(MOD_FUNC, vec![Val::INT(7), Val::INT(3)]),
+            Ok(Val::INT(1))
+        ));
+        assert!(matches!(
+            eval_math(MOD_FUNC, vec![Val::INT(-7), Val::INT(3)]),
+            Ok(Val::INT(-1))
+        ));
+        assert_float(1.5, eval_math(MOD_FUNC, vec![Val::FLOAT(7.5), Val::INT(3)]));
+    }
+
+    #[test]
+    fn eval_greatest_least_funcs() {
+        assert!(matches!(
+            eval_func(GREATEST_FUNC, vec![Val::INT(1), Val::NULL, Val::INT(3)]),
+            Ok(Val::INT(3))
+        ));
+        assert!(matches!(
+            eval_func(LEAST_FUNC, vec![Val::INT(1), Val::NULL, Val::INT(3)]),
+            Ok(Val::INT(1))
+        ));
+        assert_str(
+            "b",
+            eval_func(GREATEST_FUNC, vec![str_val("a"), str_val("b")]),
+        );
+        assert!(matches!(
+            eval_func(LEAST_FUNC, vec![Val::NULL]),
+            Ok(Val::NULL)
+        ));
+        assert!(eval_func(LEAST_FUNC, vec![]).is_err());
+        assert!(eval_func(LEAST_FUNC, vec![Val::INT(1), str_val("a")]).is_err());
+    }
+
+    #[test]
+    fn eval_trunc_and_round_funcs() {
+        assert_float(-3.0, eval_math(TRUNC_FUNC, vec![Val::FLOAT(-3.7)]));
+        assert!(matches!(
+            eval_math(TRUNC_FUNC, vec![Val::INT(4)]),
+            Ok(Val::INT(4))
+        ));
+        assert_float(
+            1.23,
+            eval_math(ROUND_FUNC, vec![Val::FLOAT(1.23456), Val::INT(2)]),
+        );
+        assert_float(
+            1200.0,
+            eval_math(ROUND_FUNC, vec![Val::FLOAT(1234.5), Val::INT(-2)]),
+        );
+        assert!(matches!(
+            eval_math(ROUND_FUNC, vec![Val::INT(1250), Val::INT(-2)]),
+            Ok(Val::INT(1300))
+        ));
+        assert!(matches!(
+            eval_math(ROUND_FUNC, vec![Val::INT(12), Val::INT(1)]),
+            Ok(Val::INT(12))
+        ));
+    }
+
+    #[test]
+    fn eval_casefold_func() {
+        let exp = call(CASEFOLD_FUNC, vec![str_const("Stra\u{df}e")]);
+        match exp.eval(&[], &Ctx::new()) {
+            Ok(Val::STR(val)) => assert_eq!("strasse", val),
+            _ => panic!("casefold() errors"),
+        }
+    }
+
+    #[test]
+    fn eval_normalization_funcs() {
+        let exp = call(NFC_FUNC, vec![str_const("e\u{301}")]);
+        match exp.eval(&[], &Ctx::new()) {
+            Ok(Val::STR(val)) => assert_eq!("\u{e9}", val),
+            _ => panic!("nfc() errors"),
+        }
+
+        let exp = call(NFD_FUNC, vec![str_const("\u{e9}")]);
+        match exp.eval(&[], &Ctx::new()) {
+            Ok(Val::STR(val)) => assert_eq!("e\u{301}", val),
+            _ => panic!("nfd() errors"),
+        }
+    }
+
     #[test]
     fn eval_sign_func() {
-        let exp = FuncCall::new(SIGN_FUNC, Rc::new(IntConst::new(33)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(SIGN_FUNC, vec![Rc::new(IntConst::new(33))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::INT(1)) => (),
             _ => panic!("sign(1) errors"),
         }
 
-        let exp = FuncCall::new(SIGN_FUNC, Rc::new(IntConst::new(-33)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(SIGN_FUNC, vec![Rc::new(IntConst::new(-33))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::INT(-1)) => (),
             _ => panic!("sign(-1) errors"),
         }
 
-        let exp = FuncCall::new(SIGN_FUNC, Rc::new(IntConst::new(0)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(SIGN_FUNC, vec![Rc::new(IntConst::new(0))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::INT(0)) => (),
             _ => panic!("sign(0) errors"),
         }
@@ -920,8 +2756,8 @@ mod tests {
 
     #[test]
     fn eval_ceil_func() {
-        let exp = FuncCall::new(CEIL_FUNC, Rc::new(FloatConst::new(3.44)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(CEIL_FUNC, vec![Rc::new(FloatConst::new(3.44))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::FLOAT(val)) => assert!((val - 4.0).abs() < f32::EPSILON),
             _ => panic!("ceil() errors"),
         }
@@ -929,8 +2765,8 @@ mod tests {
 
     #[test]
     fn eval_floor_func() {
-        let exp = FuncCall::new(FLOOR_FUNC, Rc::new(FloatConst::new(3.44)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(FLOOR_FUNC, vec![Rc::new(FloatConst::new(3.44))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::FLOAT(val)) => assert!((val - 3.0).abs() < f32::EPSILON),
             _ => panic!("floor() errors"),
         }
@@ -938,8 +2774,8 @@ mod tests {
 
     #[test]
     fn eval_round_func() {
-        let exp = FuncCall::new(ROUND_FUNC, Rc::new(FloatConst::new(3.44)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(ROUND_FUNC, vec![Rc::new(FloatConst::new(3.44))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::FLOAT(val)) => assert!((val - 3.0).abs() < f32::EPSILON),
             _ => panic!("round() errors"),
         }
@@ -947,8 +2783,8 @@ mod tests {
 
     #[test]
     fn eval_cos_func() {
-        let exp = FuncCall::new(COS_FUNC, Rc::new(FloatConst::new(3.4)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(COS_FUNC, vec![Rc::new(FloatConst::new(3.4))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::FLOAT(val)) => assert!((val + 0.9667982).abs() < f32::EPSILON),
             _ => panic!("cos() errors"),
         }
@@ -956,106 +2792,84 @@ mod tests {
 
     #[test]
     fn eval_sin_func() {
-        let exp = FuncCall::new(SIN_FUNC, Rc::new(FloatConst::new(3.4)));
-        match exp.eval(&vec![], &None) {
+        let exp = call(SIN_FUNC, vec![Rc::new(FloatConst::new(3.4))]);
+        match exp.eval(&[], &Ctx::new()) {
             Ok(Val::FLOAT(val)) => assert!((val + 0.2555412).abs() < f32::EPSILON, "{val}"),
             _ => panic!("sin() errors"),
         }
     }
 
-    #[test]
-    fn eval_sum_func() {
-        let exp = FuncCall::new(SUM_FUNC, Rc::new(IntCast::new(Rc::new(ColRef::new(0)))));
-        let mut acc = None;
-
-        match exp.eval(&vec![String::from("22")], &acc) {
-            Ok(Val::ACC(b)) => acc = Some(Box::new(*b)),
-            _ => panic!("sum() 1st call error"),
-        }
-
-        match exp.eval(&vec![String::from("33")], &acc) {
-            Ok(Val::ACC(b)) => acc = Some(Box::new(*b)),
-            _ => panic!("sum() 2nd call error"),
+    /// Evaluates the given expression over the given rows (each row
+    /// has a single value).
+    fn eval_over(exp: &dyn Expr, rows: &[&str]) -> Result<Val, FqError> {
+        let mut ctx = Ctx::new();
+        let mut accs = Accs::new();
+        for row in rows {
+            exp.accumulate(&[row.to_string()], &ctx, &mut accs)?;
         }
+        exp.finish(&accs, &mut ctx)?;
+        exp.eval(&[], &ctx)
+    }
 
-        match acc {
-            Some(boxed) => match *boxed {
-                Val::INT(val) => assert_eq!(55, val),
-                _ => panic!("Unexpected variant"),
-            },
-            None => panic!("sum() total error"),
+    #[test]
+    fn eval_sum_func() {
+        let exp = call(
+            SUM_FUNC,
+            vec![Rc::new(IntCast::new(Rc::new(ColRef::new(0))))],
+        );
+        match eval_over(&exp, &["22", "33"]) {
+            Ok(Val::INT(val)) => assert_eq!(55, val),
+            _ => panic!("sum() error"),
         }
     }
 
     #[test]
     fn eval_count_func() {
-        let exp = FuncCall::new(COUNT_FUNC, Rc::new(IntCast::new(Rc::new(ColRef::new(0)))));
-        let mut acc = None;
-
-        match exp.eval(&vec![String::from("3")], &acc) {
-            Ok(Val::ACC(b)) => acc = Some(Box::new(*b)),
-            _ => panic!("count() 1st error"),
-        }
-
-        match exp.eval(&vec![String::from("5")], &acc) {
-            Ok(Val::ACC(b)) => acc = Some(Box::new(*b)),
-            _ => panic!("count() 2nd error"),
-        }
-
-        match acc {
-            Some(boxed) => match *boxed {
-                Val::INT(val) => assert_eq!(2, val),
-                _ => panic!("Unexpected variant"),
-            },
-            None => panic!("count() total error"),
+        let exp = call(
+            COUNT_FUNC,
+            vec![Rc::new(IntCast::new(Rc::new(ColRef::new(0))))],
+        );
+        match eval_over(&exp, &["3", "5"]) {
+            Ok(Val::INT(val)) => assert_eq!(2, val),
+            _ => panic!("count() error"),
         }
     }
 
     #[test]
     fn eval_max_func() {
-        let exp = FuncCall::new(MAX_FUNC, Rc::new(IntCast::new(Rc::new(ColRef::new(0)))));
-        let mut acc = None;
-
-        match exp.eval(&vec![String::from("3")], &acc) {
-            Ok(Val::ACC(boxed)) => acc = Some(Box::new(*boxed)),
-            _ => panic!("max() 1st eerror"),
-        }
-
-        match exp.eval(&vec![String::from("20")], &acc) {
-            Ok(Val::ACC(boxed)) => acc = Some(Box::new(*boxed)),
-            _ => panic!("max() 2nd error"),
-        }
-
-        match acc {
-            Some(boxed) => match *boxed {
-                Val::INT(val) => assert_eq!(20, val),
-                _ => panic!("Unexpected variant"),
-            },
-            None => panic!("max() total error"),
+        let exp = call(
+            MAX_FUNC,
+            vec![Rc::new(IntCast::new(Rc::new(ColRef::new(0))))],
+        );
+        match eval_over(&exp, &["3", "20"]) {
+            Ok(Val::INT(val)) => assert_eq!(20, val),
+            _ => panic!("max() error"),
         }
     }
 
     #[test]
     fn eval_min_func() {
-        let exp = FuncCall::new(MIN_FUNC, Rc::new(IntCast::new(Rc::new(ColRef::new(0)))));
-        let mut acc = None;
-
-        match exp.eval(&vec![String::from("3")], &acc) {
-            Ok(Val::ACC(boxed)) => acc = Some(Box::new(*boxed)),
-            _ => panic!("min() 1st error"),
-        }
-
-        match exp.eval(&vec![String::from("20")], &acc) {
-            Ok(Val::ACC(boxed)) => acc = Some(Box::new(*boxed)),
-            _ => panic!("min() 2nd error"),
+        let exp = call(
+            MIN_FUNC,
+            vec![Rc::new(IntCast::new(Rc::new(ColRef::new(0))))],
+        );
+        match eval_over(&exp, &["3", "20"]) {
+            Ok(Val::INT(val)) => assert_eq!(3, val),
+            _ => panic!("min() error"),
         }
+    }
 
-        match acc {
-            Some(boxed) => match *boxed {
-                Val::INT(val) => assert_eq!(3, val),
-                _ => panic!("Unexpected variant"),
-            },
-            None => panic!("min() total error"),
+    #[test]
+    fn eval_expr_over_aggregates() {
+        let col = || -> Rc<dyn Expr> { Rc::new(IntCast::new(Rc::new(ColRef::new(0)))) };
+        let exp = BinExpr::new(
+            Bop::MINUS,
+            Rc::new(call(MAX_FUNC, vec![col()])),
+            Rc::new(call(MIN_FUNC, vec![col()])),
+        );
+        match eval_over(&exp, &["3", "20", "7"]) {
+            Ok(Val::INT(val)) => assert_eq!(17, val),
+            _ => panic!("max() - min() error"),
         }
     }
 }
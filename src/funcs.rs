@@ -0,0 +1,360 @@
+//! Functions that can be called in queries. Built-in functions and
+//! functions of library users are looked up in the same registry,
+//! e.g.,
+//!
+//! ```
+//! use fqs::args::Args;
+//! use fqs::funcs::{Arity, FqError, ScalarFunc, Signature, Type, Val, ValFormat};
+//! use std::rc::Rc;
+//!
+//! struct Kb;
+//!
+//! impl ScalarFunc for Kb {
+//!     fn name(&self) -> &str {
+//!         "kb"
+//!     }
+//!
+//!     fn arity(&self) -> Arity {
+//!         Arity::exact(1)
+//!     }
+//!
+//!     fn signature(&self) -> Signature {
+//!         Signature::new(vec![Type::INT], Type::INT)
+//!     }
+//!
+//!     fn eval(&self, args: &[Val], _format: &ValFormat) -> Result<Val, FqError> {
+//!         match &args[0] {
+//!             Val::INT(val) => Ok(Val::INT(val / 1024)),
+//!             Val::NULL => Ok(Val::NULL),
+//!             _ => Err(FqError::exe("kb() only works for int types")),
+//!         }
+//!     }
+//! }
+//!
+//! let args = Args::new("select kb(int(@0)) from tests/fixtures/types.txt".to_string())
+//!     .with_scalar_func(Rc::new(Kb));
+//! assert!(fqs::query(args).is_ok());
+//! ```
+
+pub use crate::aggs::Accumulator;
+pub use crate::errors::FqError;
+pub use crate::expr::{Val, ValFormat};
+
+use crate::aggs::builtin_aggregates;
+use crate::expr::builtin_scalars;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Number of arguments that a function accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` if there is no limit.
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exact(n: usize) -> Arity {
+        Arity {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub fn range(min: usize, max: usize) -> Arity {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub fn at_least(min: usize) -> Arity {
+        Arity { min, max: None }
+    }
+
+    /// Returns true if the given number of arguments is accepted.
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+/// Type of a value as far as it is known before the query runs.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    INT,
+    FLOAT,
+    STR,
+    BOOL,
+    /// an int or a float
+    NUMBER,
+    /// any type
+    ANY,
+}
+
+impl Type {
+    /// Returns true if a value of the given type can be a value of
+    /// this type, e.g., a number can be an int.
+    pub fn accepts(self, other: Type) -> bool {
+        match (self, other) {
+            (Type::ANY, _) | (_, Type::ANY) => true,
+            (Type::NUMBER, Type::INT | Type::FLOAT) => true,
+            (Type::INT | Type::FLOAT, Type::NUMBER) => true,
+            (this, other) => this == other,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::INT => "int",
+            Type::FLOAT => "float",
+            Type::STR => "string",
+            Type::BOOL => "bool",
+            Type::NUMBER => "number",
+            Type::ANY => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Types of the arguments of a function and of its result. The type
+/// of the last argument applies to any further arguments, e.g., for
+/// `concat_ws`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub args: Vec<Type>,
+    pub ret: Type,
+}
+
+impl Signature {
+    pub fn new(args: Vec<Type>, ret: Type) -> Signature {
+        Signature { args, ret }
+    }
+
+    /// Returns the type of the argument at the given index.
+    pub fn arg(&self, ix: usize) -> Type {
+        match self.args.get(ix).or(self.args.last()) {
+            Some(ty) => *ty,
+            None => Type::ANY,
+        }
+    }
+
+    /// Checks the types of the arguments of a call of the function
+    /// with the given name; returns a message about the first
+    /// argument with an incorrect type.
+    pub fn check(&self, name: &str, args: &[Type]) -> Result<(), String> {
+        for (ix, ty) in args.iter().enumerate() {
+            let expected = self.arg(ix);
+            if !expected.accepts(*ty) {
+                return Err(format!(
+                    "Argument {} of {}() has to be {}, not {}",
+                    ix + 1,
+                    name,
+                    expected,
+                    ty
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function that computes a value for each row from the values of
+/// its arguments.
+pub trait ScalarFunc {
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> Arity;
+
+    /// Returns the types of the arguments and of the result, which
+    /// are checked before the query runs.
+    fn signature(&self) -> Signature;
+
+    /// Computes the value of the call. The number of arguments and
+    /// their types (see `arity` and `signature`) are checked before
+    /// the call as far as the types are known then, so a function
+    /// still reports an error if an argument has an incorrect type.
+    /// Null arguments are passed as `Val::NULL`.
+    fn eval(&self, args: &[Val], format: &ValFormat) -> Result<Val, FqError>;
+}
+
+/// A function that computes a single value over all rows.
+pub trait AggregateFunc {
+    fn name(&self) -> &str;
+
+    /// Returns the number of arguments that are evaluated for each
+    /// row. Arguments after those are parameters of the function,
+    /// which have to be constant, e.g., the separator of
+    /// `string_agg`.
+    fn arity(&self) -> usize;
+
+    /// Returns the number of parameters that the function accepts.
+    fn params(&self) -> Arity {
+        Arity::exact(0)
+    }
+
+    /// Returns the types of the arguments (including parameters) and
+    /// of the result, which are checked before the query runs.
+    fn signature(&self) -> Signature;
+
+    /// Creates the state of a call with the given parameters.
+    fn accumulator(&self, params: &[Val]) -> Result<Box<dyn Accumulator>, FqError>;
+}
+
+/// A function in the registry.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub enum Func {
+    SCALAR(Rc<dyn ScalarFunc>),
+    AGG(Rc<dyn AggregateFunc>),
+}
+
+impl Func {
+    pub fn name(&self) -> &str {
+        match self {
+            Func::SCALAR(func) => func.name(),
+            Func::AGG(func) => func.name(),
+        }
+    }
+
+    /// Returns true if the function accepts the given number of
+    /// arguments.
+    pub fn accepts(&self, n: usize) -> bool {
+        match self {
+            Func::SCALAR(func) => func.arity().accepts(n),
+            Func::AGG(func) => n >= func.arity() && func.params().accepts(n - func.arity()),
+        }
+    }
+
+    pub fn signature(&self) -> Signature {
+        match self {
+            Func::SCALAR(func) => func.signature(),
+            Func::AGG(func) => func.signature(),
+        }
+    }
+}
+
+/// Functions that can be called in queries by name.
+#[derive(Clone)]
+pub struct Registry {
+    funcs: HashMap<String, Func>,
+}
+
+impl Registry {
+    /// Creates a registry with all built-in functions.
+    pub fn new() -> Registry {
+        let mut registry = Registry::empty();
+        for func in builtin_scalars() {
+            registry.register(Func::SCALAR(func));
+        }
+        for func in builtin_aggregates() {
+            registry.register(Func::AGG(func));
+        }
+        registry
+    }
+
+    /// Creates a registry without any functions.
+    pub fn empty() -> Registry {
+        Registry {
+            funcs: HashMap::new(),
+        }
+    }
+
+    /// Adds the given function; it replaces a function (built-in or
+    /// not) with the same name.
+    pub fn register(&mut self, func: Func) {
+        self.funcs.insert(func.name().to_string(), func);
+    }
+
+    /// Returns the function with the given name.
+    pub fn get(&self, name: &str) -> Option<&Func> {
+        self.funcs.get(name)
+    }
+
+    /// Returns true if there is an aggregate function with the given
+    /// name.
+    pub fn is_agg(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(Func::AGG(_)))
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.funcs.keys().collect();
+        names.sort();
+        f.debug_struct("Registry").field("funcs", &names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arity_accepts() {
+        assert!(Arity::exact(1).accepts(1));
+        assert!(!Arity::exact(1).accepts(2));
+        assert!(Arity::range(1, 2).accepts(2));
+        assert!(!Arity::range(1, 2).accepts(0));
+        assert!(Arity::at_least(1).accepts(100));
+        assert!(!Arity::at_least(1).accepts(0));
+    }
+
+    #[test]
+    fn signature_check() {
+        let sig = Signature::new(vec![Type::STR, Type::NUMBER], Type::STR);
+        assert!(sig.check("f", &[Type::STR]).is_ok());
+        assert!(sig.check("f", &[Type::STR, Type::INT, Type::FLOAT]).is_ok());
+        assert!(sig.check("f", &[Type::ANY, Type::NUMBER]).is_ok());
+        assert_eq!(
+            sig.check("f", &[Type::STR, Type::INT, Type::BOOL]),
+            Err("Argument 3 of f() has to be number, not bool".to_string())
+        );
+        assert!(sig.check("f", &[Type::INT]).is_err());
+        assert!(!Type::INT.accepts(Type::FLOAT));
+        assert_eq!(Signature::new(vec![], Type::INT).arg(0), Type::ANY);
+    }
+
+    struct Answer;
+
+    impl ScalarFunc for Answer {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn arity(&self) -> Arity {
+            Arity::exact(0)
+        }
+
+        fn signature(&self) -> Signature {
+            Signature::new(vec![], Type::INT)
+        }
+
+        fn eval(&self, _args: &[Val], _format: &ValFormat) -> Result<Val, FqError> {
+            Ok(Val::INT(42))
+        }
+    }
+
+    #[test]
+    fn registry_register() {
+        let mut registry = Registry::new();
+        assert!(matches!(registry.get("upper"), Some(Func::SCALAR(_))));
+        assert!(registry.is_agg("count"));
+        assert!(registry.get("row_number").is_none());
+
+        // A function replaces a built-in function with the same name.
+        registry.register(Func::SCALAR(Rc::new(Answer)));
+        assert!(!registry.is_agg("count"));
+
+        assert!(Registry::empty().get("upper").is_none());
+    }
+}
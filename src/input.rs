@@ -0,0 +1,388 @@
+// Formats of input files, i.e., how the text of a file is split into
+// rows and fields.
+
+use crate::args::Args;
+use crate::errors::FqError;
+use crate::regex::Regex;
+use std::path::Path;
+
+/// Format of an input file.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Format {
+    /// Each line is a row; fields are separated by the given
+    /// (non-empty) text.
+    DELIM(String),
+    /// Each line is a row; fields are separated by runs of whitespace,
+    /// and whitespace at the start and the end of the line is
+    /// ignored (like in `awk`).
+    WHITESPACE,
+    /// Comma-separated values (RFC 4180), or values separated by the
+    /// given (non-empty) text; fields can be quoted, and quoted fields
+    /// can contain the separator, doubled quotes and line breaks, so a
+    /// row can span several lines.
+    CSV(String),
+    /// JSON Lines; each line is a row with a single field, the whole
+    /// line, and values are read with JSON paths, e.g., `$.a.b`.
+    JSONL,
+    /// Each line is a row; fields are at the given ranges of
+    /// characters (1-based and inclusive; no end means the end of the
+    /// line), and whitespace around them is trimmed.
+    WIDTHS(Vec<(usize, Option<usize>)>),
+    /// Each line that matches the regex is a row; the fields are the
+    /// whole match and the groups. Other lines are skipped.
+    REGEX(Regex),
+}
+
+/// Result of splitting the text of a row.
+#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Record {
+    FIELDS(Vec<String>),
+    /// The row continues on the next line.
+    PARTIAL,
+    /// The text is not a row, e.g., it does not match the regex.
+    SKIP,
+}
+
+impl Format {
+    /// Creates a format in which fields are separated by the given
+    /// text, which can contain escape sequences (e.g., `\t`).
+    pub fn delimited(delim: &str) -> Result<Format, FqError> {
+        let delim = unescape(delim)?;
+        if delim.is_empty() {
+            return Err(FqError::parse("Delimiter cannot be empty"));
+        }
+        Ok(Format::DELIM(delim))
+    }
+
+    /// Creates a CSV format in which fields are separated by the given
+    /// text (see `delimited`) instead of commas.
+    pub fn csv(delim: &str) -> Result<Format, FqError> {
+        match Format::delimited(delim)? {
+            Format::DELIM(delim) => Ok(Format::CSV(delim)),
+            _ => Err(FqError::internal("Delimited format expected")),
+        }
+    }
+
+    /// Returns a splitter for the records of a file in this format.
+    pub fn splitter(&self) -> Splitter<'_> {
+        Splitter {
+            format: self,
+            csv: Csv::new(),
+        }
+    }
+
+    /// Returns the format given with the options (`--format`,
+    /// `--whitespace` or `--delimiter`), if any. The delimiter
+    /// separates CSV fields if the format is CSV.
+    pub fn from_args(args: &Args) -> Result<Option<Format>, FqError> {
+        match (args.format(), args.whitespace(), args.delimiter()) {
+            (Some(name), _, delim) => match name.as_str() {
+                "csv" => match delim {
+                    Some(delim) => Ok(Some(Format::csv(&delim)?)),
+                    None => Ok(Some(Format::csv_default())),
+                },
+                "jsonl" => Ok(Some(Format::JSONL)),
+                _ => Err(FqError::semantics(format!("Unknown format {}", name))),
+            },
+            (None, true, _) => Ok(Some(Format::WHITESPACE)),
+            (None, false, Some(delim)) => Ok(Some(Format::delimited(&delim)?)),
+            (None, false, None) => Ok(None),
+        }
+    }
+
+    /// Returns the format for the extension of the given path, e.g.,
+    /// CSV for `.csv` files and JSON Lines for `.jsonl` and `.ndjson`
+    /// files.
+    pub fn for_path(path: &str) -> Format {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("csv") => Format::csv_default(),
+            Some("jsonl") | Some("ndjson") => Format::JSONL,
+            _ => Format::default(),
+        }
+    }
+
+    /// Returns the CSV format with commas between fields.
+    pub fn csv_default() -> Format {
+        Format::CSV(String::from(","))
+    }
+}
+
+/// Splits the lines of a file into records. A CSV record can span
+/// several lines; the splitter keeps the state of such a record
+/// between lines, so each line is scanned once.
+pub struct Splitter<'a> {
+    format: &'a Format,
+    csv: Csv,
+}
+
+impl Splitter<'_> {
+    /// Splits the next line (without the line terminator) into
+    /// fields, or returns `Record::PARTIAL` if the record continues
+    /// on the next line.
+    pub fn split(&mut self, text: &str) -> Record {
+        match self.format {
+            // This does not match the split done by the `cut`
+            // command for runs of delimiters.
+            Format::DELIM(delim) => {
+                Record::FIELDS(text.split(delim.as_str()).map(String::from).collect())
+            }
+            Format::WHITESPACE => {
+                Record::FIELDS(text.split_whitespace().map(String::from).collect())
+            }
+            Format::CSV(delim) => match self.csv.feed(delim, text) {
+                Some(fields) => Record::FIELDS(fields),
+                None => Record::PARTIAL,
+            },
+            Format::JSONL => Record::FIELDS(vec![text.to_string()]),
+            Format::REGEX(regex) => match regex.captures(text) {
+                Some(fields) => Record::FIELDS(fields),
+                None => Record::SKIP,
+            },
+            Format::WIDTHS(ranges) => {
+                let chars: Vec<char> = text.chars().collect();
+                let fields = ranges
+                    .iter()
+                    .map(|(start, end)| {
+                        let start = (start - 1).min(chars.len());
+                        let end = end.map_or(chars.len(), |end| end.min(chars.len()));
+                        let field: String = chars[start..end.max(start)].iter().collect();
+                        field.trim().to_string()
+                    })
+                    .collect();
+                Record::FIELDS(fields)
+            }
+        }
+    }
+}
+
+/// Returns true if the given path stands for the standard input,
+/// i.e., it is `-` or `stdin`.
+pub fn is_stdin(path: &str) -> bool {
+    path == "-" || path == "stdin"
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format::DELIM(String::from(" "))
+    }
+}
+
+/// State of splitting a CSV record. A quote starts a quoted field
+/// only at the start of a field; elsewhere it is kept as is, and so
+/// is text after the closing quote of a field.
+struct Csv {
+    fields: Vec<String>,
+    field: String,
+    // in a quoted field
+    quoted: bool,
+    // at the start of a field
+    start: bool,
+    // the record continues from a previous line
+    partial: bool,
+}
+
+impl Csv {
+    fn new() -> Csv {
+        Csv {
+            fields: Vec::new(),
+            field: String::new(),
+            quoted: false,
+            start: true,
+            partial: false,
+        }
+    }
+
+    /// Adds the next line of the record; returns the fields if the
+    /// record ends on this line.
+    fn feed(&mut self, delim: &str, text: &str) -> Option<Vec<String>> {
+        if self.partial {
+            self.field.push('\n');
+        }
+        let mut chars = text.char_indices().peekable();
+        while let Some((ix, c)) = chars.next() {
+            if self.quoted {
+                match c {
+                    '"' if matches!(chars.peek(), Some((_, '"'))) => {
+                        chars.next();
+                        self.field.push('"');
+                    }
+                    '"' => self.quoted = false,
+                    _ => self.field.push(c),
+                }
+            } else if text[ix..].starts_with(delim) {
+                self.fields.push(std::mem::take(&mut self.field));
+                self.start = true;
+                // skip the rest of the delimiter
+                while matches!(chars.peek(), Some((next, _)) if *next < ix + delim.len()) {
+                    chars.next();
+                }
+                continue;
+            } else {
+                match c {
+                    '"' if self.start => self.quoted = true,
+                    _ => self.field.push(c),
+                }
+            }
+            self.start = false;
+        }
+
+        self.partial = self.quoted;
+        if self.quoted {
+            return None;
+        }
+        self.start = true;
+        self.fields.push(std::mem::take(&mut self.field));
+        Some(std::mem::take(&mut self.fields))
+    }
+}
+
+/// Replaces the escape sequences `\t`, `\n`, `\r` and `\\` with the
+/// characters they stand for.
+fn unescape(val: &str) -> Result<String, FqError> {
+    let mut out = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            _ => return Err(FqError::parse("Incorrect escape sequence in delimiter")),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimited() {
+        assert_eq!(
+            Format::DELIM("\t".to_string()),
+            Format::delimited("\\t").unwrap()
+        );
+        assert_eq!(
+            Format::DELIM("::".to_string()),
+            Format::delimited("::").unwrap()
+        );
+        assert_eq!(
+            Format::DELIM("\\|".to_string()),
+            Format::delimited("\\\\|").unwrap()
+        );
+        assert!(Format::delimited("").is_err());
+        assert!(Format::delimited("\\x").is_err());
+        assert!(Format::delimited("\\").is_err());
+    }
+
+    fn split(format: &Format, text: &str) -> Vec<String> {
+        match format.splitter().split(text) {
+            Record::FIELDS(fields) => fields,
+            other => panic!("Expected fields but was {:?}", other),
+        }
+    }
+
+    #[test]
+    fn split_delim() {
+        assert_eq!(vec!["a", "", "b"], split(&Format::default(), "a  b"));
+        assert_eq!(
+            vec!["a", "b c", ""],
+            split(&Format::DELIM("::".to_string()), "a::b c::")
+        );
+        assert_eq!(vec![""], split(&Format::default(), ""));
+        assert_eq!(vec!["a", "b"], split(&Format::WHITESPACE, "  a \t  b "));
+        assert!(split(&Format::WHITESPACE, "   ").is_empty());
+    }
+
+    #[test]
+    fn split_widths() {
+        let format = Format::WIDTHS(vec![(1, Some(3)), (4, Some(8)), (9, None)]);
+        assert_eq!(
+            vec!["ab", "é x", "rest  of"],
+            split(&format, "ab  é x  rest  of ")
+        );
+        assert_eq!(vec!["ab", "c", ""], split(&format, "ab c"));
+        assert_eq!(vec!["", "", ""], split(&format, ""));
+    }
+
+    #[test]
+    fn split_csv() {
+        assert_eq!(
+            vec!["a", "", "b c"],
+            split(&Format::csv_default(), "a,,b c")
+        );
+        assert_eq!(
+            vec!["a,b", "say \"hi\"", ""],
+            split(&Format::csv_default(), "\"a,b\",\"say \"\"hi\"\"\",\"\"")
+        );
+        assert_eq!(vec!["a\nb"], split(&Format::csv_default(), "\"a\nb\""));
+        assert_eq!(
+            vec!["5\" disk", "ab"],
+            split(&Format::csv_default(), "5\" disk,\"a\"b")
+        );
+        assert_eq!(
+            Record::PARTIAL,
+            Format::csv_default().splitter().split("1,\"a")
+        );
+        assert_eq!(
+            Record::PARTIAL,
+            Format::csv_default().splitter().split("\"a\"\"")
+        );
+    }
+
+    #[test]
+    fn split_csv_lines() {
+        let format = Format::csv("; ").unwrap();
+        let mut splitter = format.splitter();
+        assert_eq!(Record::PARTIAL, splitter.split("1; \"a; b"));
+        assert_eq!(Record::PARTIAL, splitter.split(""));
+        assert_eq!(
+            Record::FIELDS(vec![
+                "1".to_string(),
+                "a; b\n\nc \"d\"".to_string(),
+                "e,f".to_string()
+            ]),
+            splitter.split("c \"\"d\"\"\"; e,f")
+        );
+        assert_eq!(
+            Record::FIELDS(vec!["".to_string(), "2;".to_string()]),
+            splitter.split("; \"2;\"")
+        );
+        assert_eq!(Format::CSV("\t".to_string()), Format::csv("\\t").unwrap());
+        assert!(Format::csv("").is_err());
+    }
+
+    #[test]
+    fn split_regex() {
+        let format = Format::REGEX(Regex::new("(\\w+)=(\\d+)?").unwrap());
+        assert_eq!(vec!["a=1", "a", "1"], split(&format, "x a=1"));
+        assert_eq!(vec!["a=", "a", ""], split(&format, "a="));
+        assert_eq!(Record::SKIP, format.splitter().split("-"));
+    }
+
+    #[test]
+    fn for_path() {
+        assert_eq!(Format::csv_default(), Format::for_path("data/export.CSV"));
+        assert_eq!(Format::default(), Format::for_path("data/export.txt"));
+        assert_eq!(Format::default(), Format::for_path("csv"));
+        assert_eq!(Format::JSONL, Format::for_path("logs/app.ndjson"));
+    }
+
+    #[test]
+    fn stdin() {
+        assert!(is_stdin("-"));
+        assert!(is_stdin("stdin"));
+        assert!(!is_stdin("./stdin"));
+    }
+}
@@ -0,0 +1,465 @@
+// JSON values (RFC 8259) and paths into them, e.g., `$.request.method`,
+// which are used to read fields of JSON Lines files.
+
+use std::fmt::Write;
+
+/// Maximum nesting of arrays and objects; deeper values are not
+/// parsed, so that a line cannot exhaust the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value. Numbers keep their text, so they can be cast
+/// like any other field.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Json {
+    NULL,
+    BOOL(bool),
+    NUM(String),
+    STR(String),
+    ARR(Vec<Json>),
+    // keys in the order of the text
+    OBJ(Vec<(String, Json)>),
+}
+
+/// A step of a path: a key of an object or an index of an array.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Step {
+    KEY(String),
+    INDEX(usize),
+}
+
+/// Parses a path such as `$.a.b[0]` (`$` is the whole value). Keys
+/// can contain letters, digits, `_` and `-`. Returns `None` if the
+/// path is not valid.
+pub fn parse_path(path: &str) -> Option<Vec<Step>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let len = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(after.len());
+            if len == 0 {
+                return None;
+            }
+            steps.push(Step::KEY(after[..len].to_string()));
+            rest = &after[len..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let index = &after[..end];
+            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            steps.push(Step::INDEX(index.parse().ok()?));
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(steps)
+}
+
+impl Json {
+    /// Parses the given text, which has to contain exactly one value
+    /// (with optional whitespace around it).
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            text,
+            ix: 0,
+            depth: 0,
+        };
+        let val = parser.value()?;
+        parser.skip_ws();
+        if parser.ix == parser.bytes.len() {
+            Some(val)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at the given path, or `None` if a key or an
+    /// index is missing.
+    pub fn get(&self, path: &[Step]) -> Option<&Json> {
+        let mut val = self;
+        for step in path {
+            val = match (val, step) {
+                // the last one wins if a key is repeated
+                (Json::OBJ(members), Step::KEY(key)) => {
+                    &members.iter().rev().find(|(name, _)| name == key)?.1
+                }
+                (Json::ARR(items), Step::INDEX(ix)) => items.get(*ix)?,
+                _ => return None,
+            };
+        }
+        Some(val)
+    }
+
+    /// Returns the value as a field: the content of a string, the
+    /// text of a number or a boolean, and compact JSON text of an
+    /// array or an object. Returns `None` for null.
+    pub fn to_field(&self) -> Option<String> {
+        match self {
+            Json::NULL => None,
+            Json::BOOL(val) => Some(val.to_string()),
+            Json::NUM(val) | Json::STR(val) => Some(val.clone()),
+            Json::ARR(_) | Json::OBJ(_) => {
+                let mut out = String::new();
+                self.write(&mut out);
+                Some(out)
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Json::NULL => out.push_str("null"),
+            Json::BOOL(val) => out.push_str(if *val { "true" } else { "false" }),
+            Json::NUM(val) => out.push_str(val),
+            Json::STR(val) => write_str(val, out),
+            Json::ARR(items) => {
+                out.push('[');
+                for (ix, item) in items.iter().enumerate() {
+                    if ix > 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            Json::OBJ(members) => {
+                out.push('{');
+                for (ix, (key, val)) in members.iter().enumerate() {
+                    if ix > 0 {
+                        out.push(',');
+                    }
+                    write_str(key, out);
+                    out.push(':');
+                    val.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_str(val: &str, out: &mut String) {
+    out.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).expect("writing to a string cannot fail")
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    text: &'a str,
+    ix: usize,
+    // number of arrays and objects the parser is in
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.ix += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.ix).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        self.skip_ws();
+        if self.peek() == Some(byte) {
+            self.ix += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn eat_word(&mut self, word: &str, val: Json) -> Option<Json> {
+        if self.text[self.ix..].starts_with(word) {
+            self.ix += word.len();
+            Some(val)
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_ws();
+        match self.peek()? {
+            b'{' | b'[' => {
+                if self.depth == MAX_DEPTH {
+                    return None;
+                }
+                self.depth += 1;
+                let val = if self.peek()? == b'{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                val
+            }
+            b'"' => Some(Json::STR(self.string()?)),
+            b't' => self.eat_word("true", Json::BOOL(true)),
+            b'f' => self.eat_word("false", Json::BOOL(false)),
+            b'n' => self.eat_word("null", Json::NULL),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.ix += 1; // eat {
+        let mut members = Vec::new();
+        if self.eat(b'}').is_some() {
+            return Some(Json::OBJ(members));
+        }
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(b'"') {
+                return None;
+            }
+            let key = self.string()?;
+            self.eat(b':')?;
+            members.push((key, self.value()?));
+            if self.eat(b',').is_none() {
+                self.eat(b'}')?;
+                return Some(Json::OBJ(members));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.ix += 1; // eat [
+        let mut items = Vec::new();
+        if self.eat(b']').is_some() {
+            return Some(Json::ARR(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(b',').is_none() {
+                self.eat(b']')?;
+                return Some(Json::ARR(items));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.ix += 1; // eat "
+        let mut out = String::new();
+        loop {
+            // copy the text up to the next quote or escape at once
+            let start = self.ix;
+            while !matches!(self.peek()?, b'"' | b'\\') {
+                if self.peek()? < 0x20 {
+                    return None;
+                }
+                self.ix += 1;
+            }
+            out.push_str(&self.text[start..self.ix]);
+
+            let byte = self.peek()?;
+            self.ix += 1;
+            if byte == b'"' {
+                return Some(out);
+            }
+            let escape = self.peek()?;
+            self.ix += 1;
+            match escape {
+                b'"' => out.push('"'),
+                b'\\' => out.push('\\'),
+                b'/' => out.push('/'),
+                b'b' => out.push('\u{8}'),
+                b'f' => out.push('\u{c}'),
+                b'n' => out.push('\n'),
+                b'r' => out.push('\r'),
+                b't' => out.push('\t'),
+                b'u' => out.push(self.unicode_escape()?),
+                _ => return None,
+            }
+        }
+    }
+
+    // Parses the digits of `\uXXXX` (after `u`), including the low
+    // surrogate that follows a high surrogate.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if !self.text[self.ix..].starts_with("\\u") {
+            return None;
+        }
+        self.ix += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.text.get(self.ix..self.ix + 4)?;
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.ix += 4;
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.ix;
+        if self.peek() == Some(b'-') {
+            self.ix += 1;
+        }
+        match self.peek()? {
+            b'0' => self.ix += 1,
+            b'1'..=b'9' => self.digits(),
+            _ => return None,
+        }
+        if self.peek() == Some(b'.') {
+            self.ix += 1;
+            if !self.peek()?.is_ascii_digit() {
+                return None;
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.ix += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.ix += 1;
+            }
+            if !self.peek()?.is_ascii_digit() {
+                return None;
+            }
+            self.digits();
+        }
+        Some(Json::NUM(self.text[start..self.ix].to_string()))
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.ix += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(Some(Json::NULL), Json::parse(" null "));
+        assert_eq!(Some(Json::BOOL(true)), Json::parse("true"));
+        assert_eq!(Some(Json::NUM("-1.5e3".to_string())), Json::parse("-1.5e3"));
+        assert_eq!(
+            Some(Json::STR("a\"\\/\n\u{e9}\u{1f600}".to_string())),
+            Json::parse(r#""a\"\\\/\n\u00e9\ud83d\ude00""#)
+        );
+        assert_eq!(
+            Some(Json::ARR(vec![
+                Json::NUM("1".to_string()),
+                Json::ARR(vec![])
+            ])),
+            Json::parse("[1, []]")
+        );
+        assert_eq!(
+            Some(Json::OBJ(vec![("a".to_string(), Json::OBJ(vec![]))])),
+            Json::parse(r#"{ "a" : {} }"#)
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for text in [
+            "",
+            "nul",
+            "01",
+            "1.",
+            "-",
+            "1e",
+            "[1,]",
+            "{\"a\"}",
+            "{a:1}",
+            "\"a",
+            "\"\\x\"",
+            "\"\\ud83d\"",
+            "[1] 2",
+            "{\"a\":1,}",
+            "\"\t\"",
+        ] {
+            assert_eq!(None, Json::parse(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_nested() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_some());
+        assert_eq!(None, Json::parse(&nested(MAX_DEPTH + 1)));
+        // Deep input is rejected without exhausting the stack.
+        assert_eq!(None, Json::parse(&nested(1_000_000)));
+        assert_eq!(None, Json::parse(&"{\"a\":".repeat(1_000_000)));
+    }
+
+    #[test]
+    fn get_path() {
+        let val =
+            Json::parse(r#"{"a": {"b": [10, {"c": null}]}, "a-b": 1, "x": 1, "x": 2}"#).unwrap();
+        let get = |path: &str| val.get(&parse_path(path).unwrap()).cloned();
+        assert_eq!(Some(val.clone()), get("$"));
+        assert_eq!(Some(Json::NUM("10".to_string())), get("$.a.b[0]"));
+        assert_eq!(Some(Json::NULL), get("$.a.b[1].c"));
+        assert_eq!(Some(Json::NUM("1".to_string())), get("$.a-b"));
+        assert_eq!(Some(Json::NUM("2".to_string())), get("$.x"));
+        assert_eq!(None, get("$.a.b[2]"));
+        assert_eq!(None, get("$.a.c"));
+        assert_eq!(None, get("$.x.y"));
+    }
+
+    #[test]
+    fn parse_paths() {
+        assert_eq!(Some(vec![]), parse_path("$"));
+        assert_eq!(
+            Some(vec![Step::KEY("a".to_string()), Step::INDEX(2)]),
+            parse_path("$.a[2]")
+        );
+        for path in ["a", "$.", "$..a", "$[x]", "$[]", "$[1", "$a"] {
+            assert_eq!(None, parse_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn to_field() {
+        assert_eq!(None, Json::NULL.to_field());
+        assert_eq!(Some("false".to_string()), Json::BOOL(false).to_field());
+        assert_eq!(
+            Some("a b".to_string()),
+            Json::parse("\"a b\"").unwrap().to_field()
+        );
+        assert_eq!(
+            Some(r#"{"a":[1,"x\"y"],"b":null}"#.to_string()),
+            Json::parse(r#"{"a": [1, "x\"y"], "b": null}"#)
+                .unwrap()
+                .to_field()
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use crate::errors::FqError;
 
 /// Represents a token in a query.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Token {
     // keywords
     SELECT,
@@ -14,17 +15,37 @@ pub enum Token {
     BOOLK,
     TRUE,
     FALSE,
+    DISTINCT,
+    OVER,
+    PARTITION,
+    ORDER,
+    BY,
+    ASC,
+    DESC,
+    CREATE,
+    FUNCTION,
+    AS,
+    DELIMITER,
+    WHITESPACE,
+    CSV,
+    HEADER,
+    JSONL,
+    WIDTHS,
+    REGEX,
     //
     LPAREN,
     RPAREN,
     STAR,
     COMMA,
+    SEMICOLON,
     ID(String),
     PATH(String),
     STRING(String),
     INT(i32),
     FLOAT(f32),
     COLUMN(u32),
+    COLNAME(String),
+    JSONPATH(String),
     GT,
     LT,
     EQ,
@@ -38,6 +59,8 @@ pub enum Token {
 
 const COLUMN_PREFIX: char = '@';
 const COLUMN_SEP: char = ',';
+const STATEMENT_SEP: char = ';';
+const JSON_PATH_PREFIX: char = '$';
 
 /// Main tokenization loop. It splits the given string into a sequence
 /// of tokens.
@@ -102,8 +125,13 @@ pub fn tokenize(query: &str) -> Result<Vec<Token>, FqError> {
                 tokens.push(Token::PLUS);
             }
             '-' => {
+                // Right after a number (e.g., the range `1-8`), it is
+                // a minus rather than the sign of the next number.
+                let after_number = index > 0
+                    && !chars[index - 1].is_whitespace()
+                    && matches!(tokens.last(), Some(Token::INT(_) | Token::FLOAT(_)));
                 index += 1;
-                if index < len && matches!(chars[index], '0'..='9') {
+                if starts_number(&chars, index) && !after_number {
                     tokens.push(eat_negative_number(&chars, &mut index)?);
                 } else {
                     tokens.push(Token::MINUS);
@@ -117,6 +145,10 @@ pub fn tokenize(query: &str) -> Result<Vec<Token>, FqError> {
                 index += 1;
                 tokens.push(Token::COMMA);
             }
+            STATEMENT_SEP => {
+                index += 1;
+                tokens.push(Token::SEMICOLON);
+            }
             ' ' | '\t' | '\n' | '\r' => {
                 index += 1;
             }
@@ -134,9 +166,15 @@ pub fn tokenize(query: &str) -> Result<Vec<Token>, FqError> {
             '0'..='9' => {
                 tokens.push(eat_number(&chars, &mut index)?);
             }
+            '.' if starts_number(&chars, index) => {
+                tokens.push(eat_number(&chars, &mut index)?);
+            }
             COLUMN_PREFIX => {
                 tokens.push(eat_column_ref(&chars, &mut index)?);
             }
+            JSON_PATH_PREFIX => {
+                tokens.push(eat_json_path(&chars, &mut index));
+            }
             _ => {
                 return Err(FqError::syntax("Unknown char"));
             }
@@ -146,7 +184,7 @@ pub fn tokenize(query: &str) -> Result<Vec<Token>, FqError> {
     Ok(tokens)
 }
 
-fn eat_string_literal(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
+fn eat_string_literal(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
     let mut word = String::new();
     let len = chars.len();
     while *index < chars.len() {
@@ -178,15 +216,11 @@ fn eat_string_literal(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqE
 ///
 /// Returns an error if an identifier cannot be taken from the
 /// beginning of the given sequence.
-fn eat_identifier(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
+fn eat_identifier(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
     let mut word = String::new();
     while *index < chars.len() {
         match chars[*index] {
-            'a'..='z' => {
-                word.push(chars[*index]);
-                *index += 1;
-            }
-            '0'..='9' => {
+            'a'..='z' | '0'..='9' | '_' => {
                 word.push(chars[*index]);
                 *index += 1;
             }
@@ -207,16 +241,33 @@ fn eat_identifier(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError
         "bool" => Ok(Token::BOOLK),
         "true" => Ok(Token::TRUE),
         "false" => Ok(Token::FALSE),
+        "distinct" => Ok(Token::DISTINCT),
+        "over" => Ok(Token::OVER),
+        "partition" => Ok(Token::PARTITION),
+        "order" => Ok(Token::ORDER),
+        "by" => Ok(Token::BY),
+        "asc" => Ok(Token::ASC),
+        "desc" => Ok(Token::DESC),
+        "create" => Ok(Token::CREATE),
+        "function" => Ok(Token::FUNCTION),
+        "as" => Ok(Token::AS),
+        "delimiter" => Ok(Token::DELIMITER),
+        "whitespace" => Ok(Token::WHITESPACE),
+        "csv" => Ok(Token::CSV),
+        "header" => Ok(Token::HEADER),
+        "jsonl" => Ok(Token::JSONL),
+        "widths" => Ok(Token::WIDTHS),
+        "regex" => Ok(Token::REGEX),
         _ => Ok(Token::ID(word)),
     }
 }
 
-fn eat_path(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
+fn eat_path(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
     let mut word = String::new();
     let len = chars.len();
     while *index < chars.len() {
         match chars[*index] {
-            ' ' | '\t' | '\n' | '\r' => {
+            ' ' | '\t' | '\n' | '\r' | STATEMENT_SEP => {
                 break;
             }
             '\\' => {
@@ -237,41 +288,100 @@ fn eat_path(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
     Ok(Token::PATH(word))
 }
 
+/// Returns true if a number literal starts at the given index, i.e.,
+/// there is a digit or a dot followed by a digit.
+fn starts_number(chars: &[char], index: usize) -> bool {
+    match chars.get(index) {
+        Some(c) if c.is_ascii_digit() => true,
+        Some('.') => matches!(chars.get(index + 1), Some(c) if c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
 /// Creates a number token from a sequence of chars. This function is
 /// invoked only if we know that the sequence stars with a number.
+/// Supported literals are decimal (`123`, `1.5`, `.5`), scientific
+/// (`1e6`, `1.5E-3`), and hexadecimal (`0x1F`) numbers; digits can be
+/// separated with underscores (`1_000`).
 ///
 /// # Errors
 ///
 /// Returns an error if a number cannot be taken from the beginning of
 /// the given character sequence.
-fn eat_number(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
-    let mut number = String::new();
+fn eat_number(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
+    let mut literal = String::new();
     while *index < chars.len() {
-        match chars[*index] {
-            '0'..='9' | '.' => {
-                number.push(chars[*index]);
-                *index += 1;
-            }
-            _ => {
-                break;
-            }
+        let c = chars[*index];
+        // sign of an exponent, e.g., 1e-3
+        let exp_sign = matches!(c, '+' | '-')
+            && matches!(literal.chars().last(), Some('e' | 'E'))
+            && !is_hex(&literal);
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' || exp_sign {
+            literal.push(c);
+            *index += 1;
+        } else {
+            break;
         }
     }
 
-    // Check if number and create either INT or FLOAT.
-    match number.parse::<i32>() {
-        Ok(num) => Ok(Token::INT(num)),
-        Err(_) => {
-            // try to parse as float
-            match number.parse::<f32>() {
-                Ok(num) => Ok(Token::FLOAT(num)),
-                Err(_) => Err(FqError::syntax("Not a number")),
+    match parse_number(&literal) {
+        Some(token) => Ok(token),
+        None => Err(FqError::syntax(format!(
+            "Invalid number literal '{}'",
+            literal
+        ))),
+    }
+}
+
+fn is_hex(literal: &str) -> bool {
+    literal.starts_with("0x") || literal.starts_with("0X")
+}
+
+/// Removes underscores that separate digits, e.g., 1_000. Returns
+/// None if an underscore is not between two digits.
+fn remove_underscores(literal: &str, radix: u32) -> Option<String> {
+    let chars: Vec<char> = literal.chars().collect();
+    for (ix, c) in chars.iter().enumerate() {
+        if *c == '_' {
+            let before = ix > 0 && chars[ix - 1].is_digit(radix);
+            let after = ix + 1 < chars.len() && chars[ix + 1].is_digit(radix);
+            if !(before && after) {
+                return None;
             }
         }
     }
+    Some(literal.replace('_', ""))
+}
+
+/// Creates either INT or FLOAT token from the given literal.
+fn parse_number(literal: &str) -> Option<Token> {
+    if is_hex(literal) {
+        let digits = remove_underscores(&literal[2..], 16)?;
+        return match i32::from_str_radix(&digits, 16) {
+            Ok(num) => Some(Token::INT(num)),
+            Err(_) => None,
+        };
+    }
+
+    let number = remove_underscores(literal, 10)?;
+    if let Ok(num) = number.parse::<i32>() {
+        return Some(Token::INT(num));
+    }
+    // Float syntax is checked first, because Rust also accepts words
+    // like `inf` and `nan`.
+    let valid = number
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if !valid {
+        return None;
+    }
+    match number.parse::<f32>() {
+        Ok(num) => Some(Token::FLOAT(num)),
+        Err(_) => None,
+    }
 }
 
-fn eat_negative_number(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
+fn eat_negative_number(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
     match eat_number(chars, index)? {
         Token::INT(val) => Ok(Token::INT(-val)),
         Token::FLOAT(val) => Ok(Token::FLOAT(-val)),
@@ -281,9 +391,23 @@ fn eat_negative_number(chars: &Vec<char>, index: &mut usize) -> Result<Token, Fq
     }
 }
 
-fn eat_column_ref(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError> {
+fn eat_column_ref(chars: &[char], index: &mut usize) -> Result<Token, FqError> {
     // eat COLUMN_PREFIX
     *index += 1;
+
+    // A letter after the prefix starts the name of a pseudo-column or
+    // of a regex group.
+    if *index < chars.len() && chars[*index].is_ascii_alphabetic() {
+        let mut name = String::new();
+        while *index < chars.len()
+            && (chars[*index].is_ascii_alphanumeric() || chars[*index] == '_')
+        {
+            name.push(chars[*index]);
+            *index += 1;
+        }
+        return Ok(Token::COLNAME(name));
+    }
+
     let mut number = String::new();
     while *index < chars.len() {
         match chars[*index] {
@@ -297,9 +421,9 @@ fn eat_column_ref(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError
         }
     }
 
-    if number.len() == 0 {
+    if number.is_empty() {
         return Err(FqError::syntax(
-            "Column prefix has to be followed by an integer",
+            "Column prefix has to be followed by an integer or a name",
         ));
     }
 
@@ -307,6 +431,22 @@ fn eat_column_ref(chars: &Vec<char>, index: &mut usize) -> Result<Token, FqError
     Ok(Token::COLUMN(number))
 }
 
+/// Eats a JSON path, e.g., `$.a.b[0]`; the parser checks that the
+/// path is valid.
+fn eat_json_path(chars: &[char], index: &mut usize) -> Token {
+    let mut word = String::new();
+    while *index < chars.len() {
+        match chars[*index] {
+            c if c.is_alphanumeric() || "$._-[]".contains(c) => {
+                word.push(c);
+                *index += 1;
+            }
+            _ => break,
+        }
+    }
+    Token::JSONPATH(word)
+}
+
 pub struct Lexer {
     tokens: Vec<Token>,
     index: usize,
@@ -315,15 +455,23 @@ pub struct Lexer {
 impl Lexer {
     pub fn from(query: &str) -> Result<Lexer, FqError> {
         let tokens = tokenize(query)?;
-        Ok(Lexer { tokens, index: 0 })
+        Ok(Lexer::from_tokens(tokens))
+    }
+
+    /// Creates a lexer over already tokenized text, e.g., the body of
+    /// a function defined in the query.
+    pub fn from_tokens(tokens: Vec<Token>) -> Lexer {
+        Lexer { tokens, index: 0 }
     }
 
     #[allow(dead_code)]
     pub fn peek(&self) -> Option<&Token> {
-        if self.index < self.tokens.len() {
-            return Some(&self.tokens[self.index]);
-        }
-        None
+        self.peek_nth(0)
+    }
+
+    /// Returns the token that is `n` tokens after the next one.
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.index + n)
     }
 
     pub fn next(&mut self) -> Option<&Token> {
@@ -383,6 +531,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_scientific() {
+        let tokens = tokenize("1e6 1.5E-3 2e+2").expect("Tokenization failed");
+        assert_eq!(
+            tokens.len(),
+            3,
+            "Expected three tokens, got {}",
+            tokens.len()
+        );
+        assert!(matches!(tokens[0], Token::FLOAT(s) if (s - 1e6).abs() < f32::EPSILON));
+        assert!(matches!(tokens[1], Token::FLOAT(s) if (s - 1.5e-3).abs() < f32::EPSILON));
+        assert!(matches!(tokens[2], Token::FLOAT(s) if (s - 200.0).abs() < f32::EPSILON));
+    }
+
+    #[test]
+    fn tokenize_hex() {
+        let tokens = tokenize("0x1F -0Xff").expect("Tokenization failed");
+        assert_eq!(tokens.len(), 2, "Expected two tokens, got {}", tokens.len());
+        assert!(matches!(tokens[0], Token::INT(31)));
+        assert!(matches!(tokens[1], Token::INT(-255)));
+    }
+
+    #[test]
+    fn tokenize_range() {
+        let tokens = tokenize("1-8 9 -3 10.5-2").expect("Tokenization failed");
+        assert_eq!(
+            tokens.len(),
+            8,
+            "Expected eight tokens, got {}",
+            tokens.len()
+        );
+        assert!(matches!(tokens[0], Token::INT(1)));
+        assert!(matches!(tokens[1], Token::MINUS));
+        assert!(matches!(tokens[2], Token::INT(8)));
+        assert!(matches!(tokens[3], Token::INT(9)));
+        assert!(matches!(tokens[4], Token::INT(-3)));
+        assert!(matches!(tokens[5], Token::FLOAT(_)));
+        assert!(matches!(tokens[6], Token::MINUS));
+        assert!(matches!(tokens[7], Token::INT(2)));
+    }
+
+    #[test]
+    fn tokenize_underscores_and_leading_dot() {
+        let tokens = tokenize("1_000 .5 -.25 0x_1").expect_err("Should fail on 0x_1");
+        assert_eq!(
+            tokens.to_string(),
+            "Syntax error: Invalid number literal '0x_1'"
+        );
+
+        let tokens = tokenize("1_000 .5 -.25").expect("Tokenization failed");
+        assert_eq!(
+            tokens.len(),
+            3,
+            "Expected three tokens, got {}",
+            tokens.len()
+        );
+        assert!(matches!(tokens[0], Token::INT(1000)));
+        assert!(matches!(tokens[1], Token::FLOAT(s) if (s - 0.5).abs() < f32::EPSILON));
+        assert!(matches!(tokens[2], Token::FLOAT(s) if (s + 0.25).abs() < f32::EPSILON));
+    }
+
+    #[test]
+    fn tokenize_invalid_numbers() {
+        for literal in ["1.2.3", "1e", "1__0", "10_", "0x_1", "12abc", "0x", "1e5x"] {
+            match tokenize(literal) {
+                Err(err) => assert_eq!(
+                    err.to_string(),
+                    format!("Syntax error: Invalid number literal '{}'", literal)
+                ),
+                Ok(_) => panic!("{} should not be a number", literal),
+            }
+        }
+    }
+
     #[test]
     fn tokenize_zero() {
         let tokens = tokenize("0").expect("Tokenization failed");
@@ -405,6 +627,32 @@ mod tests {
         )
     }
 
+    #[test]
+    fn tokenize_pseudo_column() {
+        let query = format!("{}nr {}1", COLUMN_PREFIX, COLUMN_PREFIX);
+        let tokens = tokenize(&query).expect("Tokenization failed");
+
+        assert_eq!(tokens.len(), 2, "Expected two tokens, got {}", tokens.len());
+        assert!(
+            matches!(tokens.first(), Some(Token::COLNAME(s)) if s == "nr"),
+            "The token should be COLNAME"
+        );
+        assert!(
+            matches!(tokens.get(1), Some(Token::COLUMN(s)) if *s == 1),
+            "The token should be COLUMN"
+        );
+    }
+
+    #[test]
+    fn tokenize_json_path() {
+        let tokens = tokenize("int($.request.status_code[0])").unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert!(
+            matches!(&tokens[2], Token::JSONPATH(s) if s == "$.request.status_code[0]"),
+            "The token should be JSONPATH"
+        );
+    }
+
     #[test]
     fn tokenize_string_literal() {
         let tokens = tokenize("'this is a string'").expect("Tokenization of a string failed");
@@ -433,6 +681,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_identifier_with_underscore() {
+        let tokens = tokenize("octet_length").unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(matches!(tokens.first(), Some(Token::ID(s)) if s == "octet_length"));
+    }
+
+    #[test]
+    fn tokenize_distinct() {
+        let tokens = tokenize("count(distinct str(@0))").unwrap();
+        assert!(matches!(tokens[2], Token::DISTINCT));
+    }
+
+    #[test]
+    fn tokenize_window() {
+        let tokens = tokenize("over (partition by a order by b desc)").unwrap();
+        assert!(matches!(
+            tokens.as_slice(),
+            [
+                Token::OVER,
+                Token::LPAREN,
+                Token::PARTITION,
+                Token::BY,
+                Token::ID(_),
+                Token::ORDER,
+                Token::BY,
+                Token::ID(_),
+                Token::DESC,
+                Token::RPAREN
+            ]
+        ));
+    }
+
     #[test]
     fn tokenize_path() {
         let tokens = tokenize("from a-b_c.txt").unwrap();
@@ -444,6 +725,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tokenize_create_function() {
+        let tokens = tokenize("create function kb(x) as x / 1024; select 1 from f;").unwrap();
+        assert!(matches!(
+            tokens.as_slice(),
+            [
+                Token::CREATE,
+                Token::FUNCTION,
+                Token::ID(_),
+                Token::LPAREN,
+                Token::ID(_),
+                Token::RPAREN,
+                Token::AS,
+                Token::ID(_),
+                Token::DIV,
+                Token::INT(1024),
+                Token::SEMICOLON,
+                Token::SELECT,
+                Token::INT(1),
+                Token::FROM,
+                Token::PATH(_),
+                Token::SEMICOLON
+            ]
+        ));
+    }
+
     #[test]
     fn tokenize_keywords() {
         let tokens = tokenize("select").unwrap();
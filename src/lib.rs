@@ -1,33 +1,79 @@
+mod aggs;
 pub mod args;
+mod codec;
 mod errors;
 mod expr;
+pub mod funcs;
+mod hash;
+mod input;
+mod json;
 mod lexer;
 mod ops;
 mod parser;
+mod regex;
 mod table;
+mod unicode;
+mod unicode_tables;
 mod visitors;
+mod windows;
 
 use crate::args::Args;
 use crate::errors::FqError;
+use crate::expr::ValFormat;
+use crate::input::Format;
 use crate::lexer::Lexer;
 use crate::ops::Engine;
 use crate::parser::Node;
 use crate::table::Table;
 use crate::visitors::{Checker, Planner};
+use std::rc::Rc;
 
 pub fn query(args: Args) -> Result<Table, FqError> {
+    let planner = plan(args)?;
+    let engine = Engine::new();
+    engine.exe(planner.operations())
+}
+
+/// Runs the query like `query`, but passes each row of the result to
+/// the given function as soon as it is computed. Rows are computed
+/// while the input is read, unless aggregate or window functions need
+/// all rows; then they are passed once the input ends.
+pub fn query_each(
+    args: Args,
+    each: &mut dyn FnMut(Vec<String>) -> Result<(), FqError>,
+) -> Result<(), FqError> {
+    let planner = plan(args)?;
+    let engine = Engine::new();
+    if engine.stream(planner.operations(), each)? {
+        return Ok(());
+    }
+    for row in engine.exe(planner.operations())?.iter() {
+        each(row)?;
+    }
+    Ok(())
+}
+
+/// Parses and checks the query, and plans its operations.
+fn plan(args: Args) -> Result<Planner, FqError> {
+    let input = Format::from_args(&args)?;
     let lexer = Lexer::from(&args.query())?;
-    let ast = parser::parse_query(lexer)?;
+    let ast = parser::parse_query(lexer, args.funcs())?;
 
     let mut visitor = Checker::new();
     ast.accept(&mut visitor);
-    if let Err(err) = visitor.result() {
-        return Err(err);
-    }
+    visitor.result()?;
 
-    let mut planner = Planner::new();
+    let format = ValFormat {
+        lenient: args.lenient(),
+        thousands_sep: args.thousands_sep(),
+        decimal_sep: args.decimal_sep(),
+        true_tokens: args.true_tokens(),
+        false_tokens: args.false_tokens(),
+        null_tokens: args.null_tokens(),
+        null_output: args.null_output(),
+        null_on_decode_error: args.null_on_decode_error(),
+    };
+    let mut planner = Planner::new(Rc::new(format), input, args.header());
     ast.accept(&mut planner);
-
-    let engine = Engine::new();
-    engine.exe(planner.operations())
+    Ok(planner)
 }
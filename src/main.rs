@@ -8,8 +8,13 @@ fn main() {
     }
 
     let args = args.unwrap();
-    match fqs::query(args) {
-        Ok(table) => table.show(),
-        Err(err) => panic!("{err}"),
+    // Rows are printed as soon as they are computed, e.g., while the
+    // standard input is still being written.
+    let result = fqs::query_each(args, &mut |row| {
+        println!("{}", row.join(" "));
+        Ok(())
+    });
+    if let Err(err) = result {
+        panic!("{err}");
     }
 }
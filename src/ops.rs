@@ -1,22 +1,57 @@
 use crate::errors::FqError;
-use crate::expr::{ColRef, Expr, Val};
-use crate::table::Table;
+use crate::expr::{Accs, ColRef, Ctx, Expr, ExprKind, Val, ValFormat};
+use crate::input::{is_stdin, Format, Record};
+use crate::table::{RowMeta, Table};
+use std::cell::Cell;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader};
 use std::rc::Rc;
 
+/// Receives each row that is read (with the metadata of its line);
+/// returns false if no more rows are needed.
+pub type RowFn<'a> = dyn FnMut(Vec<String>, Rc<RowMeta>) -> Result<bool, FqError> + 'a;
+
 pub trait Op {
     fn exe(&self, table: Table) -> Result<Table, FqError>;
+
+    /// Returns true if the operation can be applied to the rows one at
+    /// a time (each in a table with a single row), so rows can be
+    /// processed while the input is read.
+    fn streams(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the operation drops all further rows, so no
+    /// more input is needed.
+    fn done(&self) -> bool {
+        false
+    }
+
+    /// Reads the rows of the input one at a time (see `Scan`).
+    fn read(&self, _each: &mut RowFn) -> Result<(), FqError> {
+        Err(FqError::internal("Operation does not read input"))
+    }
 }
 
 #[derive(Debug)]
 pub struct Scan {
     file_name: String,
+    format: Format,
+    // skip the first record
+    header: bool,
+    // keep the text of each record in its metadata (for @line and
+    // JSON paths)
+    keep_text: bool,
 }
 
 impl Scan {
-    pub fn new(file_name: String) -> Scan {
-        Scan { file_name }
+    pub fn new(file_name: String, format: Format, header: bool, keep_text: bool) -> Scan {
+        Scan {
+            file_name,
+            format,
+            header,
+            keep_text,
+        }
     }
 }
 
@@ -26,93 +61,212 @@ impl Op for Scan {
             return Err(FqError::exe("Given table has to be empty"));
         }
 
-        let file = match File::open(&self.file_name) {
-            Ok(file) => file,
-            Err(_) => {
-                return Err(FqError::exe("Failed to open file"));
+        let mut content: Vec<Vec<String>> = Vec::new();
+        let mut meta: Vec<Rc<RowMeta>> = Vec::new();
+        self.read(&mut |row, row_meta| {
+            content.push(row);
+            meta.push(row_meta);
+            Ok(true)
+        })?;
+        Table::with_meta(content, meta)
+    }
+
+    /// Reads records one at a time, so the standard input can be
+    /// processed while it is written, e.g., by `tail -f`.
+    fn read(&self, each: &mut RowFn) -> Result<(), FqError> {
+        let mut reader: Box<dyn BufRead> = if is_stdin(&self.file_name) {
+            Box::new(io::stdin().lock())
+        } else {
+            match File::open(&self.file_name) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(_) => {
+                    return Err(FqError::exe("Failed to open file"));
+                }
             }
         };
+        let file: Rc<str> = Rc::from(self.file_name.as_str());
 
-        let reader = BufReader::new(file);
-
-        let mut content: Vec<Vec<String>> = Vec::new();
+        let mut offset = 0;
+        let mut nlines = 0;
+        let mut buf = String::new();
+        let mut splitter = self.format.splitter();
+        // text, first line and offset of a record that spans lines
+        let mut record = String::new();
+        let mut start: Option<(usize, usize)> = None;
+        let mut skip = self.header;
 
-        for line in reader.lines() {
-            match line {
-                Ok(line) => {
-                    let split_line: Vec<String> = line.split(" ").map(String::from).collect();
-                    // The line below does not match the split done by
-                    // the `cut` command.
-                    //line.split_whitespace().map(String::from).collect();
-                    content.push(split_line);
-                }
+        loop {
+            buf.clear();
+            let len = match reader.read_line(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
                 Err(_) => {
                     return Err(FqError::exe("An issue reading a line"));
                 }
+            };
+            // Strip the line terminator the same way `lines` does.
+            let line = match buf.strip_suffix('\n') {
+                Some(line) => line.strip_suffix('\r').unwrap_or(line),
+                None => &buf,
+            };
+            nlines += 1;
+
+            let (line_nr, line_offset, text) = match start.take() {
+                Some((line_nr, line_offset)) => {
+                    record.push('\n');
+                    record.push_str(line);
+                    (line_nr, line_offset, std::mem::take(&mut record))
+                }
+                None => (nlines, offset, line.to_string()),
+            };
+            offset += len;
+
+            let split_line = match splitter.split(line) {
+                Record::FIELDS(split_line) => split_line,
+                Record::PARTIAL => {
+                    record = text;
+                    start = Some((line_nr, line_offset));
+                    continue;
+                }
+                Record::SKIP => continue,
+            };
+            if skip {
+                skip = false;
+                continue;
+            }
+            let fields = if text.is_empty() { 0 } else { split_line.len() };
+            let meta = Rc::new(RowMeta::new(
+                line_nr,
+                fields,
+                line_offset,
+                if self.keep_text { text } else { String::new() },
+                Rc::clone(&file),
+            ));
+            if !each(split_line, meta)? {
+                return Ok(());
             }
         }
 
-        Ok(Table::from(content))
+        if let Some((line_nr, _)) = start {
+            return Err(FqError::exe(format!(
+                "Unterminated quoted field in the record starting at line {}",
+                line_nr
+            )));
+        }
+        Ok(())
     }
 }
 
 pub struct Selection {
     exp: Rc<dyn Expr>,
+    format: Rc<ValFormat>,
 }
 
 impl Selection {
-    pub fn new(exp: Rc<dyn Expr>) -> Selection {
-        Selection { exp }
+    pub fn new(exp: Rc<dyn Expr>, format: Rc<ValFormat>) -> Selection {
+        Selection { exp, format }
     }
 }
 
 impl Op for Selection {
     fn exe(&self, table: Table) -> Result<Table, FqError> {
         let mut ntable = Table::new();
-        let mut iterator = table.iter();
-        while let Some(row) = iterator.next() {
-            if let Val::BOOL(val) = self.exp.eval(&row, &None)? {
+        let mut ctx = Ctx::with_format(Rc::clone(&self.format));
+        ctx.set_meta(&table);
+        for (ix, row) in table.iter().enumerate() {
+            ctx.set_row(ix);
+            if let Val::BOOL(val) = self.exp.eval(&row, &ctx)? {
                 if val {
-                    ntable.push_row(row)?;
+                    ntable.push_row_with(row, table.meta().get(ix).cloned())?;
                 }
             }
         }
         Ok(ntable)
     }
+
+    fn streams(&self) -> bool {
+        true
+    }
 }
 
-//#[derive(Debug)]
+/// Passes the first rows, up to the given number over all the tables
+/// it is applied to (see `Op::streams`).
 pub struct Limit {
-    func: Box<dyn Fn(&Vec<String>, &Table) -> bool>,
+    num: usize,
+    // rows passed so far
+    passed: Cell<usize>,
 }
 
 impl Limit {
-    pub fn from(func: Box<dyn Fn(&Vec<String>, &Table) -> bool>) -> Limit {
-        Limit { func }
+    pub fn new(num: usize) -> Limit {
+        Limit {
+            num,
+            passed: Cell::new(0),
+        }
     }
 }
 
 impl Op for Limit {
     fn exe(&self, table: Table) -> Result<Table, FqError> {
         let mut ntable = Table::new();
-        let mut iterator = table.iter();
-        while let Some(row) = iterator.next() {
-            if (self.func)(&row, &ntable) {
-                ntable.push_row(row)?;
+        for (ix, row) in table.iter().enumerate() {
+            if self.done() {
+                break;
             }
+            ntable.push_row_with(row, table.meta().get(ix).cloned())?;
+            self.passed.set(self.passed.get() + 1);
         }
         Ok(ntable)
     }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn done(&self) -> bool {
+        self.passed.get() >= self.num
+    }
 }
 
 //#[derive(Debug)]
 pub struct Projection {
     expressions: Vec<Rc<dyn Expr>>,
+    format: Rc<ValFormat>,
 }
 
 impl Projection {
-    pub fn new(expressions: Vec<Rc<dyn Expr>>) -> Projection {
-        Projection { expressions }
+    pub fn new(expressions: Vec<Rc<dyn Expr>>, format: Rc<ValFormat>) -> Projection {
+        Projection {
+            expressions,
+            format,
+        }
+    }
+
+    /// Computes the only row of the result when aggregate functions
+    /// are used. Each aggregate function call keeps its own state,
+    /// and the expressions are evaluated over the final values of
+    /// those calls.
+    fn aggregate(&self, table: &Table) -> Result<Vec<String>, FqError> {
+        let mut ctx = Ctx::with_format(Rc::clone(&self.format));
+        ctx.set_meta(table);
+
+        let mut accs = Accs::new();
+        for (ix, row) in table.iter().enumerate() {
+            ctx.set_row(ix);
+            for exp in &self.expressions {
+                exp.accumulate(&row, &ctx, &mut accs)?;
+            }
+        }
+
+        for exp in &self.expressions {
+            exp.finish(&accs, &mut ctx)?;
+        }
+
+        let mut row: Vec<String> = Vec::new();
+        for exp in &self.expressions {
+            row.push(self.format.show(&exp.eval(&[], &ctx)?));
+        }
+        Ok(row)
     }
 }
 
@@ -120,6 +274,19 @@ impl Op for Projection {
     fn exe(&self, table: Table) -> Result<Table, FqError> {
         let mut ntable = Table::new();
 
+        // Aggregate functions are evaluated over all rows, so the
+        // result has a single row (even if the table is empty).
+        // Constants selected together with aggregates are evaluated
+        // only once as well.
+        if self
+            .expressions
+            .iter()
+            .any(|exp| exp.kind() == ExprKind::AGG)
+        {
+            ntable.push_row(self.aggregate(&table)?)?;
+            return Ok(ntable);
+        }
+
         // if empty table, then no work to be done here.
         if table.empty() {
             return Ok(ntable);
@@ -129,39 +296,43 @@ impl Op for Projection {
         let mut expressions: Vec<Rc<dyn Expr>> = Vec::new();
         for exp in &self.expressions {
             if exp.is_star() {
-                for ix in 0..=table.ncols() - 1 {
+                for ix in 0..table.ncols() {
                     expressions.push(Rc::new(ColRef::new(ix.try_into().unwrap())));
                 }
             } else {
-                expressions.push(Rc::clone(&exp));
+                expressions.push(Rc::clone(exp));
             }
         }
 
+        // Window function calls are computed over all rows before
+        // the rows are processed.
+        let mut ctx = Ctx::with_format(Rc::clone(&self.format));
+        ctx.set_meta(&table);
+        for exp in &expressions {
+            exp.window(&table, &mut ctx)?;
+        }
+
         for exp in expressions {
-            // Accumulator to support aggragate functions.
-            let mut acc: Option<Box<Val>> = None;
             // Process one row at a time and save a value in the
             // current column.
             let mut col: Vec<String> = Vec::new();
-            let mut iterator = table.iter();
-            while let Some(row) = iterator.next() {
-                // Note that acc given to eval is never ACC.
-                let val = exp.eval(&row, &acc)?;
-                // Result from aggragate function is always ACC.
-                match val {
-                    Val::ACC(val) => acc = Some(val),
-                    _ => col.push(val.to_string()),
-                }
-            }
-            if let Some(val) = acc {
-                let val = exp.finish(*val, table.nrows() as i32)?;
-                col.push(val.to_string());
+            for (ix, row) in table.iter().enumerate() {
+                ctx.set_row(ix);
+                col.push(self.format.show(&exp.eval(&row, &ctx)?));
             }
             ntable.push_col(col)?;
         }
 
         Ok(ntable)
     }
+
+    /// Rows can be projected one at a time unless aggregate or window
+    /// functions need all rows.
+    fn streams(&self) -> bool {
+        self.expressions
+            .iter()
+            .all(|exp| matches!(exp.kind(), ExprKind::CONST | ExprKind::SCALAR))
+    }
 }
 
 pub struct Engine;
@@ -179,4 +350,37 @@ impl Engine {
         }
         Ok(table)
     }
+
+    /// Applies the operations to each row as soon as the first
+    /// operation (the scan) reads it, and passes the resulting rows
+    /// to the given function. Returns false, without reading the
+    /// input, if an operation needs all rows at once (see
+    /// `Op::streams`).
+    pub fn stream(
+        &self,
+        operations: &[Box<dyn Op>],
+        out: &mut dyn FnMut(Vec<String>) -> Result<(), FqError>,
+    ) -> Result<bool, FqError> {
+        let Some((scan, rest)) = operations.split_first() else {
+            return Ok(true);
+        };
+        if !rest.iter().all(|op| op.streams()) {
+            return Ok(false);
+        }
+        if rest.iter().any(|op| op.done()) {
+            return Ok(true);
+        }
+
+        scan.read(&mut |row, meta| {
+            let mut table = Table::with_meta(vec![row], vec![meta])?;
+            for op in rest {
+                table = op.exe(table)?;
+            }
+            for row in table.iter() {
+                out(row)?;
+            }
+            Ok(!rest.iter().any(|op| op.done()))
+        })?;
+        Ok(true)
+    }
 }
@@ -1,7 +1,14 @@
 use crate::errors::FqError;
 use crate::expr::*;
+use crate::funcs::{Func, Registry};
+use crate::input::Format;
+use crate::json::parse_path;
 use crate::lexer::Lexer;
 use crate::lexer::Token;
+use crate::regex::Regex;
+use crate::windows::{OrderBy, WindowCall, WINDOW_FUNCS};
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 pub trait Visitor {
@@ -45,22 +52,32 @@ impl Node for QueryNode {
 pub struct SelectNode {
     pub columns: Vec<ColumnNode>,
     file_name: String,
+    input: Option<Format>,
+    pub header: bool,
     pub limit: Option<LimitNode>,
     pub xwhere: Option<Rc<dyn Expr>>,
+    // @line or a JSON path is used
+    text: bool,
 }
 
 impl SelectNode {
     fn new(
         columns: Vec<ColumnNode>,
         file_name: String,
+        input: Option<Format>,
+        header: bool,
         limit: Option<LimitNode>,
         xwhere: Option<Rc<dyn Expr>>,
+        text: bool,
     ) -> SelectNode {
         SelectNode {
             columns,
             file_name,
+            input,
+            header,
             limit,
             xwhere,
+            text,
         }
     }
 
@@ -68,6 +85,17 @@ impl SelectNode {
     pub fn file_name(&self) -> &String {
         &self.file_name
     }
+
+    /// Returns the format of the file if it is given in the query.
+    pub fn input(&self) -> Option<&Format> {
+        self.input.as_ref()
+    }
+
+    /// Returns true if the query needs the text of the lines, i.e.,
+    /// it uses @line or a JSON path.
+    pub fn uses_text(&self) -> bool {
+        self.text
+    }
 }
 
 impl Node for SelectNode {
@@ -124,23 +152,193 @@ impl Node for LimitNode {
     }
 }
 
+/// Function defined in a query with `create function`. Calls are
+/// expanded by parsing the body with the parameters bound to the
+/// arguments of the call.
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Names that can be called or referenced in an expression.
+struct Scope<'a> {
+    funcs: &'a Registry,
+    macros: &'a HashMap<String, Macro>,
+    /// Tokens of the arguments bound to the parameters of the macro
+    /// being expanded; they are parsed in the scope of the caller.
+    params: HashMap<String, Vec<Token>>,
+    caller: Option<&'a Scope<'a>>,
+    /// Names of the groups of the regex the file is read with; `None`
+    /// while the body of a function is checked, as the groups are
+    /// known only when it is called.
+    groups: Option<&'a HashMap<String, usize>>,
+    /// Set when @line or a JSON path is parsed.
+    text: &'a Cell<bool>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(
+        funcs: &'a Registry,
+        macros: &'a HashMap<String, Macro>,
+        groups: Option<&'a HashMap<String, usize>>,
+        text: &'a Cell<bool>,
+    ) -> Scope<'a> {
+        Scope {
+            funcs,
+            macros,
+            params: HashMap::new(),
+            caller: None,
+            groups,
+            text,
+        }
+    }
+
+    /// Returns true if a function with the given name can be called.
+    fn is_callable(&self, name: &str) -> bool {
+        self.funcs.get(name).is_some() || self.macros.contains_key(name)
+    }
+}
+
 // entry point for parsing
-pub fn parse_query(mut lexer: Lexer) -> Result<QueryNode, FqError> {
+pub fn parse_query(mut lexer: Lexer, funcs: &Registry) -> Result<QueryNode, FqError> {
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    while matches!(lexer.peek(), Some(Token::CREATE)) {
+        lexer.next(); // eat create
+        let text = Cell::new(false);
+        let scope = Scope::new(funcs, &macros, None, &text);
+        let (name, mac) = parse_create_function(&mut lexer, &scope)?;
+        macros.insert(name, mac);
+    }
+
+    // Columns can refer to the named groups of the regex given after
+    // the path, so the regex is read before the columns are parsed.
+    let groups = find_groups(&lexer)?;
+    let text = Cell::new(false);
+    let scope = Scope::new(funcs, &macros, Some(&groups), &text);
     match lexer.next() {
         Some(Token::SELECT) => {
-            let node = parse_select(&mut lexer)?;
-            if lexer.is_empty() {
-                Ok(QueryNode::new(node))
-            } else {
-                Err(FqError::parse("It has extra tokens"))
+            let node = parse_select(&mut lexer, &scope)?;
+            if matches!(lexer.peek(), Some(Token::SEMICOLON)) {
+                lexer.next();
+            }
+            match lexer.peek() {
+                None => Ok(QueryNode::new(node)),
+                Some(Token::SELECT) => Err(FqError::parse(
+                    "A script can have only one select (run each query separately)",
+                )),
+                Some(Token::CREATE) => Err(FqError::parse(
+                    "Functions have to be created before the select",
+                )),
+                Some(_) => Err(FqError::parse("It has extra tokens")),
             }
         }
         _ => Err(FqError::parse("Expected 'select'")),
     }
 }
 
-fn parse_select(lexer: &mut Lexer) -> Result<SelectNode, FqError> {
-    let columns = parse_columns(lexer)?;
+/// Returns the numbers of the named groups of the regex given after
+/// the path, e.g., `from access.log regex '(?P<ip>\S+) '`, if any.
+fn find_groups(lexer: &Lexer) -> Result<HashMap<String, usize>, FqError> {
+    let mut n = 0;
+    while let Some(token) = lexer.peek_nth(n) {
+        if matches!(token, Token::FROM) {
+            if let (Some(Token::REGEX), Some(Token::STRING(pattern))) =
+                (lexer.peek_nth(n + 2), lexer.peek_nth(n + 3))
+            {
+                return Ok(Regex::new(pattern)?
+                    .names()
+                    .map(|(name, ix)| (name.to_string(), ix))
+                    .collect());
+            }
+            break;
+        }
+        n += 1;
+    }
+    Ok(HashMap::new())
+}
+
+/// Parses the definition of a function, e.g., `create function kb(x)
+/// as x / 1024;`. The body is checked by parsing it once.
+fn parse_create_function(lexer: &mut Lexer, scope: &Scope) -> Result<(String, Macro), FqError> {
+    if !matches!(lexer.next(), Some(Token::FUNCTION)) {
+        return Err(FqError::parse("Expecting function after create"));
+    }
+    let name = match lexer.next() {
+        Some(Token::ID(s)) => s.clone(),
+        _ => return Err(FqError::parse("Expecting function name")),
+    };
+    if scope.is_callable(&name) || WINDOW_FUNCS.contains(&name.as_str()) {
+        return Err(FqError::parse(format!(
+            "Function {}() is already defined",
+            name
+        )));
+    }
+    if !matches!(lexer.next(), Some(Token::LPAREN)) {
+        return Err(FqError::parse("Expecting ( for function parameters"));
+    }
+
+    let mut params: Vec<String> = Vec::new();
+    if !matches!(lexer.peek(), Some(Token::RPAREN)) {
+        loop {
+            match lexer.next() {
+                Some(Token::ID(s)) if !params.contains(s) => params.push(s.clone()),
+                Some(Token::ID(s)) => {
+                    return Err(FqError::parse(format!("Duplicate parameter {}", s)))
+                }
+                _ => return Err(FqError::parse("Expecting parameter name")),
+            }
+            if !matches!(lexer.peek(), Some(Token::COMMA)) {
+                break;
+            }
+            lexer.next(); // eat comma
+        }
+    }
+    if !matches!(lexer.next(), Some(Token::RPAREN)) {
+        return Err(FqError::parse("Expecting ) for function parameters"));
+    }
+    if !matches!(lexer.next(), Some(Token::AS)) {
+        return Err(FqError::parse("Expecting as after function parameters"));
+    }
+
+    let mut body: Vec<Token> = Vec::new();
+    loop {
+        match lexer.next() {
+            Some(Token::SEMICOLON) => break,
+            Some(token) => body.push(token.clone()),
+            None => return Err(FqError::parse("Expecting ; after function body")),
+        }
+    }
+
+    let mac = Macro { params, body };
+    let args = vec![vec![Token::INT(0)]; mac.params.len()];
+    parse_macro_body(&mac, args, scope)?;
+    Ok((name, mac))
+}
+
+fn parse_macro_body(
+    mac: &Macro,
+    args: Vec<Vec<Token>>,
+    caller: &Scope,
+) -> Result<Rc<dyn Expr>, FqError> {
+    let scope = Scope {
+        funcs: caller.funcs,
+        macros: caller.macros,
+        params: mac.params.iter().cloned().zip(args).collect(),
+        caller: Some(caller),
+        groups: caller.groups,
+        text: caller.text,
+    };
+    let mut lexer = Lexer::from_tokens(mac.body.clone());
+    let exp = parse_additive_expr(&mut lexer, &scope)?;
+    if lexer.is_empty() {
+        Ok(exp)
+    } else {
+        Err(FqError::parse("Function body has extra tokens"))
+    }
+}
+
+fn parse_select(lexer: &mut Lexer, scope: &Scope) -> Result<SelectNode, FqError> {
+    let columns = parse_columns(lexer, scope)?;
 
     if !matches!(lexer.next(), Some(Token::FROM)) {
         return Err(FqError::parse("Expecting 'from'"));
@@ -150,53 +348,125 @@ fn parse_select(lexer: &mut Lexer) -> Result<SelectNode, FqError> {
     let file_name = match lexer.next() {
         Some(Token::PATH(ref s)) => String::from(s),
         Some(Token::ID(ref s)) => String::from(s),
+        Some(Token::STRING(ref s)) => String::from(s),
+        // `-` stands for the standard input
+        Some(Token::MINUS) => String::from("-"),
         _ => return Err(FqError::parse("Expecting path to a file")),
     };
 
-    let xwhere = parse_where(lexer)?;
+    let input = parse_input(lexer)?;
+    let header = matches!(lexer.peek(), Some(Token::HEADER));
+    if header {
+        lexer.next();
+    }
+    let xwhere = parse_where(lexer, scope)?;
     let limit = parse_limit(lexer)?;
-    Ok(SelectNode::new(columns, file_name, limit, xwhere))
+    Ok(SelectNode::new(
+        columns,
+        file_name,
+        input,
+        header,
+        limit,
+        xwhere,
+        scope.text.get(),
+    ))
+}
+
+/// Parses the format of the file, e.g., `delimiter ':'`, `whitespace`,
+/// `csv`, `csv delimiter ';'`, `jsonl`, `widths (1-8, 9-)` or `regex
+/// '(\d+) (\w+)'`, if it is given after the path.
+fn parse_input(lexer: &mut Lexer) -> Result<Option<Format>, FqError> {
+    match lexer.peek() {
+        Some(Token::DELIMITER) => {
+            lexer.next();
+            match lexer.next() {
+                Some(Token::STRING(s)) => Ok(Some(Format::delimited(s)?)),
+                _ => Err(FqError::parse("Delimiter should be followed by a string")),
+            }
+        }
+        Some(Token::WHITESPACE) => {
+            lexer.next();
+            Ok(Some(Format::WHITESPACE))
+        }
+        Some(Token::CSV) => {
+            lexer.next();
+            if !matches!(lexer.peek(), Some(Token::DELIMITER)) {
+                return Ok(Some(Format::csv_default()));
+            }
+            lexer.next();
+            match lexer.next() {
+                Some(Token::STRING(s)) => Ok(Some(Format::csv(s)?)),
+                _ => Err(FqError::parse("Delimiter should be followed by a string")),
+            }
+        }
+        Some(Token::JSONL) => {
+            lexer.next();
+            Ok(Some(Format::JSONL))
+        }
+        Some(Token::WIDTHS) => {
+            lexer.next();
+            Ok(Some(Format::WIDTHS(parse_widths(lexer)?)))
+        }
+        Some(Token::REGEX) => {
+            lexer.next();
+            let regex = match lexer.next() {
+                Some(Token::STRING(s)) => Regex::new(s)?,
+                _ => return Err(FqError::parse("Regex should be followed by a string")),
+            };
+            if let Some((name, _)) = regex
+                .names()
+                .find(|(name, _)| Pseudo::from_name(name).is_some())
+            {
+                return Err(FqError::parse(format!(
+                    "Group name {} is reserved for a pseudo-column",
+                    name
+                )));
+            }
+            Ok(Some(Format::REGEX(regex)))
+        }
+        _ => Ok(None),
+    }
 }
 
-fn parse_columns(lexer: &mut Lexer) -> Result<Vec<ColumnNode>, FqError> {
+fn parse_columns(lexer: &mut Lexer, scope: &Scope) -> Result<Vec<ColumnNode>, FqError> {
     let mut columns: Vec<ColumnNode> = Vec::new();
 
-    let column = parse_column(lexer)?;
+    let column = parse_column(lexer, scope)?;
     columns.push(column);
 
     while matches!(lexer.peek(), Some(Token::COMMA)) {
         lexer.next(); // eat comma
-        let column = parse_column(lexer)?;
+        let column = parse_column(lexer, scope)?;
         columns.push(column);
     }
 
     Ok(columns)
 }
 
-fn parse_column(lexer: &mut Lexer) -> Result<ColumnNode, FqError> {
+fn parse_column(lexer: &mut Lexer, scope: &Scope) -> Result<ColumnNode, FqError> {
     let token = lexer.peek();
     match token {
         Some(Token::STAR) => {
             lexer.next();
-            return Ok(ColumnNode::new(Rc::new(StarConst::new())));
+            Ok(ColumnNode::new(Rc::new(StarConst::new())))
         }
-        _ => Ok(ColumnNode::new(parse_additive_expr(lexer)?)),
+        _ => Ok(ColumnNode::new(parse_additive_expr(lexer, scope)?)),
     }
 }
 
-fn parse_additive_expr(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
-    let mut exp: Rc<dyn Expr> = parse_multiplicative_expr(lexer)?;
+fn parse_additive_expr(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
+    let mut exp: Rc<dyn Expr> = parse_multiplicative_expr(lexer, scope)?;
 
     loop {
         match lexer.peek() {
             Some(Token::PLUS) => {
                 lexer.next();
-                let right = parse_multiplicative_expr(lexer)?;
+                let right = parse_multiplicative_expr(lexer, scope)?;
                 exp = Rc::new(BinExpr::new(Bop::PLUS, exp, right));
             }
             Some(Token::MINUS) => {
                 lexer.next();
-                let right = parse_multiplicative_expr(lexer)?;
+                let right = parse_multiplicative_expr(lexer, scope)?;
                 exp = Rc::new(BinExpr::new(Bop::MINUS, exp, right));
             }
             _ => break,
@@ -206,19 +476,19 @@ fn parse_additive_expr(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
     Ok(exp)
 }
 
-fn parse_multiplicative_expr(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
-    let mut exp: Rc<dyn Expr> = parse_atom(lexer)?;
+fn parse_multiplicative_expr(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
+    let mut exp: Rc<dyn Expr> = parse_atom(lexer, scope)?;
 
     loop {
         match lexer.peek() {
             Some(Token::STAR) => {
                 lexer.next();
-                let right = parse_atom(lexer)?;
+                let right = parse_atom(lexer, scope)?;
                 exp = Rc::new(BinExpr::new(Bop::MUL, exp, right));
             }
             Some(Token::DIV) => {
                 lexer.next();
-                let right = parse_atom(lexer)?;
+                let right = parse_atom(lexer, scope)?;
                 exp = Rc::new(BinExpr::new(Bop::DIV, exp, right));
             }
             _ => break,
@@ -228,22 +498,27 @@ fn parse_multiplicative_expr(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError>
     Ok(exp)
 }
 
-fn parse_atom(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
+fn parse_atom(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
     let exp: Rc<dyn Expr> = match lexer.next() {
         Some(Token::INT(n)) => Rc::new(IntConst::new(*n)),
         Some(Token::FLOAT(n)) => Rc::new(FloatConst::new(*n)),
         Some(Token::STRING(s)) => Rc::new(StrConst::new(s.to_string())),
         Some(Token::TRUE) => Rc::new(BoolConst::new(true)),
         Some(Token::FALSE) => Rc::new(BoolConst::new(false)),
-        Some(Token::INTK) => Rc::new(IntCast::new(parse_cast(lexer)?)),
-        Some(Token::FLOATK) => Rc::new(FloatCast::new(parse_cast(lexer)?)),
-        Some(Token::BOOLK) => Rc::new(BoolCast::new(parse_cast(lexer)?)),
-        Some(Token::STRK) => Rc::new(StrCast::new(parse_cast(lexer)?)),
+        Some(Token::INTK) => Rc::new(IntCast::new(parse_cast(lexer, scope)?)),
+        Some(Token::FLOATK) => Rc::new(FloatCast::new(parse_cast(lexer, scope)?)),
+        Some(Token::BOOLK) => Rc::new(BoolCast::new(parse_cast(lexer, scope)?)),
+        Some(Token::STRK) => Rc::new(StrCast::new(parse_cast(lexer, scope)?)),
         Some(Token::ID(s)) => {
-            let func = s.clone();
-            parse_func_call(lexer, func.as_str())?
+            let name = s.clone();
+            match (scope.params.get(&name), scope.caller) {
+                (Some(arg), Some(caller)) if !matches!(lexer.peek(), Some(Token::LPAREN)) => {
+                    parse_arg(arg, caller)?
+                }
+                _ => parse_func_call(lexer, scope, name.as_str())?,
+            }
         }
-        Some(Token::COLUMN(_)) => {
+        Some(Token::COLUMN(_)) | Some(Token::COLNAME(_)) | Some(Token::JSONPATH(_)) => {
             return Err(FqError::parse("Column reference has to be cast"));
         }
         _ => {
@@ -254,43 +529,255 @@ fn parse_atom(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
     Ok(exp)
 }
 
-fn parse_func_call(lexer: &mut Lexer, func: &str) -> Result<Rc<dyn Expr>, FqError> {
+fn parse_func_call(lexer: &mut Lexer, scope: &Scope, func: &str) -> Result<Rc<dyn Expr>, FqError> {
     if !matches!(lexer.next(), Some(Token::LPAREN)) {
         return Err(FqError::parse("Expecting ( for a function call"));
     }
+    if let Some(mac) = scope.macros.get(func) {
+        return parse_macro_call(lexer, scope, func, mac);
+    }
 
-    let args = parse_additive_expr(lexer)?;
+    let distinct = matches!(lexer.peek(), Some(Token::DISTINCT));
+    if distinct {
+        lexer.next(); // eat distinct
+        if !scope.funcs.is_agg(func) {
+            return Err(FqError::parse(
+                "distinct can only be used in aggregate function calls",
+            ));
+        }
+    }
+
+    let mut args: Vec<Rc<dyn Expr>> = Vec::new();
+    if !matches!(lexer.peek(), Some(Token::RPAREN)) {
+        args.push(parse_additive_expr(lexer, scope)?);
+        while matches!(lexer.peek(), Some(Token::COMMA)) {
+            lexer.next(); // eat comma
+            args.push(parse_additive_expr(lexer, scope)?);
+        }
+    }
 
     if !matches!(lexer.next(), Some(Token::RPAREN)) {
         return Err(FqError::parse("Expecting ) for a function call"));
     }
 
-    Ok(Rc::new(FuncCall::new(func, args)))
+    if matches!(lexer.peek(), Some(Token::OVER)) {
+        if !WINDOW_FUNCS.contains(&func) && !scope.funcs.is_agg(func) {
+            return Err(FqError::parse(
+                "over can only be used with window and aggregate functions",
+            ));
+        }
+        return parse_over(lexer, scope, func, args, distinct);
+    }
+    if WINDOW_FUNCS.contains(&func) {
+        return Err(FqError::parse(format!("{}() requires over", func)));
+    }
+
+    match scope.funcs.get(func) {
+        Some(found) if !found.accepts(args.len()) => Err(FqError::parse(format!(
+            "Wrong number of arguments for {}()",
+            func
+        ))),
+        Some(func) => Ok(Rc::new(
+            FuncCall::new(func.clone(), args).with_distinct(distinct),
+        )),
+        None => Err(FqError::parse(format!("Unknown function {}()", func))),
+    }
 }
 
-fn parse_where(lexer: &mut Lexer) -> Result<Option<Rc<dyn Expr>>, FqError> {
+/// Parses the arguments of a call to a function defined in the query
+/// and expands the call. Arguments are kept as tokens, so each use of
+/// a parameter gets its own expression.
+fn parse_macro_call(
+    lexer: &mut Lexer,
+    scope: &Scope,
+    func: &str,
+    mac: &Macro,
+) -> Result<Rc<dyn Expr>, FqError> {
+    let mut args: Vec<Vec<Token>> = Vec::new();
+    let mut arg: Vec<Token> = Vec::new();
+    let mut depth = 0;
+    loop {
+        match lexer.next() {
+            Some(Token::RPAREN) if depth == 0 => break,
+            Some(Token::COMMA) if depth == 0 => args.push(std::mem::take(&mut arg)),
+            Some(token) => {
+                match token {
+                    Token::LPAREN => depth += 1,
+                    Token::RPAREN => depth -= 1,
+                    _ => {}
+                }
+                arg.push(token.clone());
+            }
+            None => return Err(FqError::parse("Expecting ) for a function call")),
+        }
+    }
+    if !arg.is_empty() || !args.is_empty() {
+        args.push(arg);
+    }
+
+    if matches!(lexer.peek(), Some(Token::OVER)) {
+        return Err(FqError::parse(
+            "over can only be used with window and aggregate functions",
+        ));
+    }
+    if args.len() != mac.params.len() {
+        return Err(FqError::parse(format!(
+            "Wrong number of arguments for {}()",
+            func
+        )));
+    }
+    for arg in &args {
+        // checks each argument even if its parameter is not used
+        parse_arg(arg, scope)?;
+    }
+    parse_macro_body(mac, args, scope)
+}
+
+fn parse_arg(arg: &[Token], scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
+    let mut lexer = Lexer::from_tokens(arg.to_vec());
+    let exp = parse_additive_expr(&mut lexer, scope)?;
+    if lexer.is_empty() {
+        Ok(exp)
+    } else {
+        Err(FqError::parse("Function argument has extra tokens"))
+    }
+}
+
+/// Parses the window of a window function call, e.g., `over
+/// (partition by str(@0) order by int(@1) desc)`.
+fn parse_over(
+    lexer: &mut Lexer,
+    scope: &Scope,
+    func: &str,
+    args: Vec<Rc<dyn Expr>>,
+    distinct: bool,
+) -> Result<Rc<dyn Expr>, FqError> {
+    lexer.next(); // eat over
+    if !matches!(lexer.next(), Some(Token::LPAREN)) {
+        return Err(FqError::parse("Expecting ( after over"));
+    }
+
+    let mut partition: Vec<Rc<dyn Expr>> = Vec::new();
+    if matches!(lexer.peek(), Some(Token::PARTITION)) {
+        lexer.next(); // eat partition
+        if !matches!(lexer.next(), Some(Token::BY)) {
+            return Err(FqError::parse("Expecting by after partition"));
+        }
+        partition.push(parse_additive_expr(lexer, scope)?);
+        while matches!(lexer.peek(), Some(Token::COMMA)) {
+            lexer.next(); // eat comma
+            partition.push(parse_additive_expr(lexer, scope)?);
+        }
+    }
+
+    let mut order: Vec<OrderBy> = Vec::new();
+    if matches!(lexer.peek(), Some(Token::ORDER)) {
+        lexer.next(); // eat order
+        if !matches!(lexer.next(), Some(Token::BY)) {
+            return Err(FqError::parse("Expecting by after order"));
+        }
+        loop {
+            let exp = parse_additive_expr(lexer, scope)?;
+            let desc = match lexer.peek() {
+                Some(Token::ASC) => {
+                    lexer.next();
+                    false
+                }
+                Some(Token::DESC) => {
+                    lexer.next();
+                    true
+                }
+                _ => false,
+            };
+            order.push((exp, desc));
+            if !matches!(lexer.peek(), Some(Token::COMMA)) {
+                break;
+            }
+            lexer.next(); // eat comma
+        }
+    }
+
+    if !matches!(lexer.next(), Some(Token::RPAREN)) {
+        return Err(FqError::parse("Expecting ) for a window"));
+    }
+
+    let nargs = args.len();
+    let mut call = WindowCall::new(func, args, partition, order).with_distinct(distinct);
+    if !WINDOW_FUNCS.contains(&func) {
+        if let Some(found @ Func::AGG(agg)) = scope.funcs.get(func) {
+            if !found.accepts(nargs) {
+                return Err(FqError::parse(format!(
+                    "Wrong number of arguments for {}()",
+                    func
+                )));
+            }
+            call = call.with_aggregate(Rc::clone(agg));
+        }
+    }
+    Ok(Rc::new(call))
+}
+
+/// Parses the ranges of fixed-width fields, e.g., `(1-8, 9-20, 21-)`.
+fn parse_widths(lexer: &mut Lexer) -> Result<Vec<(usize, Option<usize>)>, FqError> {
+    if !matches!(lexer.next(), Some(Token::LPAREN)) {
+        return Err(FqError::parse("Expecting ( after widths"));
+    }
+    let mut ranges: Vec<(usize, Option<usize>)> = Vec::new();
+    loop {
+        let start = match lexer.next() {
+            Some(Token::INT(n)) if *n > 0 => *n as usize,
+            _ => return Err(FqError::parse("Range has to start with a positive number")),
+        };
+        let end = match lexer.next() {
+            Some(Token::MINUS) => match lexer.peek() {
+                Some(Token::INT(n)) if *n > 0 => {
+                    let end = *n as usize;
+                    lexer.next();
+                    Some(end)
+                }
+                _ => None,
+            },
+            _ => return Err(FqError::parse("Expecting - in a range")),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(FqError::parse(format!(
+                "Range {}-{} ends before it starts",
+                start,
+                end.unwrap_or_default()
+            )));
+        }
+        ranges.push((start, end));
+        match lexer.next() {
+            Some(Token::COMMA) => continue,
+            Some(Token::RPAREN) => break,
+            _ => return Err(FqError::parse("Expecting ) for widths")),
+        }
+    }
+    Ok(ranges)
+}
+
+fn parse_where(lexer: &mut Lexer, scope: &Scope) -> Result<Option<Rc<dyn Expr>>, FqError> {
     match lexer.peek() {
         Some(Token::WHERE) => {
             // eat `where`
             lexer.next();
 
             // parse left expression
-            let left = parse_where_expr(lexer)?;
+            let left = parse_where_expr(lexer, scope)?;
             // get the operator
-            let op;
-            match lexer.next() {
-                Some(Token::GT) => op = Bop::GT,
-                Some(Token::LT) => op = Bop::LT,
-                Some(Token::EQ) => op = Bop::EQ,
-                Some(Token::LE) => op = Bop::LE,
-                Some(Token::GE) => op = Bop::GE,
-                Some(Token::NE) => op = Bop::NE,
+            let op = match lexer.next() {
+                Some(Token::GT) => Bop::GT,
+                Some(Token::LT) => Bop::LT,
+                Some(Token::EQ) => Bop::EQ,
+                Some(Token::LE) => Bop::LE,
+                Some(Token::GE) => Bop::GE,
+                Some(Token::NE) => Bop::NE,
                 _ => {
                     return Err(FqError::parse("Unsuppported operator in where expression"));
                 }
-            }
+            };
             // parse the right expression
-            let right = parse_where_expr(lexer)?;
+            let right = parse_where_expr(lexer, scope)?;
 
             Ok(Some(Rc::new(BinExpr::new(op, left, right))))
         }
@@ -298,42 +785,73 @@ fn parse_where(lexer: &mut Lexer) -> Result<Option<Rc<dyn Expr>>, FqError> {
     }
 }
 
-fn parse_where_expr(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
+fn parse_where_expr(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
     match lexer.next() {
         Some(Token::INT(n)) => Ok(Rc::new(IntConst::new(*n))),
         Some(Token::FLOAT(n)) => Ok(Rc::new(FloatConst::new(*n))),
         Some(Token::STRING(s)) => Ok(Rc::new(StrConst::new(s.to_string()))),
         Some(Token::TRUE) => Ok(Rc::new(BoolConst::new(true))),
         Some(Token::FALSE) => Ok(Rc::new(BoolConst::new(false))),
-        Some(Token::INTK) => Ok(Rc::new(IntCast::new(parse_cast(lexer)?))),
-        Some(Token::FLOATK) => Ok(Rc::new(FloatCast::new(parse_cast(lexer)?))),
-        Some(Token::BOOLK) => Ok(Rc::new(BoolCast::new(parse_cast(lexer)?))),
-        Some(Token::STRK) => Ok(Rc::new(StrCast::new(parse_cast(lexer)?))),
-        Some(Token::COLUMN(_)) => Err(FqError::parse("Column references has to be cast")),
-        Some(Token::ID(s)) if SCALAR_FUNCS.contains(&s.as_str()) => {
+        Some(Token::INTK) => Ok(Rc::new(IntCast::new(parse_cast(lexer, scope)?))),
+        Some(Token::FLOATK) => Ok(Rc::new(FloatCast::new(parse_cast(lexer, scope)?))),
+        Some(Token::BOOLK) => Ok(Rc::new(BoolCast::new(parse_cast(lexer, scope)?))),
+        Some(Token::STRK) => Ok(Rc::new(StrCast::new(parse_cast(lexer, scope)?))),
+        Some(Token::COLUMN(_)) | Some(Token::COLNAME(_)) | Some(Token::JSONPATH(_)) => {
+            Err(FqError::parse("Column references has to be cast"))
+        }
+        Some(Token::ID(s)) if scope.is_callable(s) => {
             let func = s.clone();
-            parse_func_call(lexer, func.as_str())
+            parse_func_call(lexer, scope, func.as_str())
         }
         _ => Err(FqError::parse("Unsupported where expression")),
     }
 }
 
-fn parse_cast(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
+fn parse_cast(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
     if let Some(Token::LPAREN) = lexer.next() {
-        let col = parse_column_ref(lexer)?;
+        let col = parse_column_ref(lexer, scope)?;
         if let Some(Token::RPAREN) = lexer.next() {
-            return Ok(col);
+            Ok(col)
         } else {
-            return Err(FqError::parse("Missing )"));
+            Err(FqError::parse("Missing )"))
         }
     } else {
-        return Err(FqError::parse("Missing ("));
+        Err(FqError::parse("Missing ("))
     }
 }
 
-fn parse_column_ref(lexer: &mut Lexer) -> Result<Rc<dyn Expr>, FqError> {
+fn parse_column_ref(lexer: &mut Lexer, scope: &Scope) -> Result<Rc<dyn Expr>, FqError> {
     match lexer.next() {
         Some(Token::COLUMN(n)) => Ok(Rc::new(ColRef::new(*n))),
+        Some(Token::COLNAME(name)) => {
+            if let Some(pseudo) = Pseudo::from_name(name) {
+                if pseudo == Pseudo::LINE {
+                    scope.text.set(true);
+                }
+                return Ok(Rc::new(PseudoCol::new(pseudo)));
+            }
+            match scope.groups {
+                // The group is checked when the function is called.
+                None => Ok(Rc::new(ColRef::new(0))),
+                Some(groups) => match groups.get(name) {
+                    Some(ix) => Ok(Rc::new(ColRef::new(*ix as u32))),
+                    None if groups.is_empty() => {
+                        Err(FqError::parse(format!("Unknown pseudo-column @{}", name)))
+                    }
+                    None => Err(FqError::parse(format!(
+                        "Unknown pseudo-column or group @{}",
+                        name
+                    ))),
+                },
+            }
+        }
+        Some(Token::JSONPATH(path)) => match parse_path(path) {
+            Some(path) => {
+                scope.text.set(true);
+                Ok(Rc::new(JsonCol::new(path)))
+            }
+            None => Err(FqError::parse(format!("Invalid JSON path {}", path))),
+        },
         _ => Err(FqError::parse("Needs column reference")),
     }
 }
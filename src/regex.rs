@@ -0,0 +1,769 @@
+// A small regular expression engine, which is used to split lines into
+// the groups of a regex. Patterns are compiled to a program for a
+// backtracking machine that never visits the same instruction at the
+// same position twice, so matching takes time linear in the length of
+// the pattern times the length of the text.
+//
+// Supported syntax: literals, `.`, classes (`[a-z]`, `[^,]`), `\d`,
+// `\w`, `\s` (and their negations), `\b`, `\B`, `^`, `$`, groups
+// (`(...)`, `(?:...)`, `(?P<name>...)`, `(?<name>...)`), `|`, and
+// the greedy and lazy quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and
+// `{n,m}`.
+
+use crate::errors::FqError;
+use std::cell::RefCell;
+use std::fmt;
+
+// Larger counts would make programs too big.
+const MAX_REPEAT: u32 = 1000;
+// Maximum number of instructions of a program, which also bounds the
+// memory needed to match a line (nested repeats multiply counts).
+const MAX_PROG: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+enum Perl {
+    DIGIT,
+    WORD,
+    SPACE,
+}
+
+impl Perl {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Perl::DIGIT => c.is_ascii_digit(),
+            Perl::WORD => is_word(c),
+            Perl::SPACE => c.is_whitespace(),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+enum ClassItem {
+    RANGE(char, char),
+    // a Perl class, which is negated if the flag is set
+    PERL(Perl, bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            ClassItem::RANGE(low, high) => *low <= c && c <= *high,
+            ClassItem::PERL(perl, negated) => perl.matches(c) != *negated,
+        });
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+enum Assertion {
+    START,
+    END,
+    BOUNDARY,
+    NONBOUNDARY,
+}
+
+#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum Node {
+    EMPTY,
+    CHAR(char),
+    ANY,
+    CLASS(Class),
+    ASSERT(Assertion),
+    // a group with its index if it is capturing
+    GROUP(Box<Node>, Option<usize>),
+    CONCAT(Vec<Node>),
+    ALT(Vec<Node>),
+    REPEAT {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum Inst {
+    CHAR(char),
+    ANY,
+    CLASS(Class),
+    ASSERT(Assertion),
+    // stores the position in the given slot
+    SAVE(usize),
+    // tries the first target and then the second one
+    SPLIT(usize, usize),
+    JMP(usize),
+    MATCH,
+}
+
+/// A compiled regular expression.
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    prog: Vec<Inst>,
+    // number of groups, not counting the whole match
+    ngroups: usize,
+    names: Vec<(String, usize)>,
+    // (instruction, position) pairs tried by the machine, kept to be
+    // reused for each line
+    visited: RefCell<Vec<bool>>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, FqError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            ix: 0,
+            ngroups: 0,
+            names: Vec::new(),
+        };
+        let node = parser.alt()?;
+        if parser.ix < parser.chars.len() {
+            return Err(invalid("unmatched )"));
+        }
+
+        // Nested repeats multiply, so the size is checked before the
+        // program is built.
+        if prog_size(&node) > MAX_PROG {
+            return Err(invalid("pattern is too large"));
+        }
+        let mut prog = vec![Inst::SAVE(0)];
+        compile(&node, &mut prog);
+        prog.push(Inst::SAVE(1));
+        prog.push(Inst::MATCH);
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            prog,
+            ngroups: parser.ngroups,
+            names: parser.names,
+            visited: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Returns the names of all named groups with their indexes.
+    pub fn names(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.names.iter().map(|(name, ix)| (name.as_str(), *ix))
+    }
+
+    /// Finds the leftmost match in the given text and returns the
+    /// whole match followed by all groups (empty for groups that did
+    /// not take part in the match), or `None` if there is no match.
+    pub fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut visited = self.visited.borrow_mut();
+        visited.clear();
+        visited.resize(self.prog.len() * (chars.len() + 1), false);
+        let mut machine = Machine {
+            prog: &self.prog,
+            chars: &chars,
+            visited: &mut visited,
+            slots: vec![None; 2 * (self.ngroups + 1)],
+        };
+        let found = (0..=chars.len()).any(|start| machine.run(start));
+        if !found {
+            return None;
+        }
+        let groups = machine
+            .slots
+            .chunks(2)
+            .map(|slot| match slot {
+                [Some(start), Some(end)] => chars[*start..*end].iter().collect(),
+                _ => String::new(),
+            })
+            .collect();
+        Some(groups)
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Regex) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+fn invalid(msg: &str) -> FqError {
+    FqError::parse(format!("Invalid regex: {}", msg))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    ix: usize,
+    ngroups: usize,
+    names: Vec<(String, usize)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.ix).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.ix += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.ix += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alt(&mut self) -> Result<Node, FqError> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().expect("one alternative")
+        } else {
+            Node::ALT(alts)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, FqError> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            nodes.push(self.repeat()?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::EMPTY,
+            1 => nodes.pop().expect("one node"),
+            _ => Node::CONCAT(nodes),
+        })
+    }
+
+    fn repeat(&mut self) -> Result<Node, FqError> {
+        let node = self.atom()?;
+        let (min, max) = match self.peek() {
+            Some('{') => match self.counts()? {
+                Some(counts) => counts,
+                None => return Ok(node),
+            },
+            Some(c @ ('*' | '+' | '?')) => {
+                self.ix += 1;
+                match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                }
+            }
+            _ => return Ok(node),
+        };
+        let greedy = !self.eat('?');
+        if matches!(self.peek(), Some('*' | '+' | '?')) {
+            return Err(invalid("nothing to repeat"));
+        }
+        if let Node::ASSERT(_) = node {
+            return Err(invalid("an assertion cannot be repeated"));
+        }
+        Ok(Node::REPEAT {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`. Returns `None` (and keeps the
+    // index) if the text is not a count, in which case `{` is a
+    // literal.
+    fn counts(&mut self) -> Result<Option<(u32, Option<u32>)>, FqError> {
+        let start = self.ix;
+        self.ix += 1; // eat {
+        let (min, max) = match (self.number(), self.eat(',')) {
+            (Some(min), false) => (min, Some(min)),
+            (Some(min), true) => (min, self.number()),
+            (None, _) => {
+                self.ix = start;
+                return Ok(None);
+            }
+        };
+        if !self.eat('}') {
+            self.ix = start;
+            return Ok(None);
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(invalid("repetition count is too large"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(invalid("repetition range is reversed"));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.ix;
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.ix += 1;
+        }
+        let digits: String = self.chars[start..self.ix].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn atom(&mut self) -> Result<Node, FqError> {
+        match self.next() {
+            Some('(') => self.group(),
+            Some('[') => Ok(Node::CLASS(self.class()?)),
+            Some('.') => Ok(Node::ANY),
+            Some('^') => Ok(Node::ASSERT(Assertion::START)),
+            Some('$') => Ok(Node::ASSERT(Assertion::END)),
+            Some('\\') => match self.escape()? {
+                Escape::CHAR(c) => Ok(Node::CHAR(c)),
+                Escape::PERL(perl, negated) => Ok(Node::CLASS(Class {
+                    items: vec![ClassItem::PERL(perl, negated)],
+                    negated: false,
+                })),
+                Escape::ASSERT(assertion) => Ok(Node::ASSERT(assertion)),
+            },
+            Some('*' | '+' | '?') => Err(invalid("nothing to repeat")),
+            Some(c) => Ok(Node::CHAR(c)),
+            None => Err(invalid("unexpected end")),
+        }
+    }
+
+    fn group(&mut self) -> Result<Node, FqError> {
+        let mut index = None;
+        if self.eat('?') {
+            if self.eat(':') {
+                // non-capturing
+            } else if self.eat('<') || (self.eat('P') && self.eat('<')) {
+                let name = self.group_name()?;
+                self.ngroups += 1;
+                self.names.push((name, self.ngroups));
+                index = Some(self.ngroups);
+            } else {
+                return Err(invalid("unsupported group syntax"));
+            }
+        } else {
+            self.ngroups += 1;
+            index = Some(self.ngroups);
+        }
+        let node = self.alt()?;
+        if !self.eat(')') {
+            return Err(invalid("unmatched ("));
+        }
+        Ok(Node::GROUP(Box::new(node), index))
+    }
+
+    fn group_name(&mut self) -> Result<String, FqError> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some('>') if !name.is_empty() => break,
+                Some(c)
+                    if c.is_ascii_alphabetic()
+                        || (!name.is_empty() && (c.is_ascii_digit() || c == '_')) =>
+                {
+                    name.push(c)
+                }
+                _ => return Err(invalid("incorrect group name")),
+            }
+        }
+        if self.names.iter().any(|(other, _)| *other == name) {
+            return Err(invalid("duplicate group name"));
+        }
+        Ok(name)
+    }
+
+    fn class(&mut self) -> Result<Class, FqError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let low = match self.next() {
+                None => return Err(invalid("unmatched [")),
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Escape::CHAR(c) => c,
+                    Escape::PERL(perl, negated) => {
+                        items.push(ClassItem::PERL(perl, negated));
+                        first = false;
+                        continue;
+                    }
+                    Escape::ASSERT(_) => return Err(invalid("an assertion in a class")),
+                },
+                Some(c) => c,
+            };
+            first = false;
+            // `-` before `]` is a literal
+            if self.peek() == Some('-') && self.chars.get(self.ix + 1).is_some_and(|c| *c != ']') {
+                self.ix += 1;
+                let high = match self.next() {
+                    Some('\\') => match self.escape()? {
+                        Escape::CHAR(c) => c,
+                        _ => return Err(invalid("incorrect range in a class")),
+                    },
+                    Some(c) => c,
+                    None => return Err(invalid("unmatched [")),
+                };
+                if high < low {
+                    return Err(invalid("incorrect range in a class"));
+                }
+                items.push(ClassItem::RANGE(low, high));
+            } else {
+                items.push(ClassItem::RANGE(low, low));
+            }
+        }
+        Ok(Class { items, negated })
+    }
+
+    fn escape(&mut self) -> Result<Escape, FqError> {
+        match self.next() {
+            Some('d') => Ok(Escape::PERL(Perl::DIGIT, false)),
+            Some('D') => Ok(Escape::PERL(Perl::DIGIT, true)),
+            Some('w') => Ok(Escape::PERL(Perl::WORD, false)),
+            Some('W') => Ok(Escape::PERL(Perl::WORD, true)),
+            Some('s') => Ok(Escape::PERL(Perl::SPACE, false)),
+            Some('S') => Ok(Escape::PERL(Perl::SPACE, true)),
+            Some('b') => Ok(Escape::ASSERT(Assertion::BOUNDARY)),
+            Some('B') => Ok(Escape::ASSERT(Assertion::NONBOUNDARY)),
+            Some('t') => Ok(Escape::CHAR('\t')),
+            Some('n') => Ok(Escape::CHAR('\n')),
+            Some('r') => Ok(Escape::CHAR('\r')),
+            Some(c) if !c.is_alphanumeric() => Ok(Escape::CHAR(c)),
+            Some(_) => Err(invalid("unknown escape sequence")),
+            None => Err(invalid("unexpected end")),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum Escape {
+    CHAR(char),
+    PERL(Perl, bool),
+    ASSERT(Assertion),
+}
+
+/// Returns the number of instructions of the node (see `compile`),
+/// counting each copy of a repeated node as at least one, or
+/// `usize::MAX` if it overflows.
+fn prog_size(node: &Node) -> usize {
+    match node {
+        Node::EMPTY => 0,
+        Node::CHAR(_) | Node::ANY | Node::CLASS(_) | Node::ASSERT(_) => 1,
+        Node::GROUP(node, None) => prog_size(node),
+        Node::GROUP(node, Some(_)) => prog_size(node).saturating_add(2),
+        Node::CONCAT(nodes) => nodes
+            .iter()
+            .fold(0, |size, node| size.saturating_add(prog_size(node))),
+        Node::ALT(alts) => alts.iter().fold(0, |size, alt| {
+            size.saturating_add(prog_size(alt)).saturating_add(2)
+        }),
+        Node::REPEAT { node, min, max, .. } => {
+            let copy = prog_size(node).max(1);
+            let min = *min as usize;
+            match max {
+                // copies, SPLIT, copy and JMP
+                None => copy.saturating_mul(min + 1).saturating_add(2),
+                // copies and a SPLIT before each optional one
+                Some(max) => copy
+                    .saturating_mul(*max as usize)
+                    .saturating_add(*max as usize - min),
+            }
+        }
+    }
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) {
+    match node {
+        Node::EMPTY => {}
+        Node::CHAR(c) => prog.push(Inst::CHAR(*c)),
+        Node::ANY => prog.push(Inst::ANY),
+        Node::CLASS(class) => prog.push(Inst::CLASS(class.clone())),
+        Node::ASSERT(assertion) => prog.push(Inst::ASSERT(*assertion)),
+        Node::GROUP(node, None) => compile(node, prog),
+        Node::GROUP(node, Some(ix)) => {
+            prog.push(Inst::SAVE(2 * ix));
+            compile(node, prog);
+            prog.push(Inst::SAVE(2 * ix + 1));
+        }
+        Node::CONCAT(nodes) => {
+            for node in nodes {
+                compile(node, prog);
+            }
+        }
+        Node::ALT(alts) => {
+            // SPLIT to each alternative, each of which jumps to the end
+            let mut jumps = Vec::new();
+            for (ix, alt) in alts.iter().enumerate() {
+                if ix + 1 < alts.len() {
+                    let split = prog.len();
+                    prog.push(Inst::SPLIT(split + 1, 0));
+                    compile(alt, prog);
+                    jumps.push(prog.len());
+                    prog.push(Inst::JMP(0));
+                    let next = prog.len();
+                    prog[split] = Inst::SPLIT(split + 1, next);
+                } else {
+                    compile(alt, prog);
+                }
+            }
+            let end = prog.len();
+            for jump in jumps {
+                prog[jump] = Inst::JMP(end);
+            }
+        }
+        Node::REPEAT {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(node, prog);
+            }
+            match max {
+                None => {
+                    // L: SPLIT(body, end); body; JMP L
+                    let split = prog.len();
+                    prog.push(Inst::SPLIT(0, 0));
+                    compile(node, prog);
+                    prog.push(Inst::JMP(split));
+                    prog[split] = split_inst(split + 1, prog.len(), *greedy);
+                }
+                Some(max) => {
+                    // optional copies: SPLIT(body, end); body; ...
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::SPLIT(0, 0));
+                        compile(node, prog);
+                    }
+                    let end = prog.len();
+                    for split in splits {
+                        prog[split] = split_inst(split + 1, end, *greedy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn split_inst(body: usize, end: usize, greedy: bool) -> Inst {
+    if greedy {
+        Inst::SPLIT(body, end)
+    } else {
+        Inst::SPLIT(end, body)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum Job {
+    RUN(usize, usize),
+    // restores the old value of a slot when backtracking
+    RESTORE(usize, Option<usize>),
+}
+
+struct Machine<'a> {
+    prog: &'a [Inst],
+    chars: &'a [char],
+    // (instruction, position) pairs that were tried already
+    visited: &'a mut [bool],
+    slots: Vec<Option<usize>>,
+}
+
+impl Machine<'_> {
+    fn run(&mut self, start: usize) -> bool {
+        let mut jobs = vec![Job::RUN(0, start)];
+        while let Some(job) = jobs.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::RUN(pc, pos) => (pc, pos),
+                Job::RESTORE(slot, val) => {
+                    self.slots[slot] = val;
+                    continue;
+                }
+            };
+            loop {
+                let key = pc * (self.chars.len() + 1) + pos;
+                if self.visited[key] {
+                    break;
+                }
+                self.visited[key] = true;
+                match &self.prog[pc] {
+                    Inst::CHAR(c) => {
+                        if self.chars.get(pos) != Some(c) {
+                            break;
+                        }
+                        pc += 1;
+                        pos += 1;
+                    }
+                    Inst::ANY => {
+                        if pos >= self.chars.len() {
+                            break;
+                        }
+                        pc += 1;
+                        pos += 1;
+                    }
+                    Inst::CLASS(class) => {
+                        if !self.chars.get(pos).is_some_and(|c| class.matches(*c)) {
+                            break;
+                        }
+                        pc += 1;
+                        pos += 1;
+                    }
+                    Inst::ASSERT(assertion) => {
+                        if !self.check(*assertion, pos) {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::SAVE(slot) => {
+                        jobs.push(Job::RESTORE(*slot, self.slots[*slot]));
+                        self.slots[*slot] = Some(pos);
+                        pc += 1;
+                    }
+                    Inst::SPLIT(first, second) => {
+                        jobs.push(Job::RUN(*second, pos));
+                        pc = *first;
+                    }
+                    Inst::JMP(target) => pc = *target,
+                    Inst::MATCH => return true,
+                }
+            }
+        }
+        false
+    }
+
+    fn check(&self, assertion: Assertion, pos: usize) -> bool {
+        let word_before = pos > 0 && is_word(self.chars[pos - 1]);
+        let word_after = self.chars.get(pos).is_some_and(|c| is_word(*c));
+        match assertion {
+            Assertion::START => pos == 0,
+            Assertion::END => pos == self.chars.len(),
+            Assertion::BOUNDARY => word_before != word_after,
+            Assertion::NONBOUNDARY => word_before == word_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, text: &str) -> Option<Vec<String>> {
+        Regex::new(pattern).unwrap().captures(text)
+    }
+
+    #[test]
+    fn match_literals_and_classes() {
+        assert_eq!(Some(vec!["bc".to_string()]), captures("bc", "abcd"));
+        assert_eq!(None, captures("^bc", "abcd"));
+        assert_eq!(Some(vec!["cd".to_string()]), captures("c.$", "abcd"));
+        assert_eq!(
+            Some(vec!["a-1".to_string()]),
+            captures("[a-c][-x]\\d", "za-1")
+        );
+        assert_eq!(
+            Some(vec!["x y".to_string()]),
+            captures("[^ab]\\s\\w", "ab x y")
+        );
+        assert_eq!(Some(vec!["]".to_string()]), captures("[]]", "a]"));
+        assert_eq!(Some(vec!["a.b".to_string()]), captures("a\\.b", "axb a.b"));
+        assert_eq!(Some(vec!["é".to_string()]), captures("\\w", "-é-"));
+        assert_eq!(
+            Some(vec!["cat".to_string()]),
+            captures("\\bcat\\b", "concat cat")
+        );
+        assert_eq!(Some(vec!["{x".to_string()]), captures("{x", "a{x"));
+    }
+
+    #[test]
+    fn match_groups() {
+        let groups = captures("(\\w+)=(\\d+)?(;)?", "key=; x=1").unwrap();
+        assert_eq!(vec!["key=;", "key", "", ";"], groups);
+
+        let regex = Regex::new("(?P<method>[A-Z]+) (?:/(?<path>\\S*)) (\\d{3})").unwrap();
+        assert_eq!(
+            vec![("method", 1), ("path", 2)],
+            regex.names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(vec![
+                "GET /a/b 200".to_string(),
+                "GET".to_string(),
+                "a/b".to_string(),
+                "200".to_string()
+            ]),
+            regex.captures("\"GET /a/b 200\"")
+        );
+    }
+
+    #[test]
+    fn match_repeats_and_alternatives() {
+        assert_eq!(Some(vec!["aaa".to_string()]), captures("a+", "baaa"));
+        assert_eq!(Some(vec!["a".to_string()]), captures("a+?", "baaa"));
+        assert_eq!(Some(vec!["<a><b>".to_string()]), captures("<.*>", "<a><b>"));
+        assert_eq!(Some(vec!["<a>".to_string()]), captures("<.*?>", "<a><b>"));
+        assert_eq!(Some(vec!["aa".to_string()]), captures("a{2}", "aaa"));
+        assert_eq!(Some(vec!["aaa".to_string()]), captures("a{2,}", "aaa"));
+        assert_eq!(Some(vec!["aa".to_string()]), captures("a{1,2}", "aaa"));
+        assert_eq!(None, captures("^a{2,3}$", "aaaa"));
+        assert_eq!(
+            Some(vec!["dog".to_string(), "dog".to_string()]),
+            captures("(cat|dog|)$", "hotdog")
+        );
+        assert_eq!(Some(vec!["".to_string()]), captures("(?:a*)*", "b"));
+        // linear time for patterns that are exponential for naive
+        // backtracking
+        let text = "a".repeat(1000);
+        assert_eq!(None, captures("(a*)*b", &text));
+    }
+
+    #[test]
+    fn match_lines() {
+        // The same regex is used for lines of different lengths.
+        let regex = Regex::new("(\\d+)$").unwrap();
+        for (text, group) in [("abc 123", "123"), ("1", "1"), ("a b c 45", "45")] {
+            assert_eq!(group, regex.captures(text).unwrap()[1]);
+        }
+        assert_eq!(None, regex.captures("123 abc"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in [
+            "(a",
+            "a)",
+            "[a",
+            "*a",
+            "a**",
+            "[z-a]",
+            "\\q",
+            "(?=a)",
+            "(?<>a)",
+            "(?<a>x)(?<a>y)",
+            "a{2,1}",
+            "a{1001}",
+            "((a{1000}){1000}){1000}",
+            "(a{100}|b{100}){100}",
+            "((){1000}){1000}",
+            "^*",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -1,7 +1,48 @@
 use crate::errors::FqError;
+use crate::json::Json;
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+/// Describes the line of the input file that a row was read from.
+pub struct RowMeta {
+    /// 1-based number of the line.
+    pub line: usize,
+    /// Number of fields on the line (0 for an empty line).
+    pub fields: usize,
+    /// Byte offset of the start of the line.
+    pub offset: usize,
+    /// The whole line, without the line terminator (empty unless the
+    /// query needs it).
+    pub text: String,
+    pub file: Rc<str>,
+    // the line parsed as JSON (`None` if it is not valid JSON); it is
+    // parsed on first use
+    json: OnceCell<Option<Json>>,
+}
+
+impl RowMeta {
+    pub fn new(line: usize, fields: usize, offset: usize, text: String, file: Rc<str>) -> RowMeta {
+        RowMeta {
+            line,
+            fields,
+            offset,
+            text,
+            file,
+            json: OnceCell::new(),
+        }
+    }
+
+    /// Returns the line parsed as JSON, or `None` if it is not valid
+    /// JSON.
+    pub fn json(&self) -> Option<&Json> {
+        self.json.get_or_init(|| Json::parse(&self.text)).as_ref()
+    }
+}
 
 pub struct Table {
     content: Vec<Vec<String>>,
+    // either empty or one entry per row
+    meta: Vec<Rc<RowMeta>>,
 }
 
 pub struct TableIterator<'a> {
@@ -14,10 +55,11 @@ impl Table {
     pub fn new() -> Table {
         Table {
             content: Vec::new(),
+            meta: Vec::new(),
         }
     }
 
-    pub fn iter(&self) -> TableIterator {
+    pub fn iter(&self) -> TableIterator<'_> {
         TableIterator {
             table: self,
             row: 0,
@@ -38,12 +80,32 @@ impl Table {
                     vec.push(String::from(""));
                 }
             }
-            Table { content }
+            Table {
+                content,
+                meta: Vec::new(),
+            }
         } else {
             Table::new()
         }
     }
 
+    /// Creates a table from the given content (see `from`) and the
+    /// metadata of the line each row was read from.
+    pub fn with_meta(content: Vec<Vec<String>>, meta: Vec<Rc<RowMeta>>) -> Result<Table, FqError> {
+        if content.len() != meta.len() {
+            return Err(FqError::exe("Incorrect number of row metadata"));
+        }
+        let mut table = Table::from(content);
+        table.meta = meta;
+        Ok(table)
+    }
+
+    /// Returns the metadata of each row, or nothing if rows were not
+    /// read from a file.
+    pub fn meta(&self) -> &[Rc<RowMeta>] {
+        &self.meta
+    }
+
     /// Returns true if this is an empty table.
     pub fn empty(&self) -> bool {
         self.nrows() == 0 && self.ncols() == 0
@@ -91,9 +153,7 @@ impl Table {
 
         if self.nrows() == 0 {
             for v in col {
-                let mut row: Vec<String> = Vec::new();
-                row.push(v);
-                self.content.push(row);
+                self.content.push(vec![v]);
             }
         } else {
             for (ix, row) in self.content.iter_mut().enumerate() {
@@ -113,9 +173,23 @@ impl Table {
         Ok(())
     }
 
+    /// Adds a row together with the metadata of the line it was read
+    /// from (if any).
+    pub fn push_row_with(
+        &mut self,
+        row: Vec<String>,
+        meta: Option<Rc<RowMeta>>,
+    ) -> Result<(), FqError> {
+        self.push_row(row)?;
+        if let Some(meta) = meta {
+            self.meta.push(meta);
+        }
+        Ok(())
+    }
+
     /// Takes columns from the other table and pushes to this table.
     pub fn push_table(&mut self, other: &Table) -> Result<(), FqError> {
-        for i in 0..=other.ncols() - 1 {
+        for i in 0..other.ncols() {
             self.push_col(other.col(i)?)?;
         }
 
@@ -129,7 +203,7 @@ impl Table {
 
     /// Returns the number of columns in the table.
     pub fn ncols(&self) -> usize {
-        if self.content.len() == 0 {
+        if self.content.is_empty() {
             0
         } else {
             self.content[0].len()
@@ -191,6 +265,38 @@ mod tests {
         assert_eq!(3, table.ncols());
     }
 
+    #[test]
+    fn with_meta() {
+        let file: Rc<str> = Rc::from("file.txt");
+        let meta = |line| {
+            Rc::new(RowMeta::new(
+                line,
+                1,
+                2 * (line - 1),
+                line.to_string(),
+                Rc::clone(&file),
+            ))
+        };
+        let table = Table::with_meta(
+            vec![vec![String::from("1")], vec![String::from("2")]],
+            vec![meta(1), meta(2)],
+        )
+        .unwrap();
+        assert_eq!(2, table.meta().len());
+        assert_eq!(2, table.meta()[1].offset);
+
+        let mut other = Table::new();
+        other
+            .push_row_with(table.row(1).unwrap(), table.meta().get(1).cloned())
+            .unwrap();
+        assert_eq!(1, other.meta().len());
+        assert_eq!(2, other.meta()[0].line);
+
+        assert!(Table::with_meta(vec![vec![String::from("1")]], vec![]).is_err());
+        let table = Table::with_meta(vec![], vec![]).unwrap();
+        assert!(table.meta().is_empty());
+    }
+
     #[test]
     fn row() {
         let vec = vec![
@@ -208,7 +314,7 @@ mod tests {
             }
         }
 
-        if let Ok(_) = table.row(10) {
+        if table.row(10).is_ok() {
             panic!("should given an error when accessing outside bounds");
         }
     }
@@ -230,7 +336,7 @@ mod tests {
             }
         }
 
-        if let Ok(_) = table.col(10) {
+        if table.col(10).is_ok() {
             panic!("should given an error when accessing outside bounds");
         }
     }
@@ -0,0 +1,305 @@
+// Unicode algorithms used by the string functions: extended grapheme
+// clusters (UAX #29), full case folding, and the canonical
+// normalization forms NFC and NFD (UAX #15). They are implemented here
+// with the data in `unicode_tables.rs`, so no external crate is needed.
+
+use crate::unicode_tables::{
+    CASE_FOLDING, COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS, GRAPHEME_CATS, INCB_EXTEND,
+    INCB_LINKERS,
+};
+use std::cmp::Ordering;
+
+/// Grapheme cluster break property of a character, with the
+/// characters that start an Indic conjunct (`Indic_Conjunct_Break=
+/// Consonant`) and extended pictographs in their own categories.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum GraphemeCat {
+    ANY,
+    CR,
+    LF,
+    CONTROL,
+    EXTEND,
+    ZWJ,
+    REGIONAL,
+    PREPEND,
+    SPACINGMARK,
+    L,
+    V,
+    T,
+    LV,
+    LVT,
+    PICTOGRAPHIC,
+    CONSONANT,
+}
+
+// Hangul syllables are (de)composed algorithmically (see section 3.12
+// of the Unicode standard).
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Compares the range of characters with the given character (for a
+/// binary search in a table of ranges).
+fn cmp_range(low: char, high: char, c: char) -> Ordering {
+    if high < c {
+        Ordering::Less
+    } else if low > c {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+fn grapheme_cat(c: char) -> GraphemeCat {
+    match GRAPHEME_CATS.binary_search_by(|(low, high, _)| cmp_range(*low, *high, c)) {
+        Ok(ix) => GRAPHEME_CATS[ix].2,
+        Err(_) => GraphemeCat::ANY,
+    }
+}
+
+fn is_incb_extend(c: char) -> bool {
+    INCB_EXTEND
+        .binary_search_by(|(low, high)| cmp_range(*low, *high, c))
+        .is_ok()
+}
+
+/// Splits the text into extended grapheme clusters, i.e., what a user
+/// perceives as characters.
+pub fn graphemes(text: &str) -> Vec<&str> {
+    use GraphemeCat::*;
+
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<GraphemeCat> = None;
+    // number of regional indicators before the current character
+    let mut regional = 0;
+    // after an extended pictograph followed by extends (GB11)
+    let mut pictograph = false;
+    // after an extended pictograph, extends and a ZWJ (GB11)
+    let mut pictograph_zwj = false;
+    // after an Indic consonant followed by linkers and extends, and
+    // whether there was a linker (GB9c)
+    let mut consonant = false;
+    let mut linker = false;
+
+    for (ix, c) in text.char_indices() {
+        let cat = grapheme_cat(c);
+        if let Some(before) = prev {
+            let boundary = match (before, cat) {
+                (CR, LF) => false,                                       // GB3
+                (CONTROL | CR | LF, _) | (_, CONTROL | CR | LF) => true, // GB4, GB5
+                (L, L | V | LV | LVT) => false,                          // GB6
+                (LV | V, V | T) => false,                                // GB7
+                (LVT | T, T) => false,                                   // GB8
+                (_, EXTEND | ZWJ | SPACINGMARK) => false,                // GB9, GB9a
+                (PREPEND, _) => false,                                   // GB9b
+                (_, CONSONANT) => !(consonant && linker),                // GB9c
+                (ZWJ, PICTOGRAPHIC) => !pictograph_zwj,                  // GB11
+                (REGIONAL, REGIONAL) => regional % 2 == 0,               // GB12, GB13
+                _ => true,                                               // GB999
+            };
+            if boundary {
+                clusters.push(&text[start..ix]);
+                start = ix;
+            }
+        }
+
+        regional = if cat == REGIONAL { regional + 1 } else { 0 };
+        pictograph_zwj = cat == ZWJ && pictograph;
+        pictograph = cat == PICTOGRAPHIC || (pictograph && cat == EXTEND);
+        if cat == CONSONANT {
+            consonant = true;
+            linker = false;
+        } else if consonant && INCB_LINKERS.contains(&c) {
+            linker = true;
+        } else if !(consonant && is_incb_extend(c)) {
+            consonant = false;
+            linker = false;
+        }
+        prev = Some(cat);
+    }
+
+    if start < text.len() {
+        clusters.push(&text[start..]);
+    }
+    clusters
+}
+
+/// Returns the full case folding of the text, which is used to compare
+/// strings without regard to case.
+pub fn case_fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match CASE_FOLDING.binary_search_by_key(&c, |(c, _)| *c) {
+            Ok(ix) => out.push_str(CASE_FOLDING[ix].1),
+            Err(_) => out.push(c),
+        }
+    }
+    out
+}
+
+fn combining_class(c: char) -> u8 {
+    match COMBINING_CLASSES.binary_search_by_key(&c, |(c, _)| *c) {
+        Ok(ix) => COMBINING_CLASSES[ix].1,
+        Err(_) => 0,
+    }
+}
+
+/// Appends the full canonical decomposition of the character.
+fn decompose(c: char, out: &mut Vec<char>) {
+    let code = c as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&code) {
+        let ix = code - S_BASE;
+        let parts = [
+            L_BASE + ix / N_COUNT,
+            V_BASE + (ix % N_COUNT) / T_COUNT,
+            T_BASE + ix % T_COUNT,
+        ];
+        let len = if ix.is_multiple_of(T_COUNT) { 2 } else { 3 };
+        out.extend(parts[..len].iter().filter_map(|part| char::from_u32(*part)));
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |(c, _)| *c) {
+        Ok(ix) => out.extend(DECOMPOSITIONS[ix].1.chars()),
+        Err(_) => out.push(c),
+    }
+}
+
+/// Returns the primary composite of the given pair of characters, if
+/// any.
+fn compose(first: char, second: char) -> Option<char> {
+    let (first_code, second_code) = (first as u32, second as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&first_code)
+        && (V_BASE..V_BASE + V_COUNT).contains(&second_code)
+    {
+        let ix = (first_code - L_BASE) * N_COUNT + (second_code - V_BASE) * T_COUNT;
+        return char::from_u32(S_BASE + ix);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&first_code)
+        && (first_code - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&second_code)
+    {
+        return char::from_u32(first_code + second_code - T_BASE);
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |(pair, _)| *pair)
+        .ok()
+        .map(|ix| COMPOSITIONS[ix].1)
+}
+
+/// Returns the canonical decomposition of the text with combining
+/// marks in the canonical order.
+fn decompose_text(text: &str) -> Vec<char> {
+    let mut chars = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        decompose(c, &mut chars);
+    }
+    // Sort each run of non-starters by combining class (the sort is
+    // stable, so marks of the same class keep their order).
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < chars.len() && combining_class(chars[end]) != 0 {
+            end += 1;
+        }
+        chars[start..end].sort_by_key(|c| combining_class(*c));
+        start = end;
+    }
+    chars
+}
+
+/// Returns the text in Normalization Form D (canonical decomposition).
+pub fn nfd(text: &str) -> String {
+    decompose_text(text).into_iter().collect()
+}
+
+/// Returns the text in Normalization Form C (canonical decomposition
+/// followed by canonical composition).
+pub fn nfc(text: &str) -> String {
+    let mut out: Vec<char> = Vec::with_capacity(text.len());
+    // index of the last starter in the output
+    let mut starter: Option<usize> = None;
+    for c in decompose_text(text) {
+        let class = combining_class(c);
+        if let Some(ix) = starter {
+            // A character is blocked from the starter by a character
+            // in between with the same or a higher combining class;
+            // marks are in the canonical order, so the last one has
+            // the highest class.
+            let blocked = ix + 1 < out.len() && combining_class(out[out.len() - 1]) >= class;
+            if !blocked {
+                if let Some(composite) = compose(out[ix], c) {
+                    out[ix] = composite;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+        }
+        out.push(c);
+    }
+    out.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_graphemes() {
+        assert!(graphemes("").is_empty());
+        assert_eq!(vec!["a", "b", "\r\n", "c"], graphemes("ab\r\nc"));
+        // combining marks, Hangul jamo, a ZWJ emoji sequence, flags
+        assert_eq!(vec!["e\u{301}", "x"], graphemes("e\u{301}x"));
+        assert_eq!(
+            vec!["\u{1100}\u{1161}\u{11a8}"],
+            graphemes("\u{1100}\u{1161}\u{11a8}")
+        );
+        assert_eq!(
+            vec!["\u{1f469}\u{200d}\u{1f4bb}", "!"],
+            graphemes("\u{1f469}\u{200d}\u{1f4bb}!")
+        );
+        assert_eq!(
+            vec!["\u{1f1e9}\u{1f1ea}", "\u{1f1eb}\u{1f1f7}", "\u{1f1ec}"],
+            graphemes("\u{1f1e9}\u{1f1ea}\u{1f1eb}\u{1f1f7}\u{1f1ec}")
+        );
+        // a Devanagari conjunct (GB9c)
+        assert_eq!(
+            vec!["\u{915}\u{94d}\u{937}"],
+            graphemes("\u{915}\u{94d}\u{937}")
+        );
+    }
+
+    #[test]
+    fn fold_case() {
+        assert_eq!("strasse", case_fold("Straße"));
+        assert_eq!("σσ", case_fold("ΣΣ"));
+        assert_eq!("123 abc", case_fold("123 ABC"));
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!("e\u{301}", nfd("\u{e9}"));
+        assert_eq!("\u{e9}", nfc("e\u{301}"));
+        // marks are reordered by combining class before composition
+        assert_eq!("\u{1e0d}\u{307}", nfc("\u{1e0b}\u{323}"));
+        assert_eq!("\u{1e0d}\u{307}", nfc("d\u{307}\u{323}"));
+        assert_eq!("\u{ac01}", nfc("\u{1100}\u{1161}\u{11a8}"));
+        assert_eq!("\u{1100}\u{1161}\u{11a8}", nfd("\u{ac01}"));
+        // singletons and composition exclusions
+        assert_eq!("\u{3a9}", nfc("\u{2126}"));
+        assert_eq!("\u{915}\u{93c}", nfc("\u{958}"));
+    }
+}
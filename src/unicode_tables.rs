@@ -0,0 +1,2844 @@
+// Unicode data used by the string functions (see `unicode.rs`). The
+// tables are generated from the Unicode Character Database 17.0.0 and
+// should not be edited by hand.
+
+use crate::unicode::GraphemeCat::{self, *};
+
+/// Ranges of characters with their grapheme cluster break property
+/// (UAX #29); characters that are not in a range are `ANY`.
+#[rustfmt::skip]
+pub const GRAPHEME_CATS: &[(char, char, GraphemeCat)] = &[
+    ('\u{0}', '\u{9}', CONTROL), ('\u{a}', '\u{a}', LF), ('\u{b}', '\u{c}', CONTROL),
+    ('\u{d}', '\u{d}', CR), ('\u{e}', '\u{1f}', CONTROL), ('\u{7f}', '\u{9f}', CONTROL),
+    ('\u{a9}', '\u{a9}', PICTOGRAPHIC), ('\u{ad}', '\u{ad}', CONTROL),
+    ('\u{ae}', '\u{ae}', PICTOGRAPHIC), ('\u{300}', '\u{36f}', EXTEND),
+    ('\u{483}', '\u{489}', EXTEND), ('\u{591}', '\u{5bd}', EXTEND),
+    ('\u{5bf}', '\u{5bf}', EXTEND), ('\u{5c1}', '\u{5c2}', EXTEND),
+    ('\u{5c4}', '\u{5c5}', EXTEND), ('\u{5c7}', '\u{5c7}', EXTEND),
+    ('\u{600}', '\u{605}', PREPEND), ('\u{610}', '\u{61a}', EXTEND),
+    ('\u{61c}', '\u{61c}', CONTROL), ('\u{64b}', '\u{65f}', EXTEND),
+    ('\u{670}', '\u{670}', EXTEND), ('\u{6d6}', '\u{6dc}', EXTEND),
+    ('\u{6dd}', '\u{6dd}', PREPEND), ('\u{6df}', '\u{6e4}', EXTEND),
+    ('\u{6e7}', '\u{6e8}', EXTEND), ('\u{6ea}', '\u{6ed}', EXTEND),
+    ('\u{70f}', '\u{70f}', PREPEND), ('\u{711}', '\u{711}', EXTEND),
+    ('\u{730}', '\u{74a}', EXTEND), ('\u{7a6}', '\u{7b0}', EXTEND),
+    ('\u{7eb}', '\u{7f3}', EXTEND), ('\u{7fd}', '\u{7fd}', EXTEND),
+    ('\u{816}', '\u{819}', EXTEND), ('\u{81b}', '\u{823}', EXTEND),
+    ('\u{825}', '\u{827}', EXTEND), ('\u{829}', '\u{82d}', EXTEND),
+    ('\u{859}', '\u{85b}', EXTEND), ('\u{890}', '\u{891}', PREPEND),
+    ('\u{897}', '\u{89f}', EXTEND), ('\u{8ca}', '\u{8e1}', EXTEND),
+    ('\u{8e2}', '\u{8e2}', PREPEND), ('\u{8e3}', '\u{902}', EXTEND),
+    ('\u{903}', '\u{903}', SPACINGMARK), ('\u{915}', '\u{939}', CONSONANT),
+    ('\u{93a}', '\u{93a}', EXTEND), ('\u{93b}', '\u{93b}', SPACINGMARK),
+    ('\u{93c}', '\u{93c}', EXTEND), ('\u{93e}', '\u{940}', SPACINGMARK),
+    ('\u{941}', '\u{948}', EXTEND), ('\u{949}', '\u{94c}', SPACINGMARK),
+    ('\u{94d}', '\u{94d}', EXTEND), ('\u{94e}', '\u{94f}', SPACINGMARK),
+    ('\u{951}', '\u{957}', EXTEND), ('\u{958}', '\u{95f}', CONSONANT),
+    ('\u{962}', '\u{963}', EXTEND), ('\u{978}', '\u{97f}', CONSONANT),
+    ('\u{981}', '\u{981}', EXTEND), ('\u{982}', '\u{983}', SPACINGMARK),
+    ('\u{995}', '\u{9a8}', CONSONANT), ('\u{9aa}', '\u{9b0}', CONSONANT),
+    ('\u{9b2}', '\u{9b2}', CONSONANT), ('\u{9b6}', '\u{9b9}', CONSONANT),
+    ('\u{9bc}', '\u{9bc}', EXTEND), ('\u{9be}', '\u{9be}', EXTEND),
+    ('\u{9bf}', '\u{9c0}', SPACINGMARK), ('\u{9c1}', '\u{9c4}', EXTEND),
+    ('\u{9c7}', '\u{9c8}', SPACINGMARK), ('\u{9cb}', '\u{9cc}', SPACINGMARK),
+    ('\u{9cd}', '\u{9cd}', EXTEND), ('\u{9d7}', '\u{9d7}', EXTEND),
+    ('\u{9dc}', '\u{9dd}', CONSONANT), ('\u{9df}', '\u{9df}', CONSONANT),
+    ('\u{9e2}', '\u{9e3}', EXTEND), ('\u{9f0}', '\u{9f1}', CONSONANT),
+    ('\u{9fe}', '\u{9fe}', EXTEND), ('\u{a01}', '\u{a02}', EXTEND),
+    ('\u{a03}', '\u{a03}', SPACINGMARK), ('\u{a3c}', '\u{a3c}', EXTEND),
+    ('\u{a3e}', '\u{a40}', SPACINGMARK), ('\u{a41}', '\u{a42}', EXTEND),
+    ('\u{a47}', '\u{a48}', EXTEND), ('\u{a4b}', '\u{a4d}', EXTEND),
+    ('\u{a51}', '\u{a51}', EXTEND), ('\u{a70}', '\u{a71}', EXTEND),
+    ('\u{a75}', '\u{a75}', EXTEND), ('\u{a81}', '\u{a82}', EXTEND),
+    ('\u{a83}', '\u{a83}', SPACINGMARK), ('\u{a95}', '\u{aa8}', CONSONANT),
+    ('\u{aaa}', '\u{ab0}', CONSONANT), ('\u{ab2}', '\u{ab3}', CONSONANT),
+    ('\u{ab5}', '\u{ab9}', CONSONANT), ('\u{abc}', '\u{abc}', EXTEND),
+    ('\u{abe}', '\u{ac0}', SPACINGMARK), ('\u{ac1}', '\u{ac5}', EXTEND),
+    ('\u{ac7}', '\u{ac8}', EXTEND), ('\u{ac9}', '\u{ac9}', SPACINGMARK),
+    ('\u{acb}', '\u{acc}', SPACINGMARK), ('\u{acd}', '\u{acd}', EXTEND),
+    ('\u{ae2}', '\u{ae3}', EXTEND), ('\u{af9}', '\u{af9}', CONSONANT),
+    ('\u{afa}', '\u{aff}', EXTEND), ('\u{b01}', '\u{b01}', EXTEND),
+    ('\u{b02}', '\u{b03}', SPACINGMARK), ('\u{b15}', '\u{b28}', CONSONANT),
+    ('\u{b2a}', '\u{b30}', CONSONANT), ('\u{b32}', '\u{b33}', CONSONANT),
+    ('\u{b35}', '\u{b39}', CONSONANT), ('\u{b3c}', '\u{b3c}', EXTEND),
+    ('\u{b3e}', '\u{b3f}', EXTEND), ('\u{b40}', '\u{b40}', SPACINGMARK),
+    ('\u{b41}', '\u{b44}', EXTEND), ('\u{b47}', '\u{b48}', SPACINGMARK),
+    ('\u{b4b}', '\u{b4c}', SPACINGMARK), ('\u{b4d}', '\u{b4d}', EXTEND),
+    ('\u{b55}', '\u{b57}', EXTEND), ('\u{b5c}', '\u{b5d}', CONSONANT),
+    ('\u{b5f}', '\u{b5f}', CONSONANT), ('\u{b62}', '\u{b63}', EXTEND),
+    ('\u{b71}', '\u{b71}', CONSONANT), ('\u{b82}', '\u{b82}', EXTEND),
+    ('\u{bbe}', '\u{bbe}', EXTEND), ('\u{bbf}', '\u{bbf}', SPACINGMARK),
+    ('\u{bc0}', '\u{bc0}', EXTEND), ('\u{bc1}', '\u{bc2}', SPACINGMARK),
+    ('\u{bc6}', '\u{bc8}', SPACINGMARK), ('\u{bca}', '\u{bcc}', SPACINGMARK),
+    ('\u{bcd}', '\u{bcd}', EXTEND), ('\u{bd7}', '\u{bd7}', EXTEND),
+    ('\u{c00}', '\u{c00}', EXTEND), ('\u{c01}', '\u{c03}', SPACINGMARK),
+    ('\u{c04}', '\u{c04}', EXTEND), ('\u{c15}', '\u{c28}', CONSONANT),
+    ('\u{c2a}', '\u{c39}', CONSONANT), ('\u{c3c}', '\u{c3c}', EXTEND),
+    ('\u{c3e}', '\u{c40}', EXTEND), ('\u{c41}', '\u{c44}', SPACINGMARK),
+    ('\u{c46}', '\u{c48}', EXTEND), ('\u{c4a}', '\u{c4d}', EXTEND),
+    ('\u{c55}', '\u{c56}', EXTEND), ('\u{c58}', '\u{c5a}', CONSONANT),
+    ('\u{c62}', '\u{c63}', EXTEND), ('\u{c81}', '\u{c81}', EXTEND),
+    ('\u{c82}', '\u{c83}', SPACINGMARK), ('\u{cbc}', '\u{cbc}', EXTEND),
+    ('\u{cbe}', '\u{cbe}', SPACINGMARK), ('\u{cbf}', '\u{cc0}', EXTEND),
+    ('\u{cc1}', '\u{cc1}', SPACINGMARK), ('\u{cc2}', '\u{cc2}', EXTEND),
+    ('\u{cc3}', '\u{cc4}', SPACINGMARK), ('\u{cc6}', '\u{cc8}', EXTEND),
+    ('\u{cca}', '\u{ccd}', EXTEND), ('\u{cd5}', '\u{cd6}', EXTEND),
+    ('\u{ce2}', '\u{ce3}', EXTEND), ('\u{cf3}', '\u{cf3}', SPACINGMARK),
+    ('\u{d00}', '\u{d01}', EXTEND), ('\u{d02}', '\u{d03}', SPACINGMARK),
+    ('\u{d15}', '\u{d3a}', CONSONANT), ('\u{d3b}', '\u{d3c}', EXTEND),
+    ('\u{d3e}', '\u{d3e}', EXTEND), ('\u{d3f}', '\u{d40}', SPACINGMARK),
+    ('\u{d41}', '\u{d44}', EXTEND), ('\u{d46}', '\u{d48}', SPACINGMARK),
+    ('\u{d4a}', '\u{d4c}', SPACINGMARK), ('\u{d4d}', '\u{d4d}', EXTEND),
+    ('\u{d4e}', '\u{d4e}', PREPEND), ('\u{d57}', '\u{d57}', EXTEND),
+    ('\u{d62}', '\u{d63}', EXTEND), ('\u{d81}', '\u{d81}', EXTEND),
+    ('\u{d82}', '\u{d83}', SPACINGMARK), ('\u{dca}', '\u{dca}', EXTEND),
+    ('\u{dcf}', '\u{dcf}', EXTEND), ('\u{dd0}', '\u{dd1}', SPACINGMARK),
+    ('\u{dd2}', '\u{dd4}', EXTEND), ('\u{dd6}', '\u{dd6}', EXTEND),
+    ('\u{dd8}', '\u{dde}', SPACINGMARK), ('\u{ddf}', '\u{ddf}', EXTEND),
+    ('\u{df2}', '\u{df3}', SPACINGMARK), ('\u{e31}', '\u{e31}', EXTEND),
+    ('\u{e33}', '\u{e33}', SPACINGMARK), ('\u{e34}', '\u{e3a}', EXTEND),
+    ('\u{e47}', '\u{e4e}', EXTEND), ('\u{eb1}', '\u{eb1}', EXTEND),
+    ('\u{eb3}', '\u{eb3}', SPACINGMARK), ('\u{eb4}', '\u{ebc}', EXTEND),
+    ('\u{ec8}', '\u{ece}', EXTEND), ('\u{f18}', '\u{f19}', EXTEND),
+    ('\u{f35}', '\u{f35}', EXTEND), ('\u{f37}', '\u{f37}', EXTEND),
+    ('\u{f39}', '\u{f39}', EXTEND), ('\u{f3e}', '\u{f3f}', SPACINGMARK),
+    ('\u{f71}', '\u{f7e}', EXTEND), ('\u{f7f}', '\u{f7f}', SPACINGMARK),
+    ('\u{f80}', '\u{f84}', EXTEND), ('\u{f86}', '\u{f87}', EXTEND),
+    ('\u{f8d}', '\u{f97}', EXTEND), ('\u{f99}', '\u{fbc}', EXTEND),
+    ('\u{fc6}', '\u{fc6}', EXTEND), ('\u{1000}', '\u{102a}', CONSONANT),
+    ('\u{102d}', '\u{1030}', EXTEND), ('\u{1031}', '\u{1031}', SPACINGMARK),
+    ('\u{1032}', '\u{1037}', EXTEND), ('\u{1039}', '\u{103a}', EXTEND),
+    ('\u{103b}', '\u{103c}', SPACINGMARK), ('\u{103d}', '\u{103e}', EXTEND),
+    ('\u{103f}', '\u{103f}', CONSONANT), ('\u{1050}', '\u{1055}', CONSONANT),
+    ('\u{1056}', '\u{1057}', SPACINGMARK), ('\u{1058}', '\u{1059}', EXTEND),
+    ('\u{105a}', '\u{105d}', CONSONANT), ('\u{105e}', '\u{1060}', EXTEND),
+    ('\u{1061}', '\u{1061}', CONSONANT), ('\u{1065}', '\u{1066}', CONSONANT),
+    ('\u{106e}', '\u{1070}', CONSONANT), ('\u{1071}', '\u{1074}', EXTEND),
+    ('\u{1075}', '\u{1081}', CONSONANT), ('\u{1082}', '\u{1082}', EXTEND),
+    ('\u{1084}', '\u{1084}', SPACINGMARK), ('\u{1085}', '\u{1086}', EXTEND),
+    ('\u{108d}', '\u{108d}', EXTEND), ('\u{108e}', '\u{108e}', CONSONANT),
+    ('\u{109d}', '\u{109d}', EXTEND), ('\u{1100}', '\u{115f}', L), ('\u{1160}', '\u{11a7}', V),
+    ('\u{11a8}', '\u{11ff}', T), ('\u{135d}', '\u{135f}', EXTEND),
+    ('\u{1712}', '\u{1715}', EXTEND), ('\u{1732}', '\u{1734}', EXTEND),
+    ('\u{1752}', '\u{1753}', EXTEND), ('\u{1772}', '\u{1773}', EXTEND),
+    ('\u{1780}', '\u{17b3}', CONSONANT), ('\u{17b4}', '\u{17b5}', EXTEND),
+    ('\u{17b6}', '\u{17b6}', SPACINGMARK), ('\u{17b7}', '\u{17bd}', EXTEND),
+    ('\u{17be}', '\u{17c5}', SPACINGMARK), ('\u{17c6}', '\u{17c6}', EXTEND),
+    ('\u{17c7}', '\u{17c8}', SPACINGMARK), ('\u{17c9}', '\u{17d3}', EXTEND),
+    ('\u{17dd}', '\u{17dd}', EXTEND), ('\u{180b}', '\u{180d}', EXTEND),
+    ('\u{180e}', '\u{180e}', CONTROL), ('\u{180f}', '\u{180f}', EXTEND),
+    ('\u{1885}', '\u{1886}', EXTEND), ('\u{18a9}', '\u{18a9}', EXTEND),
+    ('\u{1920}', '\u{1922}', EXTEND), ('\u{1923}', '\u{1926}', SPACINGMARK),
+    ('\u{1927}', '\u{1928}', EXTEND), ('\u{1929}', '\u{192b}', SPACINGMARK),
+    ('\u{1930}', '\u{1931}', SPACINGMARK), ('\u{1932}', '\u{1932}', EXTEND),
+    ('\u{1933}', '\u{1938}', SPACINGMARK), ('\u{1939}', '\u{193b}', EXTEND),
+    ('\u{1a17}', '\u{1a18}', EXTEND), ('\u{1a19}', '\u{1a1a}', SPACINGMARK),
+    ('\u{1a1b}', '\u{1a1b}', EXTEND), ('\u{1a20}', '\u{1a54}', CONSONANT),
+    ('\u{1a55}', '\u{1a55}', SPACINGMARK), ('\u{1a56}', '\u{1a56}', EXTEND),
+    ('\u{1a57}', '\u{1a57}', SPACINGMARK), ('\u{1a58}', '\u{1a5e}', EXTEND),
+    ('\u{1a60}', '\u{1a60}', EXTEND), ('\u{1a62}', '\u{1a62}', EXTEND),
+    ('\u{1a65}', '\u{1a6c}', EXTEND), ('\u{1a6d}', '\u{1a72}', SPACINGMARK),
+    ('\u{1a73}', '\u{1a7c}', EXTEND), ('\u{1a7f}', '\u{1a7f}', EXTEND),
+    ('\u{1ab0}', '\u{1add}', EXTEND), ('\u{1ae0}', '\u{1aeb}', EXTEND),
+    ('\u{1b00}', '\u{1b03}', EXTEND), ('\u{1b04}', '\u{1b04}', SPACINGMARK),
+    ('\u{1b0b}', '\u{1b0c}', CONSONANT), ('\u{1b13}', '\u{1b33}', CONSONANT),
+    ('\u{1b34}', '\u{1b3d}', EXTEND), ('\u{1b3e}', '\u{1b41}', SPACINGMARK),
+    ('\u{1b42}', '\u{1b44}', EXTEND), ('\u{1b45}', '\u{1b4c}', CONSONANT),
+    ('\u{1b6b}', '\u{1b73}', EXTEND), ('\u{1b80}', '\u{1b81}', EXTEND),
+    ('\u{1b82}', '\u{1b82}', SPACINGMARK), ('\u{1b83}', '\u{1ba0}', CONSONANT),
+    ('\u{1ba1}', '\u{1ba1}', SPACINGMARK), ('\u{1ba2}', '\u{1ba5}', EXTEND),
+    ('\u{1ba6}', '\u{1ba7}', SPACINGMARK), ('\u{1ba8}', '\u{1bad}', EXTEND),
+    ('\u{1bae}', '\u{1baf}', CONSONANT), ('\u{1bbb}', '\u{1bbd}', CONSONANT),
+    ('\u{1be6}', '\u{1be6}', EXTEND), ('\u{1be7}', '\u{1be7}', SPACINGMARK),
+    ('\u{1be8}', '\u{1be9}', EXTEND), ('\u{1bea}', '\u{1bec}', SPACINGMARK),
+    ('\u{1bed}', '\u{1bed}', EXTEND), ('\u{1bee}', '\u{1bee}', SPACINGMARK),
+    ('\u{1bef}', '\u{1bf3}', EXTEND), ('\u{1c24}', '\u{1c2b}', SPACINGMARK),
+    ('\u{1c2c}', '\u{1c33}', EXTEND), ('\u{1c34}', '\u{1c35}', SPACINGMARK),
+    ('\u{1c36}', '\u{1c37}', EXTEND), ('\u{1cd0}', '\u{1cd2}', EXTEND),
+    ('\u{1cd4}', '\u{1ce0}', EXTEND), ('\u{1ce1}', '\u{1ce1}', SPACINGMARK),
+    ('\u{1ce2}', '\u{1ce8}', EXTEND), ('\u{1ced}', '\u{1ced}', EXTEND),
+    ('\u{1cf4}', '\u{1cf4}', EXTEND), ('\u{1cf7}', '\u{1cf7}', SPACINGMARK),
+    ('\u{1cf8}', '\u{1cf9}', EXTEND), ('\u{1dc0}', '\u{1dff}', EXTEND),
+    ('\u{200b}', '\u{200b}', CONTROL), ('\u{200c}', '\u{200c}', EXTEND),
+    ('\u{200d}', '\u{200d}', ZWJ), ('\u{200e}', '\u{200f}', CONTROL),
+    ('\u{2028}', '\u{202e}', CONTROL), ('\u{203c}', '\u{203c}', PICTOGRAPHIC),
+    ('\u{2049}', '\u{2049}', PICTOGRAPHIC), ('\u{2060}', '\u{206f}', CONTROL),
+    ('\u{20d0}', '\u{20f0}', EXTEND), ('\u{2122}', '\u{2122}', PICTOGRAPHIC),
+    ('\u{2139}', '\u{2139}', PICTOGRAPHIC), ('\u{2194}', '\u{2199}', PICTOGRAPHIC),
+    ('\u{21a9}', '\u{21aa}', PICTOGRAPHIC), ('\u{231a}', '\u{231b}', PICTOGRAPHIC),
+    ('\u{2328}', '\u{2328}', PICTOGRAPHIC), ('\u{23cf}', '\u{23cf}', PICTOGRAPHIC),
+    ('\u{23e9}', '\u{23f3}', PICTOGRAPHIC), ('\u{23f8}', '\u{23fa}', PICTOGRAPHIC),
+    ('\u{24c2}', '\u{24c2}', PICTOGRAPHIC), ('\u{25aa}', '\u{25ab}', PICTOGRAPHIC),
+    ('\u{25b6}', '\u{25b6}', PICTOGRAPHIC), ('\u{25c0}', '\u{25c0}', PICTOGRAPHIC),
+    ('\u{25fb}', '\u{25fe}', PICTOGRAPHIC), ('\u{2600}', '\u{2604}', PICTOGRAPHIC),
+    ('\u{260e}', '\u{260e}', PICTOGRAPHIC), ('\u{2611}', '\u{2611}', PICTOGRAPHIC),
+    ('\u{2614}', '\u{2615}', PICTOGRAPHIC), ('\u{2618}', '\u{2618}', PICTOGRAPHIC),
+    ('\u{261d}', '\u{261d}', PICTOGRAPHIC), ('\u{2620}', '\u{2620}', PICTOGRAPHIC),
+    ('\u{2622}', '\u{2623}', PICTOGRAPHIC), ('\u{2626}', '\u{2626}', PICTOGRAPHIC),
+    ('\u{262a}', '\u{262a}', PICTOGRAPHIC), ('\u{262e}', '\u{262f}', PICTOGRAPHIC),
+    ('\u{2638}', '\u{263a}', PICTOGRAPHIC), ('\u{2640}', '\u{2640}', PICTOGRAPHIC),
+    ('\u{2642}', '\u{2642}', PICTOGRAPHIC), ('\u{2648}', '\u{2653}', PICTOGRAPHIC),
+    ('\u{265f}', '\u{2660}', PICTOGRAPHIC), ('\u{2663}', '\u{2663}', PICTOGRAPHIC),
+    ('\u{2665}', '\u{2666}', PICTOGRAPHIC), ('\u{2668}', '\u{2668}', PICTOGRAPHIC),
+    ('\u{267b}', '\u{267b}', PICTOGRAPHIC), ('\u{267e}', '\u{267f}', PICTOGRAPHIC),
+    ('\u{2692}', '\u{2697}', PICTOGRAPHIC), ('\u{2699}', '\u{2699}', PICTOGRAPHIC),
+    ('\u{269b}', '\u{269c}', PICTOGRAPHIC), ('\u{26a0}', '\u{26a1}', PICTOGRAPHIC),
+    ('\u{26a7}', '\u{26a7}', PICTOGRAPHIC), ('\u{26aa}', '\u{26ab}', PICTOGRAPHIC),
+    ('\u{26b0}', '\u{26b1}', PICTOGRAPHIC), ('\u{26bd}', '\u{26be}', PICTOGRAPHIC),
+    ('\u{26c4}', '\u{26c5}', PICTOGRAPHIC), ('\u{26c8}', '\u{26c8}', PICTOGRAPHIC),
+    ('\u{26ce}', '\u{26cf}', PICTOGRAPHIC), ('\u{26d1}', '\u{26d1}', PICTOGRAPHIC),
+    ('\u{26d3}', '\u{26d4}', PICTOGRAPHIC), ('\u{26e9}', '\u{26ea}', PICTOGRAPHIC),
+    ('\u{26f0}', '\u{26f5}', PICTOGRAPHIC), ('\u{26f7}', '\u{26fa}', PICTOGRAPHIC),
+    ('\u{26fd}', '\u{26fd}', PICTOGRAPHIC), ('\u{2702}', '\u{2702}', PICTOGRAPHIC),
+    ('\u{2705}', '\u{2705}', PICTOGRAPHIC), ('\u{2708}', '\u{270d}', PICTOGRAPHIC),
+    ('\u{270f}', '\u{270f}', PICTOGRAPHIC), ('\u{2712}', '\u{2712}', PICTOGRAPHIC),
+    ('\u{2714}', '\u{2714}', PICTOGRAPHIC), ('\u{2716}', '\u{2716}', PICTOGRAPHIC),
+    ('\u{271d}', '\u{271d}', PICTOGRAPHIC), ('\u{2721}', '\u{2721}', PICTOGRAPHIC),
+    ('\u{2728}', '\u{2728}', PICTOGRAPHIC), ('\u{2733}', '\u{2734}', PICTOGRAPHIC),
+    ('\u{2744}', '\u{2744}', PICTOGRAPHIC), ('\u{2747}', '\u{2747}', PICTOGRAPHIC),
+    ('\u{274c}', '\u{274c}', PICTOGRAPHIC), ('\u{274e}', '\u{274e}', PICTOGRAPHIC),
+    ('\u{2753}', '\u{2755}', PICTOGRAPHIC), ('\u{2757}', '\u{2757}', PICTOGRAPHIC),
+    ('\u{2763}', '\u{2764}', PICTOGRAPHIC), ('\u{2795}', '\u{2797}', PICTOGRAPHIC),
+    ('\u{27a1}', '\u{27a1}', PICTOGRAPHIC), ('\u{27b0}', '\u{27b0}', PICTOGRAPHIC),
+    ('\u{27bf}', '\u{27bf}', PICTOGRAPHIC), ('\u{2934}', '\u{2935}', PICTOGRAPHIC),
+    ('\u{2b05}', '\u{2b07}', PICTOGRAPHIC), ('\u{2b1b}', '\u{2b1c}', PICTOGRAPHIC),
+    ('\u{2b50}', '\u{2b50}', PICTOGRAPHIC), ('\u{2b55}', '\u{2b55}', PICTOGRAPHIC),
+    ('\u{2cef}', '\u{2cf1}', EXTEND), ('\u{2d7f}', '\u{2d7f}', EXTEND),
+    ('\u{2de0}', '\u{2dff}', EXTEND), ('\u{302a}', '\u{302f}', EXTEND),
+    ('\u{3030}', '\u{3030}', PICTOGRAPHIC), ('\u{303d}', '\u{303d}', PICTOGRAPHIC),
+    ('\u{3099}', '\u{309a}', EXTEND), ('\u{3297}', '\u{3297}', PICTOGRAPHIC),
+    ('\u{3299}', '\u{3299}', PICTOGRAPHIC), ('\u{a66f}', '\u{a672}', EXTEND),
+    ('\u{a674}', '\u{a67d}', EXTEND), ('\u{a69e}', '\u{a69f}', EXTEND),
+    ('\u{a6f0}', '\u{a6f1}', EXTEND), ('\u{a802}', '\u{a802}', EXTEND),
+    ('\u{a806}', '\u{a806}', EXTEND), ('\u{a80b}', '\u{a80b}', EXTEND),
+    ('\u{a823}', '\u{a824}', SPACINGMARK), ('\u{a825}', '\u{a826}', EXTEND),
+    ('\u{a827}', '\u{a827}', SPACINGMARK), ('\u{a82c}', '\u{a82c}', EXTEND),
+    ('\u{a880}', '\u{a881}', SPACINGMARK), ('\u{a8b4}', '\u{a8c3}', SPACINGMARK),
+    ('\u{a8c4}', '\u{a8c5}', EXTEND), ('\u{a8e0}', '\u{a8f1}', EXTEND),
+    ('\u{a8ff}', '\u{a8ff}', EXTEND), ('\u{a926}', '\u{a92d}', EXTEND),
+    ('\u{a947}', '\u{a951}', EXTEND), ('\u{a952}', '\u{a952}', SPACINGMARK),
+    ('\u{a953}', '\u{a953}', EXTEND), ('\u{a960}', '\u{a97c}', L),
+    ('\u{a980}', '\u{a982}', EXTEND), ('\u{a983}', '\u{a983}', SPACINGMARK),
+    ('\u{a989}', '\u{a98b}', CONSONANT), ('\u{a98f}', '\u{a9b2}', CONSONANT),
+    ('\u{a9b3}', '\u{a9b3}', EXTEND), ('\u{a9b4}', '\u{a9b5}', SPACINGMARK),
+    ('\u{a9b6}', '\u{a9b9}', EXTEND), ('\u{a9ba}', '\u{a9bb}', SPACINGMARK),
+    ('\u{a9bc}', '\u{a9bd}', EXTEND), ('\u{a9be}', '\u{a9bf}', SPACINGMARK),
+    ('\u{a9c0}', '\u{a9c0}', EXTEND), ('\u{a9e0}', '\u{a9e4}', CONSONANT),
+    ('\u{a9e5}', '\u{a9e5}', EXTEND), ('\u{a9e7}', '\u{a9ef}', CONSONANT),
+    ('\u{a9fa}', '\u{a9fe}', CONSONANT), ('\u{aa29}', '\u{aa2e}', EXTEND),
+    ('\u{aa2f}', '\u{aa30}', SPACINGMARK), ('\u{aa31}', '\u{aa32}', EXTEND),
+    ('\u{aa33}', '\u{aa34}', SPACINGMARK), ('\u{aa35}', '\u{aa36}', EXTEND),
+    ('\u{aa43}', '\u{aa43}', EXTEND), ('\u{aa4c}', '\u{aa4c}', EXTEND),
+    ('\u{aa4d}', '\u{aa4d}', SPACINGMARK), ('\u{aa60}', '\u{aa6f}', CONSONANT),
+    ('\u{aa71}', '\u{aa73}', CONSONANT), ('\u{aa7a}', '\u{aa7a}', CONSONANT),
+    ('\u{aa7c}', '\u{aa7c}', EXTEND), ('\u{aa7e}', '\u{aa7f}', CONSONANT),
+    ('\u{aab0}', '\u{aab0}', EXTEND), ('\u{aab2}', '\u{aab4}', EXTEND),
+    ('\u{aab7}', '\u{aab8}', EXTEND), ('\u{aabe}', '\u{aabf}', EXTEND),
+    ('\u{aac1}', '\u{aac1}', EXTEND), ('\u{aae0}', '\u{aaea}', CONSONANT),
+    ('\u{aaeb}', '\u{aaeb}', SPACINGMARK), ('\u{aaec}', '\u{aaed}', EXTEND),
+    ('\u{aaee}', '\u{aaef}', SPACINGMARK), ('\u{aaf5}', '\u{aaf5}', SPACINGMARK),
+    ('\u{aaf6}', '\u{aaf6}', EXTEND), ('\u{abc0}', '\u{abda}', CONSONANT),
+    ('\u{abe3}', '\u{abe4}', SPACINGMARK), ('\u{abe5}', '\u{abe5}', EXTEND),
+    ('\u{abe6}', '\u{abe7}', SPACINGMARK), ('\u{abe8}', '\u{abe8}', EXTEND),
+    ('\u{abe9}', '\u{abea}', SPACINGMARK), ('\u{abec}', '\u{abec}', SPACINGMARK),
+    ('\u{abed}', '\u{abed}', EXTEND), ('\u{ac00}', '\u{ac00}', LV), ('\u{ac01}', '\u{ac1b}', LVT),
+    ('\u{ac1c}', '\u{ac1c}', LV), ('\u{ac1d}', '\u{ac37}', LVT), ('\u{ac38}', '\u{ac38}', LV),
+    ('\u{ac39}', '\u{ac53}', LVT), ('\u{ac54}', '\u{ac54}', LV), ('\u{ac55}', '\u{ac6f}', LVT),
+    ('\u{ac70}', '\u{ac70}', LV), ('\u{ac71}', '\u{ac8b}', LVT), ('\u{ac8c}', '\u{ac8c}', LV),
+    ('\u{ac8d}', '\u{aca7}', LVT), ('\u{aca8}', '\u{aca8}', LV), ('\u{aca9}', '\u{acc3}', LVT),
+    ('\u{acc4}', '\u{acc4}', LV), ('\u{acc5}', '\u{acdf}', LVT), ('\u{ace0}', '\u{ace0}', LV),
+    ('\u{ace1}', '\u{acfb}', LVT), ('\u{acfc}', '\u{acfc}', LV), ('\u{acfd}', '\u{ad17}', LVT),
+    ('\u{ad18}', '\u{ad18}', LV), ('\u{ad19}', '\u{ad33}', LVT), ('\u{ad34}', '\u{ad34}', LV),
+    ('\u{ad35}', '\u{ad4f}', LVT), ('\u{ad50}', '\u{ad50}', LV), ('\u{ad51}', '\u{ad6b}', LVT),
+    ('\u{ad6c}', '\u{ad6c}', LV), ('\u{ad6d}', '\u{ad87}', LVT), ('\u{ad88}', '\u{ad88}', LV),
+    ('\u{ad89}', '\u{ada3}', LVT), ('\u{ada4}', '\u{ada4}', LV), ('\u{ada5}', '\u{adbf}', LVT),
+    ('\u{adc0}', '\u{adc0}', LV), ('\u{adc1}', '\u{addb}', LVT), ('\u{addc}', '\u{addc}', LV),
+    ('\u{addd}', '\u{adf7}', LVT), ('\u{adf8}', '\u{adf8}', LV), ('\u{adf9}', '\u{ae13}', LVT),
+    ('\u{ae14}', '\u{ae14}', LV), ('\u{ae15}', '\u{ae2f}', LVT), ('\u{ae30}', '\u{ae30}', LV),
+    ('\u{ae31}', '\u{ae4b}', LVT), ('\u{ae4c}', '\u{ae4c}', LV), ('\u{ae4d}', '\u{ae67}', LVT),
+    ('\u{ae68}', '\u{ae68}', LV), ('\u{ae69}', '\u{ae83}', LVT), ('\u{ae84}', '\u{ae84}', LV),
+    ('\u{ae85}', '\u{ae9f}', LVT), ('\u{aea0}', '\u{aea0}', LV), ('\u{aea1}', '\u{aebb}', LVT),
+    ('\u{aebc}', '\u{aebc}', LV), ('\u{aebd}', '\u{aed7}', LVT), ('\u{aed8}', '\u{aed8}', LV),
+    ('\u{aed9}', '\u{aef3}', LVT), ('\u{aef4}', '\u{aef4}', LV), ('\u{aef5}', '\u{af0f}', LVT),
+    ('\u{af10}', '\u{af10}', LV), ('\u{af11}', '\u{af2b}', LVT), ('\u{af2c}', '\u{af2c}', LV),
+    ('\u{af2d}', '\u{af47}', LVT), ('\u{af48}', '\u{af48}', LV), ('\u{af49}', '\u{af63}', LVT),
+    ('\u{af64}', '\u{af64}', LV), ('\u{af65}', '\u{af7f}', LVT), ('\u{af80}', '\u{af80}', LV),
+    ('\u{af81}', '\u{af9b}', LVT), ('\u{af9c}', '\u{af9c}', LV), ('\u{af9d}', '\u{afb7}', LVT),
+    ('\u{afb8}', '\u{afb8}', LV), ('\u{afb9}', '\u{afd3}', LVT), ('\u{afd4}', '\u{afd4}', LV),
+    ('\u{afd5}', '\u{afef}', LVT), ('\u{aff0}', '\u{aff0}', LV), ('\u{aff1}', '\u{b00b}', LVT),
+    ('\u{b00c}', '\u{b00c}', LV), ('\u{b00d}', '\u{b027}', LVT), ('\u{b028}', '\u{b028}', LV),
+    ('\u{b029}', '\u{b043}', LVT), ('\u{b044}', '\u{b044}', LV), ('\u{b045}', '\u{b05f}', LVT),
+    ('\u{b060}', '\u{b060}', LV), ('\u{b061}', '\u{b07b}', LVT), ('\u{b07c}', '\u{b07c}', LV),
+    ('\u{b07d}', '\u{b097}', LVT), ('\u{b098}', '\u{b098}', LV), ('\u{b099}', '\u{b0b3}', LVT),
+    ('\u{b0b4}', '\u{b0b4}', LV), ('\u{b0b5}', '\u{b0cf}', LVT), ('\u{b0d0}', '\u{b0d0}', LV),
+    ('\u{b0d1}', '\u{b0eb}', LVT), ('\u{b0ec}', '\u{b0ec}', LV), ('\u{b0ed}', '\u{b107}', LVT),
+    ('\u{b108}', '\u{b108}', LV), ('\u{b109}', '\u{b123}', LVT), ('\u{b124}', '\u{b124}', LV),
+    ('\u{b125}', '\u{b13f}', LVT), ('\u{b140}', '\u{b140}', LV), ('\u{b141}', '\u{b15b}', LVT),
+    ('\u{b15c}', '\u{b15c}', LV), ('\u{b15d}', '\u{b177}', LVT), ('\u{b178}', '\u{b178}', LV),
+    ('\u{b179}', '\u{b193}', LVT), ('\u{b194}', '\u{b194}', LV), ('\u{b195}', '\u{b1af}', LVT),
+    ('\u{b1b0}', '\u{b1b0}', LV), ('\u{b1b1}', '\u{b1cb}', LVT), ('\u{b1cc}', '\u{b1cc}', LV),
+    ('\u{b1cd}', '\u{b1e7}', LVT), ('\u{b1e8}', '\u{b1e8}', LV), ('\u{b1e9}', '\u{b203}', LVT),
+    ('\u{b204}', '\u{b204}', LV), ('\u{b205}', '\u{b21f}', LVT), ('\u{b220}', '\u{b220}', LV),
+    ('\u{b221}', '\u{b23b}', LVT), ('\u{b23c}', '\u{b23c}', LV), ('\u{b23d}', '\u{b257}', LVT),
+    ('\u{b258}', '\u{b258}', LV), ('\u{b259}', '\u{b273}', LVT), ('\u{b274}', '\u{b274}', LV),
+    ('\u{b275}', '\u{b28f}', LVT), ('\u{b290}', '\u{b290}', LV), ('\u{b291}', '\u{b2ab}', LVT),
+    ('\u{b2ac}', '\u{b2ac}', LV), ('\u{b2ad}', '\u{b2c7}', LVT), ('\u{b2c8}', '\u{b2c8}', LV),
+    ('\u{b2c9}', '\u{b2e3}', LVT), ('\u{b2e4}', '\u{b2e4}', LV), ('\u{b2e5}', '\u{b2ff}', LVT),
+    ('\u{b300}', '\u{b300}', LV), ('\u{b301}', '\u{b31b}', LVT), ('\u{b31c}', '\u{b31c}', LV),
+    ('\u{b31d}', '\u{b337}', LVT), ('\u{b338}', '\u{b338}', LV), ('\u{b339}', '\u{b353}', LVT),
+    ('\u{b354}', '\u{b354}', LV), ('\u{b355}', '\u{b36f}', LVT), ('\u{b370}', '\u{b370}', LV),
+    ('\u{b371}', '\u{b38b}', LVT), ('\u{b38c}', '\u{b38c}', LV), ('\u{b38d}', '\u{b3a7}', LVT),
+    ('\u{b3a8}', '\u{b3a8}', LV), ('\u{b3a9}', '\u{b3c3}', LVT), ('\u{b3c4}', '\u{b3c4}', LV),
+    ('\u{b3c5}', '\u{b3df}', LVT), ('\u{b3e0}', '\u{b3e0}', LV), ('\u{b3e1}', '\u{b3fb}', LVT),
+    ('\u{b3fc}', '\u{b3fc}', LV), ('\u{b3fd}', '\u{b417}', LVT), ('\u{b418}', '\u{b418}', LV),
+    ('\u{b419}', '\u{b433}', LVT), ('\u{b434}', '\u{b434}', LV), ('\u{b435}', '\u{b44f}', LVT),
+    ('\u{b450}', '\u{b450}', LV), ('\u{b451}', '\u{b46b}', LVT), ('\u{b46c}', '\u{b46c}', LV),
+    ('\u{b46d}', '\u{b487}', LVT), ('\u{b488}', '\u{b488}', LV), ('\u{b489}', '\u{b4a3}', LVT),
+    ('\u{b4a4}', '\u{b4a4}', LV), ('\u{b4a5}', '\u{b4bf}', LVT), ('\u{b4c0}', '\u{b4c0}', LV),
+    ('\u{b4c1}', '\u{b4db}', LVT), ('\u{b4dc}', '\u{b4dc}', LV), ('\u{b4dd}', '\u{b4f7}', LVT),
+    ('\u{b4f8}', '\u{b4f8}', LV), ('\u{b4f9}', '\u{b513}', LVT), ('\u{b514}', '\u{b514}', LV),
+    ('\u{b515}', '\u{b52f}', LVT), ('\u{b530}', '\u{b530}', LV), ('\u{b531}', '\u{b54b}', LVT),
+    ('\u{b54c}', '\u{b54c}', LV), ('\u{b54d}', '\u{b567}', LVT), ('\u{b568}', '\u{b568}', LV),
+    ('\u{b569}', '\u{b583}', LVT), ('\u{b584}', '\u{b584}', LV), ('\u{b585}', '\u{b59f}', LVT),
+    ('\u{b5a0}', '\u{b5a0}', LV), ('\u{b5a1}', '\u{b5bb}', LVT), ('\u{b5bc}', '\u{b5bc}', LV),
+    ('\u{b5bd}', '\u{b5d7}', LVT), ('\u{b5d8}', '\u{b5d8}', LV), ('\u{b5d9}', '\u{b5f3}', LVT),
+    ('\u{b5f4}', '\u{b5f4}', LV), ('\u{b5f5}', '\u{b60f}', LVT), ('\u{b610}', '\u{b610}', LV),
+    ('\u{b611}', '\u{b62b}', LVT), ('\u{b62c}', '\u{b62c}', LV), ('\u{b62d}', '\u{b647}', LVT),
+    ('\u{b648}', '\u{b648}', LV), ('\u{b649}', '\u{b663}', LVT), ('\u{b664}', '\u{b664}', LV),
+    ('\u{b665}', '\u{b67f}', LVT), ('\u{b680}', '\u{b680}', LV), ('\u{b681}', '\u{b69b}', LVT),
+    ('\u{b69c}', '\u{b69c}', LV), ('\u{b69d}', '\u{b6b7}', LVT), ('\u{b6b8}', '\u{b6b8}', LV),
+    ('\u{b6b9}', '\u{b6d3}', LVT), ('\u{b6d4}', '\u{b6d4}', LV), ('\u{b6d5}', '\u{b6ef}', LVT),
+    ('\u{b6f0}', '\u{b6f0}', LV), ('\u{b6f1}', '\u{b70b}', LVT), ('\u{b70c}', '\u{b70c}', LV),
+    ('\u{b70d}', '\u{b727}', LVT), ('\u{b728}', '\u{b728}', LV), ('\u{b729}', '\u{b743}', LVT),
+    ('\u{b744}', '\u{b744}', LV), ('\u{b745}', '\u{b75f}', LVT), ('\u{b760}', '\u{b760}', LV),
+    ('\u{b761}', '\u{b77b}', LVT), ('\u{b77c}', '\u{b77c}', LV), ('\u{b77d}', '\u{b797}', LVT),
+    ('\u{b798}', '\u{b798}', LV), ('\u{b799}', '\u{b7b3}', LVT), ('\u{b7b4}', '\u{b7b4}', LV),
+    ('\u{b7b5}', '\u{b7cf}', LVT), ('\u{b7d0}', '\u{b7d0}', LV), ('\u{b7d1}', '\u{b7eb}', LVT),
+    ('\u{b7ec}', '\u{b7ec}', LV), ('\u{b7ed}', '\u{b807}', LVT), ('\u{b808}', '\u{b808}', LV),
+    ('\u{b809}', '\u{b823}', LVT), ('\u{b824}', '\u{b824}', LV), ('\u{b825}', '\u{b83f}', LVT),
+    ('\u{b840}', '\u{b840}', LV), ('\u{b841}', '\u{b85b}', LVT), ('\u{b85c}', '\u{b85c}', LV),
+    ('\u{b85d}', '\u{b877}', LVT), ('\u{b878}', '\u{b878}', LV), ('\u{b879}', '\u{b893}', LVT),
+    ('\u{b894}', '\u{b894}', LV), ('\u{b895}', '\u{b8af}', LVT), ('\u{b8b0}', '\u{b8b0}', LV),
+    ('\u{b8b1}', '\u{b8cb}', LVT), ('\u{b8cc}', '\u{b8cc}', LV), ('\u{b8cd}', '\u{b8e7}', LVT),
+    ('\u{b8e8}', '\u{b8e8}', LV), ('\u{b8e9}', '\u{b903}', LVT), ('\u{b904}', '\u{b904}', LV),
+    ('\u{b905}', '\u{b91f}', LVT), ('\u{b920}', '\u{b920}', LV), ('\u{b921}', '\u{b93b}', LVT),
+    ('\u{b93c}', '\u{b93c}', LV), ('\u{b93d}', '\u{b957}', LVT), ('\u{b958}', '\u{b958}', LV),
+    ('\u{b959}', '\u{b973}', LVT), ('\u{b974}', '\u{b974}', LV), ('\u{b975}', '\u{b98f}', LVT),
+    ('\u{b990}', '\u{b990}', LV), ('\u{b991}', '\u{b9ab}', LVT), ('\u{b9ac}', '\u{b9ac}', LV),
+    ('\u{b9ad}', '\u{b9c7}', LVT), ('\u{b9c8}', '\u{b9c8}', LV), ('\u{b9c9}', '\u{b9e3}', LVT),
+    ('\u{b9e4}', '\u{b9e4}', LV), ('\u{b9e5}', '\u{b9ff}', LVT), ('\u{ba00}', '\u{ba00}', LV),
+    ('\u{ba01}', '\u{ba1b}', LVT), ('\u{ba1c}', '\u{ba1c}', LV), ('\u{ba1d}', '\u{ba37}', LVT),
+    ('\u{ba38}', '\u{ba38}', LV), ('\u{ba39}', '\u{ba53}', LVT), ('\u{ba54}', '\u{ba54}', LV),
+    ('\u{ba55}', '\u{ba6f}', LVT), ('\u{ba70}', '\u{ba70}', LV), ('\u{ba71}', '\u{ba8b}', LVT),
+    ('\u{ba8c}', '\u{ba8c}', LV), ('\u{ba8d}', '\u{baa7}', LVT), ('\u{baa8}', '\u{baa8}', LV),
+    ('\u{baa9}', '\u{bac3}', LVT), ('\u{bac4}', '\u{bac4}', LV), ('\u{bac5}', '\u{badf}', LVT),
+    ('\u{bae0}', '\u{bae0}', LV), ('\u{bae1}', '\u{bafb}', LVT), ('\u{bafc}', '\u{bafc}', LV),
+    ('\u{bafd}', '\u{bb17}', LVT), ('\u{bb18}', '\u{bb18}', LV), ('\u{bb19}', '\u{bb33}', LVT),
+    ('\u{bb34}', '\u{bb34}', LV), ('\u{bb35}', '\u{bb4f}', LVT), ('\u{bb50}', '\u{bb50}', LV),
+    ('\u{bb51}', '\u{bb6b}', LVT), ('\u{bb6c}', '\u{bb6c}', LV), ('\u{bb6d}', '\u{bb87}', LVT),
+    ('\u{bb88}', '\u{bb88}', LV), ('\u{bb89}', '\u{bba3}', LVT), ('\u{bba4}', '\u{bba4}', LV),
+    ('\u{bba5}', '\u{bbbf}', LVT), ('\u{bbc0}', '\u{bbc0}', LV), ('\u{bbc1}', '\u{bbdb}', LVT),
+    ('\u{bbdc}', '\u{bbdc}', LV), ('\u{bbdd}', '\u{bbf7}', LVT), ('\u{bbf8}', '\u{bbf8}', LV),
+    ('\u{bbf9}', '\u{bc13}', LVT), ('\u{bc14}', '\u{bc14}', LV), ('\u{bc15}', '\u{bc2f}', LVT),
+    ('\u{bc30}', '\u{bc30}', LV), ('\u{bc31}', '\u{bc4b}', LVT), ('\u{bc4c}', '\u{bc4c}', LV),
+    ('\u{bc4d}', '\u{bc67}', LVT), ('\u{bc68}', '\u{bc68}', LV), ('\u{bc69}', '\u{bc83}', LVT),
+    ('\u{bc84}', '\u{bc84}', LV), ('\u{bc85}', '\u{bc9f}', LVT), ('\u{bca0}', '\u{bca0}', LV),
+    ('\u{bca1}', '\u{bcbb}', LVT), ('\u{bcbc}', '\u{bcbc}', LV), ('\u{bcbd}', '\u{bcd7}', LVT),
+    ('\u{bcd8}', '\u{bcd8}', LV), ('\u{bcd9}', '\u{bcf3}', LVT), ('\u{bcf4}', '\u{bcf4}', LV),
+    ('\u{bcf5}', '\u{bd0f}', LVT), ('\u{bd10}', '\u{bd10}', LV), ('\u{bd11}', '\u{bd2b}', LVT),
+    ('\u{bd2c}', '\u{bd2c}', LV), ('\u{bd2d}', '\u{bd47}', LVT), ('\u{bd48}', '\u{bd48}', LV),
+    ('\u{bd49}', '\u{bd63}', LVT), ('\u{bd64}', '\u{bd64}', LV), ('\u{bd65}', '\u{bd7f}', LVT),
+    ('\u{bd80}', '\u{bd80}', LV), ('\u{bd81}', '\u{bd9b}', LVT), ('\u{bd9c}', '\u{bd9c}', LV),
+    ('\u{bd9d}', '\u{bdb7}', LVT), ('\u{bdb8}', '\u{bdb8}', LV), ('\u{bdb9}', '\u{bdd3}', LVT),
+    ('\u{bdd4}', '\u{bdd4}', LV), ('\u{bdd5}', '\u{bdef}', LVT), ('\u{bdf0}', '\u{bdf0}', LV),
+    ('\u{bdf1}', '\u{be0b}', LVT), ('\u{be0c}', '\u{be0c}', LV), ('\u{be0d}', '\u{be27}', LVT),
+    ('\u{be28}', '\u{be28}', LV), ('\u{be29}', '\u{be43}', LVT), ('\u{be44}', '\u{be44}', LV),
+    ('\u{be45}', '\u{be5f}', LVT), ('\u{be60}', '\u{be60}', LV), ('\u{be61}', '\u{be7b}', LVT),
+    ('\u{be7c}', '\u{be7c}', LV), ('\u{be7d}', '\u{be97}', LVT), ('\u{be98}', '\u{be98}', LV),
+    ('\u{be99}', '\u{beb3}', LVT), ('\u{beb4}', '\u{beb4}', LV), ('\u{beb5}', '\u{becf}', LVT),
+    ('\u{bed0}', '\u{bed0}', LV), ('\u{bed1}', '\u{beeb}', LVT), ('\u{beec}', '\u{beec}', LV),
+    ('\u{beed}', '\u{bf07}', LVT), ('\u{bf08}', '\u{bf08}', LV), ('\u{bf09}', '\u{bf23}', LVT),
+    ('\u{bf24}', '\u{bf24}', LV), ('\u{bf25}', '\u{bf3f}', LVT), ('\u{bf40}', '\u{bf40}', LV),
+    ('\u{bf41}', '\u{bf5b}', LVT), ('\u{bf5c}', '\u{bf5c}', LV), ('\u{bf5d}', '\u{bf77}', LVT),
+    ('\u{bf78}', '\u{bf78}', LV), ('\u{bf79}', '\u{bf93}', LVT), ('\u{bf94}', '\u{bf94}', LV),
+    ('\u{bf95}', '\u{bfaf}', LVT), ('\u{bfb0}', '\u{bfb0}', LV), ('\u{bfb1}', '\u{bfcb}', LVT),
+    ('\u{bfcc}', '\u{bfcc}', LV), ('\u{bfcd}', '\u{bfe7}', LVT), ('\u{bfe8}', '\u{bfe8}', LV),
+    ('\u{bfe9}', '\u{c003}', LVT), ('\u{c004}', '\u{c004}', LV), ('\u{c005}', '\u{c01f}', LVT),
+    ('\u{c020}', '\u{c020}', LV), ('\u{c021}', '\u{c03b}', LVT), ('\u{c03c}', '\u{c03c}', LV),
+    ('\u{c03d}', '\u{c057}', LVT), ('\u{c058}', '\u{c058}', LV), ('\u{c059}', '\u{c073}', LVT),
+    ('\u{c074}', '\u{c074}', LV), ('\u{c075}', '\u{c08f}', LVT), ('\u{c090}', '\u{c090}', LV),
+    ('\u{c091}', '\u{c0ab}', LVT), ('\u{c0ac}', '\u{c0ac}', LV), ('\u{c0ad}', '\u{c0c7}', LVT),
+    ('\u{c0c8}', '\u{c0c8}', LV), ('\u{c0c9}', '\u{c0e3}', LVT), ('\u{c0e4}', '\u{c0e4}', LV),
+    ('\u{c0e5}', '\u{c0ff}', LVT), ('\u{c100}', '\u{c100}', LV), ('\u{c101}', '\u{c11b}', LVT),
+    ('\u{c11c}', '\u{c11c}', LV), ('\u{c11d}', '\u{c137}', LVT), ('\u{c138}', '\u{c138}', LV),
+    ('\u{c139}', '\u{c153}', LVT), ('\u{c154}', '\u{c154}', LV), ('\u{c155}', '\u{c16f}', LVT),
+    ('\u{c170}', '\u{c170}', LV), ('\u{c171}', '\u{c18b}', LVT), ('\u{c18c}', '\u{c18c}', LV),
+    ('\u{c18d}', '\u{c1a7}', LVT), ('\u{c1a8}', '\u{c1a8}', LV), ('\u{c1a9}', '\u{c1c3}', LVT),
+    ('\u{c1c4}', '\u{c1c4}', LV), ('\u{c1c5}', '\u{c1df}', LVT), ('\u{c1e0}', '\u{c1e0}', LV),
+    ('\u{c1e1}', '\u{c1fb}', LVT), ('\u{c1fc}', '\u{c1fc}', LV), ('\u{c1fd}', '\u{c217}', LVT),
+    ('\u{c218}', '\u{c218}', LV), ('\u{c219}', '\u{c233}', LVT), ('\u{c234}', '\u{c234}', LV),
+    ('\u{c235}', '\u{c24f}', LVT), ('\u{c250}', '\u{c250}', LV), ('\u{c251}', '\u{c26b}', LVT),
+    ('\u{c26c}', '\u{c26c}', LV), ('\u{c26d}', '\u{c287}', LVT), ('\u{c288}', '\u{c288}', LV),
+    ('\u{c289}', '\u{c2a3}', LVT), ('\u{c2a4}', '\u{c2a4}', LV), ('\u{c2a5}', '\u{c2bf}', LVT),
+    ('\u{c2c0}', '\u{c2c0}', LV), ('\u{c2c1}', '\u{c2db}', LVT), ('\u{c2dc}', '\u{c2dc}', LV),
+    ('\u{c2dd}', '\u{c2f7}', LVT), ('\u{c2f8}', '\u{c2f8}', LV), ('\u{c2f9}', '\u{c313}', LVT),
+    ('\u{c314}', '\u{c314}', LV), ('\u{c315}', '\u{c32f}', LVT), ('\u{c330}', '\u{c330}', LV),
+    ('\u{c331}', '\u{c34b}', LVT), ('\u{c34c}', '\u{c34c}', LV), ('\u{c34d}', '\u{c367}', LVT),
+    ('\u{c368}', '\u{c368}', LV), ('\u{c369}', '\u{c383}', LVT), ('\u{c384}', '\u{c384}', LV),
+    ('\u{c385}', '\u{c39f}', LVT), ('\u{c3a0}', '\u{c3a0}', LV), ('\u{c3a1}', '\u{c3bb}', LVT),
+    ('\u{c3bc}', '\u{c3bc}', LV), ('\u{c3bd}', '\u{c3d7}', LVT), ('\u{c3d8}', '\u{c3d8}', LV),
+    ('\u{c3d9}', '\u{c3f3}', LVT), ('\u{c3f4}', '\u{c3f4}', LV), ('\u{c3f5}', '\u{c40f}', LVT),
+    ('\u{c410}', '\u{c410}', LV), ('\u{c411}', '\u{c42b}', LVT), ('\u{c42c}', '\u{c42c}', LV),
+    ('\u{c42d}', '\u{c447}', LVT), ('\u{c448}', '\u{c448}', LV), ('\u{c449}', '\u{c463}', LVT),
+    ('\u{c464}', '\u{c464}', LV), ('\u{c465}', '\u{c47f}', LVT), ('\u{c480}', '\u{c480}', LV),
+    ('\u{c481}', '\u{c49b}', LVT), ('\u{c49c}', '\u{c49c}', LV), ('\u{c49d}', '\u{c4b7}', LVT),
+    ('\u{c4b8}', '\u{c4b8}', LV), ('\u{c4b9}', '\u{c4d3}', LVT), ('\u{c4d4}', '\u{c4d4}', LV),
+    ('\u{c4d5}', '\u{c4ef}', LVT), ('\u{c4f0}', '\u{c4f0}', LV), ('\u{c4f1}', '\u{c50b}', LVT),
+    ('\u{c50c}', '\u{c50c}', LV), ('\u{c50d}', '\u{c527}', LVT), ('\u{c528}', '\u{c528}', LV),
+    ('\u{c529}', '\u{c543}', LVT), ('\u{c544}', '\u{c544}', LV), ('\u{c545}', '\u{c55f}', LVT),
+    ('\u{c560}', '\u{c560}', LV), ('\u{c561}', '\u{c57b}', LVT), ('\u{c57c}', '\u{c57c}', LV),
+    ('\u{c57d}', '\u{c597}', LVT), ('\u{c598}', '\u{c598}', LV), ('\u{c599}', '\u{c5b3}', LVT),
+    ('\u{c5b4}', '\u{c5b4}', LV), ('\u{c5b5}', '\u{c5cf}', LVT), ('\u{c5d0}', '\u{c5d0}', LV),
+    ('\u{c5d1}', '\u{c5eb}', LVT), ('\u{c5ec}', '\u{c5ec}', LV), ('\u{c5ed}', '\u{c607}', LVT),
+    ('\u{c608}', '\u{c608}', LV), ('\u{c609}', '\u{c623}', LVT), ('\u{c624}', '\u{c624}', LV),
+    ('\u{c625}', '\u{c63f}', LVT), ('\u{c640}', '\u{c640}', LV), ('\u{c641}', '\u{c65b}', LVT),
+    ('\u{c65c}', '\u{c65c}', LV), ('\u{c65d}', '\u{c677}', LVT), ('\u{c678}', '\u{c678}', LV),
+    ('\u{c679}', '\u{c693}', LVT), ('\u{c694}', '\u{c694}', LV), ('\u{c695}', '\u{c6af}', LVT),
+    ('\u{c6b0}', '\u{c6b0}', LV), ('\u{c6b1}', '\u{c6cb}', LVT), ('\u{c6cc}', '\u{c6cc}', LV),
+    ('\u{c6cd}', '\u{c6e7}', LVT), ('\u{c6e8}', '\u{c6e8}', LV), ('\u{c6e9}', '\u{c703}', LVT),
+    ('\u{c704}', '\u{c704}', LV), ('\u{c705}', '\u{c71f}', LVT), ('\u{c720}', '\u{c720}', LV),
+    ('\u{c721}', '\u{c73b}', LVT), ('\u{c73c}', '\u{c73c}', LV), ('\u{c73d}', '\u{c757}', LVT),
+    ('\u{c758}', '\u{c758}', LV), ('\u{c759}', '\u{c773}', LVT), ('\u{c774}', '\u{c774}', LV),
+    ('\u{c775}', '\u{c78f}', LVT), ('\u{c790}', '\u{c790}', LV), ('\u{c791}', '\u{c7ab}', LVT),
+    ('\u{c7ac}', '\u{c7ac}', LV), ('\u{c7ad}', '\u{c7c7}', LVT), ('\u{c7c8}', '\u{c7c8}', LV),
+    ('\u{c7c9}', '\u{c7e3}', LVT), ('\u{c7e4}', '\u{c7e4}', LV), ('\u{c7e5}', '\u{c7ff}', LVT),
+    ('\u{c800}', '\u{c800}', LV), ('\u{c801}', '\u{c81b}', LVT), ('\u{c81c}', '\u{c81c}', LV),
+    ('\u{c81d}', '\u{c837}', LVT), ('\u{c838}', '\u{c838}', LV), ('\u{c839}', '\u{c853}', LVT),
+    ('\u{c854}', '\u{c854}', LV), ('\u{c855}', '\u{c86f}', LVT), ('\u{c870}', '\u{c870}', LV),
+    ('\u{c871}', '\u{c88b}', LVT), ('\u{c88c}', '\u{c88c}', LV), ('\u{c88d}', '\u{c8a7}', LVT),
+    ('\u{c8a8}', '\u{c8a8}', LV), ('\u{c8a9}', '\u{c8c3}', LVT), ('\u{c8c4}', '\u{c8c4}', LV),
+    ('\u{c8c5}', '\u{c8df}', LVT), ('\u{c8e0}', '\u{c8e0}', LV), ('\u{c8e1}', '\u{c8fb}', LVT),
+    ('\u{c8fc}', '\u{c8fc}', LV), ('\u{c8fd}', '\u{c917}', LVT), ('\u{c918}', '\u{c918}', LV),
+    ('\u{c919}', '\u{c933}', LVT), ('\u{c934}', '\u{c934}', LV), ('\u{c935}', '\u{c94f}', LVT),
+    ('\u{c950}', '\u{c950}', LV), ('\u{c951}', '\u{c96b}', LVT), ('\u{c96c}', '\u{c96c}', LV),
+    ('\u{c96d}', '\u{c987}', LVT), ('\u{c988}', '\u{c988}', LV), ('\u{c989}', '\u{c9a3}', LVT),
+    ('\u{c9a4}', '\u{c9a4}', LV), ('\u{c9a5}', '\u{c9bf}', LVT), ('\u{c9c0}', '\u{c9c0}', LV),
+    ('\u{c9c1}', '\u{c9db}', LVT), ('\u{c9dc}', '\u{c9dc}', LV), ('\u{c9dd}', '\u{c9f7}', LVT),
+    ('\u{c9f8}', '\u{c9f8}', LV), ('\u{c9f9}', '\u{ca13}', LVT), ('\u{ca14}', '\u{ca14}', LV),
+    ('\u{ca15}', '\u{ca2f}', LVT), ('\u{ca30}', '\u{ca30}', LV), ('\u{ca31}', '\u{ca4b}', LVT),
+    ('\u{ca4c}', '\u{ca4c}', LV), ('\u{ca4d}', '\u{ca67}', LVT), ('\u{ca68}', '\u{ca68}', LV),
+    ('\u{ca69}', '\u{ca83}', LVT), ('\u{ca84}', '\u{ca84}', LV), ('\u{ca85}', '\u{ca9f}', LVT),
+    ('\u{caa0}', '\u{caa0}', LV), ('\u{caa1}', '\u{cabb}', LVT), ('\u{cabc}', '\u{cabc}', LV),
+    ('\u{cabd}', '\u{cad7}', LVT), ('\u{cad8}', '\u{cad8}', LV), ('\u{cad9}', '\u{caf3}', LVT),
+    ('\u{caf4}', '\u{caf4}', LV), ('\u{caf5}', '\u{cb0f}', LVT), ('\u{cb10}', '\u{cb10}', LV),
+    ('\u{cb11}', '\u{cb2b}', LVT), ('\u{cb2c}', '\u{cb2c}', LV), ('\u{cb2d}', '\u{cb47}', LVT),
+    ('\u{cb48}', '\u{cb48}', LV), ('\u{cb49}', '\u{cb63}', LVT), ('\u{cb64}', '\u{cb64}', LV),
+    ('\u{cb65}', '\u{cb7f}', LVT), ('\u{cb80}', '\u{cb80}', LV), ('\u{cb81}', '\u{cb9b}', LVT),
+    ('\u{cb9c}', '\u{cb9c}', LV), ('\u{cb9d}', '\u{cbb7}', LVT), ('\u{cbb8}', '\u{cbb8}', LV),
+    ('\u{cbb9}', '\u{cbd3}', LVT), ('\u{cbd4}', '\u{cbd4}', LV), ('\u{cbd5}', '\u{cbef}', LVT),
+    ('\u{cbf0}', '\u{cbf0}', LV), ('\u{cbf1}', '\u{cc0b}', LVT), ('\u{cc0c}', '\u{cc0c}', LV),
+    ('\u{cc0d}', '\u{cc27}', LVT), ('\u{cc28}', '\u{cc28}', LV), ('\u{cc29}', '\u{cc43}', LVT),
+    ('\u{cc44}', '\u{cc44}', LV), ('\u{cc45}', '\u{cc5f}', LVT), ('\u{cc60}', '\u{cc60}', LV),
+    ('\u{cc61}', '\u{cc7b}', LVT), ('\u{cc7c}', '\u{cc7c}', LV), ('\u{cc7d}', '\u{cc97}', LVT),
+    ('\u{cc98}', '\u{cc98}', LV), ('\u{cc99}', '\u{ccb3}', LVT), ('\u{ccb4}', '\u{ccb4}', LV),
+    ('\u{ccb5}', '\u{cccf}', LVT), ('\u{ccd0}', '\u{ccd0}', LV), ('\u{ccd1}', '\u{cceb}', LVT),
+    ('\u{ccec}', '\u{ccec}', LV), ('\u{cced}', '\u{cd07}', LVT), ('\u{cd08}', '\u{cd08}', LV),
+    ('\u{cd09}', '\u{cd23}', LVT), ('\u{cd24}', '\u{cd24}', LV), ('\u{cd25}', '\u{cd3f}', LVT),
+    ('\u{cd40}', '\u{cd40}', LV), ('\u{cd41}', '\u{cd5b}', LVT), ('\u{cd5c}', '\u{cd5c}', LV),
+    ('\u{cd5d}', '\u{cd77}', LVT), ('\u{cd78}', '\u{cd78}', LV), ('\u{cd79}', '\u{cd93}', LVT),
+    ('\u{cd94}', '\u{cd94}', LV), ('\u{cd95}', '\u{cdaf}', LVT), ('\u{cdb0}', '\u{cdb0}', LV),
+    ('\u{cdb1}', '\u{cdcb}', LVT), ('\u{cdcc}', '\u{cdcc}', LV), ('\u{cdcd}', '\u{cde7}', LVT),
+    ('\u{cde8}', '\u{cde8}', LV), ('\u{cde9}', '\u{ce03}', LVT), ('\u{ce04}', '\u{ce04}', LV),
+    ('\u{ce05}', '\u{ce1f}', LVT), ('\u{ce20}', '\u{ce20}', LV), ('\u{ce21}', '\u{ce3b}', LVT),
+    ('\u{ce3c}', '\u{ce3c}', LV), ('\u{ce3d}', '\u{ce57}', LVT), ('\u{ce58}', '\u{ce58}', LV),
+    ('\u{ce59}', '\u{ce73}', LVT), ('\u{ce74}', '\u{ce74}', LV), ('\u{ce75}', '\u{ce8f}', LVT),
+    ('\u{ce90}', '\u{ce90}', LV), ('\u{ce91}', '\u{ceab}', LVT), ('\u{ceac}', '\u{ceac}', LV),
+    ('\u{cead}', '\u{cec7}', LVT), ('\u{cec8}', '\u{cec8}', LV), ('\u{cec9}', '\u{cee3}', LVT),
+    ('\u{cee4}', '\u{cee4}', LV), ('\u{cee5}', '\u{ceff}', LVT), ('\u{cf00}', '\u{cf00}', LV),
+    ('\u{cf01}', '\u{cf1b}', LVT), ('\u{cf1c}', '\u{cf1c}', LV), ('\u{cf1d}', '\u{cf37}', LVT),
+    ('\u{cf38}', '\u{cf38}', LV), ('\u{cf39}', '\u{cf53}', LVT), ('\u{cf54}', '\u{cf54}', LV),
+    ('\u{cf55}', '\u{cf6f}', LVT), ('\u{cf70}', '\u{cf70}', LV), ('\u{cf71}', '\u{cf8b}', LVT),
+    ('\u{cf8c}', '\u{cf8c}', LV), ('\u{cf8d}', '\u{cfa7}', LVT), ('\u{cfa8}', '\u{cfa8}', LV),
+    ('\u{cfa9}', '\u{cfc3}', LVT), ('\u{cfc4}', '\u{cfc4}', LV), ('\u{cfc5}', '\u{cfdf}', LVT),
+    ('\u{cfe0}', '\u{cfe0}', LV), ('\u{cfe1}', '\u{cffb}', LVT), ('\u{cffc}', '\u{cffc}', LV),
+    ('\u{cffd}', '\u{d017}', LVT), ('\u{d018}', '\u{d018}', LV), ('\u{d019}', '\u{d033}', LVT),
+    ('\u{d034}', '\u{d034}', LV), ('\u{d035}', '\u{d04f}', LVT), ('\u{d050}', '\u{d050}', LV),
+    ('\u{d051}', '\u{d06b}', LVT), ('\u{d06c}', '\u{d06c}', LV), ('\u{d06d}', '\u{d087}', LVT),
+    ('\u{d088}', '\u{d088}', LV), ('\u{d089}', '\u{d0a3}', LVT), ('\u{d0a4}', '\u{d0a4}', LV),
+    ('\u{d0a5}', '\u{d0bf}', LVT), ('\u{d0c0}', '\u{d0c0}', LV), ('\u{d0c1}', '\u{d0db}', LVT),
+    ('\u{d0dc}', '\u{d0dc}', LV), ('\u{d0dd}', '\u{d0f7}', LVT), ('\u{d0f8}', '\u{d0f8}', LV),
+    ('\u{d0f9}', '\u{d113}', LVT), ('\u{d114}', '\u{d114}', LV), ('\u{d115}', '\u{d12f}', LVT),
+    ('\u{d130}', '\u{d130}', LV), ('\u{d131}', '\u{d14b}', LVT), ('\u{d14c}', '\u{d14c}', LV),
+    ('\u{d14d}', '\u{d167}', LVT), ('\u{d168}', '\u{d168}', LV), ('\u{d169}', '\u{d183}', LVT),
+    ('\u{d184}', '\u{d184}', LV), ('\u{d185}', '\u{d19f}', LVT), ('\u{d1a0}', '\u{d1a0}', LV),
+    ('\u{d1a1}', '\u{d1bb}', LVT), ('\u{d1bc}', '\u{d1bc}', LV), ('\u{d1bd}', '\u{d1d7}', LVT),
+    ('\u{d1d8}', '\u{d1d8}', LV), ('\u{d1d9}', '\u{d1f3}', LVT), ('\u{d1f4}', '\u{d1f4}', LV),
+    ('\u{d1f5}', '\u{d20f}', LVT), ('\u{d210}', '\u{d210}', LV), ('\u{d211}', '\u{d22b}', LVT),
+    ('\u{d22c}', '\u{d22c}', LV), ('\u{d22d}', '\u{d247}', LVT), ('\u{d248}', '\u{d248}', LV),
+    ('\u{d249}', '\u{d263}', LVT), ('\u{d264}', '\u{d264}', LV), ('\u{d265}', '\u{d27f}', LVT),
+    ('\u{d280}', '\u{d280}', LV), ('\u{d281}', '\u{d29b}', LVT), ('\u{d29c}', '\u{d29c}', LV),
+    ('\u{d29d}', '\u{d2b7}', LVT), ('\u{d2b8}', '\u{d2b8}', LV), ('\u{d2b9}', '\u{d2d3}', LVT),
+    ('\u{d2d4}', '\u{d2d4}', LV), ('\u{d2d5}', '\u{d2ef}', LVT), ('\u{d2f0}', '\u{d2f0}', LV),
+    ('\u{d2f1}', '\u{d30b}', LVT), ('\u{d30c}', '\u{d30c}', LV), ('\u{d30d}', '\u{d327}', LVT),
+    ('\u{d328}', '\u{d328}', LV), ('\u{d329}', '\u{d343}', LVT), ('\u{d344}', '\u{d344}', LV),
+    ('\u{d345}', '\u{d35f}', LVT), ('\u{d360}', '\u{d360}', LV), ('\u{d361}', '\u{d37b}', LVT),
+    ('\u{d37c}', '\u{d37c}', LV), ('\u{d37d}', '\u{d397}', LVT), ('\u{d398}', '\u{d398}', LV),
+    ('\u{d399}', '\u{d3b3}', LVT), ('\u{d3b4}', '\u{d3b4}', LV), ('\u{d3b5}', '\u{d3cf}', LVT),
+    ('\u{d3d0}', '\u{d3d0}', LV), ('\u{d3d1}', '\u{d3eb}', LVT), ('\u{d3ec}', '\u{d3ec}', LV),
+    ('\u{d3ed}', '\u{d407}', LVT), ('\u{d408}', '\u{d408}', LV), ('\u{d409}', '\u{d423}', LVT),
+    ('\u{d424}', '\u{d424}', LV), ('\u{d425}', '\u{d43f}', LVT), ('\u{d440}', '\u{d440}', LV),
+    ('\u{d441}', '\u{d45b}', LVT), ('\u{d45c}', '\u{d45c}', LV), ('\u{d45d}', '\u{d477}', LVT),
+    ('\u{d478}', '\u{d478}', LV), ('\u{d479}', '\u{d493}', LVT), ('\u{d494}', '\u{d494}', LV),
+    ('\u{d495}', '\u{d4af}', LVT), ('\u{d4b0}', '\u{d4b0}', LV), ('\u{d4b1}', '\u{d4cb}', LVT),
+    ('\u{d4cc}', '\u{d4cc}', LV), ('\u{d4cd}', '\u{d4e7}', LVT), ('\u{d4e8}', '\u{d4e8}', LV),
+    ('\u{d4e9}', '\u{d503}', LVT), ('\u{d504}', '\u{d504}', LV), ('\u{d505}', '\u{d51f}', LVT),
+    ('\u{d520}', '\u{d520}', LV), ('\u{d521}', '\u{d53b}', LVT), ('\u{d53c}', '\u{d53c}', LV),
+    ('\u{d53d}', '\u{d557}', LVT), ('\u{d558}', '\u{d558}', LV), ('\u{d559}', '\u{d573}', LVT),
+    ('\u{d574}', '\u{d574}', LV), ('\u{d575}', '\u{d58f}', LVT), ('\u{d590}', '\u{d590}', LV),
+    ('\u{d591}', '\u{d5ab}', LVT), ('\u{d5ac}', '\u{d5ac}', LV), ('\u{d5ad}', '\u{d5c7}', LVT),
+    ('\u{d5c8}', '\u{d5c8}', LV), ('\u{d5c9}', '\u{d5e3}', LVT), ('\u{d5e4}', '\u{d5e4}', LV),
+    ('\u{d5e5}', '\u{d5ff}', LVT), ('\u{d600}', '\u{d600}', LV), ('\u{d601}', '\u{d61b}', LVT),
+    ('\u{d61c}', '\u{d61c}', LV), ('\u{d61d}', '\u{d637}', LVT), ('\u{d638}', '\u{d638}', LV),
+    ('\u{d639}', '\u{d653}', LVT), ('\u{d654}', '\u{d654}', LV), ('\u{d655}', '\u{d66f}', LVT),
+    ('\u{d670}', '\u{d670}', LV), ('\u{d671}', '\u{d68b}', LVT), ('\u{d68c}', '\u{d68c}', LV),
+    ('\u{d68d}', '\u{d6a7}', LVT), ('\u{d6a8}', '\u{d6a8}', LV), ('\u{d6a9}', '\u{d6c3}', LVT),
+    ('\u{d6c4}', '\u{d6c4}', LV), ('\u{d6c5}', '\u{d6df}', LVT), ('\u{d6e0}', '\u{d6e0}', LV),
+    ('\u{d6e1}', '\u{d6fb}', LVT), ('\u{d6fc}', '\u{d6fc}', LV), ('\u{d6fd}', '\u{d717}', LVT),
+    ('\u{d718}', '\u{d718}', LV), ('\u{d719}', '\u{d733}', LVT), ('\u{d734}', '\u{d734}', LV),
+    ('\u{d735}', '\u{d74f}', LVT), ('\u{d750}', '\u{d750}', LV), ('\u{d751}', '\u{d76b}', LVT),
+    ('\u{d76c}', '\u{d76c}', LV), ('\u{d76d}', '\u{d787}', LVT), ('\u{d788}', '\u{d788}', LV),
+    ('\u{d789}', '\u{d7a3}', LVT), ('\u{d7b0}', '\u{d7c6}', V), ('\u{d7cb}', '\u{d7fb}', T),
+    ('\u{fb1e}', '\u{fb1e}', EXTEND), ('\u{fe00}', '\u{fe0f}', EXTEND),
+    ('\u{fe20}', '\u{fe2f}', EXTEND), ('\u{feff}', '\u{feff}', CONTROL),
+    ('\u{ff9e}', '\u{ff9f}', EXTEND), ('\u{fff0}', '\u{fffb}', CONTROL),
+    ('\u{101fd}', '\u{101fd}', EXTEND), ('\u{102e0}', '\u{102e0}', EXTEND),
+    ('\u{10376}', '\u{1037a}', EXTEND), ('\u{10a00}', '\u{10a00}', CONSONANT),
+    ('\u{10a01}', '\u{10a03}', EXTEND), ('\u{10a05}', '\u{10a06}', EXTEND),
+    ('\u{10a0c}', '\u{10a0f}', EXTEND), ('\u{10a10}', '\u{10a13}', CONSONANT),
+    ('\u{10a15}', '\u{10a17}', CONSONANT), ('\u{10a19}', '\u{10a35}', CONSONANT),
+    ('\u{10a38}', '\u{10a3a}', EXTEND), ('\u{10a3f}', '\u{10a3f}', EXTEND),
+    ('\u{10ae5}', '\u{10ae6}', EXTEND), ('\u{10d24}', '\u{10d27}', EXTEND),
+    ('\u{10d69}', '\u{10d6d}', EXTEND), ('\u{10eab}', '\u{10eac}', EXTEND),
+    ('\u{10efa}', '\u{10eff}', EXTEND), ('\u{10f46}', '\u{10f50}', EXTEND),
+    ('\u{10f82}', '\u{10f85}', EXTEND), ('\u{11000}', '\u{11000}', SPACINGMARK),
+    ('\u{11001}', '\u{11001}', EXTEND), ('\u{11002}', '\u{11002}', SPACINGMARK),
+    ('\u{11038}', '\u{11046}', EXTEND), ('\u{11070}', '\u{11070}', EXTEND),
+    ('\u{11073}', '\u{11074}', EXTEND), ('\u{1107f}', '\u{11081}', EXTEND),
+    ('\u{11082}', '\u{11082}', SPACINGMARK), ('\u{110b0}', '\u{110b2}', SPACINGMARK),
+    ('\u{110b3}', '\u{110b6}', EXTEND), ('\u{110b7}', '\u{110b8}', SPACINGMARK),
+    ('\u{110b9}', '\u{110ba}', EXTEND), ('\u{110bd}', '\u{110bd}', PREPEND),
+    ('\u{110c2}', '\u{110c2}', EXTEND), ('\u{110cd}', '\u{110cd}', PREPEND),
+    ('\u{11100}', '\u{11102}', EXTEND), ('\u{11103}', '\u{11126}', CONSONANT),
+    ('\u{11127}', '\u{1112b}', EXTEND), ('\u{1112c}', '\u{1112c}', SPACINGMARK),
+    ('\u{1112d}', '\u{11134}', EXTEND), ('\u{11144}', '\u{11144}', CONSONANT),
+    ('\u{11145}', '\u{11146}', SPACINGMARK), ('\u{11147}', '\u{11147}', CONSONANT),
+    ('\u{11173}', '\u{11173}', EXTEND), ('\u{11180}', '\u{11181}', EXTEND),
+    ('\u{11182}', '\u{11182}', SPACINGMARK), ('\u{111b3}', '\u{111b5}', SPACINGMARK),
+    ('\u{111b6}', '\u{111be}', EXTEND), ('\u{111bf}', '\u{111bf}', SPACINGMARK),
+    ('\u{111c0}', '\u{111c0}', EXTEND), ('\u{111c2}', '\u{111c3}', PREPEND),
+    ('\u{111c9}', '\u{111cc}', EXTEND), ('\u{111ce}', '\u{111ce}', SPACINGMARK),
+    ('\u{111cf}', '\u{111cf}', EXTEND), ('\u{1122c}', '\u{1122e}', SPACINGMARK),
+    ('\u{1122f}', '\u{11231}', EXTEND), ('\u{11232}', '\u{11233}', SPACINGMARK),
+    ('\u{11234}', '\u{11237}', EXTEND), ('\u{1123e}', '\u{1123e}', EXTEND),
+    ('\u{11241}', '\u{11241}', EXTEND), ('\u{112df}', '\u{112df}', EXTEND),
+    ('\u{112e0}', '\u{112e2}', SPACINGMARK), ('\u{112e3}', '\u{112ea}', EXTEND),
+    ('\u{11300}', '\u{11301}', EXTEND), ('\u{11302}', '\u{11303}', SPACINGMARK),
+    ('\u{1133b}', '\u{1133c}', EXTEND), ('\u{1133e}', '\u{1133e}', EXTEND),
+    ('\u{1133f}', '\u{1133f}', SPACINGMARK), ('\u{11340}', '\u{11340}', EXTEND),
+    ('\u{11341}', '\u{11344}', SPACINGMARK), ('\u{11347}', '\u{11348}', SPACINGMARK),
+    ('\u{1134b}', '\u{1134c}', SPACINGMARK), ('\u{1134d}', '\u{1134d}', EXTEND),
+    ('\u{11357}', '\u{11357}', EXTEND), ('\u{11362}', '\u{11363}', SPACINGMARK),
+    ('\u{11366}', '\u{1136c}', EXTEND), ('\u{11370}', '\u{11374}', EXTEND),
+    ('\u{11380}', '\u{11389}', CONSONANT), ('\u{1138b}', '\u{1138b}', CONSONANT),
+    ('\u{1138e}', '\u{1138e}', CONSONANT), ('\u{11390}', '\u{113b5}', CONSONANT),
+    ('\u{113b8}', '\u{113b8}', EXTEND), ('\u{113b9}', '\u{113ba}', SPACINGMARK),
+    ('\u{113bb}', '\u{113c0}', EXTEND), ('\u{113c2}', '\u{113c2}', EXTEND),
+    ('\u{113c5}', '\u{113c5}', EXTEND), ('\u{113c7}', '\u{113c9}', EXTEND),
+    ('\u{113ca}', '\u{113ca}', SPACINGMARK), ('\u{113cc}', '\u{113cd}', SPACINGMARK),
+    ('\u{113ce}', '\u{113d0}', EXTEND), ('\u{113d1}', '\u{113d1}', PREPEND),
+    ('\u{113d2}', '\u{113d2}', EXTEND), ('\u{113e1}', '\u{113e2}', EXTEND),
+    ('\u{11435}', '\u{11437}', SPACINGMARK), ('\u{11438}', '\u{1143f}', EXTEND),
+    ('\u{11440}', '\u{11441}', SPACINGMARK), ('\u{11442}', '\u{11444}', EXTEND),
+    ('\u{11445}', '\u{11445}', SPACINGMARK), ('\u{11446}', '\u{11446}', EXTEND),
+    ('\u{1145e}', '\u{1145e}', EXTEND), ('\u{114b0}', '\u{114b0}', EXTEND),
+    ('\u{114b1}', '\u{114b2}', SPACINGMARK), ('\u{114b3}', '\u{114b8}', EXTEND),
+    ('\u{114b9}', '\u{114b9}', SPACINGMARK), ('\u{114ba}', '\u{114ba}', EXTEND),
+    ('\u{114bb}', '\u{114bc}', SPACINGMARK), ('\u{114bd}', '\u{114bd}', EXTEND),
+    ('\u{114be}', '\u{114be}', SPACINGMARK), ('\u{114bf}', '\u{114c0}', EXTEND),
+    ('\u{114c1}', '\u{114c1}', SPACINGMARK), ('\u{114c2}', '\u{114c3}', EXTEND),
+    ('\u{115af}', '\u{115af}', EXTEND), ('\u{115b0}', '\u{115b1}', SPACINGMARK),
+    ('\u{115b2}', '\u{115b5}', EXTEND), ('\u{115b8}', '\u{115bb}', SPACINGMARK),
+    ('\u{115bc}', '\u{115bd}', EXTEND), ('\u{115be}', '\u{115be}', SPACINGMARK),
+    ('\u{115bf}', '\u{115c0}', EXTEND), ('\u{115dc}', '\u{115dd}', EXTEND),
+    ('\u{11630}', '\u{11632}', SPACINGMARK), ('\u{11633}', '\u{1163a}', EXTEND),
+    ('\u{1163b}', '\u{1163c}', SPACINGMARK), ('\u{1163d}', '\u{1163d}', EXTEND),
+    ('\u{1163e}', '\u{1163e}', SPACINGMARK), ('\u{1163f}', '\u{11640}', EXTEND),
+    ('\u{116ab}', '\u{116ab}', EXTEND), ('\u{116ac}', '\u{116ac}', SPACINGMARK),
+    ('\u{116ad}', '\u{116ad}', EXTEND), ('\u{116ae}', '\u{116af}', SPACINGMARK),
+    ('\u{116b0}', '\u{116b7}', EXTEND), ('\u{1171d}', '\u{1171d}', EXTEND),
+    ('\u{1171e}', '\u{1171e}', SPACINGMARK), ('\u{1171f}', '\u{1171f}', EXTEND),
+    ('\u{11722}', '\u{11725}', EXTEND), ('\u{11726}', '\u{11726}', SPACINGMARK),
+    ('\u{11727}', '\u{1172b}', EXTEND), ('\u{1182c}', '\u{1182e}', SPACINGMARK),
+    ('\u{1182f}', '\u{11837}', EXTEND), ('\u{11838}', '\u{11838}', SPACINGMARK),
+    ('\u{11839}', '\u{1183a}', EXTEND), ('\u{11900}', '\u{11906}', CONSONANT),
+    ('\u{11909}', '\u{11909}', CONSONANT), ('\u{1190c}', '\u{11913}', CONSONANT),
+    ('\u{11915}', '\u{11916}', CONSONANT), ('\u{11918}', '\u{1192f}', CONSONANT),
+    ('\u{11930}', '\u{11930}', EXTEND), ('\u{11931}', '\u{11935}', SPACINGMARK),
+    ('\u{11937}', '\u{11938}', SPACINGMARK), ('\u{1193b}', '\u{1193e}', EXTEND),
+    ('\u{1193f}', '\u{1193f}', PREPEND), ('\u{11940}', '\u{11940}', SPACINGMARK),
+    ('\u{11941}', '\u{11941}', PREPEND), ('\u{11942}', '\u{11942}', SPACINGMARK),
+    ('\u{11943}', '\u{11943}', EXTEND), ('\u{119d1}', '\u{119d3}', SPACINGMARK),
+    ('\u{119d4}', '\u{119d7}', EXTEND), ('\u{119da}', '\u{119db}', EXTEND),
+    ('\u{119dc}', '\u{119df}', SPACINGMARK), ('\u{119e0}', '\u{119e0}', EXTEND),
+    ('\u{119e4}', '\u{119e4}', SPACINGMARK), ('\u{11a00}', '\u{11a00}', CONSONANT),
+    ('\u{11a01}', '\u{11a0a}', EXTEND), ('\u{11a0b}', '\u{11a32}', CONSONANT),
+    ('\u{11a33}', '\u{11a38}', EXTEND), ('\u{11a39}', '\u{11a39}', SPACINGMARK),
+    ('\u{11a3b}', '\u{11a3e}', EXTEND), ('\u{11a47}', '\u{11a47}', EXTEND),
+    ('\u{11a50}', '\u{11a50}', CONSONANT), ('\u{11a51}', '\u{11a56}', EXTEND),
+    ('\u{11a57}', '\u{11a58}', SPACINGMARK), ('\u{11a59}', '\u{11a5b}', EXTEND),
+    ('\u{11a5c}', '\u{11a83}', CONSONANT), ('\u{11a84}', '\u{11a89}', PREPEND),
+    ('\u{11a8a}', '\u{11a96}', EXTEND), ('\u{11a97}', '\u{11a97}', SPACINGMARK),
+    ('\u{11a98}', '\u{11a99}', EXTEND), ('\u{11b60}', '\u{11b60}', EXTEND),
+    ('\u{11b61}', '\u{11b61}', SPACINGMARK), ('\u{11b62}', '\u{11b64}', EXTEND),
+    ('\u{11b65}', '\u{11b65}', SPACINGMARK), ('\u{11b66}', '\u{11b66}', EXTEND),
+    ('\u{11b67}', '\u{11b67}', SPACINGMARK), ('\u{11c2f}', '\u{11c2f}', SPACINGMARK),
+    ('\u{11c30}', '\u{11c36}', EXTEND), ('\u{11c38}', '\u{11c3d}', EXTEND),
+    ('\u{11c3e}', '\u{11c3e}', SPACINGMARK), ('\u{11c3f}', '\u{11c3f}', EXTEND),
+    ('\u{11c92}', '\u{11ca7}', EXTEND), ('\u{11ca9}', '\u{11ca9}', SPACINGMARK),
+    ('\u{11caa}', '\u{11cb0}', EXTEND), ('\u{11cb1}', '\u{11cb1}', SPACINGMARK),
+    ('\u{11cb2}', '\u{11cb3}', EXTEND), ('\u{11cb4}', '\u{11cb4}', SPACINGMARK),
+    ('\u{11cb5}', '\u{11cb6}', EXTEND), ('\u{11d31}', '\u{11d36}', EXTEND),
+    ('\u{11d3a}', '\u{11d3a}', EXTEND), ('\u{11d3c}', '\u{11d3d}', EXTEND),
+    ('\u{11d3f}', '\u{11d45}', EXTEND), ('\u{11d46}', '\u{11d46}', PREPEND),
+    ('\u{11d47}', '\u{11d47}', EXTEND), ('\u{11d8a}', '\u{11d8e}', SPACINGMARK),
+    ('\u{11d90}', '\u{11d91}', EXTEND), ('\u{11d93}', '\u{11d94}', SPACINGMARK),
+    ('\u{11d95}', '\u{11d95}', EXTEND), ('\u{11d96}', '\u{11d96}', SPACINGMARK),
+    ('\u{11d97}', '\u{11d97}', EXTEND), ('\u{11ef3}', '\u{11ef4}', EXTEND),
+    ('\u{11ef5}', '\u{11ef6}', SPACINGMARK), ('\u{11f00}', '\u{11f01}', EXTEND),
+    ('\u{11f02}', '\u{11f02}', PREPEND), ('\u{11f03}', '\u{11f03}', SPACINGMARK),
+    ('\u{11f04}', '\u{11f10}', CONSONANT), ('\u{11f12}', '\u{11f33}', CONSONANT),
+    ('\u{11f34}', '\u{11f35}', SPACINGMARK), ('\u{11f36}', '\u{11f3a}', EXTEND),
+    ('\u{11f3e}', '\u{11f3f}', SPACINGMARK), ('\u{11f40}', '\u{11f42}', EXTEND),
+    ('\u{11f5a}', '\u{11f5a}', EXTEND), ('\u{13430}', '\u{1343f}', CONTROL),
+    ('\u{13440}', '\u{13440}', EXTEND), ('\u{13447}', '\u{13455}', EXTEND),
+    ('\u{1611e}', '\u{16129}', EXTEND), ('\u{1612a}', '\u{1612c}', SPACINGMARK),
+    ('\u{1612d}', '\u{1612f}', EXTEND), ('\u{16af0}', '\u{16af4}', EXTEND),
+    ('\u{16b30}', '\u{16b36}', EXTEND), ('\u{16d63}', '\u{16d63}', V),
+    ('\u{16d67}', '\u{16d6a}', V), ('\u{16f4f}', '\u{16f4f}', EXTEND),
+    ('\u{16f51}', '\u{16f87}', SPACINGMARK), ('\u{16f8f}', '\u{16f92}', EXTEND),
+    ('\u{16fe4}', '\u{16fe4}', EXTEND), ('\u{16ff0}', '\u{16ff1}', EXTEND),
+    ('\u{1bc9d}', '\u{1bc9e}', EXTEND), ('\u{1bca0}', '\u{1bca3}', CONTROL),
+    ('\u{1cf00}', '\u{1cf2d}', EXTEND), ('\u{1cf30}', '\u{1cf46}', EXTEND),
+    ('\u{1d165}', '\u{1d169}', EXTEND), ('\u{1d16d}', '\u{1d172}', EXTEND),
+    ('\u{1d173}', '\u{1d17a}', CONTROL), ('\u{1d17b}', '\u{1d182}', EXTEND),
+    ('\u{1d185}', '\u{1d18b}', EXTEND), ('\u{1d1aa}', '\u{1d1ad}', EXTEND),
+    ('\u{1d242}', '\u{1d244}', EXTEND), ('\u{1da00}', '\u{1da36}', EXTEND),
+    ('\u{1da3b}', '\u{1da6c}', EXTEND), ('\u{1da75}', '\u{1da75}', EXTEND),
+    ('\u{1da84}', '\u{1da84}', EXTEND), ('\u{1da9b}', '\u{1da9f}', EXTEND),
+    ('\u{1daa1}', '\u{1daaf}', EXTEND), ('\u{1e000}', '\u{1e006}', EXTEND),
+    ('\u{1e008}', '\u{1e018}', EXTEND), ('\u{1e01b}', '\u{1e021}', EXTEND),
+    ('\u{1e023}', '\u{1e024}', EXTEND), ('\u{1e026}', '\u{1e02a}', EXTEND),
+    ('\u{1e08f}', '\u{1e08f}', EXTEND), ('\u{1e130}', '\u{1e136}', EXTEND),
+    ('\u{1e2ae}', '\u{1e2ae}', EXTEND), ('\u{1e2ec}', '\u{1e2ef}', EXTEND),
+    ('\u{1e4ec}', '\u{1e4ef}', EXTEND), ('\u{1e5ee}', '\u{1e5ef}', EXTEND),
+    ('\u{1e6e3}', '\u{1e6e3}', EXTEND), ('\u{1e6e6}', '\u{1e6e6}', EXTEND),
+    ('\u{1e6ee}', '\u{1e6ef}', EXTEND), ('\u{1e6f5}', '\u{1e6f5}', EXTEND),
+    ('\u{1e8d0}', '\u{1e8d6}', EXTEND), ('\u{1e944}', '\u{1e94a}', EXTEND),
+    ('\u{1f004}', '\u{1f004}', PICTOGRAPHIC), ('\u{1f02c}', '\u{1f02f}', PICTOGRAPHIC),
+    ('\u{1f094}', '\u{1f09f}', PICTOGRAPHIC), ('\u{1f0af}', '\u{1f0b0}', PICTOGRAPHIC),
+    ('\u{1f0c0}', '\u{1f0c0}', PICTOGRAPHIC), ('\u{1f0cf}', '\u{1f0d0}', PICTOGRAPHIC),
+    ('\u{1f0f6}', '\u{1f0ff}', PICTOGRAPHIC), ('\u{1f170}', '\u{1f171}', PICTOGRAPHIC),
+    ('\u{1f17e}', '\u{1f17f}', PICTOGRAPHIC), ('\u{1f18e}', '\u{1f18e}', PICTOGRAPHIC),
+    ('\u{1f191}', '\u{1f19a}', PICTOGRAPHIC), ('\u{1f1ae}', '\u{1f1e5}', PICTOGRAPHIC),
+    ('\u{1f1e6}', '\u{1f1ff}', REGIONAL), ('\u{1f201}', '\u{1f20f}', PICTOGRAPHIC),
+    ('\u{1f21a}', '\u{1f21a}', PICTOGRAPHIC), ('\u{1f22f}', '\u{1f22f}', PICTOGRAPHIC),
+    ('\u{1f232}', '\u{1f23a}', PICTOGRAPHIC), ('\u{1f23c}', '\u{1f23f}', PICTOGRAPHIC),
+    ('\u{1f249}', '\u{1f25f}', PICTOGRAPHIC), ('\u{1f266}', '\u{1f321}', PICTOGRAPHIC),
+    ('\u{1f324}', '\u{1f393}', PICTOGRAPHIC), ('\u{1f396}', '\u{1f397}', PICTOGRAPHIC),
+    ('\u{1f399}', '\u{1f39b}', PICTOGRAPHIC), ('\u{1f39e}', '\u{1f3f0}', PICTOGRAPHIC),
+    ('\u{1f3f3}', '\u{1f3f5}', PICTOGRAPHIC), ('\u{1f3f7}', '\u{1f3fa}', PICTOGRAPHIC),
+    ('\u{1f3fb}', '\u{1f3ff}', EXTEND), ('\u{1f400}', '\u{1f4fd}', PICTOGRAPHIC),
+    ('\u{1f4ff}', '\u{1f53d}', PICTOGRAPHIC), ('\u{1f549}', '\u{1f54e}', PICTOGRAPHIC),
+    ('\u{1f550}', '\u{1f567}', PICTOGRAPHIC), ('\u{1f56f}', '\u{1f570}', PICTOGRAPHIC),
+    ('\u{1f573}', '\u{1f57a}', PICTOGRAPHIC), ('\u{1f587}', '\u{1f587}', PICTOGRAPHIC),
+    ('\u{1f58a}', '\u{1f58d}', PICTOGRAPHIC), ('\u{1f590}', '\u{1f590}', PICTOGRAPHIC),
+    ('\u{1f595}', '\u{1f596}', PICTOGRAPHIC), ('\u{1f5a4}', '\u{1f5a5}', PICTOGRAPHIC),
+    ('\u{1f5a8}', '\u{1f5a8}', PICTOGRAPHIC), ('\u{1f5b1}', '\u{1f5b2}', PICTOGRAPHIC),
+    ('\u{1f5bc}', '\u{1f5bc}', PICTOGRAPHIC), ('\u{1f5c2}', '\u{1f5c4}', PICTOGRAPHIC),
+    ('\u{1f5d1}', '\u{1f5d3}', PICTOGRAPHIC), ('\u{1f5dc}', '\u{1f5de}', PICTOGRAPHIC),
+    ('\u{1f5e1}', '\u{1f5e1}', PICTOGRAPHIC), ('\u{1f5e3}', '\u{1f5e3}', PICTOGRAPHIC),
+    ('\u{1f5e8}', '\u{1f5e8}', PICTOGRAPHIC), ('\u{1f5ef}', '\u{1f5ef}', PICTOGRAPHIC),
+    ('\u{1f5f3}', '\u{1f5f3}', PICTOGRAPHIC), ('\u{1f5fa}', '\u{1f64f}', PICTOGRAPHIC),
+    ('\u{1f680}', '\u{1f6c5}', PICTOGRAPHIC), ('\u{1f6cb}', '\u{1f6d2}', PICTOGRAPHIC),
+    ('\u{1f6d5}', '\u{1f6e5}', PICTOGRAPHIC), ('\u{1f6e9}', '\u{1f6e9}', PICTOGRAPHIC),
+    ('\u{1f6eb}', '\u{1f6f0}', PICTOGRAPHIC), ('\u{1f6f3}', '\u{1f6ff}', PICTOGRAPHIC),
+    ('\u{1f7da}', '\u{1f7ff}', PICTOGRAPHIC), ('\u{1f80c}', '\u{1f80f}', PICTOGRAPHIC),
+    ('\u{1f848}', '\u{1f84f}', PICTOGRAPHIC), ('\u{1f85a}', '\u{1f85f}', PICTOGRAPHIC),
+    ('\u{1f888}', '\u{1f88f}', PICTOGRAPHIC), ('\u{1f8ae}', '\u{1f8af}', PICTOGRAPHIC),
+    ('\u{1f8bc}', '\u{1f8bf}', PICTOGRAPHIC), ('\u{1f8c2}', '\u{1f8cf}', PICTOGRAPHIC),
+    ('\u{1f8d9}', '\u{1f8ff}', PICTOGRAPHIC), ('\u{1f90c}', '\u{1f93a}', PICTOGRAPHIC),
+    ('\u{1f93c}', '\u{1f945}', PICTOGRAPHIC), ('\u{1f947}', '\u{1f9ff}', PICTOGRAPHIC),
+    ('\u{1fa58}', '\u{1fa5f}', PICTOGRAPHIC), ('\u{1fa6e}', '\u{1faff}', PICTOGRAPHIC),
+    ('\u{1fc00}', '\u{1fffd}', PICTOGRAPHIC), ('\u{e0000}', '\u{e001f}', CONTROL),
+    ('\u{e0020}', '\u{e007f}', EXTEND), ('\u{e0080}', '\u{e00ff}', CONTROL),
+    ('\u{e0100}', '\u{e01ef}', EXTEND), ('\u{e01f0}', '\u{e0fff}', CONTROL),
+];
+
+/// Ranges of characters with `Indic_Conjunct_Break=Extend`.
+#[rustfmt::skip]
+pub const INCB_EXTEND: &[(char, char)] = &[
+    ('\u{300}', '\u{36f}'), ('\u{483}', '\u{489}'), ('\u{591}', '\u{5bd}'),
+    ('\u{5bf}', '\u{5bf}'), ('\u{5c1}', '\u{5c2}'), ('\u{5c4}', '\u{5c5}'),
+    ('\u{5c7}', '\u{5c7}'), ('\u{610}', '\u{61a}'), ('\u{64b}', '\u{65f}'),
+    ('\u{670}', '\u{670}'), ('\u{6d6}', '\u{6dc}'), ('\u{6df}', '\u{6e4}'),
+    ('\u{6e7}', '\u{6e8}'), ('\u{6ea}', '\u{6ed}'), ('\u{711}', '\u{711}'),
+    ('\u{730}', '\u{74a}'), ('\u{7a6}', '\u{7b0}'), ('\u{7eb}', '\u{7f3}'),
+    ('\u{7fd}', '\u{7fd}'), ('\u{816}', '\u{819}'), ('\u{81b}', '\u{823}'),
+    ('\u{825}', '\u{827}'), ('\u{829}', '\u{82d}'), ('\u{859}', '\u{85b}'),
+    ('\u{897}', '\u{89f}'), ('\u{8ca}', '\u{8e1}'), ('\u{8e3}', '\u{902}'),
+    ('\u{93a}', '\u{93a}'), ('\u{93c}', '\u{93c}'), ('\u{941}', '\u{948}'),
+    ('\u{951}', '\u{957}'), ('\u{962}', '\u{963}'), ('\u{981}', '\u{981}'),
+    ('\u{9bc}', '\u{9bc}'), ('\u{9be}', '\u{9be}'), ('\u{9c1}', '\u{9c4}'),
+    ('\u{9d7}', '\u{9d7}'), ('\u{9e2}', '\u{9e3}'), ('\u{9fe}', '\u{9fe}'),
+    ('\u{a01}', '\u{a02}'), ('\u{a3c}', '\u{a3c}'), ('\u{a41}', '\u{a42}'),
+    ('\u{a47}', '\u{a48}'), ('\u{a4b}', '\u{a4d}'), ('\u{a51}', '\u{a51}'),
+    ('\u{a70}', '\u{a71}'), ('\u{a75}', '\u{a75}'), ('\u{a81}', '\u{a82}'),
+    ('\u{abc}', '\u{abc}'), ('\u{ac1}', '\u{ac5}'), ('\u{ac7}', '\u{ac8}'),
+    ('\u{ae2}', '\u{ae3}'), ('\u{afa}', '\u{aff}'), ('\u{b01}', '\u{b01}'),
+    ('\u{b3c}', '\u{b3c}'), ('\u{b3e}', '\u{b3f}'), ('\u{b41}', '\u{b44}'),
+    ('\u{b55}', '\u{b57}'), ('\u{b62}', '\u{b63}'), ('\u{b82}', '\u{b82}'),
+    ('\u{bbe}', '\u{bbe}'), ('\u{bc0}', '\u{bc0}'), ('\u{bcd}', '\u{bcd}'),
+    ('\u{bd7}', '\u{bd7}'), ('\u{c00}', '\u{c00}'), ('\u{c04}', '\u{c04}'),
+    ('\u{c3c}', '\u{c3c}'), ('\u{c3e}', '\u{c40}'), ('\u{c46}', '\u{c48}'),
+    ('\u{c4a}', '\u{c4c}'), ('\u{c55}', '\u{c56}'), ('\u{c62}', '\u{c63}'),
+    ('\u{c81}', '\u{c81}'), ('\u{cbc}', '\u{cbc}'), ('\u{cbf}', '\u{cc0}'),
+    ('\u{cc2}', '\u{cc2}'), ('\u{cc6}', '\u{cc8}'), ('\u{cca}', '\u{ccd}'),
+    ('\u{cd5}', '\u{cd6}'), ('\u{ce2}', '\u{ce3}'), ('\u{d00}', '\u{d01}'),
+    ('\u{d3b}', '\u{d3c}'), ('\u{d3e}', '\u{d3e}'), ('\u{d41}', '\u{d44}'),
+    ('\u{d57}', '\u{d57}'), ('\u{d62}', '\u{d63}'), ('\u{d81}', '\u{d81}'),
+    ('\u{dca}', '\u{dca}'), ('\u{dcf}', '\u{dcf}'), ('\u{dd2}', '\u{dd4}'),
+    ('\u{dd6}', '\u{dd6}'), ('\u{ddf}', '\u{ddf}'), ('\u{e31}', '\u{e31}'),
+    ('\u{e34}', '\u{e3a}'), ('\u{e47}', '\u{e4e}'), ('\u{eb1}', '\u{eb1}'),
+    ('\u{eb4}', '\u{ebc}'), ('\u{ec8}', '\u{ece}'), ('\u{f18}', '\u{f19}'),
+    ('\u{f35}', '\u{f35}'), ('\u{f37}', '\u{f37}'), ('\u{f39}', '\u{f39}'),
+    ('\u{f71}', '\u{f7e}'), ('\u{f80}', '\u{f84}'), ('\u{f86}', '\u{f87}'),
+    ('\u{f8d}', '\u{f97}'), ('\u{f99}', '\u{fbc}'), ('\u{fc6}', '\u{fc6}'),
+    ('\u{102d}', '\u{1030}'), ('\u{1032}', '\u{1037}'), ('\u{103a}', '\u{103a}'),
+    ('\u{103d}', '\u{103e}'), ('\u{1058}', '\u{1059}'), ('\u{105e}', '\u{1060}'),
+    ('\u{1071}', '\u{1074}'), ('\u{1082}', '\u{1082}'), ('\u{1085}', '\u{1086}'),
+    ('\u{108d}', '\u{108d}'), ('\u{109d}', '\u{109d}'), ('\u{135d}', '\u{135f}'),
+    ('\u{1712}', '\u{1715}'), ('\u{1732}', '\u{1734}'), ('\u{1752}', '\u{1753}'),
+    ('\u{1772}', '\u{1773}'), ('\u{17b4}', '\u{17b5}'), ('\u{17b7}', '\u{17bd}'),
+    ('\u{17c6}', '\u{17c6}'), ('\u{17c9}', '\u{17d1}'), ('\u{17d3}', '\u{17d3}'),
+    ('\u{17dd}', '\u{17dd}'), ('\u{180b}', '\u{180d}'), ('\u{180f}', '\u{180f}'),
+    ('\u{1885}', '\u{1886}'), ('\u{18a9}', '\u{18a9}'), ('\u{1920}', '\u{1922}'),
+    ('\u{1927}', '\u{1928}'), ('\u{1932}', '\u{1932}'), ('\u{1939}', '\u{193b}'),
+    ('\u{1a17}', '\u{1a18}'), ('\u{1a1b}', '\u{1a1b}'), ('\u{1a56}', '\u{1a56}'),
+    ('\u{1a58}', '\u{1a5e}'), ('\u{1a62}', '\u{1a62}'), ('\u{1a65}', '\u{1a6c}'),
+    ('\u{1a73}', '\u{1a7c}'), ('\u{1a7f}', '\u{1a7f}'), ('\u{1ab0}', '\u{1add}'),
+    ('\u{1ae0}', '\u{1aeb}'), ('\u{1b00}', '\u{1b03}'), ('\u{1b34}', '\u{1b3d}'),
+    ('\u{1b42}', '\u{1b43}'), ('\u{1b6b}', '\u{1b73}'), ('\u{1b80}', '\u{1b81}'),
+    ('\u{1ba2}', '\u{1ba5}'), ('\u{1ba8}', '\u{1baa}'), ('\u{1bac}', '\u{1bad}'),
+    ('\u{1be6}', '\u{1be6}'), ('\u{1be8}', '\u{1be9}'), ('\u{1bed}', '\u{1bed}'),
+    ('\u{1bef}', '\u{1bf3}'), ('\u{1c2c}', '\u{1c33}'), ('\u{1c36}', '\u{1c37}'),
+    ('\u{1cd0}', '\u{1cd2}'), ('\u{1cd4}', '\u{1ce0}'), ('\u{1ce2}', '\u{1ce8}'),
+    ('\u{1ced}', '\u{1ced}'), ('\u{1cf4}', '\u{1cf4}'), ('\u{1cf8}', '\u{1cf9}'),
+    ('\u{1dc0}', '\u{1dff}'), ('\u{200d}', '\u{200d}'), ('\u{20d0}', '\u{20f0}'),
+    ('\u{2cef}', '\u{2cf1}'), ('\u{2d7f}', '\u{2d7f}'), ('\u{2de0}', '\u{2dff}'),
+    ('\u{302a}', '\u{302f}'), ('\u{3099}', '\u{309a}'), ('\u{a66f}', '\u{a672}'),
+    ('\u{a674}', '\u{a67d}'), ('\u{a69e}', '\u{a69f}'), ('\u{a6f0}', '\u{a6f1}'),
+    ('\u{a802}', '\u{a802}'), ('\u{a806}', '\u{a806}'), ('\u{a80b}', '\u{a80b}'),
+    ('\u{a825}', '\u{a826}'), ('\u{a82c}', '\u{a82c}'), ('\u{a8c4}', '\u{a8c5}'),
+    ('\u{a8e0}', '\u{a8f1}'), ('\u{a8ff}', '\u{a8ff}'), ('\u{a926}', '\u{a92d}'),
+    ('\u{a947}', '\u{a951}'), ('\u{a953}', '\u{a953}'), ('\u{a980}', '\u{a982}'),
+    ('\u{a9b3}', '\u{a9b3}'), ('\u{a9b6}', '\u{a9b9}'), ('\u{a9bc}', '\u{a9bd}'),
+    ('\u{a9e5}', '\u{a9e5}'), ('\u{aa29}', '\u{aa2e}'), ('\u{aa31}', '\u{aa32}'),
+    ('\u{aa35}', '\u{aa36}'), ('\u{aa43}', '\u{aa43}'), ('\u{aa4c}', '\u{aa4c}'),
+    ('\u{aa7c}', '\u{aa7c}'), ('\u{aab0}', '\u{aab0}'), ('\u{aab2}', '\u{aab4}'),
+    ('\u{aab7}', '\u{aab8}'), ('\u{aabe}', '\u{aabf}'), ('\u{aac1}', '\u{aac1}'),
+    ('\u{aaec}', '\u{aaed}'), ('\u{abe5}', '\u{abe5}'), ('\u{abe8}', '\u{abe8}'),
+    ('\u{abed}', '\u{abed}'), ('\u{fb1e}', '\u{fb1e}'), ('\u{fe00}', '\u{fe0f}'),
+    ('\u{fe20}', '\u{fe2f}'), ('\u{ff9e}', '\u{ff9f}'), ('\u{101fd}', '\u{101fd}'),
+    ('\u{102e0}', '\u{102e0}'), ('\u{10376}', '\u{1037a}'), ('\u{10a01}', '\u{10a03}'),
+    ('\u{10a05}', '\u{10a06}'), ('\u{10a0c}', '\u{10a0f}'), ('\u{10a38}', '\u{10a3a}'),
+    ('\u{10ae5}', '\u{10ae6}'), ('\u{10d24}', '\u{10d27}'), ('\u{10d69}', '\u{10d6d}'),
+    ('\u{10eab}', '\u{10eac}'), ('\u{10efa}', '\u{10eff}'), ('\u{10f46}', '\u{10f50}'),
+    ('\u{10f82}', '\u{10f85}'), ('\u{11001}', '\u{11001}'), ('\u{11038}', '\u{11046}'),
+    ('\u{11070}', '\u{11070}'), ('\u{11073}', '\u{11074}'), ('\u{1107f}', '\u{11081}'),
+    ('\u{110b3}', '\u{110b6}'), ('\u{110b9}', '\u{110ba}'), ('\u{110c2}', '\u{110c2}'),
+    ('\u{11100}', '\u{11102}'), ('\u{11127}', '\u{1112b}'), ('\u{1112d}', '\u{11132}'),
+    ('\u{11134}', '\u{11134}'), ('\u{11173}', '\u{11173}'), ('\u{11180}', '\u{11181}'),
+    ('\u{111b6}', '\u{111be}'), ('\u{111c0}', '\u{111c0}'), ('\u{111c9}', '\u{111cc}'),
+    ('\u{111cf}', '\u{111cf}'), ('\u{1122f}', '\u{11231}'), ('\u{11234}', '\u{11237}'),
+    ('\u{1123e}', '\u{1123e}'), ('\u{11241}', '\u{11241}'), ('\u{112df}', '\u{112df}'),
+    ('\u{112e3}', '\u{112ea}'), ('\u{11300}', '\u{11301}'), ('\u{1133b}', '\u{1133c}'),
+    ('\u{1133e}', '\u{1133e}'), ('\u{11340}', '\u{11340}'), ('\u{1134d}', '\u{1134d}'),
+    ('\u{11357}', '\u{11357}'), ('\u{11366}', '\u{1136c}'), ('\u{11370}', '\u{11374}'),
+    ('\u{113b8}', '\u{113b8}'), ('\u{113bb}', '\u{113c0}'), ('\u{113c2}', '\u{113c2}'),
+    ('\u{113c5}', '\u{113c5}'), ('\u{113c7}', '\u{113c9}'), ('\u{113ce}', '\u{113cf}'),
+    ('\u{113d2}', '\u{113d2}'), ('\u{113e1}', '\u{113e2}'), ('\u{11438}', '\u{1143f}'),
+    ('\u{11442}', '\u{11444}'), ('\u{11446}', '\u{11446}'), ('\u{1145e}', '\u{1145e}'),
+    ('\u{114b0}', '\u{114b0}'), ('\u{114b3}', '\u{114b8}'), ('\u{114ba}', '\u{114ba}'),
+    ('\u{114bd}', '\u{114bd}'), ('\u{114bf}', '\u{114c0}'), ('\u{114c2}', '\u{114c3}'),
+    ('\u{115af}', '\u{115af}'), ('\u{115b2}', '\u{115b5}'), ('\u{115bc}', '\u{115bd}'),
+    ('\u{115bf}', '\u{115c0}'), ('\u{115dc}', '\u{115dd}'), ('\u{11633}', '\u{1163a}'),
+    ('\u{1163d}', '\u{1163d}'), ('\u{1163f}', '\u{11640}'), ('\u{116ab}', '\u{116ab}'),
+    ('\u{116ad}', '\u{116ad}'), ('\u{116b0}', '\u{116b7}'), ('\u{1171d}', '\u{1171d}'),
+    ('\u{1171f}', '\u{1171f}'), ('\u{11722}', '\u{11725}'), ('\u{11727}', '\u{1172b}'),
+    ('\u{1182f}', '\u{11837}'), ('\u{11839}', '\u{1183a}'), ('\u{11930}', '\u{11930}'),
+    ('\u{1193b}', '\u{1193d}'), ('\u{11943}', '\u{11943}'), ('\u{119d4}', '\u{119d7}'),
+    ('\u{119da}', '\u{119db}'), ('\u{119e0}', '\u{119e0}'), ('\u{11a01}', '\u{11a0a}'),
+    ('\u{11a33}', '\u{11a38}'), ('\u{11a3b}', '\u{11a3e}'), ('\u{11a51}', '\u{11a56}'),
+    ('\u{11a59}', '\u{11a5b}'), ('\u{11a8a}', '\u{11a96}'), ('\u{11a98}', '\u{11a98}'),
+    ('\u{11b60}', '\u{11b60}'), ('\u{11b62}', '\u{11b64}'), ('\u{11b66}', '\u{11b66}'),
+    ('\u{11c30}', '\u{11c36}'), ('\u{11c38}', '\u{11c3d}'), ('\u{11c3f}', '\u{11c3f}'),
+    ('\u{11c92}', '\u{11ca7}'), ('\u{11caa}', '\u{11cb0}'), ('\u{11cb2}', '\u{11cb3}'),
+    ('\u{11cb5}', '\u{11cb6}'), ('\u{11d31}', '\u{11d36}'), ('\u{11d3a}', '\u{11d3a}'),
+    ('\u{11d3c}', '\u{11d3d}'), ('\u{11d3f}', '\u{11d45}'), ('\u{11d47}', '\u{11d47}'),
+    ('\u{11d90}', '\u{11d91}'), ('\u{11d95}', '\u{11d95}'), ('\u{11d97}', '\u{11d97}'),
+    ('\u{11ef3}', '\u{11ef4}'), ('\u{11f00}', '\u{11f01}'), ('\u{11f36}', '\u{11f3a}'),
+    ('\u{11f40}', '\u{11f41}'), ('\u{11f5a}', '\u{11f5a}'), ('\u{13440}', '\u{13440}'),
+    ('\u{13447}', '\u{13455}'), ('\u{1611e}', '\u{16129}'), ('\u{1612d}', '\u{1612f}'),
+    ('\u{16af0}', '\u{16af4}'), ('\u{16b30}', '\u{16b36}'), ('\u{16f4f}', '\u{16f4f}'),
+    ('\u{16f8f}', '\u{16f92}'), ('\u{16fe4}', '\u{16fe4}'), ('\u{16ff0}', '\u{16ff1}'),
+    ('\u{1bc9d}', '\u{1bc9e}'), ('\u{1cf00}', '\u{1cf2d}'), ('\u{1cf30}', '\u{1cf46}'),
+    ('\u{1d165}', '\u{1d169}'), ('\u{1d16d}', '\u{1d172}'), ('\u{1d17b}', '\u{1d182}'),
+    ('\u{1d185}', '\u{1d18b}'), ('\u{1d1aa}', '\u{1d1ad}'), ('\u{1d242}', '\u{1d244}'),
+    ('\u{1da00}', '\u{1da36}'), ('\u{1da3b}', '\u{1da6c}'), ('\u{1da75}', '\u{1da75}'),
+    ('\u{1da84}', '\u{1da84}'), ('\u{1da9b}', '\u{1da9f}'), ('\u{1daa1}', '\u{1daaf}'),
+    ('\u{1e000}', '\u{1e006}'), ('\u{1e008}', '\u{1e018}'), ('\u{1e01b}', '\u{1e021}'),
+    ('\u{1e023}', '\u{1e024}'), ('\u{1e026}', '\u{1e02a}'), ('\u{1e08f}', '\u{1e08f}'),
+    ('\u{1e130}', '\u{1e136}'), ('\u{1e2ae}', '\u{1e2ae}'), ('\u{1e2ec}', '\u{1e2ef}'),
+    ('\u{1e4ec}', '\u{1e4ef}'), ('\u{1e5ee}', '\u{1e5ef}'), ('\u{1e6e3}', '\u{1e6e3}'),
+    ('\u{1e6e6}', '\u{1e6e6}'), ('\u{1e6ee}', '\u{1e6ef}'), ('\u{1e6f5}', '\u{1e6f5}'),
+    ('\u{1e8d0}', '\u{1e8d6}'), ('\u{1e944}', '\u{1e94a}'), ('\u{1f3fb}', '\u{1f3ff}'),
+    ('\u{e0020}', '\u{e007f}'), ('\u{e0100}', '\u{e01ef}'),
+];
+
+/// Characters with `Indic_Conjunct_Break=Linker`.
+#[rustfmt::skip]
+pub const INCB_LINKERS: &[char] = &[
+    '\u{94d}', '\u{9cd}', '\u{acd}', '\u{b4d}', '\u{c4d}', '\u{d4d}', '\u{1039}', '\u{17d2}',
+    '\u{1a60}', '\u{1b44}', '\u{1bab}', '\u{a9c0}', '\u{aaf6}', '\u{10a3f}', '\u{11133}',
+    '\u{113d0}', '\u{1193e}', '\u{11a47}', '\u{11a99}', '\u{11f42}',
+];
+
+/// Canonical combining classes of characters (other characters have
+/// class 0).
+#[rustfmt::skip]
+pub const COMBINING_CLASSES: &[(char, u8)] = &[
+    ('\u{300}', 230), ('\u{301}', 230), ('\u{302}', 230), ('\u{303}', 230), ('\u{304}', 230),
+    ('\u{305}', 230), ('\u{306}', 230), ('\u{307}', 230), ('\u{308}', 230), ('\u{309}', 230),
+    ('\u{30a}', 230), ('\u{30b}', 230), ('\u{30c}', 230), ('\u{30d}', 230), ('\u{30e}', 230),
+    ('\u{30f}', 230), ('\u{310}', 230), ('\u{311}', 230), ('\u{312}', 230), ('\u{313}', 230),
+    ('\u{314}', 230), ('\u{315}', 232), ('\u{316}', 220), ('\u{317}', 220), ('\u{318}', 220),
+    ('\u{319}', 220), ('\u{31a}', 232), ('\u{31b}', 216), ('\u{31c}', 220), ('\u{31d}', 220),
+    ('\u{31e}', 220), ('\u{31f}', 220), ('\u{320}', 220), ('\u{321}', 202), ('\u{322}', 202),
+    ('\u{323}', 220), ('\u{324}', 220), ('\u{325}', 220), ('\u{326}', 220), ('\u{327}', 202),
+    ('\u{328}', 202), ('\u{329}', 220), ('\u{32a}', 220), ('\u{32b}', 220), ('\u{32c}', 220),
+    ('\u{32d}', 220), ('\u{32e}', 220), ('\u{32f}', 220), ('\u{330}', 220), ('\u{331}', 220),
+    ('\u{332}', 220), ('\u{333}', 220), ('\u{334}', 1), ('\u{335}', 1), ('\u{336}', 1),
+    ('\u{337}', 1), ('\u{338}', 1), ('\u{339}', 220), ('\u{33a}', 220), ('\u{33b}', 220),
+    ('\u{33c}', 220), ('\u{33d}', 230), ('\u{33e}', 230), ('\u{33f}', 230), ('\u{340}', 230),
+    ('\u{341}', 230), ('\u{342}', 230), ('\u{343}', 230), ('\u{344}', 230), ('\u{345}', 240),
+    ('\u{346}', 230), ('\u{347}', 220), ('\u{348}', 220), ('\u{349}', 220), ('\u{34a}', 230),
+    ('\u{34b}', 230), ('\u{34c}', 230), ('\u{34d}', 220), ('\u{34e}', 220), ('\u{350}', 230),
+    ('\u{351}', 230), ('\u{352}', 230), ('\u{353}', 220), ('\u{354}', 220), ('\u{355}', 220),
+    ('\u{356}', 220), ('\u{357}', 230), ('\u{358}', 232), ('\u{359}', 220), ('\u{35a}', 220),
+    ('\u{35b}', 230), ('\u{35c}', 233), ('\u{35d}', 234), ('\u{35e}', 234), ('\u{35f}', 233),
+    ('\u{360}', 234), ('\u{361}', 234), ('\u{362}', 233), ('\u{363}', 230), ('\u{364}', 230),
+    ('\u{365}', 230), ('\u{366}', 230), ('\u{367}', 230), ('\u{368}', 230), ('\u{369}', 230),
+    ('\u{36a}', 230), ('\u{36b}', 230), ('\u{36c}', 230), ('\u{36d}', 230), ('\u{36e}', 230),
+    ('\u{36f}', 230), ('\u{483}', 230), ('\u{484}', 230), ('\u{485}', 230), ('\u{486}', 230),
+    ('\u{487}', 230), ('\u{591}', 220), ('\u{592}', 230), ('\u{593}', 230), ('\u{594}', 230),
+    ('\u{595}', 230), ('\u{596}', 220), ('\u{597}', 230), ('\u{598}', 230), ('\u{599}', 230),
+    ('\u{59a}', 222), ('\u{59b}', 220), ('\u{59c}', 230), ('\u{59d}', 230), ('\u{59e}', 230),
+    ('\u{59f}', 230), ('\u{5a0}', 230), ('\u{5a1}', 230), ('\u{5a2}', 220), ('\u{5a3}', 220),
+    ('\u{5a4}', 220), ('\u{5a5}', 220), ('\u{5a6}', 220), ('\u{5a7}', 220), ('\u{5a8}', 230),
+    ('\u{5a9}', 230), ('\u{5aa}', 220), ('\u{5ab}', 230), ('\u{5ac}', 230), ('\u{5ad}', 222),
+    ('\u{5ae}', 228), ('\u{5af}', 230), ('\u{5b0}', 10), ('\u{5b1}', 11), ('\u{5b2}', 12),
+    ('\u{5b3}', 13), ('\u{5b4}', 14), ('\u{5b5}', 15), ('\u{5b6}', 16), ('\u{5b7}', 17),
+    ('\u{5b8}', 18), ('\u{5b9}', 19), ('\u{5ba}', 19), ('\u{5bb}', 20), ('\u{5bc}', 21),
+    ('\u{5bd}', 22), ('\u{5bf}', 23), ('\u{5c1}', 24), ('\u{5c2}', 25), ('\u{5c4}', 230),
+    ('\u{5c5}', 220), ('\u{5c7}', 18), ('\u{610}', 230), ('\u{611}', 230), ('\u{612}', 230),
+    ('\u{613}', 230), ('\u{614}', 230), ('\u{615}', 230), ('\u{616}', 230), ('\u{617}', 230),
+    ('\u{618}', 30), ('\u{619}', 31), ('\u{61a}', 32), ('\u{64b}', 27), ('\u{64c}', 28),
+    ('\u{64d}', 29), ('\u{64e}', 30), ('\u{64f}', 31), ('\u{650}', 32), ('\u{651}', 33),
+    ('\u{652}', 34), ('\u{653}', 230), ('\u{654}', 230), ('\u{655}', 220), ('\u{656}', 220),
+    ('\u{657}', 230), ('\u{658}', 230), ('\u{659}', 230), ('\u{65a}', 230), ('\u{65b}', 230),
+    ('\u{65c}', 220), ('\u{65d}', 230), ('\u{65e}', 230), ('\u{65f}', 220), ('\u{670}', 35),
+    ('\u{6d6}', 230), ('\u{6d7}', 230), ('\u{6d8}', 230), ('\u{6d9}', 230), ('\u{6da}', 230),
+    ('\u{6db}', 230), ('\u{6dc}', 230), ('\u{6df}', 230), ('\u{6e0}', 230), ('\u{6e1}', 230),
+    ('\u{6e2}', 230), ('\u{6e3}', 220), ('\u{6e4}', 230), ('\u{6e7}', 230), ('\u{6e8}', 230),
+    ('\u{6ea}', 220), ('\u{6eb}', 230), ('\u{6ec}', 230), ('\u{6ed}', 220), ('\u{711}', 36),
+    ('\u{730}', 230), ('\u{731}', 220), ('\u{732}', 230), ('\u{733}', 230), ('\u{734}', 220),
+    ('\u{735}', 230), ('\u{736}', 230), ('\u{737}', 220), ('\u{738}', 220), ('\u{739}', 220),
+    ('\u{73a}', 230), ('\u{73b}', 220), ('\u{73c}', 220), ('\u{73d}', 230), ('\u{73e}', 220),
+    ('\u{73f}', 230), ('\u{740}', 230), ('\u{741}', 230), ('\u{742}', 220), ('\u{743}', 230),
+    ('\u{744}', 220), ('\u{745}', 230), ('\u{746}', 220), ('\u{747}', 230), ('\u{748}', 220),
+    ('\u{749}', 230), ('\u{74a}', 230), ('\u{7eb}', 230), ('\u{7ec}', 230), ('\u{7ed}', 230),
+    ('\u{7ee}', 230), ('\u{7ef}', 230), ('\u{7f0}', 230), ('\u{7f1}', 230), ('\u{7f2}', 220),
+    ('\u{7f3}', 230), ('\u{7fd}', 220), ('\u{816}', 230), ('\u{817}', 230), ('\u{818}', 230),
+    ('\u{819}', 230), ('\u{81b}', 230), ('\u{81c}', 230), ('\u{81d}', 230), ('\u{81e}', 230),
+    ('\u{81f}', 230), ('\u{820}', 230), ('\u{821}', 230), ('\u{822}', 230), ('\u{823}', 230),
+    ('\u{825}', 230), ('\u{826}', 230), ('\u{827}', 230), ('\u{829}', 230), ('\u{82a}', 230),
+    ('\u{82b}', 230), ('\u{82c}', 230), ('\u{82d}', 230), ('\u{859}', 220), ('\u{85a}', 220),
+    ('\u{85b}', 220), ('\u{897}', 230), ('\u{898}', 230), ('\u{899}', 220), ('\u{89a}', 220),
+    ('\u{89b}', 220), ('\u{89c}', 230), ('\u{89d}', 230), ('\u{89e}', 230), ('\u{89f}', 230),
+    ('\u{8ca}', 230), ('\u{8cb}', 230), ('\u{8cc}', 230), ('\u{8cd}', 230), ('\u{8ce}', 230),
+    ('\u{8cf}', 220), ('\u{8d0}', 220), ('\u{8d1}', 220), ('\u{8d2}', 220), ('\u{8d3}', 220),
+    ('\u{8d4}', 230), ('\u{8d5}', 230), ('\u{8d6}', 230), ('\u{8d7}', 230), ('\u{8d8}', 230),
+    ('\u{8d9}', 230), ('\u{8da}', 230), ('\u{8db}', 230), ('\u{8dc}', 230), ('\u{8dd}', 230),
+    ('\u{8de}', 230), ('\u{8df}', 230), ('\u{8e0}', 230), ('\u{8e1}', 230), ('\u{8e3}', 220),
+    ('\u{8e4}', 230), ('\u{8e5}', 230), ('\u{8e6}', 220), ('\u{8e7}', 230), ('\u{8e8}', 230),
+    ('\u{8e9}', 220), ('\u{8ea}', 230), ('\u{8eb}', 230), ('\u{8ec}', 230), ('\u{8ed}', 220),
+    ('\u{8ee}', 220), ('\u{8ef}', 220), ('\u{8f0}', 27), ('\u{8f1}', 28), ('\u{8f2}', 29),
+    ('\u{8f3}', 230), ('\u{8f4}', 230), ('\u{8f5}', 230), ('\u{8f6}', 220), ('\u{8f7}', 230),
+    ('\u{8f8}', 230), ('\u{8f9}', 220), ('\u{8fa}', 220), ('\u{8fb}', 230), ('\u{8fc}', 230),
+    ('\u{8fd}', 230), ('\u{8fe}', 230), ('\u{8ff}', 230), ('\u{93c}', 7), ('\u{94d}', 9),
+    ('\u{951}', 230), ('\u{952}', 220), ('\u{953}', 230), ('\u{954}', 230), ('\u{9bc}', 7),
+    ('\u{9cd}', 9), ('\u{9fe}', 230), ('\u{a3c}', 7), ('\u{a4d}', 9), ('\u{abc}', 7),
+    ('\u{acd}', 9), ('\u{b3c}', 7), ('\u{b4d}', 9), ('\u{bcd}', 9), ('\u{c3c}', 7),
+    ('\u{c4d}', 9), ('\u{c55}', 84), ('\u{c56}', 91), ('\u{cbc}', 7), ('\u{ccd}', 9),
+    ('\u{d3b}', 9), ('\u{d3c}', 9), ('\u{d4d}', 9), ('\u{dca}', 9), ('\u{e38}', 103),
+    ('\u{e39}', 103), ('\u{e3a}', 9), ('\u{e48}', 107), ('\u{e49}', 107), ('\u{e4a}', 107),
+    ('\u{e4b}', 107), ('\u{eb8}', 118), ('\u{eb9}', 118), ('\u{eba}', 9), ('\u{ec8}', 122),
+    ('\u{ec9}', 122), ('\u{eca}', 122), ('\u{ecb}', 122), ('\u{f18}', 220), ('\u{f19}', 220),
+    ('\u{f35}', 220), ('\u{f37}', 220), ('\u{f39}', 216), ('\u{f71}', 129), ('\u{f72}', 130),
+    ('\u{f74}', 132), ('\u{f7a}', 130), ('\u{f7b}', 130), ('\u{f7c}', 130), ('\u{f7d}', 130),
+    ('\u{f80}', 130), ('\u{f82}', 230), ('\u{f83}', 230), ('\u{f84}', 9), ('\u{f86}', 230),
+    ('\u{f87}', 230), ('\u{fc6}', 220), ('\u{1037}', 7), ('\u{1039}', 9), ('\u{103a}', 9),
+    ('\u{108d}', 220), ('\u{135d}', 230), ('\u{135e}', 230), ('\u{135f}', 230), ('\u{1714}', 9),
+    ('\u{1715}', 9), ('\u{1734}', 9), ('\u{17d2}', 9), ('\u{17dd}', 230), ('\u{18a9}', 228),
+    ('\u{1939}', 222), ('\u{193a}', 230), ('\u{193b}', 220), ('\u{1a17}', 230), ('\u{1a18}', 220),
+    ('\u{1a60}', 9), ('\u{1a75}', 230), ('\u{1a76}', 230), ('\u{1a77}', 230), ('\u{1a78}', 230),
+    ('\u{1a79}', 230), ('\u{1a7a}', 230), ('\u{1a7b}', 230), ('\u{1a7c}', 230), ('\u{1a7f}', 220),
+    ('\u{1ab0}', 230), ('\u{1ab1}', 230), ('\u{1ab2}', 230), ('\u{1ab3}', 230), ('\u{1ab4}', 230),
+    ('\u{1ab5}', 220), ('\u{1ab6}', 220), ('\u{1ab7}', 220), ('\u{1ab8}', 220), ('\u{1ab9}', 220),
+    ('\u{1aba}', 220), ('\u{1abb}', 230), ('\u{1abc}', 230), ('\u{1abd}', 220), ('\u{1abf}', 220),
+    ('\u{1ac0}', 220), ('\u{1ac1}', 230), ('\u{1ac2}', 230), ('\u{1ac3}', 220), ('\u{1ac4}', 220),
+    ('\u{1ac5}', 230), ('\u{1ac6}', 230), ('\u{1ac7}', 230), ('\u{1ac8}', 230), ('\u{1ac9}', 230),
+    ('\u{1aca}', 220), ('\u{1acb}', 230), ('\u{1acc}', 230), ('\u{1acd}', 230), ('\u{1ace}', 230),
+    ('\u{1acf}', 230), ('\u{1ad0}', 230), ('\u{1ad1}', 230), ('\u{1ad2}', 230), ('\u{1ad3}', 230),
+    ('\u{1ad4}', 230), ('\u{1ad5}', 230), ('\u{1ad6}', 230), ('\u{1ad7}', 230), ('\u{1ad8}', 230),
+    ('\u{1ad9}', 230), ('\u{1ada}', 230), ('\u{1adb}', 230), ('\u{1adc}', 230), ('\u{1add}', 220),
+    ('\u{1ae0}', 230), ('\u{1ae1}', 230), ('\u{1ae2}', 230), ('\u{1ae3}', 230), ('\u{1ae4}', 230),
+    ('\u{1ae5}', 230), ('\u{1ae6}', 220), ('\u{1ae7}', 230), ('\u{1ae8}', 230), ('\u{1ae9}', 230),
+    ('\u{1aea}', 230), ('\u{1aeb}', 234), ('\u{1b34}', 7), ('\u{1b44}', 9), ('\u{1b6b}', 230),
+    ('\u{1b6c}', 220), ('\u{1b6d}', 230), ('\u{1b6e}', 230), ('\u{1b6f}', 230), ('\u{1b70}', 230),
+    ('\u{1b71}', 230), ('\u{1b72}', 230), ('\u{1b73}', 230), ('\u{1baa}', 9), ('\u{1bab}', 9),
+    ('\u{1be6}', 7), ('\u{1bf2}', 9), ('\u{1bf3}', 9), ('\u{1c37}', 7), ('\u{1cd0}', 230),
+    ('\u{1cd1}', 230), ('\u{1cd2}', 230), ('\u{1cd4}', 1), ('\u{1cd5}', 220), ('\u{1cd6}', 220),
+    ('\u{1cd7}', 220), ('\u{1cd8}', 220), ('\u{1cd9}', 220), ('\u{1cda}', 230), ('\u{1cdb}', 230),
+    ('\u{1cdc}', 220), ('\u{1cdd}', 220), ('\u{1cde}', 220), ('\u{1cdf}', 220), ('\u{1ce0}', 230),
+    ('\u{1ce2}', 1), ('\u{1ce3}', 1), ('\u{1ce4}', 1), ('\u{1ce5}', 1), ('\u{1ce6}', 1),
+    ('\u{1ce7}', 1), ('\u{1ce8}', 1), ('\u{1ced}', 220), ('\u{1cf4}', 230), ('\u{1cf8}', 230),
+    ('\u{1cf9}', 230), ('\u{1dc0}', 230), ('\u{1dc1}', 230), ('\u{1dc2}', 220), ('\u{1dc3}', 230),
+    ('\u{1dc4}', 230), ('\u{1dc5}', 230), ('\u{1dc6}', 230), ('\u{1dc7}', 230), ('\u{1dc8}', 230),
+    ('\u{1dc9}', 230), ('\u{1dca}', 220), ('\u{1dcb}', 230), ('\u{1dcc}', 230), ('\u{1dcd}', 234),
+    ('\u{1dce}', 214), ('\u{1dcf}', 220), ('\u{1dd0}', 202), ('\u{1dd1}', 230), ('\u{1dd2}', 230),
+    ('\u{1dd3}', 230), ('\u{1dd4}', 230), ('\u{1dd5}', 230), ('\u{1dd6}', 230), ('\u{1dd7}', 230),
+    ('\u{1dd8}', 230), ('\u{1dd9}', 230), ('\u{1dda}', 230), ('\u{1ddb}', 230), ('\u{1ddc}', 230),
+    ('\u{1ddd}', 230), ('\u{1dde}', 230), ('\u{1ddf}', 230), ('\u{1de0}', 230), ('\u{1de1}', 230),
+    ('\u{1de2}', 230), ('\u{1de3}', 230), ('\u{1de4}', 230), ('\u{1de5}', 230), ('\u{1de6}', 230),
+    ('\u{1de7}', 230), ('\u{1de8}', 230), ('\u{1de9}', 230), ('\u{1dea}', 230), ('\u{1deb}', 230),
+    ('\u{1dec}', 230), ('\u{1ded}', 230), ('\u{1dee}', 230), ('\u{1def}', 230), ('\u{1df0}', 230),
+    ('\u{1df1}', 230), ('\u{1df2}', 230), ('\u{1df3}', 230), ('\u{1df4}', 230), ('\u{1df5}', 230),
+    ('\u{1df6}', 232), ('\u{1df7}', 228), ('\u{1df8}', 228), ('\u{1df9}', 220), ('\u{1dfa}', 218),
+    ('\u{1dfb}', 230), ('\u{1dfc}', 233), ('\u{1dfd}', 220), ('\u{1dfe}', 230), ('\u{1dff}', 220),
+    ('\u{20d0}', 230), ('\u{20d1}', 230), ('\u{20d2}', 1), ('\u{20d3}', 1), ('\u{20d4}', 230),
+    ('\u{20d5}', 230), ('\u{20d6}', 230), ('\u{20d7}', 230), ('\u{20d8}', 1), ('\u{20d9}', 1),
+    ('\u{20da}', 1), ('\u{20db}', 230), ('\u{20dc}', 230), ('\u{20e1}', 230), ('\u{20e5}', 1),
+    ('\u{20e6}', 1), ('\u{20e7}', 230), ('\u{20e8}', 220), ('\u{20e9}', 230), ('\u{20ea}', 1),
+    ('\u{20eb}', 1), ('\u{20ec}', 220), ('\u{20ed}', 220), ('\u{20ee}', 220), ('\u{20ef}', 220),
+    ('\u{20f0}', 230), ('\u{2cef}', 230), ('\u{2cf0}', 230), ('\u{2cf1}', 230), ('\u{2d7f}', 9),
+    ('\u{2de0}', 230), ('\u{2de1}', 230), ('\u{2de2}', 230), ('\u{2de3}', 230), ('\u{2de4}', 230),
+    ('\u{2de5}', 230), ('\u{2de6}', 230), ('\u{2de7}', 230), ('\u{2de8}', 230), ('\u{2de9}', 230),
+    ('\u{2dea}', 230), ('\u{2deb}', 230), ('\u{2dec}', 230), ('\u{2ded}', 230), ('\u{2dee}', 230),
+    ('\u{2def}', 230), ('\u{2df0}', 230), ('\u{2df1}', 230), ('\u{2df2}', 230), ('\u{2df3}', 230),
+    ('\u{2df4}', 230), ('\u{2df5}', 230), ('\u{2df6}', 230), ('\u{2df7}', 230), ('\u{2df8}', 230),
+    ('\u{2df9}', 230), ('\u{2dfa}', 230), ('\u{2dfb}', 230), ('\u{2dfc}', 230), ('\u{2dfd}', 230),
+    ('\u{2dfe}', 230), ('\u{2dff}', 230), ('\u{302a}', 218), ('\u{302b}', 228), ('\u{302c}', 232),
+    ('\u{302d}', 222), ('\u{302e}', 224), ('\u{302f}', 224), ('\u{3099}', 8), ('\u{309a}', 8),
+    ('\u{a66f}', 230), ('\u{a674}', 230), ('\u{a675}', 230), ('\u{a676}', 230), ('\u{a677}', 230),
+    ('\u{a678}', 230), ('\u{a679}', 230), ('\u{a67a}', 230), ('\u{a67b}', 230), ('\u{a67c}', 230),
+    ('\u{a67d}', 230), ('\u{a69e}', 230), ('\u{a69f}', 230), ('\u{a6f0}', 230), ('\u{a6f1}', 230),
+    ('\u{a806}', 9), ('\u{a82c}', 9), ('\u{a8c4}', 9), ('\u{a8e0}', 230), ('\u{a8e1}', 230),
+    ('\u{a8e2}', 230), ('\u{a8e3}', 230), ('\u{a8e4}', 230), ('\u{a8e5}', 230), ('\u{a8e6}', 230),
+    ('\u{a8e7}', 230), ('\u{a8e8}', 230), ('\u{a8e9}', 230), ('\u{a8ea}', 230), ('\u{a8eb}', 230),
+    ('\u{a8ec}', 230), ('\u{a8ed}', 230), ('\u{a8ee}', 230), ('\u{a8ef}', 230), ('\u{a8f0}', 230),
+    ('\u{a8f1}', 230), ('\u{a92b}', 220), ('\u{a92c}', 220), ('\u{a92d}', 220), ('\u{a953}', 9),
+    ('\u{a9b3}', 7), ('\u{a9c0}', 9), ('\u{aab0}', 230), ('\u{aab2}', 230), ('\u{aab3}', 230),
+    ('\u{aab4}', 220), ('\u{aab7}', 230), ('\u{aab8}', 230), ('\u{aabe}', 230), ('\u{aabf}', 230),
+    ('\u{aac1}', 230), ('\u{aaf6}', 9), ('\u{abed}', 9), ('\u{fb1e}', 26), ('\u{fe20}', 230),
+    ('\u{fe21}', 230), ('\u{fe22}', 230), ('\u{fe23}', 230), ('\u{fe24}', 230), ('\u{fe25}', 230),
+    ('\u{fe26}', 230), ('\u{fe27}', 220), ('\u{fe28}', 220), ('\u{fe29}', 220), ('\u{fe2a}', 220),
+    ('\u{fe2b}', 220), ('\u{fe2c}', 220), ('\u{fe2d}', 220), ('\u{fe2e}', 230), ('\u{fe2f}', 230),
+    ('\u{101fd}', 220), ('\u{102e0}', 220), ('\u{10376}', 230), ('\u{10377}', 230),
+    ('\u{10378}', 230), ('\u{10379}', 230), ('\u{1037a}', 230), ('\u{10a0d}', 220),
+    ('\u{10a0f}', 230), ('\u{10a38}', 230), ('\u{10a39}', 1), ('\u{10a3a}', 220),
+    ('\u{10a3f}', 9), ('\u{10ae5}', 230), ('\u{10ae6}', 220), ('\u{10d24}', 230),
+    ('\u{10d25}', 230), ('\u{10d26}', 230), ('\u{10d27}', 230), ('\u{10d69}', 230),
+    ('\u{10d6a}', 230), ('\u{10d6b}', 230), ('\u{10d6c}', 230), ('\u{10d6d}', 230),
+    ('\u{10eab}', 230), ('\u{10eac}', 230), ('\u{10efa}', 220), ('\u{10efb}', 220),
+    ('\u{10efd}', 220), ('\u{10efe}', 220), ('\u{10eff}', 220), ('\u{10f46}', 220),
+    ('\u{10f47}', 220), ('\u{10f48}', 230), ('\u{10f49}', 230), ('\u{10f4a}', 230),
+    ('\u{10f4b}', 220), ('\u{10f4c}', 230), ('\u{10f4d}', 220), ('\u{10f4e}', 220),
+    ('\u{10f4f}', 220), ('\u{10f50}', 220), ('\u{10f82}', 230), ('\u{10f83}', 220),
+    ('\u{10f84}', 230), ('\u{10f85}', 220), ('\u{11046}', 9), ('\u{11070}', 9), ('\u{1107f}', 9),
+    ('\u{110b9}', 9), ('\u{110ba}', 7), ('\u{11100}', 230), ('\u{11101}', 230),
+    ('\u{11102}', 230), ('\u{11133}', 9), ('\u{11134}', 9), ('\u{11173}', 7), ('\u{111c0}', 9),
+    ('\u{111ca}', 7), ('\u{11235}', 9), ('\u{11236}', 7), ('\u{112e9}', 7), ('\u{112ea}', 9),
+    ('\u{1133b}', 7), ('\u{1133c}', 7), ('\u{1134d}', 9), ('\u{11366}', 230), ('\u{11367}', 230),
+    ('\u{11368}', 230), ('\u{11369}', 230), ('\u{1136a}', 230), ('\u{1136b}', 230),
+    ('\u{1136c}', 230), ('\u{11370}', 230), ('\u{11371}', 230), ('\u{11372}', 230),
+    ('\u{11373}', 230), ('\u{11374}', 230), ('\u{113ce}', 9), ('\u{113cf}', 9), ('\u{113d0}', 9),
+    ('\u{11442}', 9), ('\u{11446}', 7), ('\u{1145e}', 230), ('\u{114c2}', 9), ('\u{114c3}', 7),
+    ('\u{115bf}', 9), ('\u{115c0}', 7), ('\u{1163f}', 9), ('\u{116b6}', 9), ('\u{116b7}', 7),
+    ('\u{1172b}', 9), ('\u{11839}', 9), ('\u{1183a}', 7), ('\u{1193d}', 9), ('\u{1193e}', 9),
+    ('\u{11943}', 7), ('\u{119e0}', 9), ('\u{11a34}', 9), ('\u{11a47}', 9), ('\u{11a99}', 9),
+    ('\u{11c3f}', 9), ('\u{11d42}', 7), ('\u{11d44}', 9), ('\u{11d45}', 9), ('\u{11d97}', 9),
+    ('\u{11f41}', 9), ('\u{11f42}', 9), ('\u{1612f}', 9), ('\u{16af0}', 1), ('\u{16af1}', 1),
+    ('\u{16af2}', 1), ('\u{16af3}', 1), ('\u{16af4}', 1), ('\u{16b30}', 230), ('\u{16b31}', 230),
+    ('\u{16b32}', 230), ('\u{16b33}', 230), ('\u{16b34}', 230), ('\u{16b35}', 230),
+    ('\u{16b36}', 230), ('\u{16ff0}', 6), ('\u{16ff1}', 6), ('\u{1bc9e}', 1), ('\u{1d165}', 216),
+    ('\u{1d166}', 216), ('\u{1d167}', 1), ('\u{1d168}', 1), ('\u{1d169}', 1), ('\u{1d16d}', 226),
+    ('\u{1d16e}', 216), ('\u{1d16f}', 216), ('\u{1d170}', 216), ('\u{1d171}', 216),
+    ('\u{1d172}', 216), ('\u{1d17b}', 220), ('\u{1d17c}', 220), ('\u{1d17d}', 220),
+    ('\u{1d17e}', 220), ('\u{1d17f}', 220), ('\u{1d180}', 220), ('\u{1d181}', 220),
+    ('\u{1d182}', 220), ('\u{1d185}', 230), ('\u{1d186}', 230), ('\u{1d187}', 230),
+    ('\u{1d188}', 230), ('\u{1d189}', 230), ('\u{1d18a}', 220), ('\u{1d18b}', 220),
+    ('\u{1d1aa}', 230), ('\u{1d1ab}', 230), ('\u{1d1ac}', 230), ('\u{1d1ad}', 230),
+    ('\u{1d242}', 230), ('\u{1d243}', 230), ('\u{1d244}', 230), ('\u{1e000}', 230),
+    ('\u{1e001}', 230), ('\u{1e002}', 230), ('\u{1e003}', 230), ('\u{1e004}', 230),
+    ('\u{1e005}', 230), ('\u{1e006}', 230), ('\u{1e008}', 230), ('\u{1e009}', 230),
+    ('\u{1e00a}', 230), ('\u{1e00b}', 230), ('\u{1e00c}', 230), ('\u{1e00d}', 230),
+    ('\u{1e00e}', 230), ('\u{1e00f}', 230), ('\u{1e010}', 230), ('\u{1e011}', 230),
+    ('\u{1e012}', 230), ('\u{1e013}', 230), ('\u{1e014}', 230), ('\u{1e015}', 230),
+    ('\u{1e016}', 230), ('\u{1e017}', 230), ('\u{1e018}', 230), ('\u{1e01b}', 230),
+    ('\u{1e01c}', 230), ('\u{1e01d}', 230), ('\u{1e01e}', 230), ('\u{1e01f}', 230),
+    ('\u{1e020}', 230), ('\u{1e021}', 230), ('\u{1e023}', 230), ('\u{1e024}', 230),
+    ('\u{1e026}', 230), ('\u{1e027}', 230), ('\u{1e028}', 230), ('\u{1e029}', 230),
+    ('\u{1e02a}', 230), ('\u{1e08f}', 230), ('\u{1e130}', 230), ('\u{1e131}', 230),
+    ('\u{1e132}', 230), ('\u{1e133}', 230), ('\u{1e134}', 230), ('\u{1e135}', 230),
+    ('\u{1e136}', 230), ('\u{1e2ae}', 230), ('\u{1e2ec}', 230), ('\u{1e2ed}', 230),
+    ('\u{1e2ee}', 230), ('\u{1e2ef}', 230), ('\u{1e4ec}', 232), ('\u{1e4ed}', 232),
+    ('\u{1e4ee}', 220), ('\u{1e4ef}', 230), ('\u{1e5ee}', 230), ('\u{1e5ef}', 220),
+    ('\u{1e6e3}', 230), ('\u{1e6e6}', 230), ('\u{1e6ee}', 230), ('\u{1e6ef}', 230),
+    ('\u{1e6f5}', 230), ('\u{1e8d0}', 220), ('\u{1e8d1}', 220), ('\u{1e8d2}', 220),
+    ('\u{1e8d3}', 220), ('\u{1e8d4}', 220), ('\u{1e8d5}', 220), ('\u{1e8d6}', 220),
+    ('\u{1e944}', 230), ('\u{1e945}', 230), ('\u{1e946}', 230), ('\u{1e947}', 230),
+    ('\u{1e948}', 230), ('\u{1e949}', 230), ('\u{1e94a}', 7),
+];
+
+/// Full canonical decompositions (without Hangul syllables, which are
+/// decomposed algorithmically).
+#[rustfmt::skip]
+pub const DECOMPOSITIONS: &[(char, &str)] = &[
+    ('\u{c0}', "\u{41}\u{300}"), ('\u{c1}', "\u{41}\u{301}"), ('\u{c2}', "\u{41}\u{302}"),
+    ('\u{c3}', "\u{41}\u{303}"), ('\u{c4}', "\u{41}\u{308}"), ('\u{c5}', "\u{41}\u{30a}"),
+    ('\u{c7}', "\u{43}\u{327}"), ('\u{c8}', "\u{45}\u{300}"), ('\u{c9}', "\u{45}\u{301}"),
+    ('\u{ca}', "\u{45}\u{302}"), ('\u{cb}', "\u{45}\u{308}"), ('\u{cc}', "\u{49}\u{300}"),
+    ('\u{cd}', "\u{49}\u{301}"), ('\u{ce}', "\u{49}\u{302}"), ('\u{cf}', "\u{49}\u{308}"),
+    ('\u{d1}', "\u{4e}\u{303}"), ('\u{d2}', "\u{4f}\u{300}"), ('\u{d3}', "\u{4f}\u{301}"),
+    ('\u{d4}', "\u{4f}\u{302}"), ('\u{d5}', "\u{4f}\u{303}"), ('\u{d6}', "\u{4f}\u{308}"),
+    ('\u{d9}', "\u{55}\u{300}"), ('\u{da}', "\u{55}\u{301}"), ('\u{db}', "\u{55}\u{302}"),
+    ('\u{dc}', "\u{55}\u{308}"), ('\u{dd}', "\u{59}\u{301}"), ('\u{e0}', "\u{61}\u{300}"),
+    ('\u{e1}', "\u{61}\u{301}"), ('\u{e2}', "\u{61}\u{302}"), ('\u{e3}', "\u{61}\u{303}"),
+    ('\u{e4}', "\u{61}\u{308}"), ('\u{e5}', "\u{61}\u{30a}"), ('\u{e7}', "\u{63}\u{327}"),
+    ('\u{e8}', "\u{65}\u{300}"), ('\u{e9}', "\u{65}\u{301}"), ('\u{ea}', "\u{65}\u{302}"),
+    ('\u{eb}', "\u{65}\u{308}"), ('\u{ec}', "\u{69}\u{300}"), ('\u{ed}', "\u{69}\u{301}"),
+    ('\u{ee}', "\u{69}\u{302}"), ('\u{ef}', "\u{69}\u{308}"), ('\u{f1}', "\u{6e}\u{303}"),
+    ('\u{f2}', "\u{6f}\u{300}"), ('\u{f3}', "\u{6f}\u{301}"), ('\u{f4}', "\u{6f}\u{302}"),
+    ('\u{f5}', "\u{6f}\u{303}"), ('\u{f6}', "\u{6f}\u{308}"), ('\u{f9}', "\u{75}\u{300}"),
+    ('\u{fa}', "\u{75}\u{301}"), ('\u{fb}', "\u{75}\u{302}"), ('\u{fc}', "\u{75}\u{308}"),
+    ('\u{fd}', "\u{79}\u{301}"), ('\u{ff}', "\u{79}\u{308}"), ('\u{100}', "\u{41}\u{304}"),
+    ('\u{101}', "\u{61}\u{304}"), ('\u{102}', "\u{41}\u{306}"), ('\u{103}', "\u{61}\u{306}"),
+    ('\u{104}', "\u{41}\u{328}"), ('\u{105}', "\u{61}\u{328}"), ('\u{106}', "\u{43}\u{301}"),
+    ('\u{107}', "\u{63}\u{301}"), ('\u{108}', "\u{43}\u{302}"), ('\u{109}', "\u{63}\u{302}"),
+    ('\u{10a}', "\u{43}\u{307}"), ('\u{10b}', "\u{63}\u{307}"), ('\u{10c}', "\u{43}\u{30c}"),
+    ('\u{10d}', "\u{63}\u{30c}"), ('\u{10e}', "\u{44}\u{30c}"), ('\u{10f}', "\u{64}\u{30c}"),
+    ('\u{112}', "\u{45}\u{304}"), ('\u{113}', "\u{65}\u{304}"), ('\u{114}', "\u{45}\u{306}"),
+    ('\u{115}', "\u{65}\u{306}"), ('\u{116}', "\u{45}\u{307}"), ('\u{117}', "\u{65}\u{307}"),
+    ('\u{118}', "\u{45}\u{328}"), ('\u{119}', "\u{65}\u{328}"), ('\u{11a}', "\u{45}\u{30c}"),
+    ('\u{11b}', "\u{65}\u{30c}"), ('\u{11c}', "\u{47}\u{302}"), ('\u{11d}', "\u{67}\u{302}"),
+    ('\u{11e}', "\u{47}\u{306}"), ('\u{11f}', "\u{67}\u{306}"), ('\u{120}', "\u{47}\u{307}"),
+    ('\u{121}', "\u{67}\u{307}"), ('\u{122}', "\u{47}\u{327}"), ('\u{123}', "\u{67}\u{327}"),
+    ('\u{124}', "\u{48}\u{302}"), ('\u{125}', "\u{68}\u{302}"), ('\u{128}', "\u{49}\u{303}"),
+    ('\u{129}', "\u{69}\u{303}"), ('\u{12a}', "\u{49}\u{304}"), ('\u{12b}', "\u{69}\u{304}"),
+    ('\u{12c}', "\u{49}\u{306}"), ('\u{12d}', "\u{69}\u{306}"), ('\u{12e}', "\u{49}\u{328}"),
+    ('\u{12f}', "\u{69}\u{328}"), ('\u{130}', "\u{49}\u{307}"), ('\u{134}', "\u{4a}\u{302}"),
+    ('\u{135}', "\u{6a}\u{302}"), ('\u{136}', "\u{4b}\u{327}"), ('\u{137}', "\u{6b}\u{327}"),
+    ('\u{139}', "\u{4c}\u{301}"), ('\u{13a}', "\u{6c}\u{301}"), ('\u{13b}', "\u{4c}\u{327}"),
+    ('\u{13c}', "\u{6c}\u{327}"), ('\u{13d}', "\u{4c}\u{30c}"), ('\u{13e}', "\u{6c}\u{30c}"),
+    ('\u{143}', "\u{4e}\u{301}"), ('\u{144}', "\u{6e}\u{301}"), ('\u{145}', "\u{4e}\u{327}"),
+    ('\u{146}', "\u{6e}\u{327}"), ('\u{147}', "\u{4e}\u{30c}"), ('\u{148}', "\u{6e}\u{30c}"),
+    ('\u{14c}', "\u{4f}\u{304}"), ('\u{14d}', "\u{6f}\u{304}"), ('\u{14e}', "\u{4f}\u{306}"),
+    ('\u{14f}', "\u{6f}\u{306}"), ('\u{150}', "\u{4f}\u{30b}"), ('\u{151}', "\u{6f}\u{30b}"),
+    ('\u{154}', "\u{52}\u{301}"), ('\u{155}', "\u{72}\u{301}"), ('\u{156}', "\u{52}\u{327}"),
+    ('\u{157}', "\u{72}\u{327}"), ('\u{158}', "\u{52}\u{30c}"), ('\u{159}', "\u{72}\u{30c}"),
+    ('\u{15a}', "\u{53}\u{301}"), ('\u{15b}', "\u{73}\u{301}"), ('\u{15c}', "\u{53}\u{302}"),
+    ('\u{15d}', "\u{73}\u{302}"), ('\u{15e}', "\u{53}\u{327}"), ('\u{15f}', "\u{73}\u{327}"),
+    ('\u{160}', "\u{53}\u{30c}"), ('\u{161}', "\u{73}\u{30c}"), ('\u{162}', "\u{54}\u{327}"),
+    ('\u{163}', "\u{74}\u{327}"), ('\u{164}', "\u{54}\u{30c}"), ('\u{165}', "\u{74}\u{30c}"),
+    ('\u{168}', "\u{55}\u{303}"), ('\u{169}', "\u{75}\u{303}"), ('\u{16a}', "\u{55}\u{304}"),
+    ('\u{16b}', "\u{75}\u{304}"), ('\u{16c}', "\u{55}\u{306}"), ('\u{16d}', "\u{75}\u{306}"),
+    ('\u{16e}', "\u{55}\u{30a}"), ('\u{16f}', "\u{75}\u{30a}"), ('\u{170}', "\u{55}\u{30b}"),
+    ('\u{171}', "\u{75}\u{30b}"), ('\u{172}', "\u{55}\u{328}"), ('\u{173}', "\u{75}\u{328}"),
+    ('\u{174}', "\u{57}\u{302}"), ('\u{175}', "\u{77}\u{302}"), ('\u{176}', "\u{59}\u{302}"),
+    ('\u{177}', "\u{79}\u{302}"), ('\u{178}', "\u{59}\u{308}"), ('\u{179}', "\u{5a}\u{301}"),
+    ('\u{17a}', "\u{7a}\u{301}"), ('\u{17b}', "\u{5a}\u{307}"), ('\u{17c}', "\u{7a}\u{307}"),
+    ('\u{17d}', "\u{5a}\u{30c}"), ('\u{17e}', "\u{7a}\u{30c}"), ('\u{1a0}', "\u{4f}\u{31b}"),
+    ('\u{1a1}', "\u{6f}\u{31b}"), ('\u{1af}', "\u{55}\u{31b}"), ('\u{1b0}', "\u{75}\u{31b}"),
+    ('\u{1cd}', "\u{41}\u{30c}"), ('\u{1ce}', "\u{61}\u{30c}"), ('\u{1cf}', "\u{49}\u{30c}"),
+    ('\u{1d0}', "\u{69}\u{30c}"), ('\u{1d1}', "\u{4f}\u{30c}"), ('\u{1d2}', "\u{6f}\u{30c}"),
+    ('\u{1d3}', "\u{55}\u{30c}"), ('\u{1d4}', "\u{75}\u{30c}"),
+    ('\u{1d5}', "\u{55}\u{308}\u{304}"), ('\u{1d6}', "\u{75}\u{308}\u{304}"),
+    ('\u{1d7}', "\u{55}\u{308}\u{301}"), ('\u{1d8}', "\u{75}\u{308}\u{301}"),
+    ('\u{1d9}', "\u{55}\u{308}\u{30c}"), ('\u{1da}', "\u{75}\u{308}\u{30c}"),
+    ('\u{1db}', "\u{55}\u{308}\u{300}"), ('\u{1dc}', "\u{75}\u{308}\u{300}"),
+    ('\u{1de}', "\u{41}\u{308}\u{304}"), ('\u{1df}', "\u{61}\u{308}\u{304}"),
+    ('\u{1e0}', "\u{41}\u{307}\u{304}"), ('\u{1e1}', "\u{61}\u{307}\u{304}"),
+    ('\u{1e2}', "\u{c6}\u{304}"), ('\u{1e3}', "\u{e6}\u{304}"), ('\u{1e6}', "\u{47}\u{30c}"),
+    ('\u{1e7}', "\u{67}\u{30c}"), ('\u{1e8}', "\u{4b}\u{30c}"), ('\u{1e9}', "\u{6b}\u{30c}"),
+    ('\u{1ea}', "\u{4f}\u{328}"), ('\u{1eb}', "\u{6f}\u{328}"),
+    ('\u{1ec}', "\u{4f}\u{328}\u{304}"), ('\u{1ed}', "\u{6f}\u{328}\u{304}"),
+    ('\u{1ee}', "\u{1b7}\u{30c}"), ('\u{1ef}', "\u{292}\u{30c}"), ('\u{1f0}', "\u{6a}\u{30c}"),
+    ('\u{1f4}', "\u{47}\u{301}"), ('\u{1f5}', "\u{67}\u{301}"), ('\u{1f8}', "\u{4e}\u{300}"),
+    ('\u{1f9}', "\u{6e}\u{300}"), ('\u{1fa}', "\u{41}\u{30a}\u{301}"),
+    ('\u{1fb}', "\u{61}\u{30a}\u{301}"), ('\u{1fc}', "\u{c6}\u{301}"),
+    ('\u{1fd}', "\u{e6}\u{301}"), ('\u{1fe}', "\u{d8}\u{301}"), ('\u{1ff}', "\u{f8}\u{301}"),
+    ('\u{200}', "\u{41}\u{30f}"), ('\u{201}', "\u{61}\u{30f}"), ('\u{202}', "\u{41}\u{311}"),
+    ('\u{203}', "\u{61}\u{311}"), ('\u{204}', "\u{45}\u{30f}"), ('\u{205}', "\u{65}\u{30f}"),
+    ('\u{206}', "\u{45}\u{311}"), ('\u{207}', "\u{65}\u{311}"), ('\u{208}', "\u{49}\u{30f}"),
+    ('\u{209}', "\u{69}\u{30f}"), ('\u{20a}', "\u{49}\u{311}"), ('\u{20b}', "\u{69}\u{311}"),
+    ('\u{20c}', "\u{4f}\u{30f}"), ('\u{20d}', "\u{6f}\u{30f}"), ('\u{20e}', "\u{4f}\u{311}"),
+    ('\u{20f}', "\u{6f}\u{311}"), ('\u{210}', "\u{52}\u{30f}"), ('\u{211}', "\u{72}\u{30f}"),
+    ('\u{212}', "\u{52}\u{311}"), ('\u{213}', "\u{72}\u{311}"), ('\u{214}', "\u{55}\u{30f}"),
+    ('\u{215}', "\u{75}\u{30f}"), ('\u{216}', "\u{55}\u{311}"), ('\u{217}', "\u{75}\u{311}"),
+    ('\u{218}', "\u{53}\u{326}"), ('\u{219}', "\u{73}\u{326}"), ('\u{21a}', "\u{54}\u{326}"),
+    ('\u{21b}', "\u{74}\u{326}"), ('\u{21e}', "\u{48}\u{30c}"), ('\u{21f}', "\u{68}\u{30c}"),
+    ('\u{226}', "\u{41}\u{307}"), ('\u{227}', "\u{61}\u{307}"), ('\u{228}', "\u{45}\u{327}"),
+    ('\u{229}', "\u{65}\u{327}"), ('\u{22a}', "\u{4f}\u{308}\u{304}"),
+    ('\u{22b}', "\u{6f}\u{308}\u{304}"), ('\u{22c}', "\u{4f}\u{303}\u{304}"),
+    ('\u{22d}', "\u{6f}\u{303}\u{304}"), ('\u{22e}', "\u{4f}\u{307}"),
+    ('\u{22f}', "\u{6f}\u{307}"), ('\u{230}', "\u{4f}\u{307}\u{304}"),
+    ('\u{231}', "\u{6f}\u{307}\u{304}"), ('\u{232}', "\u{59}\u{304}"),
+    ('\u{233}', "\u{79}\u{304}"), ('\u{340}', "\u{300}"), ('\u{341}', "\u{301}"),
+    ('\u{343}', "\u{313}"), ('\u{344}', "\u{308}\u{301}"), ('\u{374}', "\u{2b9}"),
+    ('\u{37e}', "\u{3b}"), ('\u{385}', "\u{a8}\u{301}"), ('\u{386}', "\u{391}\u{301}"),
+    ('\u{387}', "\u{b7}"), ('\u{388}', "\u{395}\u{301}"), ('\u{389}', "\u{397}\u{301}"),
+    ('\u{38a}', "\u{399}\u{301}"), ('\u{38c}', "\u{39f}\u{301}"), ('\u{38e}', "\u{3a5}\u{301}"),
+    ('\u{38f}', "\u{3a9}\u{301}"), ('\u{390}', "\u{3b9}\u{308}\u{301}"),
+    ('\u{3aa}', "\u{399}\u{308}"), ('\u{3ab}', "\u{3a5}\u{308}"), ('\u{3ac}', "\u{3b1}\u{301}"),
+    ('\u{3ad}', "\u{3b5}\u{301}"), ('\u{3ae}', "\u{3b7}\u{301}"), ('\u{3af}', "\u{3b9}\u{301}"),
+    ('\u{3b0}', "\u{3c5}\u{308}\u{301}"), ('\u{3ca}', "\u{3b9}\u{308}"),
+    ('\u{3cb}', "\u{3c5}\u{308}"), ('\u{3cc}', "\u{3bf}\u{301}"), ('\u{3cd}', "\u{3c5}\u{301}"),
+    ('\u{3ce}', "\u{3c9}\u{301}"), ('\u{3d3}', "\u{3d2}\u{301}"), ('\u{3d4}', "\u{3d2}\u{308}"),
+    ('\u{400}', "\u{415}\u{300}"), ('\u{401}', "\u{415}\u{308}"), ('\u{403}', "\u{413}\u{301}"),
+    ('\u{407}', "\u{406}\u{308}"), ('\u{40c}', "\u{41a}\u{301}"), ('\u{40d}', "\u{418}\u{300}"),
+    ('\u{40e}', "\u{423}\u{306}"), ('\u{419}', "\u{418}\u{306}"), ('\u{439}', "\u{438}\u{306}"),
+    ('\u{450}', "\u{435}\u{300}"), ('\u{451}', "\u{435}\u{308}"), ('\u{453}', "\u{433}\u{301}"),
+    ('\u{457}', "\u{456}\u{308}"), ('\u{45c}', "\u{43a}\u{301}"), ('\u{45d}', "\u{438}\u{300}"),
+    ('\u{45e}', "\u{443}\u{306}"), ('\u{476}', "\u{474}\u{30f}"), ('\u{477}', "\u{475}\u{30f}"),
+    ('\u{4c1}', "\u{416}\u{306}"), ('\u{4c2}', "\u{436}\u{306}"), ('\u{4d0}', "\u{410}\u{306}"),
+    ('\u{4d1}', "\u{430}\u{306}"), ('\u{4d2}', "\u{410}\u{308}"), ('\u{4d3}', "\u{430}\u{308}"),
+    ('\u{4d6}', "\u{415}\u{306}"), ('\u{4d7}', "\u{435}\u{306}"), ('\u{4da}', "\u{4d8}\u{308}"),
+    ('\u{4db}', "\u{4d9}\u{308}"), ('\u{4dc}', "\u{416}\u{308}"), ('\u{4dd}', "\u{436}\u{308}"),
+    ('\u{4de}', "\u{417}\u{308}"), ('\u{4df}', "\u{437}\u{308}"), ('\u{4e2}', "\u{418}\u{304}"),
+    ('\u{4e3}', "\u{438}\u{304}"), ('\u{4e4}', "\u{418}\u{308}"), ('\u{4e5}', "\u{438}\u{308}"),
+    ('\u{4e6}', "\u{41e}\u{308}"), ('\u{4e7}', "\u{43e}\u{308}"), ('\u{4ea}', "\u{4e8}\u{308}"),
+    ('\u{4eb}', "\u{4e9}\u{308}"), ('\u{4ec}', "\u{42d}\u{308}"), ('\u{4ed}', "\u{44d}\u{308}"),
+    ('\u{4ee}', "\u{423}\u{304}"), ('\u{4ef}', "\u{443}\u{304}"), ('\u{4f0}', "\u{423}\u{308}"),
+    ('\u{4f1}', "\u{443}\u{308}"), ('\u{4f2}', "\u{423}\u{30b}"), ('\u{4f3}', "\u{443}\u{30b}"),
+    ('\u{4f4}', "\u{427}\u{308}"), ('\u{4f5}', "\u{447}\u{308}"), ('\u{4f8}', "\u{42b}\u{308}"),
+    ('\u{4f9}', "\u{44b}\u{308}"), ('\u{622}', "\u{627}\u{653}"), ('\u{623}', "\u{627}\u{654}"),
+    ('\u{624}', "\u{648}\u{654}"), ('\u{625}', "\u{627}\u{655}"), ('\u{626}', "\u{64a}\u{654}"),
+    ('\u{6c0}', "\u{6d5}\u{654}"), ('\u{6c2}', "\u{6c1}\u{654}"), ('\u{6d3}', "\u{6d2}\u{654}"),
+    ('\u{929}', "\u{928}\u{93c}"), ('\u{931}', "\u{930}\u{93c}"), ('\u{934}', "\u{933}\u{93c}"),
+    ('\u{958}', "\u{915}\u{93c}"), ('\u{959}', "\u{916}\u{93c}"), ('\u{95a}', "\u{917}\u{93c}"),
+    ('\u{95b}', "\u{91c}\u{93c}"), ('\u{95c}', "\u{921}\u{93c}"), ('\u{95d}', "\u{922}\u{93c}"),
+    ('\u{95e}', "\u{92b}\u{93c}"), ('\u{95f}', "\u{92f}\u{93c}"), ('\u{9cb}', "\u{9c7}\u{9be}"),
+    ('\u{9cc}', "\u{9c7}\u{9d7}"), ('\u{9dc}', "\u{9a1}\u{9bc}"), ('\u{9dd}', "\u{9a2}\u{9bc}"),
+    ('\u{9df}', "\u{9af}\u{9bc}"), ('\u{a33}', "\u{a32}\u{a3c}"), ('\u{a36}', "\u{a38}\u{a3c}"),
+    ('\u{a59}', "\u{a16}\u{a3c}"), ('\u{a5a}', "\u{a17}\u{a3c}"), ('\u{a5b}', "\u{a1c}\u{a3c}"),
+    ('\u{a5e}', "\u{a2b}\u{a3c}"), ('\u{b48}', "\u{b47}\u{b56}"), ('\u{b4b}', "\u{b47}\u{b3e}"),
+    ('\u{b4c}', "\u{b47}\u{b57}"), ('\u{b5c}', "\u{b21}\u{b3c}"), ('\u{b5d}', "\u{b22}\u{b3c}"),
+    ('\u{b94}', "\u{b92}\u{bd7}"), ('\u{bca}', "\u{bc6}\u{bbe}"), ('\u{bcb}', "\u{bc7}\u{bbe}"),
+    ('\u{bcc}', "\u{bc6}\u{bd7}"), ('\u{c48}', "\u{c46}\u{c56}"), ('\u{cc0}', "\u{cbf}\u{cd5}"),
+    ('\u{cc7}', "\u{cc6}\u{cd5}"), ('\u{cc8}', "\u{cc6}\u{cd6}"), ('\u{cca}', "\u{cc6}\u{cc2}"),
+    ('\u{ccb}', "\u{cc6}\u{cc2}\u{cd5}"), ('\u{d4a}', "\u{d46}\u{d3e}"),
+    ('\u{d4b}', "\u{d47}\u{d3e}"), ('\u{d4c}', "\u{d46}\u{d57}"), ('\u{dda}', "\u{dd9}\u{dca}"),
+    ('\u{ddc}', "\u{dd9}\u{dcf}"), ('\u{ddd}', "\u{dd9}\u{dcf}\u{dca}"),
+    ('\u{dde}', "\u{dd9}\u{ddf}"), ('\u{f43}', "\u{f42}\u{fb7}"), ('\u{f4d}', "\u{f4c}\u{fb7}"),
+    ('\u{f52}', "\u{f51}\u{fb7}"), ('\u{f57}', "\u{f56}\u{fb7}"), ('\u{f5c}', "\u{f5b}\u{fb7}"),
+    ('\u{f69}', "\u{f40}\u{fb5}"), ('\u{f73}', "\u{f71}\u{f72}"), ('\u{f75}', "\u{f71}\u{f74}"),
+    ('\u{f76}', "\u{fb2}\u{f80}"), ('\u{f78}', "\u{fb3}\u{f80}"), ('\u{f81}', "\u{f71}\u{f80}"),
+    ('\u{f93}', "\u{f92}\u{fb7}"), ('\u{f9d}', "\u{f9c}\u{fb7}"), ('\u{fa2}', "\u{fa1}\u{fb7}"),
+    ('\u{fa7}', "\u{fa6}\u{fb7}"), ('\u{fac}', "\u{fab}\u{fb7}"), ('\u{fb9}', "\u{f90}\u{fb5}"),
+    ('\u{1026}', "\u{1025}\u{102e}"), ('\u{1b06}', "\u{1b05}\u{1b35}"),
+    ('\u{1b08}', "\u{1b07}\u{1b35}"), ('\u{1b0a}', "\u{1b09}\u{1b35}"),
+    ('\u{1b0c}', "\u{1b0b}\u{1b35}"), ('\u{1b0e}', "\u{1b0d}\u{1b35}"),
+    ('\u{1b12}', "\u{1b11}\u{1b35}"), ('\u{1b3b}', "\u{1b3a}\u{1b35}"),
+    ('\u{1b3d}', "\u{1b3c}\u{1b35}"), ('\u{1b40}', "\u{1b3e}\u{1b35}"),
+    ('\u{1b41}', "\u{1b3f}\u{1b35}"), ('\u{1b43}', "\u{1b42}\u{1b35}"),
+    ('\u{1e00}', "\u{41}\u{325}"), ('\u{1e01}', "\u{61}\u{325}"), ('\u{1e02}', "\u{42}\u{307}"),
+    ('\u{1e03}', "\u{62}\u{307}"), ('\u{1e04}', "\u{42}\u{323}"), ('\u{1e05}', "\u{62}\u{323}"),
+    ('\u{1e06}', "\u{42}\u{331}"), ('\u{1e07}', "\u{62}\u{331}"),
+    ('\u{1e08}', "\u{43}\u{327}\u{301}"), ('\u{1e09}', "\u{63}\u{327}\u{301}"),
+    ('\u{1e0a}', "\u{44}\u{307}"), ('\u{1e0b}', "\u{64}\u{307}"), ('\u{1e0c}', "\u{44}\u{323}"),
+    ('\u{1e0d}', "\u{64}\u{323}"), ('\u{1e0e}', "\u{44}\u{331}"), ('\u{1e0f}', "\u{64}\u{331}"),
+    ('\u{1e10}', "\u{44}\u{327}"), ('\u{1e11}', "\u{64}\u{327}"), ('\u{1e12}', "\u{44}\u{32d}"),
+    ('\u{1e13}', "\u{64}\u{32d}"), ('\u{1e14}', "\u{45}\u{304}\u{300}"),
+    ('\u{1e15}', "\u{65}\u{304}\u{300}"), ('\u{1e16}', "\u{45}\u{304}\u{301}"),
+    ('\u{1e17}', "\u{65}\u{304}\u{301}"), ('\u{1e18}', "\u{45}\u{32d}"),
+    ('\u{1e19}', "\u{65}\u{32d}"), ('\u{1e1a}', "\u{45}\u{330}"), ('\u{1e1b}', "\u{65}\u{330}"),
+    ('\u{1e1c}', "\u{45}\u{327}\u{306}"), ('\u{1e1d}', "\u{65}\u{327}\u{306}"),
+    ('\u{1e1e}', "\u{46}\u{307}"), ('\u{1e1f}', "\u{66}\u{307}"), ('\u{1e20}', "\u{47}\u{304}"),
+    ('\u{1e21}', "\u{67}\u{304}"), ('\u{1e22}', "\u{48}\u{307}"), ('\u{1e23}', "\u{68}\u{307}"),
+    ('\u{1e24}', "\u{48}\u{323}"), ('\u{1e25}', "\u{68}\u{323}"), ('\u{1e26}', "\u{48}\u{308}"),
+    ('\u{1e27}', "\u{68}\u{308}"), ('\u{1e28}', "\u{48}\u{327}"), ('\u{1e29}', "\u{68}\u{327}"),
+    ('\u{1e2a}', "\u{48}\u{32e}"), ('\u{1e2b}', "\u{68}\u{32e}"), ('\u{1e2c}', "\u{49}\u{330}"),
+    ('\u{1e2d}', "\u{69}\u{330}"), ('\u{1e2e}', "\u{49}\u{308}\u{301}"),
+    ('\u{1e2f}', "\u{69}\u{308}\u{301}"), ('\u{1e30}', "\u{4b}\u{301}"),
+    ('\u{1e31}', "\u{6b}\u{301}"), ('\u{1e32}', "\u{4b}\u{323}"), ('\u{1e33}', "\u{6b}\u{323}"),
+    ('\u{1e34}', "\u{4b}\u{331}"), ('\u{1e35}', "\u{6b}\u{331}"), ('\u{1e36}', "\u{4c}\u{323}"),
+    ('\u{1e37}', "\u{6c}\u{323}"), ('\u{1e38}', "\u{4c}\u{323}\u{304}"),
+    ('\u{1e39}', "\u{6c}\u{323}\u{304}"), ('\u{1e3a}', "\u{4c}\u{331}"),
+    ('\u{1e3b}', "\u{6c}\u{331}"), ('\u{1e3c}', "\u{4c}\u{32d}"), ('\u{1e3d}', "\u{6c}\u{32d}"),
+    ('\u{1e3e}', "\u{4d}\u{301}"), ('\u{1e3f}', "\u{6d}\u{301}"), ('\u{1e40}', "\u{4d}\u{307}"),
+    ('\u{1e41}', "\u{6d}\u{307}"), ('\u{1e42}', "\u{4d}\u{323}"), ('\u{1e43}', "\u{6d}\u{323}"),
+    ('\u{1e44}', "\u{4e}\u{307}"), ('\u{1e45}', "\u{6e}\u{307}"), ('\u{1e46}', "\u{4e}\u{323}"),
+    ('\u{1e47}', "\u{6e}\u{323}"), ('\u{1e48}', "\u{4e}\u{331}"), ('\u{1e49}', "\u{6e}\u{331}"),
+    ('\u{1e4a}', "\u{4e}\u{32d}"), ('\u{1e4b}', "\u{6e}\u{32d}"),
+    ('\u{1e4c}', "\u{4f}\u{303}\u{301}"), ('\u{1e4d}', "\u{6f}\u{303}\u{301}"),
+    ('\u{1e4e}', "\u{4f}\u{303}\u{308}"), ('\u{1e4f}', "\u{6f}\u{303}\u{308}"),
+    ('\u{1e50}', "\u{4f}\u{304}\u{300}"), ('\u{1e51}', "\u{6f}\u{304}\u{300}"),
+    ('\u{1e52}', "\u{4f}\u{304}\u{301}"), ('\u{1e53}', "\u{6f}\u{304}\u{301}"),
+    ('\u{1e54}', "\u{50}\u{301}"), ('\u{1e55}', "\u{70}\u{301}"), ('\u{1e56}', "\u{50}\u{307}"),
+    ('\u{1e57}', "\u{70}\u{307}"), ('\u{1e58}', "\u{52}\u{307}"), ('\u{1e59}', "\u{72}\u{307}"),
+    ('\u{1e5a}', "\u{52}\u{323}"), ('\u{1e5b}', "\u{72}\u{323}"),
+    ('\u{1e5c}', "\u{52}\u{323}\u{304}"), ('\u{1e5d}', "\u{72}\u{323}\u{304}"),
+    ('\u{1e5e}', "\u{52}\u{331}"), ('\u{1e5f}', "\u{72}\u{331}"), ('\u{1e60}', "\u{53}\u{307}"),
+    ('\u{1e61}', "\u{73}\u{307}"), ('\u{1e62}', "\u{53}\u{323}"), ('\u{1e63}', "\u{73}\u{323}"),
+    ('\u{1e64}', "\u{53}\u{301}\u{307}"), ('\u{1e65}', "\u{73}\u{301}\u{307}"),
+    ('\u{1e66}', "\u{53}\u{30c}\u{307}"), ('\u{1e67}', "\u{73}\u{30c}\u{307}"),
+    ('\u{1e68}', "\u{53}\u{323}\u{307}"), ('\u{1e69}', "\u{73}\u{323}\u{307}"),
+    ('\u{1e6a}', "\u{54}\u{307}"), ('\u{1e6b}', "\u{74}\u{307}"), ('\u{1e6c}', "\u{54}\u{323}"),
+    ('\u{1e6d}', "\u{74}\u{323}"), ('\u{1e6e}', "\u{54}\u{331}"), ('\u{1e6f}', "\u{74}\u{331}"),
+    ('\u{1e70}', "\u{54}\u{32d}"), ('\u{1e71}', "\u{74}\u{32d}"), ('\u{1e72}', "\u{55}\u{324}"),
+    ('\u{1e73}', "\u{75}\u{324}"), ('\u{1e74}', "\u{55}\u{330}"), ('\u{1e75}', "\u{75}\u{330}"),
+    ('\u{1e76}', "\u{55}\u{32d}"), ('\u{1e77}', "\u{75}\u{32d}"),
+    ('\u{1e78}', "\u{55}\u{303}\u{301}"), ('\u{1e79}', "\u{75}\u{303}\u{301}"),
+    ('\u{1e7a}', "\u{55}\u{304}\u{308}"), ('\u{1e7b}', "\u{75}\u{304}\u{308}"),
+    ('\u{1e7c}', "\u{56}\u{303}"), ('\u{1e7d}', "\u{76}\u{303}"), ('\u{1e7e}', "\u{56}\u{323}"),
+    ('\u{1e7f}', "\u{76}\u{323}"), ('\u{1e80}', "\u{57}\u{300}"), ('\u{1e81}', "\u{77}\u{300}"),
+    ('\u{1e82}', "\u{57}\u{301}"), ('\u{1e83}', "\u{77}\u{301}"), ('\u{1e84}', "\u{57}\u{308}"),
+    ('\u{1e85}', "\u{77}\u{308}"), ('\u{1e86}', "\u{57}\u{307}"), ('\u{1e87}', "\u{77}\u{307}"),
+    ('\u{1e88}', "\u{57}\u{323}"), ('\u{1e89}', "\u{77}\u{323}"), ('\u{1e8a}', "\u{58}\u{307}"),
+    ('\u{1e8b}', "\u{78}\u{307}"), ('\u{1e8c}', "\u{58}\u{308}"), ('\u{1e8d}', "\u{78}\u{308}"),
+    ('\u{1e8e}', "\u{59}\u{307}"), ('\u{1e8f}', "\u{79}\u{307}"), ('\u{1e90}', "\u{5a}\u{302}"),
+    ('\u{1e91}', "\u{7a}\u{302}"), ('\u{1e92}', "\u{5a}\u{323}"), ('\u{1e93}', "\u{7a}\u{323}"),
+    ('\u{1e94}', "\u{5a}\u{331}"), ('\u{1e95}', "\u{7a}\u{331}"), ('\u{1e96}', "\u{68}\u{331}"),
+    ('\u{1e97}', "\u{74}\u{308}"), ('\u{1e98}', "\u{77}\u{30a}"), ('\u{1e99}', "\u{79}\u{30a}"),
+    ('\u{1e9b}', "\u{17f}\u{307}"), ('\u{1ea0}', "\u{41}\u{323}"), ('\u{1ea1}', "\u{61}\u{323}"),
+    ('\u{1ea2}', "\u{41}\u{309}"), ('\u{1ea3}', "\u{61}\u{309}"),
+    ('\u{1ea4}', "\u{41}\u{302}\u{301}"), ('\u{1ea5}', "\u{61}\u{302}\u{301}"),
+    ('\u{1ea6}', "\u{41}\u{302}\u{300}"), ('\u{1ea7}', "\u{61}\u{302}\u{300}"),
+    ('\u{1ea8}', "\u{41}\u{302}\u{309}"), ('\u{1ea9}', "\u{61}\u{302}\u{309}"),
+    ('\u{1eaa}', "\u{41}\u{302}\u{303}"), ('\u{1eab}', "\u{61}\u{302}\u{303}"),
+    ('\u{1eac}', "\u{41}\u{323}\u{302}"), ('\u{1ead}', "\u{61}\u{323}\u{302}"),
+    ('\u{1eae}', "\u{41}\u{306}\u{301}"), ('\u{1eaf}', "\u{61}\u{306}\u{301}"),
+    ('\u{1eb0}', "\u{41}\u{306}\u{300}"), ('\u{1eb1}', "\u{61}\u{306}\u{300}"),
+    ('\u{1eb2}', "\u{41}\u{306}\u{309}"), ('\u{1eb3}', "\u{61}\u{306}\u{309}"),
+    ('\u{1eb4}', "\u{41}\u{306}\u{303}"), ('\u{1eb5}', "\u{61}\u{306}\u{303}"),
+    ('\u{1eb6}', "\u{41}\u{323}\u{306}"), ('\u{1eb7}', "\u{61}\u{323}\u{306}"),
+    ('\u{1eb8}', "\u{45}\u{323}"), ('\u{1eb9}', "\u{65}\u{323}"), ('\u{1eba}', "\u{45}\u{309}"),
+    ('\u{1ebb}', "\u{65}\u{309}"), ('\u{1ebc}', "\u{45}\u{303}"), ('\u{1ebd}', "\u{65}\u{303}"),
+    ('\u{1ebe}', "\u{45}\u{302}\u{301}"), ('\u{1ebf}', "\u{65}\u{302}\u{301}"),
+    ('\u{1ec0}', "\u{45}\u{302}\u{300}"), ('\u{1ec1}', "\u{65}\u{302}\u{300}"),
+    ('\u{1ec2}', "\u{45}\u{302}\u{309}"), ('\u{1ec3}', "\u{65}\u{302}\u{309}"),
+    ('\u{1ec4}', "\u{45}\u{302}\u{303}"), ('\u{1ec5}', "\u{65}\u{302}\u{303}"),
+    ('\u{1ec6}', "\u{45}\u{323}\u{302}"), ('\u{1ec7}', "\u{65}\u{323}\u{302}"),
+    ('\u{1ec8}', "\u{49}\u{309}"), ('\u{1ec9}', "\u{69}\u{309}"), ('\u{1eca}', "\u{49}\u{323}"),
+    ('\u{1ecb}', "\u{69}\u{323}"), ('\u{1ecc}', "\u{4f}\u{323}"), ('\u{1ecd}', "\u{6f}\u{323}"),
+    ('\u{1ece}', "\u{4f}\u{309}"), ('\u{1ecf}', "\u{6f}\u{309}"),
+    ('\u{1ed0}', "\u{4f}\u{302}\u{301}"), ('\u{1ed1}', "\u{6f}\u{302}\u{301}"),
+    ('\u{1ed2}', "\u{4f}\u{302}\u{300}"), ('\u{1ed3}', "\u{6f}\u{302}\u{300}"),
+    ('\u{1ed4}', "\u{4f}\u{302}\u{309}"), ('\u{1ed5}', "\u{6f}\u{302}\u{309}"),
+    ('\u{1ed6}', "\u{4f}\u{302}\u{303}"), ('\u{1ed7}', "\u{6f}\u{302}\u{303}"),
+    ('\u{1ed8}', "\u{4f}\u{323}\u{302}"), ('\u{1ed9}', "\u{6f}\u{323}\u{302}"),
+    ('\u{1eda}', "\u{4f}\u{31b}\u{301}"), ('\u{1edb}', "\u{6f}\u{31b}\u{301}"),
+    ('\u{1edc}', "\u{4f}\u{31b}\u{300}"), ('\u{1edd}', "\u{6f}\u{31b}\u{300}"),
+    ('\u{1ede}', "\u{4f}\u{31b}\u{309}"), ('\u{1edf}', "\u{6f}\u{31b}\u{309}"),
+    ('\u{1ee0}', "\u{4f}\u{31b}\u{303}"), ('\u{1ee1}', "\u{6f}\u{31b}\u{303}"),
+    ('\u{1ee2}', "\u{4f}\u{31b}\u{323}"), ('\u{1ee3}', "\u{6f}\u{31b}\u{323}"),
+    ('\u{1ee4}', "\u{55}\u{323}"), ('\u{1ee5}', "\u{75}\u{323}"), ('\u{1ee6}', "\u{55}\u{309}"),
+    ('\u{1ee7}', "\u{75}\u{309}"), ('\u{1ee8}', "\u{55}\u{31b}\u{301}"),
+    ('\u{1ee9}', "\u{75}\u{31b}\u{301}"), ('\u{1eea}', "\u{55}\u{31b}\u{300}"),
+    ('\u{1eeb}', "\u{75}\u{31b}\u{300}"), ('\u{1eec}', "\u{55}\u{31b}\u{309}"),
+    ('\u{1eed}', "\u{75}\u{31b}\u{309}"), ('\u{1eee}', "\u{55}\u{31b}\u{303}"),
+    ('\u{1eef}', "\u{75}\u{31b}\u{303}"), ('\u{1ef0}', "\u{55}\u{31b}\u{323}"),
+    ('\u{1ef1}', "\u{75}\u{31b}\u{323}"), ('\u{1ef2}', "\u{59}\u{300}"),
+    ('\u{1ef3}', "\u{79}\u{300}"), ('\u{1ef4}', "\u{59}\u{323}"), ('\u{1ef5}', "\u{79}\u{323}"),
+    ('\u{1ef6}', "\u{59}\u{309}"), ('\u{1ef7}', "\u{79}\u{309}"), ('\u{1ef8}', "\u{59}\u{303}"),
+    ('\u{1ef9}', "\u{79}\u{303}"), ('\u{1f00}', "\u{3b1}\u{313}"), ('\u{1f01}', "\u{3b1}\u{314}"),
+    ('\u{1f02}', "\u{3b1}\u{313}\u{300}"), ('\u{1f03}', "\u{3b1}\u{314}\u{300}"),
+    ('\u{1f04}', "\u{3b1}\u{313}\u{301}"), ('\u{1f05}', "\u{3b1}\u{314}\u{301}"),
+    ('\u{1f06}', "\u{3b1}\u{313}\u{342}"), ('\u{1f07}', "\u{3b1}\u{314}\u{342}"),
+    ('\u{1f08}', "\u{391}\u{313}"), ('\u{1f09}', "\u{391}\u{314}"),
+    ('\u{1f0a}', "\u{391}\u{313}\u{300}"), ('\u{1f0b}', "\u{391}\u{314}\u{300}"),
+    ('\u{1f0c}', "\u{391}\u{313}\u{301}"), ('\u{1f0d}', "\u{391}\u{314}\u{301}"),
+    ('\u{1f0e}', "\u{391}\u{313}\u{342}"), ('\u{1f0f}', "\u{391}\u{314}\u{342}"),
+    ('\u{1f10}', "\u{3b5}\u{313}"), ('\u{1f11}', "\u{3b5}\u{314}"),
+    ('\u{1f12}', "\u{3b5}\u{313}\u{300}"), ('\u{1f13}', "\u{3b5}\u{314}\u{300}"),
+    ('\u{1f14}', "\u{3b5}\u{313}\u{301}"), ('\u{1f15}', "\u{3b5}\u{314}\u{301}"),
+    ('\u{1f18}', "\u{395}\u{313}"), ('\u{1f19}', "\u{395}\u{314}"),
+    ('\u{1f1a}', "\u{395}\u{313}\u{300}"), ('\u{1f1b}', "\u{395}\u{314}\u{300}"),
+    ('\u{1f1c}', "\u{395}\u{313}\u{301}"), ('\u{1f1d}', "\u{395}\u{314}\u{301}"),
+    ('\u{1f20}', "\u{3b7}\u{313}"), ('\u{1f21}', "\u{3b7}\u{314}"),
+    ('\u{1f22}', "\u{3b7}\u{313}\u{300}"), ('\u{1f23}', "\u{3b7}\u{314}\u{300}"),
+    ('\u{1f24}', "\u{3b7}\u{313}\u{301}"), ('\u{1f25}', "\u{3b7}\u{314}\u{301}"),
+    ('\u{1f26}', "\u{3b7}\u{313}\u{342}"), ('\u{1f27}', "\u{3b7}\u{314}\u{342}"),
+    ('\u{1f28}', "\u{397}\u{313}"), ('\u{1f29}', "\u{397}\u{314}"),
+    ('\u{1f2a}', "\u{397}\u{313}\u{300}"), ('\u{1f2b}', "\u{397}\u{314}\u{300}"),
+    ('\u{1f2c}', "\u{397}\u{313}\u{301}"), ('\u{1f2d}', "\u{397}\u{314}\u{301}"),
+    ('\u{1f2e}', "\u{397}\u{313}\u{342}"), ('\u{1f2f}', "\u{397}\u{314}\u{342}"),
+    ('\u{1f30}', "\u{3b9}\u{313}"), ('\u{1f31}', "\u{3b9}\u{314}"),
+    ('\u{1f32}', "\u{3b9}\u{313}\u{300}"), ('\u{1f33}', "\u{3b9}\u{314}\u{300}"),
+    ('\u{1f34}', "\u{3b9}\u{313}\u{301}"), ('\u{1f35}', "\u{3b9}\u{314}\u{301}"),
+    ('\u{1f36}', "\u{3b9}\u{313}\u{342}"), ('\u{1f37}', "\u{3b9}\u{314}\u{342}"),
+    ('\u{1f38}', "\u{399}\u{313}"), ('\u{1f39}', "\u{399}\u{314}"),
+    ('\u{1f3a}', "\u{399}\u{313}\u{300}"), ('\u{1f3b}', "\u{399}\u{314}\u{300}"),
+    ('\u{1f3c}', "\u{399}\u{313}\u{301}"), ('\u{1f3d}', "\u{399}\u{314}\u{301}"),
+    ('\u{1f3e}', "\u{399}\u{313}\u{342}"), ('\u{1f3f}', "\u{399}\u{314}\u{342}"),
+    ('\u{1f40}', "\u{3bf}\u{313}"), ('\u{1f41}', "\u{3bf}\u{314}"),
+    ('\u{1f42}', "\u{3bf}\u{313}\u{300}"), ('\u{1f43}', "\u{3bf}\u{314}\u{300}"),
+    ('\u{1f44}', "\u{3bf}\u{313}\u{301}"), ('\u{1f45}', "\u{3bf}\u{314}\u{301}"),
+    ('\u{1f48}', "\u{39f}\u{313}"), ('\u{1f49}', "\u{39f}\u{314}"),
+    ('\u{1f4a}', "\u{39f}\u{313}\u{300}"), ('\u{1f4b}', "\u{39f}\u{314}\u{300}"),
+    ('\u{1f4c}', "\u{39f}\u{313}\u{301}"), ('\u{1f4d}', "\u{39f}\u{314}\u{301}"),
+    ('\u{1f50}', "\u{3c5}\u{313}"), ('\u{1f51}', "\u{3c5}\u{314}"),
+    ('\u{1f52}', "\u{3c5}\u{313}\u{300}"), ('\u{1f53}', "\u{3c5}\u{314}\u{300}"),
+    ('\u{1f54}', "\u{3c5}\u{313}\u{301}"), ('\u{1f55}', "\u{3c5}\u{314}\u{301}"),
+    ('\u{1f56}', "\u{3c5}\u{313}\u{342}"), ('\u{1f57}', "\u{3c5}\u{314}\u{342}"),
+    ('\u{1f59}', "\u{3a5}\u{314}"), ('\u{1f5b}', "\u{3a5}\u{314}\u{300}"),
+    ('\u{1f5d}', "\u{3a5}\u{314}\u{301}"), ('\u{1f5f}', "\u{3a5}\u{314}\u{342}"),
+    ('\u{1f60}', "\u{3c9}\u{313}"), ('\u{1f61}', "\u{3c9}\u{314}"),
+    ('\u{1f62}', "\u{3c9}\u{313}\u{300}"), ('\u{1f63}', "\u{3c9}\u{314}\u{300}"),
+    ('\u{1f64}', "\u{3c9}\u{313}\u{301}"), ('\u{1f65}', "\u{3c9}\u{314}\u{301}"),
+    ('\u{1f66}', "\u{3c9}\u{313}\u{342}"), ('\u{1f67}', "\u{3c9}\u{314}\u{342}"),
+    ('\u{1f68}', "\u{3a9}\u{313}"), ('\u{1f69}', "\u{3a9}\u{314}"),
+    ('\u{1f6a}', "\u{3a9}\u{313}\u{300}"), ('\u{1f6b}', "\u{3a9}\u{314}\u{300}"),
+    ('\u{1f6c}', "\u{3a9}\u{313}\u{301}"), ('\u{1f6d}', "\u{3a9}\u{314}\u{301}"),
+    ('\u{1f6e}', "\u{3a9}\u{313}\u{342}"), ('\u{1f6f}', "\u{3a9}\u{314}\u{342}"),
+    ('\u{1f70}', "\u{3b1}\u{300}"), ('\u{1f71}', "\u{3b1}\u{301}"),
+    ('\u{1f72}', "\u{3b5}\u{300}"), ('\u{1f73}', "\u{3b5}\u{301}"),
+    ('\u{1f74}', "\u{3b7}\u{300}"), ('\u{1f75}', "\u{3b7}\u{301}"),
+    ('\u{1f76}', "\u{3b9}\u{300}"), ('\u{1f77}', "\u{3b9}\u{301}"),
+    ('\u{1f78}', "\u{3bf}\u{300}"), ('\u{1f79}', "\u{3bf}\u{301}"),
+    ('\u{1f7a}', "\u{3c5}\u{300}"), ('\u{1f7b}', "\u{3c5}\u{301}"),
+    ('\u{1f7c}', "\u{3c9}\u{300}"), ('\u{1f7d}', "\u{3c9}\u{301}"),
+    ('\u{1f80}', "\u{3b1}\u{313}\u{345}"), ('\u{1f81}', "\u{3b1}\u{314}\u{345}"),
+    ('\u{1f82}', "\u{3b1}\u{313}\u{300}\u{345}"), ('\u{1f83}', "\u{3b1}\u{314}\u{300}\u{345}"),
+    ('\u{1f84}', "\u{3b1}\u{313}\u{301}\u{345}"), ('\u{1f85}', "\u{3b1}\u{314}\u{301}\u{345}"),
+    ('\u{1f86}', "\u{3b1}\u{313}\u{342}\u{345}"), ('\u{1f87}', "\u{3b1}\u{314}\u{342}\u{345}"),
+    ('\u{1f88}', "\u{391}\u{313}\u{345}"), ('\u{1f89}', "\u{391}\u{314}\u{345}"),
+    ('\u{1f8a}', "\u{391}\u{313}\u{300}\u{345}"), ('\u{1f8b}', "\u{391}\u{314}\u{300}\u{345}"),
+    ('\u{1f8c}', "\u{391}\u{313}\u{301}\u{345}"), ('\u{1f8d}', "\u{391}\u{314}\u{301}\u{345}"),
+    ('\u{1f8e}', "\u{391}\u{313}\u{342}\u{345}"), ('\u{1f8f}', "\u{391}\u{314}\u{342}\u{345}"),
+    ('\u{1f90}', "\u{3b7}\u{313}\u{345}"), ('\u{1f91}', "\u{3b7}\u{314}\u{345}"),
+    ('\u{1f92}', "\u{3b7}\u{313}\u{300}\u{345}"), ('\u{1f93}', "\u{3b7}\u{314}\u{300}\u{345}"),
+    ('\u{1f94}', "\u{3b7}\u{313}\u{301}\u{345}"), ('\u{1f95}', "\u{3b7}\u{314}\u{301}\u{345}"),
+    ('\u{1f96}', "\u{3b7}\u{313}\u{342}\u{345}"), ('\u{1f97}', "\u{3b7}\u{314}\u{342}\u{345}"),
+    ('\u{1f98}', "\u{397}\u{313}\u{345}"), ('\u{1f99}', "\u{397}\u{314}\u{345}"),
+    ('\u{1f9a}', "\u{397}\u{313}\u{300}\u{345}"), ('\u{1f9b}', "\u{397}\u{314}\u{300}\u{345}"),
+    ('\u{1f9c}', "\u{397}\u{313}\u{301}\u{345}"), ('\u{1f9d}', "\u{397}\u{314}\u{301}\u{345}"),
+    ('\u{1f9e}', "\u{397}\u{313}\u{342}\u{345}"), ('\u{1f9f}', "\u{397}\u{314}\u{342}\u{345}"),
+    ('\u{1fa0}', "\u{3c9}\u{313}\u{345}"), ('\u{1fa1}', "\u{3c9}\u{314}\u{345}"),
+    ('\u{1fa2}', "\u{3c9}\u{313}\u{300}\u{345}"), ('\u{1fa3}', "\u{3c9}\u{314}\u{300}\u{345}"),
+    ('\u{1fa4}', "\u{3c9}\u{313}\u{301}\u{345}"), ('\u{1fa5}', "\u{3c9}\u{314}\u{301}\u{345}"),
+    ('\u{1fa6}', "\u{3c9}\u{313}\u{342}\u{345}"), ('\u{1fa7}', "\u{3c9}\u{314}\u{342}\u{345}"),
+    ('\u{1fa8}', "\u{3a9}\u{313}\u{345}"), ('\u{1fa9}', "\u{3a9}\u{314}\u{345}"),
+    ('\u{1faa}', "\u{3a9}\u{313}\u{300}\u{345}"), ('\u{1fab}', "\u{3a9}\u{314}\u{300}\u{345}"),
+    ('\u{1fac}', "\u{3a9}\u{313}\u{301}\u{345}"), ('\u{1fad}', "\u{3a9}\u{314}\u{301}\u{345}"),
+    ('\u{1fae}', "\u{3a9}\u{313}\u{342}\u{345}"), ('\u{1faf}', "\u{3a9}\u{314}\u{342}\u{345}"),
+    ('\u{1fb0}', "\u{3b1}\u{306}"), ('\u{1fb1}', "\u{3b1}\u{304}"),
+    ('\u{1fb2}', "\u{3b1}\u{300}\u{345}"), ('\u{1fb3}', "\u{3b1}\u{345}"),
+    ('\u{1fb4}', "\u{3b1}\u{301}\u{345}"), ('\u{1fb6}', "\u{3b1}\u{342}"),
+    ('\u{1fb7}', "\u{3b1}\u{342}\u{345}"), ('\u{1fb8}', "\u{391}\u{306}"),
+    ('\u{1fb9}', "\u{391}\u{304}"), ('\u{1fba}', "\u{391}\u{300}"),
+    ('\u{1fbb}', "\u{391}\u{301}"), ('\u{1fbc}', "\u{391}\u{345}"), ('\u{1fbe}', "\u{3b9}"),
+    ('\u{1fc1}', "\u{a8}\u{342}"), ('\u{1fc2}', "\u{3b7}\u{300}\u{345}"),
+    ('\u{1fc3}', "\u{3b7}\u{345}"), ('\u{1fc4}', "\u{3b7}\u{301}\u{345}"),
+    ('\u{1fc6}', "\u{3b7}\u{342}"), ('\u{1fc7}', "\u{3b7}\u{342}\u{345}"),
+    ('\u{1fc8}', "\u{395}\u{300}"), ('\u{1fc9}', "\u{395}\u{301}"),
+    ('\u{1fca}', "\u{397}\u{300}"), ('\u{1fcb}', "\u{397}\u{301}"),
+    ('\u{1fcc}', "\u{397}\u{345}"), ('\u{1fcd}', "\u{1fbf}\u{300}"),
+    ('\u{1fce}', "\u{1fbf}\u{301}"), ('\u{1fcf}', "\u{1fbf}\u{342}"),
+    ('\u{1fd0}', "\u{3b9}\u{306}"), ('\u{1fd1}', "\u{3b9}\u{304}"),
+    ('\u{1fd2}', "\u{3b9}\u{308}\u{300}"), ('\u{1fd3}', "\u{3b9}\u{308}\u{301}"),
+    ('\u{1fd6}', "\u{3b9}\u{342}"), ('\u{1fd7}', "\u{3b9}\u{308}\u{342}"),
+    ('\u{1fd8}', "\u{399}\u{306}"), ('\u{1fd9}', "\u{399}\u{304}"),
+    ('\u{1fda}', "\u{399}\u{300}"), ('\u{1fdb}', "\u{399}\u{301}"),
+    ('\u{1fdd}', "\u{1ffe}\u{300}"), ('\u{1fde}', "\u{1ffe}\u{301}"),
+    ('\u{1fdf}', "\u{1ffe}\u{342}"), ('\u{1fe0}', "\u{3c5}\u{306}"),
+    ('\u{1fe1}', "\u{3c5}\u{304}"), ('\u{1fe2}', "\u{3c5}\u{308}\u{300}"),
+    ('\u{1fe3}', "\u{3c5}\u{308}\u{301}"), ('\u{1fe4}', "\u{3c1}\u{313}"),
+    ('\u{1fe5}', "\u{3c1}\u{314}"), ('\u{1fe6}', "\u{3c5}\u{342}"),
+    ('\u{1fe7}', "\u{3c5}\u{308}\u{342}"), ('\u{1fe8}', "\u{3a5}\u{306}"),
+    ('\u{1fe9}', "\u{3a5}\u{304}"), ('\u{1fea}', "\u{3a5}\u{300}"),
+    ('\u{1feb}', "\u{3a5}\u{301}"), ('\u{1fec}', "\u{3a1}\u{314}"), ('\u{1fed}', "\u{a8}\u{300}"),
+    ('\u{1fee}', "\u{a8}\u{301}"), ('\u{1fef}', "\u{60}"), ('\u{1ff2}', "\u{3c9}\u{300}\u{345}"),
+    ('\u{1ff3}', "\u{3c9}\u{345}"), ('\u{1ff4}', "\u{3c9}\u{301}\u{345}"),
+    ('\u{1ff6}', "\u{3c9}\u{342}"), ('\u{1ff7}', "\u{3c9}\u{342}\u{345}"),
+    ('\u{1ff8}', "\u{39f}\u{300}"), ('\u{1ff9}', "\u{39f}\u{301}"),
+    ('\u{1ffa}', "\u{3a9}\u{300}"), ('\u{1ffb}', "\u{3a9}\u{301}"),
+    ('\u{1ffc}', "\u{3a9}\u{345}"), ('\u{1ffd}', "\u{b4}"), ('\u{2000}', "\u{2002}"),
+    ('\u{2001}', "\u{2003}"), ('\u{2126}', "\u{3a9}"), ('\u{212a}', "\u{4b}"),
+    ('\u{212b}', "\u{41}\u{30a}"), ('\u{219a}', "\u{2190}\u{338}"),
+    ('\u{219b}', "\u{2192}\u{338}"), ('\u{21ae}', "\u{2194}\u{338}"),
+    ('\u{21cd}', "\u{21d0}\u{338}"), ('\u{21ce}', "\u{21d4}\u{338}"),
+    ('\u{21cf}', "\u{21d2}\u{338}"), ('\u{2204}', "\u{2203}\u{338}"),
+    ('\u{2209}', "\u{2208}\u{338}"), ('\u{220c}', "\u{220b}\u{338}"),
+    ('\u{2224}', "\u{2223}\u{338}"), ('\u{2226}', "\u{2225}\u{338}"),
+    ('\u{2241}', "\u{223c}\u{338}"), ('\u{2244}', "\u{2243}\u{338}"),
+    ('\u{2247}', "\u{2245}\u{338}"), ('\u{2249}', "\u{2248}\u{338}"),
+    ('\u{2260}', "\u{3d}\u{338}"), ('\u{2262}', "\u{2261}\u{338}"),
+    ('\u{226d}', "\u{224d}\u{338}"), ('\u{226e}', "\u{3c}\u{338}"), ('\u{226f}', "\u{3e}\u{338}"),
+    ('\u{2270}', "\u{2264}\u{338}"), ('\u{2271}', "\u{2265}\u{338}"),
+    ('\u{2274}', "\u{2272}\u{338}"), ('\u{2275}', "\u{2273}\u{338}"),
+    ('\u{2278}', "\u{2276}\u{338}"), ('\u{2279}', "\u{2277}\u{338}"),
+    ('\u{2280}', "\u{227a}\u{338}"), ('\u{2281}', "\u{227b}\u{338}"),
+    ('\u{2284}', "\u{2282}\u{338}"), ('\u{2285}', "\u{2283}\u{338}"),
+    ('\u{2288}', "\u{2286}\u{338}"), ('\u{2289}', "\u{2287}\u{338}"),
+    ('\u{22ac}', "\u{22a2}\u{338}"), ('\u{22ad}', "\u{22a8}\u{338}"),
+    ('\u{22ae}', "\u{22a9}\u{338}"), ('\u{22af}', "\u{22ab}\u{338}"),
+    ('\u{22e0}', "\u{227c}\u{338}"), ('\u{22e1}', "\u{227d}\u{338}"),
+    ('\u{22e2}', "\u{2291}\u{338}"), ('\u{22e3}', "\u{2292}\u{338}"),
+    ('\u{22ea}', "\u{22b2}\u{338}"), ('\u{22eb}', "\u{22b3}\u{338}"),
+    ('\u{22ec}', "\u{22b4}\u{338}"), ('\u{22ed}', "\u{22b5}\u{338}"), ('\u{2329}', "\u{3008}"),
+    ('\u{232a}', "\u{3009}"), ('\u{2adc}', "\u{2add}\u{338}"), ('\u{304c}', "\u{304b}\u{3099}"),
+    ('\u{304e}', "\u{304d}\u{3099}"), ('\u{3050}', "\u{304f}\u{3099}"),
+    ('\u{3052}', "\u{3051}\u{3099}"), ('\u{3054}', "\u{3053}\u{3099}"),
+    ('\u{3056}', "\u{3055}\u{3099}"), ('\u{3058}', "\u{3057}\u{3099}"),
+    ('\u{305a}', "\u{3059}\u{3099}"), ('\u{305c}', "\u{305b}\u{3099}"),
+    ('\u{305e}', "\u{305d}\u{3099}"), ('\u{3060}', "\u{305f}\u{3099}"),
+    ('\u{3062}', "\u{3061}\u{3099}"), ('\u{3065}', "\u{3064}\u{3099}"),
+    ('\u{3067}', "\u{3066}\u{3099}"), ('\u{3069}', "\u{3068}\u{3099}"),
+    ('\u{3070}', "\u{306f}\u{3099}"), ('\u{3071}', "\u{306f}\u{309a}"),
+    ('\u{3073}', "\u{3072}\u{3099}"), ('\u{3074}', "\u{3072}\u{309a}"),
+    ('\u{3076}', "\u{3075}\u{3099}"), ('\u{3077}', "\u{3075}\u{309a}"),
+    ('\u{3079}', "\u{3078}\u{3099}"), ('\u{307a}', "\u{3078}\u{309a}"),
+    ('\u{307c}', "\u{307b}\u{3099}"), ('\u{307d}', "\u{307b}\u{309a}"),
+    ('\u{3094}', "\u{3046}\u{3099}"), ('\u{309e}', "\u{309d}\u{3099}"),
+    ('\u{30ac}', "\u{30ab}\u{3099}"), ('\u{30ae}', "\u{30ad}\u{3099}"),
+    ('\u{30b0}', "\u{30af}\u{3099}"), ('\u{30b2}', "\u{30b1}\u{3099}"),
+    ('\u{30b4}', "\u{30b3}\u{3099}"), ('\u{30b6}', "\u{30b5}\u{3099}"),
+    ('\u{30b8}', "\u{30b7}\u{3099}"), ('\u{30ba}', "\u{30b9}\u{3099}"),
+    ('\u{30bc}', "\u{30bb}\u{3099}"), ('\u{30be}', "\u{30bd}\u{3099}"),
+    ('\u{30c0}', "\u{30bf}\u{3099}"), ('\u{30c2}', "\u{30c1}\u{3099}"),
+    ('\u{30c5}', "\u{30c4}\u{3099}"), ('\u{30c7}', "\u{30c6}\u{3099}"),
+    ('\u{30c9}', "\u{30c8}\u{3099}"), ('\u{30d0}', "\u{30cf}\u{3099}"),
+    ('\u{30d1}', "\u{30cf}\u{309a}"), ('\u{30d3}', "\u{30d2}\u{3099}"),
+    ('\u{30d4}', "\u{30d2}\u{309a}"), ('\u{30d6}', "\u{30d5}\u{3099}"),
+    ('\u{30d7}', "\u{30d5}\u{309a}"), ('\u{30d9}', "\u{30d8}\u{3099}"),
+    ('\u{30da}', "\u{30d8}\u{309a}"), ('\u{30dc}', "\u{30db}\u{3099}"),
+    ('\u{30dd}', "\u{30db}\u{309a}"), ('\u{30f4}', "\u{30a6}\u{3099}"),
+    ('\u{30f7}', "\u{30ef}\u{3099}"), ('\u{30f8}', "\u{30f0}\u{3099}"),
+    ('\u{30f9}', "\u{30f1}\u{3099}"), ('\u{30fa}', "\u{30f2}\u{3099}"),
+    ('\u{30fe}', "\u{30fd}\u{3099}"), ('\u{f900}', "\u{8c48}"), ('\u{f901}', "\u{66f4}"),
+    ('\u{f902}', "\u{8eca}"), ('\u{f903}', "\u{8cc8}"), ('\u{f904}', "\u{6ed1}"),
+    ('\u{f905}', "\u{4e32}"), ('\u{f906}', "\u{53e5}"), ('\u{f907}', "\u{9f9c}"),
+    ('\u{f908}', "\u{9f9c}"), ('\u{f909}', "\u{5951}"), ('\u{f90a}', "\u{91d1}"),
+    ('\u{f90b}', "\u{5587}"), ('\u{f90c}', "\u{5948}"), ('\u{f90d}', "\u{61f6}"),
+    ('\u{f90e}', "\u{7669}"), ('\u{f90f}', "\u{7f85}"), ('\u{f910}', "\u{863f}"),
+    ('\u{f911}', "\u{87ba}"), ('\u{f912}', "\u{88f8}"), ('\u{f913}', "\u{908f}"),
+    ('\u{f914}', "\u{6a02}"), ('\u{f915}', "\u{6d1b}"), ('\u{f916}', "\u{70d9}"),
+    ('\u{f917}', "\u{73de}"), ('\u{f918}', "\u{843d}"), ('\u{f919}', "\u{916a}"),
+    ('\u{f91a}', "\u{99f1}"), ('\u{f91b}', "\u{4e82}"), ('\u{f91c}', "\u{5375}"),
+    ('\u{f91d}', "\u{6b04}"), ('\u{f91e}', "\u{721b}"), ('\u{f91f}', "\u{862d}"),
+    ('\u{f920}', "\u{9e1e}"), ('\u{f921}', "\u{5d50}"), ('\u{f922}', "\u{6feb}"),
+    ('\u{f923}', "\u{85cd}"), ('\u{f924}', "\u{8964}"), ('\u{f925}', "\u{62c9}"),
+    ('\u{f926}', "\u{81d8}"), ('\u{f927}', "\u{881f}"), ('\u{f928}', "\u{5eca}"),
+    ('\u{f929}', "\u{6717}"), ('\u{f92a}', "\u{6d6a}"), ('\u{f92b}', "\u{72fc}"),
+    ('\u{f92c}', "\u{90ce}"), ('\u{f92d}', "\u{4f86}"), ('\u{f92e}', "\u{51b7}"),
+    ('\u{f92f}', "\u{52de}"), ('\u{f930}', "\u{64c4}"), ('\u{f931}', "\u{6ad3}"),
+    ('\u{f932}', "\u{7210}"), ('\u{f933}', "\u{76e7}"), ('\u{f934}', "\u{8001}"),
+    ('\u{f935}', "\u{8606}"), ('\u{f936}', "\u{865c}"), ('\u{f937}', "\u{8def}"),
+    ('\u{f938}', "\u{9732}"), ('\u{f939}', "\u{9b6f}"), ('\u{f93a}', "\u{9dfa}"),
+    ('\u{f93b}', "\u{788c}"), ('\u{f93c}', "\u{797f}"), ('\u{f93d}', "\u{7da0}"),
+    ('\u{f93e}', "\u{83c9}"), ('\u{f93f}', "\u{9304}"), ('\u{f940}', "\u{9e7f}"),
+    ('\u{f941}', "\u{8ad6}"), ('\u{f942}', "\u{58df}"), ('\u{f943}', "\u{5f04}"),
+    ('\u{f944}', "\u{7c60}"), ('\u{f945}', "\u{807e}"), ('\u{f946}', "\u{7262}"),
+    ('\u{f947}', "\u{78ca}"), ('\u{f948}', "\u{8cc2}"), ('\u{f949}', "\u{96f7}"),
+    ('\u{f94a}', "\u{58d8}"), ('\u{f94b}', "\u{5c62}"), ('\u{f94c}', "\u{6a13}"),
+    ('\u{f94d}', "\u{6dda}"), ('\u{f94e}', "\u{6f0f}"), ('\u{f94f}', "\u{7d2f}"),
+    ('\u{f950}', "\u{7e37}"), ('\u{f951}', "\u{964b}"), ('\u{f952}', "\u{52d2}"),
+    ('\u{f953}', "\u{808b}"), ('\u{f954}', "\u{51dc}"), ('\u{f955}', "\u{51cc}"),
+    ('\u{f956}', "\u{7a1c}"), ('\u{f957}', "\u{7dbe}"), ('\u{f958}', "\u{83f1}"),
+    ('\u{f959}', "\u{9675}"), ('\u{f95a}', "\u{8b80}"), ('\u{f95b}', "\u{62cf}"),
+    ('\u{f95c}', "\u{6a02}"), ('\u{f95d}', "\u{8afe}"), ('\u{f95e}', "\u{4e39}"),
+    ('\u{f95f}', "\u{5be7}"), ('\u{f960}', "\u{6012}"), ('\u{f961}', "\u{7387}"),
+    ('\u{f962}', "\u{7570}"), ('\u{f963}', "\u{5317}"), ('\u{f964}', "\u{78fb}"),
+    ('\u{f965}', "\u{4fbf}"), ('\u{f966}', "\u{5fa9}"), ('\u{f967}', "\u{4e0d}"),
+    ('\u{f968}', "\u{6ccc}"), ('\u{f969}', "\u{6578}"), ('\u{f96a}', "\u{7d22}"),
+    ('\u{f96b}', "\u{53c3}"), ('\u{f96c}', "\u{585e}"), ('\u{f96d}', "\u{7701}"),
+    ('\u{f96e}', "\u{8449}"), ('\u{f96f}', "\u{8aaa}"), ('\u{f970}', "\u{6bba}"),
+    ('\u{f971}', "\u{8fb0}"), ('\u{f972}', "\u{6c88}"), ('\u{f973}', "\u{62fe}"),
+    ('\u{f974}', "\u{82e5}"), ('\u{f975}', "\u{63a0}"), ('\u{f976}', "\u{7565}"),
+    ('\u{f977}', "\u{4eae}"), ('\u{f978}', "\u{5169}"), ('\u{f979}', "\u{51c9}"),
+    ('\u{f97a}', "\u{6881}"), ('\u{f97b}', "\u{7ce7}"), ('\u{f97c}', "\u{826f}"),
+    ('\u{f97d}', "\u{8ad2}"), ('\u{f97e}', "\u{91cf}"), ('\u{f97f}', "\u{52f5}"),
+    ('\u{f980}', "\u{5442}"), ('\u{f981}', "\u{5973}"), ('\u{f982}', "\u{5eec}"),
+    ('\u{f983}', "\u{65c5}"), ('\u{f984}', "\u{6ffe}"), ('\u{f985}', "\u{792a}"),
+    ('\u{f986}', "\u{95ad}"), ('\u{f987}', "\u{9a6a}"), ('\u{f988}', "\u{9e97}"),
+    ('\u{f989}', "\u{9ece}"), ('\u{f98a}', "\u{529b}"), ('\u{f98b}', "\u{66c6}"),
+    ('\u{f98c}', "\u{6b77}"), ('\u{f98d}', "\u{8f62}"), ('\u{f98e}', "\u{5e74}"),
+    ('\u{f98f}', "\u{6190}"), ('\u{f990}', "\u{6200}"), ('\u{f991}', "\u{649a}"),
+    ('\u{f992}', "\u{6f23}"), ('\u{f993}', "\u{7149}"), ('\u{f994}', "\u{7489}"),
+    ('\u{f995}', "\u{79ca}"), ('\u{f996}', "\u{7df4}"), ('\u{f997}', "\u{806f}"),
+    ('\u{f998}', "\u{8f26}"), ('\u{f999}', "\u{84ee}"), ('\u{f99a}', "\u{9023}"),
+    ('\u{f99b}', "\u{934a}"), ('\u{f99c}', "\u{5217}"), ('\u{f99d}', "\u{52a3}"),
+    ('\u{f99e}', "\u{54bd}"), ('\u{f99f}', "\u{70c8}"), ('\u{f9a0}', "\u{88c2}"),
+    ('\u{f9a1}', "\u{8aaa}"), ('\u{f9a2}', "\u{5ec9}"), ('\u{f9a3}', "\u{5ff5}"),
+    ('\u{f9a4}', "\u{637b}"), ('\u{f9a5}', "\u{6bae}"), ('\u{f9a6}', "\u{7c3e}"),
+    ('\u{f9a7}', "\u{7375}"), ('\u{f9a8}', "\u{4ee4}"), ('\u{f9a9}', "\u{56f9}"),
+    ('\u{f9aa}', "\u{5be7}"), ('\u{f9ab}', "\u{5dba}"), ('\u{f9ac}', "\u{601c}"),
+    ('\u{f9ad}', "\u{73b2}"), ('\u{f9ae}', "\u{7469}"), ('\u{f9af}', "\u{7f9a}"),
+    ('\u{f9b0}', "\u{8046}"), ('\u{f9b1}', "\u{9234}"), ('\u{f9b2}', "\u{96f6}"),
+    ('\u{f9b3}', "\u{9748}"), ('\u{f9b4}', "\u{9818}"), ('\u{f9b5}', "\u{4f8b}"),
+    ('\u{f9b6}', "\u{79ae}"), ('\u{f9b7}', "\u{91b4}"), ('\u{f9b8}', "\u{96b8}"),
+    ('\u{f9b9}', "\u{60e1}"), ('\u{f9ba}', "\u{4e86}"), ('\u{f9bb}', "\u{50da}"),
+    ('\u{f9bc}', "\u{5bee}"), ('\u{f9bd}', "\u{5c3f}"), ('\u{f9be}', "\u{6599}"),
+    ('\u{f9bf}', "\u{6a02}"), ('\u{f9c0}', "\u{71ce}"), ('\u{f9c1}', "\u{7642}"),
+    ('\u{f9c2}', "\u{84fc}"), ('\u{f9c3}', "\u{907c}"), ('\u{f9c4}', "\u{9f8d}"),
+    ('\u{f9c5}', "\u{6688}"), ('\u{f9c6}', "\u{962e}"), ('\u{f9c7}', "\u{5289}"),
+    ('\u{f9c8}', "\u{677b}"), ('\u{f9c9}', "\u{67f3}"), ('\u{f9ca}', "\u{6d41}"),
+    ('\u{f9cb}', "\u{6e9c}"), ('\u{f9cc}', "\u{7409}"), ('\u{f9cd}', "\u{7559}"),
+    ('\u{f9ce}', "\u{786b}"), ('\u{f9cf}', "\u{7d10}"), ('\u{f9d0}', "\u{985e}"),
+    ('\u{f9d1}', "\u{516d}"), ('\u{f9d2}', "\u{622e}"), ('\u{f9d3}', "\u{9678}"),
+    ('\u{f9d4}', "\u{502b}"), ('\u{f9d5}', "\u{5d19}"), ('\u{f9d6}', "\u{6dea}"),
+    ('\u{f9d7}', "\u{8f2a}"), ('\u{f9d8}', "\u{5f8b}"), ('\u{f9d9}', "\u{6144}"),
+    ('\u{f9da}', "\u{6817}"), ('\u{f9db}', "\u{7387}"), ('\u{f9dc}', "\u{9686}"),
+    ('\u{f9dd}', "\u{5229}"), ('\u{f9de}', "\u{540f}"), ('\u{f9df}', "\u{5c65}"),
+    ('\u{f9e0}', "\u{6613}"), ('\u{f9e1}', "\u{674e}"), ('\u{f9e2}', "\u{68a8}"),
+    ('\u{f9e3}', "\u{6ce5}"), ('\u{f9e4}', "\u{7406}"), ('\u{f9e5}', "\u{75e2}"),
+    ('\u{f9e6}', "\u{7f79}"), ('\u{f9e7}', "\u{88cf}"), ('\u{f9e8}', "\u{88e1}"),
+    ('\u{f9e9}', "\u{91cc}"), ('\u{f9ea}', "\u{96e2}"), ('\u{f9eb}', "\u{533f}"),
+    ('\u{f9ec}', "\u{6eba}"), ('\u{f9ed}', "\u{541d}"), ('\u{f9ee}', "\u{71d0}"),
+    ('\u{f9ef}', "\u{7498}"), ('\u{f9f0}', "\u{85fa}"), ('\u{f9f1}', "\u{96a3}"),
+    ('\u{f9f2}', "\u{9c57}"), ('\u{f9f3}', "\u{9e9f}"), ('\u{f9f4}', "\u{6797}"),
+    ('\u{f9f5}', "\u{6dcb}"), ('\u{f9f6}', "\u{81e8}"), ('\u{f9f7}', "\u{7acb}"),
+    ('\u{f9f8}', "\u{7b20}"), ('\u{f9f9}', "\u{7c92}"), ('\u{f9fa}', "\u{72c0}"),
+    ('\u{f9fb}', "\u{7099}"), ('\u{f9fc}', "\u{8b58}"), ('\u{f9fd}', "\u{4ec0}"),
+    ('\u{f9fe}', "\u{8336}"), ('\u{f9ff}', "\u{523a}"), ('\u{fa00}', "\u{5207}"),
+    ('\u{fa01}', "\u{5ea6}"), ('\u{fa02}', "\u{62d3}"), ('\u{fa03}', "\u{7cd6}"),
+    ('\u{fa04}', "\u{5b85}"), ('\u{fa05}', "\u{6d1e}"), ('\u{fa06}', "\u{66b4}"),
+    ('\u{fa07}', "\u{8f3b}"), ('\u{fa08}', "\u{884c}"), ('\u{fa09}', "\u{964d}"),
+    ('\u{fa0a}', "\u{898b}"), ('\u{fa0b}', "\u{5ed3}"), ('\u{fa0c}', "\u{5140}"),
+    ('\u{fa0d}', "\u{55c0}"), ('\u{fa10}', "\u{585a}"), ('\u{fa12}', "\u{6674}"),
+    ('\u{fa15}', "\u{51de}"), ('\u{fa16}', "\u{732a}"), ('\u{fa17}', "\u{76ca}"),
+    ('\u{fa18}', "\u{793c}"), ('\u{fa19}', "\u{795e}"), ('\u{fa1a}', "\u{7965}"),
+    ('\u{fa1b}', "\u{798f}"), ('\u{fa1c}', "\u{9756}"), ('\u{fa1d}', "\u{7cbe}"),
+    ('\u{fa1e}', "\u{7fbd}"), ('\u{fa20}', "\u{8612}"), ('\u{fa22}', "\u{8af8}"),
+    ('\u{fa25}', "\u{9038}"), ('\u{fa26}', "\u{90fd}"), ('\u{fa2a}', "\u{98ef}"),
+    ('\u{fa2b}', "\u{98fc}"), ('\u{fa2c}', "\u{9928}"), ('\u{fa2d}', "\u{9db4}"),
+    ('\u{fa2e}', "\u{90de}"), ('\u{fa2f}', "\u{96b7}"), ('\u{fa30}', "\u{4fae}"),
+    ('\u{fa31}', "\u{50e7}"), ('\u{fa32}', "\u{514d}"), ('\u{fa33}', "\u{52c9}"),
+    ('\u{fa34}', "\u{52e4}"), ('\u{fa35}', "\u{5351}"), ('\u{fa36}', "\u{559d}"),
+    ('\u{fa37}', "\u{5606}"), ('\u{fa38}', "\u{5668}"), ('\u{fa39}', "\u{5840}"),
+    ('\u{fa3a}', "\u{58a8}"), ('\u{fa3b}', "\u{5c64}"), ('\u{fa3c}', "\u{5c6e}"),
+    ('\u{fa3d}', "\u{6094}"), ('\u{fa3e}', "\u{6168}"), ('\u{fa3f}', "\u{618e}"),
+    ('\u{fa40}', "\u{61f2}"), ('\u{fa41}', "\u{654f}"), ('\u{fa42}', "\u{65e2}"),
+    ('\u{fa43}', "\u{6691}"), ('\u{fa44}', "\u{6885}"), ('\u{fa45}', "\u{6d77}"),
+    ('\u{fa46}', "\u{6e1a}"), ('\u{fa47}', "\u{6f22}"), ('\u{fa48}', "\u{716e}"),
+    ('\u{fa49}', "\u{722b}"), ('\u{fa4a}', "\u{7422}"), ('\u{fa4b}', "\u{7891}"),
+    ('\u{fa4c}', "\u{793e}"), ('\u{fa4d}', "\u{7949}"), ('\u{fa4e}', "\u{7948}"),
+    ('\u{fa4f}', "\u{7950}"), ('\u{fa50}', "\u{7956}"), ('\u{fa51}', "\u{795d}"),
+    ('\u{fa52}', "\u{798d}"), ('\u{fa53}', "\u{798e}"), ('\u{fa54}', "\u{7a40}"),
+    ('\u{fa55}', "\u{7a81}"), ('\u{fa56}', "\u{7bc0}"), ('\u{fa57}', "\u{7df4}"),
+    ('\u{fa58}', "\u{7e09}"), ('\u{fa59}', "\u{7e41}"), ('\u{fa5a}', "\u{7f72}"),
+    ('\u{fa5b}', "\u{8005}"), ('\u{fa5c}', "\u{81ed}"), ('\u{fa5d}', "\u{8279}"),
+    ('\u{fa5e}', "\u{8279}"), ('\u{fa5f}', "\u{8457}"), ('\u{fa60}', "\u{8910}"),
+    ('\u{fa61}', "\u{8996}"), ('\u{fa62}', "\u{8b01}"), ('\u{fa63}', "\u{8b39}"),
+    ('\u{fa64}', "\u{8cd3}"), ('\u{fa65}', "\u{8d08}"), ('\u{fa66}', "\u{8fb6}"),
+    ('\u{fa67}', "\u{9038}"), ('\u{fa68}', "\u{96e3}"), ('\u{fa69}', "\u{97ff}"),
+    ('\u{fa6a}', "\u{983b}"), ('\u{fa6b}', "\u{6075}"), ('\u{fa6c}', "\u{242ee}"),
+    ('\u{fa6d}', "\u{8218}"), ('\u{fa70}', "\u{4e26}"), ('\u{fa71}', "\u{51b5}"),
+    ('\u{fa72}', "\u{5168}"), ('\u{fa73}', "\u{4f80}"), ('\u{fa74}', "\u{5145}"),
+    ('\u{fa75}', "\u{5180}"), ('\u{fa76}', "\u{52c7}"), ('\u{fa77}', "\u{52fa}"),
+    ('\u{fa78}', "\u{559d}"), ('\u{fa79}', "\u{5555}"), ('\u{fa7a}', "\u{5599}"),
+    ('\u{fa7b}', "\u{55e2}"), ('\u{fa7c}', "\u{585a}"), ('\u{fa7d}', "\u{58b3}"),
+    ('\u{fa7e}', "\u{5944}"), ('\u{fa7f}', "\u{5954}"), ('\u{fa80}', "\u{5a62}"),
+    ('\u{fa81}', "\u{5b28}"), ('\u{fa82}', "\u{5ed2}"), ('\u{fa83}', "\u{5ed9}"),
+    ('\u{fa84}', "\u{5f69}"), ('\u{fa85}', "\u{5fad}"), ('\u{fa86}', "\u{60d8}"),
+    ('\u{fa87}', "\u{614e}"), ('\u{fa88}', "\u{6108}"), ('\u{fa89}', "\u{618e}"),
+    ('\u{fa8a}', "\u{6160}"), ('\u{fa8b}', "\u{61f2}"), ('\u{fa8c}', "\u{6234}"),
+    ('\u{fa8d}', "\u{63c4}"), ('\u{fa8e}', "\u{641c}"), ('\u{fa8f}', "\u{6452}"),
+    ('\u{fa90}', "\u{6556}"), ('\u{fa91}', "\u{6674}"), ('\u{fa92}', "\u{6717}"),
+    ('\u{fa93}', "\u{671b}"), ('\u{fa94}', "\u{6756}"), ('\u{fa95}', "\u{6b79}"),
+    ('\u{fa96}', "\u{6bba}"), ('\u{fa97}', "\u{6d41}"), ('\u{fa98}', "\u{6edb}"),
+    ('\u{fa99}', "\u{6ecb}"), ('\u{fa9a}', "\u{6f22}"), ('\u{fa9b}', "\u{701e}"),
+    ('\u{fa9c}', "\u{716e}"), ('\u{fa9d}', "\u{77a7}"), ('\u{fa9e}', "\u{7235}"),
+    ('\u{fa9f}', "\u{72af}"), ('\u{faa0}', "\u{732a}"), ('\u{faa1}', "\u{7471}"),
+    ('\u{faa2}', "\u{7506}"), ('\u{faa3}', "\u{753b}"), ('\u{faa4}', "\u{761d}"),
+    ('\u{faa5}', "\u{761f}"), ('\u{faa6}', "\u{76ca}"), ('\u{faa7}', "\u{76db}"),
+    ('\u{faa8}', "\u{76f4}"), ('\u{faa9}', "\u{774a}"), ('\u{faaa}', "\u{7740}"),
+    ('\u{faab}', "\u{78cc}"), ('\u{faac}', "\u{7ab1}"), ('\u{faad}', "\u{7bc0}"),
+    ('\u{faae}', "\u{7c7b}"), ('\u{faaf}', "\u{7d5b}"), ('\u{fab0}', "\u{7df4}"),
+    ('\u{fab1}', "\u{7f3e}"), ('\u{fab2}', "\u{8005}"), ('\u{fab3}', "\u{8352}"),
+    ('\u{fab4}', "\u{83ef}"), ('\u{fab5}', "\u{8779}"), ('\u{fab6}', "\u{8941}"),
+    ('\u{fab7}', "\u{8986}"), ('\u{fab8}', "\u{8996}"), ('\u{fab9}', "\u{8abf}"),
+    ('\u{faba}', "\u{8af8}"), ('\u{fabb}', "\u{8acb}"), ('\u{fabc}', "\u{8b01}"),
+    ('\u{fabd}', "\u{8afe}"), ('\u{fabe}', "\u{8aed}"), ('\u{fabf}', "\u{8b39}"),
+    ('\u{fac0}', "\u{8b8a}"), ('\u{fac1}', "\u{8d08}"), ('\u{fac2}', "\u{8f38}"),
+    ('\u{fac3}', "\u{9072}"), ('\u{fac4}', "\u{9199}"), ('\u{fac5}', "\u{9276}"),
+    ('\u{fac6}', "\u{967c}"), ('\u{fac7}', "\u{96e3}"), ('\u{fac8}', "\u{9756}"),
+    ('\u{fac9}', "\u{97db}"), ('\u{faca}', "\u{97ff}"), ('\u{facb}', "\u{980b}"),
+    ('\u{facc}', "\u{983b}"), ('\u{facd}', "\u{9b12}"), ('\u{face}', "\u{9f9c}"),
+    ('\u{facf}', "\u{2284a}"), ('\u{fad0}', "\u{22844}"), ('\u{fad1}', "\u{233d5}"),
+    ('\u{fad2}', "\u{3b9d}"), ('\u{fad3}', "\u{4018}"), ('\u{fad4}', "\u{4039}"),
+    ('\u{fad5}', "\u{25249}"), ('\u{fad6}', "\u{25cd0}"), ('\u{fad7}', "\u{27ed3}"),
+    ('\u{fad8}', "\u{9f43}"), ('\u{fad9}', "\u{9f8e}"), ('\u{fb1d}', "\u{5d9}\u{5b4}"),
+    ('\u{fb1f}', "\u{5f2}\u{5b7}"), ('\u{fb2a}', "\u{5e9}\u{5c1}"),
+    ('\u{fb2b}', "\u{5e9}\u{5c2}"), ('\u{fb2c}', "\u{5e9}\u{5bc}\u{5c1}"),
+    ('\u{fb2d}', "\u{5e9}\u{5bc}\u{5c2}"), ('\u{fb2e}', "\u{5d0}\u{5b7}"),
+    ('\u{fb2f}', "\u{5d0}\u{5b8}"), ('\u{fb30}', "\u{5d0}\u{5bc}"),
+    ('\u{fb31}', "\u{5d1}\u{5bc}"), ('\u{fb32}', "\u{5d2}\u{5bc}"),
+    ('\u{fb33}', "\u{5d3}\u{5bc}"), ('\u{fb34}', "\u{5d4}\u{5bc}"),
+    ('\u{fb35}', "\u{5d5}\u{5bc}"), ('\u{fb36}', "\u{5d6}\u{5bc}"),
+    ('\u{fb38}', "\u{5d8}\u{5bc}"), ('\u{fb39}', "\u{5d9}\u{5bc}"),
+    ('\u{fb3a}', "\u{5da}\u{5bc}"), ('\u{fb3b}', "\u{5db}\u{5bc}"),
+    ('\u{fb3c}', "\u{5dc}\u{5bc}"), ('\u{fb3e}', "\u{5de}\u{5bc}"),
+    ('\u{fb40}', "\u{5e0}\u{5bc}"), ('\u{fb41}', "\u{5e1}\u{5bc}"),
+    ('\u{fb43}', "\u{5e3}\u{5bc}"), ('\u{fb44}', "\u{5e4}\u{5bc}"),
+    ('\u{fb46}', "\u{5e6}\u{5bc}"), ('\u{fb47}', "\u{5e7}\u{5bc}"),
+    ('\u{fb48}', "\u{5e8}\u{5bc}"), ('\u{fb49}', "\u{5e9}\u{5bc}"),
+    ('\u{fb4a}', "\u{5ea}\u{5bc}"), ('\u{fb4b}', "\u{5d5}\u{5b9}"),
+    ('\u{fb4c}', "\u{5d1}\u{5bf}"), ('\u{fb4d}', "\u{5db}\u{5bf}"),
+    ('\u{fb4e}', "\u{5e4}\u{5bf}"), ('\u{105c9}', "\u{105d2}\u{307}"),
+    ('\u{105e4}', "\u{105da}\u{307}"), ('\u{1109a}', "\u{11099}\u{110ba}"),
+    ('\u{1109c}', "\u{1109b}\u{110ba}"), ('\u{110ab}', "\u{110a5}\u{110ba}"),
+    ('\u{1112e}', "\u{11131}\u{11127}"), ('\u{1112f}', "\u{11132}\u{11127}"),
+    ('\u{1134b}', "\u{11347}\u{1133e}"), ('\u{1134c}', "\u{11347}\u{11357}"),
+    ('\u{11383}', "\u{11382}\u{113c9}"), ('\u{11385}', "\u{11384}\u{113bb}"),
+    ('\u{1138e}', "\u{1138b}\u{113c2}"), ('\u{11391}', "\u{11390}\u{113c9}"),
+    ('\u{113c5}', "\u{113c2}\u{113c2}"), ('\u{113c7}', "\u{113c2}\u{113b8}"),
+    ('\u{113c8}', "\u{113c2}\u{113c9}"), ('\u{114bb}', "\u{114b9}\u{114ba}"),
+    ('\u{114bc}', "\u{114b9}\u{114b0}"), ('\u{114be}', "\u{114b9}\u{114bd}"),
+    ('\u{115ba}', "\u{115b8}\u{115af}"), ('\u{115bb}', "\u{115b9}\u{115af}"),
+    ('\u{11938}', "\u{11935}\u{11930}"), ('\u{16121}', "\u{1611e}\u{1611e}"),
+    ('\u{16122}', "\u{1611e}\u{16129}"), ('\u{16123}', "\u{1611e}\u{1611f}"),
+    ('\u{16124}', "\u{16129}\u{1611f}"), ('\u{16125}', "\u{1611e}\u{16120}"),
+    ('\u{16126}', "\u{1611e}\u{1611e}\u{1611f}"), ('\u{16127}', "\u{1611e}\u{16129}\u{1611f}"),
+    ('\u{16128}', "\u{1611e}\u{1611e}\u{16120}"), ('\u{16d68}', "\u{16d67}\u{16d67}"),
+    ('\u{16d69}', "\u{16d63}\u{16d67}"), ('\u{16d6a}', "\u{16d63}\u{16d67}\u{16d67}"),
+    ('\u{1d15e}', "\u{1d157}\u{1d165}"), ('\u{1d15f}', "\u{1d158}\u{1d165}"),
+    ('\u{1d160}', "\u{1d158}\u{1d165}\u{1d16e}"), ('\u{1d161}', "\u{1d158}\u{1d165}\u{1d16f}"),
+    ('\u{1d162}', "\u{1d158}\u{1d165}\u{1d170}"), ('\u{1d163}', "\u{1d158}\u{1d165}\u{1d171}"),
+    ('\u{1d164}', "\u{1d158}\u{1d165}\u{1d172}"), ('\u{1d1bb}', "\u{1d1b9}\u{1d165}"),
+    ('\u{1d1bc}', "\u{1d1ba}\u{1d165}"), ('\u{1d1bd}', "\u{1d1b9}\u{1d165}\u{1d16e}"),
+    ('\u{1d1be}', "\u{1d1ba}\u{1d165}\u{1d16e}"), ('\u{1d1bf}', "\u{1d1b9}\u{1d165}\u{1d16f}"),
+    ('\u{1d1c0}', "\u{1d1ba}\u{1d165}\u{1d16f}"), ('\u{2f800}', "\u{4e3d}"),
+    ('\u{2f801}', "\u{4e38}"), ('\u{2f802}', "\u{4e41}"), ('\u{2f803}', "\u{20122}"),
+    ('\u{2f804}', "\u{4f60}"), ('\u{2f805}', "\u{4fae}"), ('\u{2f806}', "\u{4fbb}"),
+    ('\u{2f807}', "\u{5002}"), ('\u{2f808}', "\u{507a}"), ('\u{2f809}', "\u{5099}"),
+    ('\u{2f80a}', "\u{50e7}"), ('\u{2f80b}', "\u{50cf}"), ('\u{2f80c}', "\u{349e}"),
+    ('\u{2f80d}', "\u{2063a}"), ('\u{2f80e}', "\u{514d}"), ('\u{2f80f}', "\u{5154}"),
+    ('\u{2f810}', "\u{5164}"), ('\u{2f811}', "\u{5177}"), ('\u{2f812}', "\u{2051c}"),
+    ('\u{2f813}', "\u{34b9}"), ('\u{2f814}', "\u{5167}"), ('\u{2f815}', "\u{518d}"),
+    ('\u{2f816}', "\u{2054b}"), ('\u{2f817}', "\u{5197}"), ('\u{2f818}', "\u{51a4}"),
+    ('\u{2f819}', "\u{4ecc}"), ('\u{2f81a}', "\u{51ac}"), ('\u{2f81b}', "\u{51b5}"),
+    ('\u{2f81c}', "\u{291df}"), ('\u{2f81d}', "\u{51f5}"), ('\u{2f81e}', "\u{5203}"),
+    ('\u{2f81f}', "\u{34df}"), ('\u{2f820}', "\u{523b}"), ('\u{2f821}', "\u{5246}"),
+    ('\u{2f822}', "\u{5272}"), ('\u{2f823}', "\u{5277}"), ('\u{2f824}', "\u{3515}"),
+    ('\u{2f825}', "\u{52c7}"), ('\u{2f826}', "\u{52c9}"), ('\u{2f827}', "\u{52e4}"),
+    ('\u{2f828}', "\u{52fa}"), ('\u{2f829}', "\u{5305}"), ('\u{2f82a}', "\u{5306}"),
+    ('\u{2f82b}', "\u{5317}"), ('\u{2f82c}', "\u{5349}"), ('\u{2f82d}', "\u{5351}"),
+    ('\u{2f82e}', "\u{535a}"), ('\u{2f82f}', "\u{5373}"), ('\u{2f830}', "\u{537d}"),
+    ('\u{2f831}', "\u{537f}"), ('\u{2f832}', "\u{537f}"), ('\u{2f833}', "\u{537f}"),
+    ('\u{2f834}', "\u{20a2c}"), ('\u{2f835}', "\u{7070}"), ('\u{2f836}', "\u{53ca}"),
+    ('\u{2f837}', "\u{53df}"), ('\u{2f838}', "\u{20b63}"), ('\u{2f839}', "\u{53eb}"),
+    ('\u{2f83a}', "\u{53f1}"), ('\u{2f83b}', "\u{5406}"), ('\u{2f83c}', "\u{549e}"),
+    ('\u{2f83d}', "\u{5438}"), ('\u{2f83e}', "\u{5448}"), ('\u{2f83f}', "\u{5468}"),
+    ('\u{2f840}', "\u{54a2}"), ('\u{2f841}', "\u{54f6}"), ('\u{2f842}', "\u{5510}"),
+    ('\u{2f843}', "\u{5553}"), ('\u{2f844}', "\u{5563}"), ('\u{2f845}', "\u{5584}"),
+    ('\u{2f846}', "\u{5584}"), ('\u{2f847}', "\u{5599}"), ('\u{2f848}', "\u{55ab}"),
+    ('\u{2f849}', "\u{55b3}"), ('\u{2f84a}', "\u{55c2}"), ('\u{2f84b}', "\u{5716}"),
+    ('\u{2f84c}', "\u{5606}"), ('\u{2f84d}', "\u{5717}"), ('\u{2f84e}', "\u{5651}"),
+    ('\u{2f84f}', "\u{5674}"), ('\u{2f850}', "\u{5207}"), ('\u{2f851}', "\u{58ee}"),
+    ('\u{2f852}', "\u{57ce}"), ('\u{2f853}', "\u{57f4}"), ('\u{2f854}', "\u{580d}"),
+    ('\u{2f855}', "\u{578b}"), ('\u{2f856}', "\u{5832}"), ('\u{2f857}', "\u{5831}"),
+    ('\u{2f858}', "\u{58ac}"), ('\u{2f859}', "\u{214e4}"), ('\u{2f85a}', "\u{58f2}"),
+    ('\u{2f85b}', "\u{58f7}"), ('\u{2f85c}', "\u{5906}"), ('\u{2f85d}', "\u{591a}"),
+    ('\u{2f85e}', "\u{5922}"), ('\u{2f85f}', "\u{5962}"), ('\u{2f860}', "\u{216a8}"),
+    ('\u{2f861}', "\u{216ea}"), ('\u{2f862}', "\u{59ec}"), ('\u{2f863}', "\u{5a1b}"),
+    ('\u{2f864}', "\u{5a27}"), ('\u{2f865}', "\u{59d8}"), ('\u{2f866}', "\u{5a66}"),
+    ('\u{2f867}', "\u{36ee}"), ('\u{2f868}', "\u{36fc}"), ('\u{2f869}', "\u{5b08}"),
+    ('\u{2f86a}', "\u{5b3e}"), ('\u{2f86b}', "\u{5b3e}"), ('\u{2f86c}', "\u{219c8}"),
+    ('\u{2f86d}', "\u{5bc3}"), ('\u{2f86e}', "\u{5bd8}"), ('\u{2f86f}', "\u{5be7}"),
+    ('\u{2f870}', "\u{5bf3}"), ('\u{2f871}', "\u{21b18}"), ('\u{2f872}', "\u{5bff}"),
+    ('\u{2f873}', "\u{5c06}"), ('\u{2f874}', "\u{5f53}"), ('\u{2f875}', "\u{5c22}"),
+    ('\u{2f876}', "\u{3781}"), ('\u{2f877}', "\u{5c60}"), ('\u{2f878}', "\u{5c6e}"),
+    ('\u{2f879}', "\u{5cc0}"), ('\u{2f87a}', "\u{5c8d}"), ('\u{2f87b}', "\u{21de4}"),
+    ('\u{2f87c}', "\u{5d43}"), ('\u{2f87d}', "\u{21de6}"), ('\u{2f87e}', "\u{5d6e}"),
+    ('\u{2f87f}', "\u{5d6b}"), ('\u{2f880}', "\u{5d7c}"), ('\u{2f881}', "\u{5de1}"),
+    ('\u{2f882}', "\u{5de2}"), ('\u{2f883}', "\u{382f}"), ('\u{2f884}', "\u{5dfd}"),
+    ('\u{2f885}', "\u{5e28}"), ('\u{2f886}', "\u{5e3d}"), ('\u{2f887}', "\u{5e69}"),
+    ('\u{2f888}', "\u{3862}"), ('\u{2f889}', "\u{22183}"), ('\u{2f88a}', "\u{387c}"),
+    ('\u{2f88b}', "\u{5eb0}"), ('\u{2f88c}', "\u{5eb3}"), ('\u{2f88d}', "\u{5eb6}"),
+    ('\u{2f88e}', "\u{5eca}"), ('\u{2f88f}', "\u{2a392}"), ('\u{2f890}', "\u{5efe}"),
+    ('\u{2f891}', "\u{22331}"), ('\u{2f892}', "\u{22331}"), ('\u{2f893}', "\u{8201}"),
+    ('\u{2f894}', "\u{5f22}"), ('\u{2f895}', "\u{5f22}"), ('\u{2f896}', "\u{38c7}"),
+    ('\u{2f897}', "\u{232b8}"), ('\u{2f898}', "\u{261da}"), ('\u{2f899}', "\u{5f62}"),
+    ('\u{2f89a}', "\u{5f6b}"), ('\u{2f89b}', "\u{38e3}"), ('\u{2f89c}', "\u{5f9a}"),
+    ('\u{2f89d}', "\u{5fcd}"), ('\u{2f89e}', "\u{5fd7}"), ('\u{2f89f}', "\u{5ff9}"),
+    ('\u{2f8a0}', "\u{6081}"), ('\u{2f8a1}', "\u{393a}"), ('\u{2f8a2}', "\u{391c}"),
+    ('\u{2f8a3}', "\u{6094}"), ('\u{2f8a4}', "\u{226d4}"), ('\u{2f8a5}', "\u{60c7}"),
+    ('\u{2f8a6}', "\u{6148}"), ('\u{2f8a7}', "\u{614c}"), ('\u{2f8a8}', "\u{614e}"),
+    ('\u{2f8a9}', "\u{614c}"), ('\u{2f8aa}', "\u{617a}"), ('\u{2f8ab}', "\u{618e}"),
+    ('\u{2f8ac}', "\u{61b2}"), ('\u{2f8ad}', "\u{61a4}"), ('\u{2f8ae}', "\u{61af}"),
+    ('\u{2f8af}', "\u{61de}"), ('\u{2f8b0}', "\u{61f2}"), ('\u{2f8b1}', "\u{61f6}"),
+    ('\u{2f8b2}', "\u{6210}"), ('\u{2f8b3}', "\u{621b}"), ('\u{2f8b4}', "\u{625d}"),
+    ('\u{2f8b5}', "\u{62b1}"), ('\u{2f8b6}', "\u{62d4}"), ('\u{2f8b7}', "\u{6350}"),
+    ('\u{2f8b8}', "\u{22b0c}"), ('\u{2f8b9}', "\u{633d}"), ('\u{2f8ba}', "\u{62fc}"),
+    ('\u{2f8bb}', "\u{6368}"), ('\u{2f8bc}', "\u{6383}"), ('\u{2f8bd}', "\u{63e4}"),
+    ('\u{2f8be}', "\u{22bf1}"), ('\u{2f8bf}', "\u{6422}"), ('\u{2f8c0}', "\u{63c5}"),
+    ('\u{2f8c1}', "\u{63a9}"), ('\u{2f8c2}', "\u{3a2e}"), ('\u{2f8c3}', "\u{6469}"),
+    ('\u{2f8c4}', "\u{647e}"), ('\u{2f8c5}', "\u{649d}"), ('\u{2f8c6}', "\u{6477}"),
+    ('\u{2f8c7}', "\u{3a6c}"), ('\u{2f8c8}', "\u{654f}"), ('\u{2f8c9}', "\u{656c}"),
+    ('\u{2f8ca}', "\u{2300a}"), ('\u{2f8cb}', "\u{65e3}"), ('\u{2f8cc}', "\u{66f8}"),
+    ('\u{2f8cd}', "\u{6649}"), ('\u{2f8ce}', "\u{3b19}"), ('\u{2f8cf}', "\u{6691}"),
+    ('\u{2f8d0}', "\u{3b08}"), ('\u{2f8d1}', "\u{3ae4}"), ('\u{2f8d2}', "\u{5192}"),
+    ('\u{2f8d3}', "\u{5195}"), ('\u{2f8d4}', "\u{6700}"), ('\u{2f8d5}', "\u{669c}"),
+    ('\u{2f8d6}', "\u{80ad}"), ('\u{2f8d7}', "\u{43d9}"), ('\u{2f8d8}', "\u{6717}"),
+    ('\u{2f8d9}', "\u{671b}"), ('\u{2f8da}', "\u{6721}"), ('\u{2f8db}', "\u{675e}"),
+    ('\u{2f8dc}', "\u{6753}"), ('\u{2f8dd}', "\u{233c3}"), ('\u{2f8de}', "\u{3b49}"),
+    ('\u{2f8df}', "\u{67fa}"), ('\u{2f8e0}', "\u{6785}"), ('\u{2f8e1}', "\u{6852}"),
+    ('\u{2f8e2}', "\u{6885}"), ('\u{2f8e3}', "\u{2346d}"), ('\u{2f8e4}', "\u{688e}"),
+    ('\u{2f8e5}', "\u{681f}"), ('\u{2f8e6}', "\u{6914}"), ('\u{2f8e7}', "\u{3b9d}"),
+    ('\u{2f8e8}', "\u{6942}"), ('\u{2f8e9}', "\u{69a3}"), ('\u{2f8ea}', "\u{69ea}"),
+    ('\u{2f8eb}', "\u{6aa8}"), ('\u{2f8ec}', "\u{236a3}"), ('\u{2f8ed}', "\u{6adb}"),
+    ('\u{2f8ee}', "\u{3c18}"), ('\u{2f8ef}', "\u{6b21}"), ('\u{2f8f0}', "\u{238a7}"),
+    ('\u{2f8f1}', "\u{6b54}"), ('\u{2f8f2}', "\u{3c4e}"), ('\u{2f8f3}', "\u{6b72}"),
+    ('\u{2f8f4}', "\u{6b9f}"), ('\u{2f8f5}', "\u{6bba}"), ('\u{2f8f6}', "\u{6bbb}"),
+    ('\u{2f8f7}', "\u{23a8d}"), ('\u{2f8f8}', "\u{21d0b}"), ('\u{2f8f9}', "\u{23afa}"),
+    ('\u{2f8fa}', "\u{6c4e}"), ('\u{2f8fb}', "\u{23cbc}"), ('\u{2f8fc}', "\u{6cbf}"),
+    ('\u{2f8fd}', "\u{6ccd}"), ('\u{2f8fe}', "\u{6c67}"), ('\u{2f8ff}', "\u{6d16}"),
+    ('\u{2f900}', "\u{6d3e}"), ('\u{2f901}', "\u{6d77}"), ('\u{2f902}', "\u{6d41}"),
+    ('\u{2f903}', "\u{6d69}"), ('\u{2f904}', "\u{6d78}"), ('\u{2f905}', "\u{6d85}"),
+    ('\u{2f906}', "\u{23d1e}"), ('\u{2f907}', "\u{6d34}"), ('\u{2f908}', "\u{6e2f}"),
+    ('\u{2f909}', "\u{6e6e}"), ('\u{2f90a}', "\u{3d33}"), ('\u{2f90b}', "\u{6ecb}"),
+    ('\u{2f90c}', "\u{6ec7}"), ('\u{2f90d}', "\u{23ed1}"), ('\u{2f90e}', "\u{6df9}"),
+    ('\u{2f90f}', "\u{6f6e}"), ('\u{2f910}', "\u{23f5e}"), ('\u{2f911}', "\u{23f8e}"),
+    ('\u{2f912}', "\u{6fc6}"), ('\u{2f913}', "\u{7039}"), ('\u{2f914}', "\u{701e}"),
+    ('\u{2f915}', "\u{701b}"), ('\u{2f916}', "\u{3d96}"), ('\u{2f917}', "\u{704a}"),
+    ('\u{2f918}', "\u{707d}"), ('\u{2f919}', "\u{7077}"), ('\u{2f91a}', "\u{70ad}"),
+    ('\u{2f91b}', "\u{20525}"), ('\u{2f91c}', "\u{7145}"), ('\u{2f91d}', "\u{24263}"),
+    ('\u{2f91e}', "\u{719c}"), ('\u{2f91f}', "\u{243ab}"), ('\u{2f920}', "\u{7228}"),
+    ('\u{2f921}', "\u{7235}"), ('\u{2f922}', "\u{7250}"), ('\u{2f923}', "\u{24608}"),
+    ('\u{2f924}', "\u{7280}"), ('\u{2f925}', "\u{7295}"), ('\u{2f926}', "\u{24735}"),
+    ('\u{2f927}', "\u{24814}"), ('\u{2f928}', "\u{737a}"), ('\u{2f929}', "\u{738b}"),
+    ('\u{2f92a}', "\u{3eac}"), ('\u{2f92b}', "\u{73a5}"), ('\u{2f92c}', "\u{3eb8}"),
+    ('\u{2f92d}', "\u{3eb8}"), ('\u{2f92e}', "\u{7447}"), ('\u{2f92f}', "\u{745c}"),
+    ('\u{2f930}', "\u{7471}"), ('\u{2f931}', "\u{7485}"), ('\u{2f932}', "\u{74ca}"),
+    ('\u{2f933}', "\u{3f1b}"), ('\u{2f934}', "\u{7524}"), ('\u{2f935}', "\u{24c36}"),
+    ('\u{2f936}', "\u{753e}"), ('\u{2f937}', "\u{24c92}"), ('\u{2f938}', "\u{7570}"),
+    ('\u{2f939}', "\u{2219f}"), ('\u{2f93a}', "\u{7610}"), ('\u{2f93b}', "\u{24fa1}"),
+    ('\u{2f93c}', "\u{24fb8}"), ('\u{2f93d}', "\u{25044}"), ('\u{2f93e}', "\u{3ffc}"),
+    ('\u{2f93f}', "\u{4008}"), ('\u{2f940}', "\u{76f4}"), ('\u{2f941}', "\u{250f3}"),
+    ('\u{2f942}', "\u{250f2}"), ('\u{2f943}', "\u{25119}"), ('\u{2f944}', "\u{25133}"),
+    ('\u{2f945}', "\u{771e}"), ('\u{2f946}', "\u{771f}"), ('\u{2f947}', "\u{771f}"),
+    ('\u{2f948}', "\u{774a}"), ('\u{2f949}', "\u{4039}"), ('\u{2f94a}', "\u{778b}"),
+    ('\u{2f94b}', "\u{4046}"), ('\u{2f94c}', "\u{4096}"), ('\u{2f94d}', "\u{2541d}"),
+    ('\u{2f94e}', "\u{784e}"), ('\u{2f94f}', "\u{788c}"), ('\u{2f950}', "\u{78cc}"),
+    ('\u{2f951}', "\u{40e3}"), ('\u{2f952}', "\u{25626}"), ('\u{2f953}', "\u{7956}"),
+    ('\u{2f954}', "\u{2569a}"), ('\u{2f955}', "\u{256c5}"), ('\u{2f956}', "\u{798f}"),
+    ('\u{2f957}', "\u{79eb}"), ('\u{2f958}', "\u{412f}"), ('\u{2f959}', "\u{7a40}"),
+    ('\u{2f95a}', "\u{7a4a}"), ('\u{2f95b}', "\u{7a4f}"), ('\u{2f95c}', "\u{2597c}"),
+    ('\u{2f95d}', "\u{25aa7}"), ('\u{2f95e}', "\u{25aa7}"), ('\u{2f95f}', "\u{7aee}"),
+    ('\u{2f960}', "\u{4202}"), ('\u{2f961}', "\u{25bab}"), ('\u{2f962}', "\u{7bc6}"),
+    ('\u{2f963}', "\u{7bc9}"), ('\u{2f964}', "\u{4227}"), ('\u{2f965}', "\u{25c80}"),
+    ('\u{2f966}', "\u{7cd2}"), ('\u{2f967}', "\u{42a0}"), ('\u{2f968}', "\u{7ce8}"),
+    ('\u{2f969}', "\u{7ce3}"), ('\u{2f96a}', "\u{7d00}"), ('\u{2f96b}', "\u{25f86}"),
+    ('\u{2f96c}', "\u{7d63}"), ('\u{2f96d}', "\u{4301}"), ('\u{2f96e}', "\u{7dc7}"),
+    ('\u{2f96f}', "\u{7e02}"), ('\u{2f970}', "\u{7e45}"), ('\u{2f971}', "\u{4334}"),
+    ('\u{2f972}', "\u{26228}"), ('\u{2f973}', "\u{26247}"), ('\u{2f974}', "\u{4359}"),
+    ('\u{2f975}', "\u{262d9}"), ('\u{2f976}', "\u{7f7a}"), ('\u{2f977}', "\u{2633e}"),
+    ('\u{2f978}', "\u{7f95}"), ('\u{2f979}', "\u{7ffa}"), ('\u{2f97a}', "\u{8005}"),
+    ('\u{2f97b}', "\u{264da}"), ('\u{2f97c}', "\u{26523}"), ('\u{2f97d}', "\u{8060}"),
+    ('\u{2f97e}', "\u{265a8}"), ('\u{2f97f}', "\u{8070}"), ('\u{2f980}', "\u{2335f}"),
+    ('\u{2f981}', "\u{43d5}"), ('\u{2f982}', "\u{80b2}"), ('\u{2f983}', "\u{8103}"),
+    ('\u{2f984}', "\u{440b}"), ('\u{2f985}', "\u{813e}"), ('\u{2f986}', "\u{5ab5}"),
+    ('\u{2f987}', "\u{267a7}"), ('\u{2f988}', "\u{267b5}"), ('\u{2f989}', "\u{23393}"),
+    ('\u{2f98a}', "\u{2339c}"), ('\u{2f98b}', "\u{8201}"), ('\u{2f98c}', "\u{8204}"),
+    ('\u{2f98d}', "\u{8f9e}"), ('\u{2f98e}', "\u{446b}"), ('\u{2f98f}', "\u{8291}"),
+    ('\u{2f990}', "\u{828b}"), ('\u{2f991}', "\u{829d}"), ('\u{2f992}', "\u{52b3}"),
+    ('\u{2f993}', "\u{82b1}"), ('\u{2f994}', "\u{82b3}"), ('\u{2f995}', "\u{82bd}"),
+    ('\u{2f996}', "\u{82e6}"), ('\u{2f997}', "\u{26b3c}"), ('\u{2f998}', "\u{82e5}"),
+    ('\u{2f999}', "\u{831d}"), ('\u{2f99a}', "\u{8363}"), ('\u{2f99b}', "\u{83ad}"),
+    ('\u{2f99c}', "\u{8323}"), ('\u{2f99d}', "\u{83bd}"), ('\u{2f99e}', "\u{83e7}"),
+    ('\u{2f99f}', "\u{8457}"), ('\u{2f9a0}', "\u{8353}"), ('\u{2f9a1}', "\u{83ca}"),
+    ('\u{2f9a2}', "\u{83cc}"), ('\u{2f9a3}', "\u{83dc}"), ('\u{2f9a4}', "\u{26c36}"),
+    ('\u{2f9a5}', "\u{26d6b}"), ('\u{2f9a6}', "\u{26cd5}"), ('\u{2f9a7}', "\u{452b}"),
+    ('\u{2f9a8}', "\u{84f1}"), ('\u{2f9a9}', "\u{84f3}"), ('\u{2f9aa}', "\u{8516}"),
+    ('\u{2f9ab}', "\u{273ca}"), ('\u{2f9ac}', "\u{8564}"), ('\u{2f9ad}', "\u{26f2c}"),
+    ('\u{2f9ae}', "\u{455d}"), ('\u{2f9af}', "\u{4561}"), ('\u{2f9b0}', "\u{26fb1}"),
+    ('\u{2f9b1}', "\u{270d2}"), ('\u{2f9b2}', "\u{456b}"), ('\u{2f9b3}', "\u{8650}"),
+    ('\u{2f9b4}', "\u{865c}"), ('\u{2f9b5}', "\u{8667}"), ('\u{2f9b6}', "\u{8669}"),
+    ('\u{2f9b7}', "\u{86a9}"), ('\u{2f9b8}', "\u{8688}"), ('\u{2f9b9}', "\u{870e}"),
+    ('\u{2f9ba}', "\u{86e2}"), ('\u{2f9bb}', "\u{8779}"), ('\u{2f9bc}', "\u{8728}"),
+    ('\u{2f9bd}', "\u{876b}"), ('\u{2f9be}', "\u{8786}"), ('\u{2f9bf}', "\u{45d7}"),
+    ('\u{2f9c0}', "\u{87e1}"), ('\u{2f9c1}', "\u{8801}"), ('\u{2f9c2}', "\u{45f9}"),
+    ('\u{2f9c3}', "\u{8860}"), ('\u{2f9c4}', "\u{8863}"), ('\u{2f9c5}', "\u{27667}"),
+    ('\u{2f9c6}', "\u{88d7}"), ('\u{2f9c7}', "\u{88de}"), ('\u{2f9c8}', "\u{4635}"),
+    ('\u{2f9c9}', "\u{88fa}"), ('\u{2f9ca}', "\u{34bb}"), ('\u{2f9cb}', "\u{278ae}"),
+    ('\u{2f9cc}', "\u{27966}"), ('\u{2f9cd}', "\u{46be}"), ('\u{2f9ce}', "\u{46c7}"),
+    ('\u{2f9cf}', "\u{8aa0}"), ('\u{2f9d0}', "\u{8aed}"), ('\u{2f9d1}', "\u{8b8a}"),
+    ('\u{2f9d2}', "\u{8c55}"), ('\u{2f9d3}', "\u{27ca8}"), ('\u{2f9d4}', "\u{8cab}"),
+    ('\u{2f9d5}', "\u{8cc1}"), ('\u{2f9d6}', "\u{8d1b}"), ('\u{2f9d7}', "\u{8d77}"),
+    ('\u{2f9d8}', "\u{27f2f}"), ('\u{2f9d9}', "\u{20804}"), ('\u{2f9da}', "\u{8dcb}"),
+    ('\u{2f9db}', "\u{8dbc}"), ('\u{2f9dc}', "\u{8df0}"), ('\u{2f9dd}', "\u{208de}"),
+    ('\u{2f9de}', "\u{8ed4}"), ('\u{2f9df}', "\u{8f38}"), ('\u{2f9e0}', "\u{285d2}"),
+    ('\u{2f9e1}', "\u{285ed}"), ('\u{2f9e2}', "\u{9094}"), ('\u{2f9e3}', "\u{90f1}"),
+    ('\u{2f9e4}', "\u{9111}"), ('\u{2f9e5}', "\u{2872e}"), ('\u{2f9e6}', "\u{911b}"),
+    ('\u{2f9e7}', "\u{9238}"), ('\u{2f9e8}', "\u{92d7}"), ('\u{2f9e9}', "\u{92d8}"),
+    ('\u{2f9ea}', "\u{927c}"), ('\u{2f9eb}', "\u{93f9}"), ('\u{2f9ec}', "\u{9415}"),
+    ('\u{2f9ed}', "\u{28bfa}"), ('\u{2f9ee}', "\u{958b}"), ('\u{2f9ef}', "\u{4995}"),
+    ('\u{2f9f0}', "\u{95b7}"), ('\u{2f9f1}', "\u{28d77}"), ('\u{2f9f2}', "\u{49e6}"),
+    ('\u{2f9f3}', "\u{96c3}"), ('\u{2f9f4}', "\u{5db2}"), ('\u{2f9f5}', "\u{9723}"),
+    ('\u{2f9f6}', "\u{29145}"), ('\u{2f9f7}', "\u{2921a}"), ('\u{2f9f8}', "\u{4a6e}"),
+    ('\u{2f9f9}', "\u{4a76}"), ('\u{2f9fa}', "\u{97e0}"), ('\u{2f9fb}', "\u{2940a}"),
+    ('\u{2f9fc}', "\u{4ab2}"), ('\u{2f9fd}', "\u{29496}"), ('\u{2f9fe}', "\u{980b}"),
+    ('\u{2f9ff}', "\u{980b}"), ('\u{2fa00}', "\u{9829}"), ('\u{2fa01}', "\u{295b6}"),
+    ('\u{2fa02}', "\u{98e2}"), ('\u{2fa03}', "\u{4b33}"), ('\u{2fa04}', "\u{9929}"),
+    ('\u{2fa05}', "\u{99a7}"), ('\u{2fa06}', "\u{99c2}"), ('\u{2fa07}', "\u{99fe}"),
+    ('\u{2fa08}', "\u{4bce}"), ('\u{2fa09}', "\u{29b30}"), ('\u{2fa0a}', "\u{9b12}"),
+    ('\u{2fa0b}', "\u{9c40}"), ('\u{2fa0c}', "\u{9cfd}"), ('\u{2fa0d}', "\u{4cce}"),
+    ('\u{2fa0e}', "\u{4ced}"), ('\u{2fa0f}', "\u{9d67}"), ('\u{2fa10}', "\u{2a0ce}"),
+    ('\u{2fa11}', "\u{4cf8}"), ('\u{2fa12}', "\u{2a105}"), ('\u{2fa13}', "\u{2a20e}"),
+    ('\u{2fa14}', "\u{2a291}"), ('\u{2fa15}', "\u{9ebb}"), ('\u{2fa16}', "\u{4d56}"),
+    ('\u{2fa17}', "\u{9ef9}"), ('\u{2fa18}', "\u{9efe}"), ('\u{2fa19}', "\u{9f05}"),
+    ('\u{2fa1a}', "\u{9f0f}"), ('\u{2fa1b}', "\u{9f16}"), ('\u{2fa1c}', "\u{9f3b}"),
+    ('\u{2fa1d}', "\u{2a600}"),
+];
+
+/// Primary composites by the pair of characters they are composed of
+/// (without Hangul syllables).
+#[rustfmt::skip]
+pub const COMPOSITIONS: &[((char, char), char)] = &[
+    (('\u{3c}', '\u{338}'), '\u{226e}'), (('\u{3d}', '\u{338}'), '\u{2260}'),
+    (('\u{3e}', '\u{338}'), '\u{226f}'), (('\u{41}', '\u{300}'), '\u{c0}'),
+    (('\u{41}', '\u{301}'), '\u{c1}'), (('\u{41}', '\u{302}'), '\u{c2}'),
+    (('\u{41}', '\u{303}'), '\u{c3}'), (('\u{41}', '\u{304}'), '\u{100}'),
+    (('\u{41}', '\u{306}'), '\u{102}'), (('\u{41}', '\u{307}'), '\u{226}'),
+    (('\u{41}', '\u{308}'), '\u{c4}'), (('\u{41}', '\u{309}'), '\u{1ea2}'),
+    (('\u{41}', '\u{30a}'), '\u{c5}'), (('\u{41}', '\u{30c}'), '\u{1cd}'),
+    (('\u{41}', '\u{30f}'), '\u{200}'), (('\u{41}', '\u{311}'), '\u{202}'),
+    (('\u{41}', '\u{323}'), '\u{1ea0}'), (('\u{41}', '\u{325}'), '\u{1e00}'),
+    (('\u{41}', '\u{328}'), '\u{104}'), (('\u{42}', '\u{307}'), '\u{1e02}'),
+    (('\u{42}', '\u{323}'), '\u{1e04}'), (('\u{42}', '\u{331}'), '\u{1e06}'),
+    (('\u{43}', '\u{301}'), '\u{106}'), (('\u{43}', '\u{302}'), '\u{108}'),
+    (('\u{43}', '\u{307}'), '\u{10a}'), (('\u{43}', '\u{30c}'), '\u{10c}'),
+    (('\u{43}', '\u{327}'), '\u{c7}'), (('\u{44}', '\u{307}'), '\u{1e0a}'),
+    (('\u{44}', '\u{30c}'), '\u{10e}'), (('\u{44}', '\u{323}'), '\u{1e0c}'),
+    (('\u{44}', '\u{327}'), '\u{1e10}'), (('\u{44}', '\u{32d}'), '\u{1e12}'),
+    (('\u{44}', '\u{331}'), '\u{1e0e}'), (('\u{45}', '\u{300}'), '\u{c8}'),
+    (('\u{45}', '\u{301}'), '\u{c9}'), (('\u{45}', '\u{302}'), '\u{ca}'),
+    (('\u{45}', '\u{303}'), '\u{1ebc}'), (('\u{45}', '\u{304}'), '\u{112}'),
+    (('\u{45}', '\u{306}'), '\u{114}'), (('\u{45}', '\u{307}'), '\u{116}'),
+    (('\u{45}', '\u{308}'), '\u{cb}'), (('\u{45}', '\u{309}'), '\u{1eba}'),
+    (('\u{45}', '\u{30c}'), '\u{11a}'), (('\u{45}', '\u{30f}'), '\u{204}'),
+    (('\u{45}', '\u{311}'), '\u{206}'), (('\u{45}', '\u{323}'), '\u{1eb8}'),
+    (('\u{45}', '\u{327}'), '\u{228}'), (('\u{45}', '\u{328}'), '\u{118}'),
+    (('\u{45}', '\u{32d}'), '\u{1e18}'), (('\u{45}', '\u{330}'), '\u{1e1a}'),
+    (('\u{46}', '\u{307}'), '\u{1e1e}'), (('\u{47}', '\u{301}'), '\u{1f4}'),
+    (('\u{47}', '\u{302}'), '\u{11c}'), (('\u{47}', '\u{304}'), '\u{1e20}'),
+    (('\u{47}', '\u{306}'), '\u{11e}'), (('\u{47}', '\u{307}'), '\u{120}'),
+    (('\u{47}', '\u{30c}'), '\u{1e6}'), (('\u{47}', '\u{327}'), '\u{122}'),
+    (('\u{48}', '\u{302}'), '\u{124}'), (('\u{48}', '\u{307}'), '\u{1e22}'),
+    (('\u{48}', '\u{308}'), '\u{1e26}'), (('\u{48}', '\u{30c}'), '\u{21e}'),
+    (('\u{48}', '\u{323}'), '\u{1e24}'), (('\u{48}', '\u{327}'), '\u{1e28}'),
+    (('\u{48}', '\u{32e}'), '\u{1e2a}'), (('\u{49}', '\u{300}'), '\u{cc}'),
+    (('\u{49}', '\u{301}'), '\u{cd}'), (('\u{49}', '\u{302}'), '\u{ce}'),
+    (('\u{49}', '\u{303}'), '\u{128}'), (('\u{49}', '\u{304}'), '\u{12a}'),
+    (('\u{49}', '\u{306}'), '\u{12c}'), (('\u{49}', '\u{307}'), '\u{130}'),
+    (('\u{49}', '\u{308}'), '\u{cf}'), (('\u{49}', '\u{309}'), '\u{1ec8}'),
+    (('\u{49}', '\u{30c}'), '\u{1cf}'), (('\u{49}', '\u{30f}'), '\u{208}'),
+    (('\u{49}', '\u{311}'), '\u{20a}'), (('\u{49}', '\u{323}'), '\u{1eca}'),
+    (('\u{49}', '\u{328}'), '\u{12e}'), (('\u{49}', '\u{330}'), '\u{1e2c}'),
+    (('\u{4a}', '\u{302}'), '\u{134}'), (('\u{4b}', '\u{301}'), '\u{1e30}'),
+    (('\u{4b}', '\u{30c}'), '\u{1e8}'), (('\u{4b}', '\u{323}'), '\u{1e32}'),
+    (('\u{4b}', '\u{327}'), '\u{136}'), (('\u{4b}', '\u{331}'), '\u{1e34}'),
+    (('\u{4c}', '\u{301}'), '\u{139}'), (('\u{4c}', '\u{30c}'), '\u{13d}'),
+    (('\u{4c}', '\u{323}'), '\u{1e36}'), (('\u{4c}', '\u{327}'), '\u{13b}'),
+    (('\u{4c}', '\u{32d}'), '\u{1e3c}'), (('\u{4c}', '\u{331}'), '\u{1e3a}'),
+    (('\u{4d}', '\u{301}'), '\u{1e3e}'), (('\u{4d}', '\u{307}'), '\u{1e40}'),
+    (('\u{4d}', '\u{323}'), '\u{1e42}'), (('\u{4e}', '\u{300}'), '\u{1f8}'),
+    (('\u{4e}', '\u{301}'), '\u{143}'), (('\u{4e}', '\u{303}'), '\u{d1}'),
+    (('\u{4e}', '\u{307}'), '\u{1e44}'), (('\u{4e}', '\u{30c}'), '\u{147}'),
+    (('\u{4e}', '\u{323}'), '\u{1e46}'), (('\u{4e}', '\u{327}'), '\u{145}'),
+    (('\u{4e}', '\u{32d}'), '\u{1e4a}'), (('\u{4e}', '\u{331}'), '\u{1e48}'),
+    (('\u{4f}', '\u{300}'), '\u{d2}'), (('\u{4f}', '\u{301}'), '\u{d3}'),
+    (('\u{4f}', '\u{302}'), '\u{d4}'), (('\u{4f}', '\u{303}'), '\u{d5}'),
+    (('\u{4f}', '\u{304}'), '\u{14c}'), (('\u{4f}', '\u{306}'), '\u{14e}'),
+    (('\u{4f}', '\u{307}'), '\u{22e}'), (('\u{4f}', '\u{308}'), '\u{d6}'),
+    (('\u{4f}', '\u{309}'), '\u{1ece}'), (('\u{4f}', '\u{30b}'), '\u{150}'),
+    (('\u{4f}', '\u{30c}'), '\u{1d1}'), (('\u{4f}', '\u{30f}'), '\u{20c}'),
+    (('\u{4f}', '\u{311}'), '\u{20e}'), (('\u{4f}', '\u{31b}'), '\u{1a0}'),
+    (('\u{4f}', '\u{323}'), '\u{1ecc}'), (('\u{4f}', '\u{328}'), '\u{1ea}'),
+    (('\u{50}', '\u{301}'), '\u{1e54}'), (('\u{50}', '\u{307}'), '\u{1e56}'),
+    (('\u{52}', '\u{301}'), '\u{154}'), (('\u{52}', '\u{307}'), '\u{1e58}'),
+    (('\u{52}', '\u{30c}'), '\u{158}'), (('\u{52}', '\u{30f}'), '\u{210}'),
+    (('\u{52}', '\u{311}'), '\u{212}'), (('\u{52}', '\u{323}'), '\u{1e5a}'),
+    (('\u{52}', '\u{327}'), '\u{156}'), (('\u{52}', '\u{331}'), '\u{1e5e}'),
+    (('\u{53}', '\u{301}'), '\u{15a}'), (('\u{53}', '\u{302}'), '\u{15c}'),
+    (('\u{53}', '\u{307}'), '\u{1e60}'), (('\u{53}', '\u{30c}'), '\u{160}'),
+    (('\u{53}', '\u{323}'), '\u{1e62}'), (('\u{53}', '\u{326}'), '\u{218}'),
+    (('\u{53}', '\u{327}'), '\u{15e}'), (('\u{54}', '\u{307}'), '\u{1e6a}'),
+    (('\u{54}', '\u{30c}'), '\u{164}'), (('\u{54}', '\u{323}'), '\u{1e6c}'),
+    (('\u{54}', '\u{326}'), '\u{21a}'), (('\u{54}', '\u{327}'), '\u{162}'),
+    (('\u{54}', '\u{32d}'), '\u{1e70}'), (('\u{54}', '\u{331}'), '\u{1e6e}'),
+    (('\u{55}', '\u{300}'), '\u{d9}'), (('\u{55}', '\u{301}'), '\u{da}'),
+    (('\u{55}', '\u{302}'), '\u{db}'), (('\u{55}', '\u{303}'), '\u{168}'),
+    (('\u{55}', '\u{304}'), '\u{16a}'), (('\u{55}', '\u{306}'), '\u{16c}'),
+    (('\u{55}', '\u{308}'), '\u{dc}'), (('\u{55}', '\u{309}'), '\u{1ee6}'),
+    (('\u{55}', '\u{30a}'), '\u{16e}'), (('\u{55}', '\u{30b}'), '\u{170}'),
+    (('\u{55}', '\u{30c}'), '\u{1d3}'), (('\u{55}', '\u{30f}'), '\u{214}'),
+    (('\u{55}', '\u{311}'), '\u{216}'), (('\u{55}', '\u{31b}'), '\u{1af}'),
+    (('\u{55}', '\u{323}'), '\u{1ee4}'), (('\u{55}', '\u{324}'), '\u{1e72}'),
+    (('\u{55}', '\u{328}'), '\u{172}'), (('\u{55}', '\u{32d}'), '\u{1e76}'),
+    (('\u{55}', '\u{330}'), '\u{1e74}'), (('\u{56}', '\u{303}'), '\u{1e7c}'),
+    (('\u{56}', '\u{323}'), '\u{1e7e}'), (('\u{57}', '\u{300}'), '\u{1e80}'),
+    (('\u{57}', '\u{301}'), '\u{1e82}'), (('\u{57}', '\u{302}'), '\u{174}'),
+    (('\u{57}', '\u{307}'), '\u{1e86}'), (('\u{57}', '\u{308}'), '\u{1e84}'),
+    (('\u{57}', '\u{323}'), '\u{1e88}'), (('\u{58}', '\u{307}'), '\u{1e8a}'),
+    (('\u{58}', '\u{308}'), '\u{1e8c}'), (('\u{59}', '\u{300}'), '\u{1ef2}'),
+    (('\u{59}', '\u{301}'), '\u{dd}'), (('\u{59}', '\u{302}'), '\u{176}'),
+    (('\u{59}', '\u{303}'), '\u{1ef8}'), (('\u{59}', '\u{304}'), '\u{232}'),
+    (('\u{59}', '\u{307}'), '\u{1e8e}'), (('\u{59}', '\u{308}'), '\u{178}'),
+    (('\u{59}', '\u{309}'), '\u{1ef6}'), (('\u{59}', '\u{323}'), '\u{1ef4}'),
+    (('\u{5a}', '\u{301}'), '\u{179}'), (('\u{5a}', '\u{302}'), '\u{1e90}'),
+    (('\u{5a}', '\u{307}'), '\u{17b}'), (('\u{5a}', '\u{30c}'), '\u{17d}'),
+    (('\u{5a}', '\u{323}'), '\u{1e92}'), (('\u{5a}', '\u{331}'), '\u{1e94}'),
+    (('\u{61}', '\u{300}'), '\u{e0}'), (('\u{61}', '\u{301}'), '\u{e1}'),
+    (('\u{61}', '\u{302}'), '\u{e2}'), (('\u{61}', '\u{303}'), '\u{e3}'),
+    (('\u{61}', '\u{304}'), '\u{101}'), (('\u{61}', '\u{306}'), '\u{103}'),
+    (('\u{61}', '\u{307}'), '\u{227}'), (('\u{61}', '\u{308}'), '\u{e4}'),
+    (('\u{61}', '\u{309}'), '\u{1ea3}'), (('\u{61}', '\u{30a}'), '\u{e5}'),
+    (('\u{61}', '\u{30c}'), '\u{1ce}'), (('\u{61}', '\u{30f}'), '\u{201}'),
+    (('\u{61}', '\u{311}'), '\u{203}'), (('\u{61}', '\u{323}'), '\u{1ea1}'),
+    (('\u{61}', '\u{325}'), '\u{1e01}'), (('\u{61}', '\u{328}'), '\u{105}'),
+    (('\u{62}', '\u{307}'), '\u{1e03}'), (('\u{62}', '\u{323}'), '\u{1e05}'),
+    (('\u{62}', '\u{331}'), '\u{1e07}'), (('\u{63}', '\u{301}'), '\u{107}'),
+    (('\u{63}', '\u{302}'), '\u{109}'), (('\u{63}', '\u{307}'), '\u{10b}'),
+    (('\u{63}', '\u{30c}'), '\u{10d}'), (('\u{63}', '\u{327}'), '\u{e7}'),
+    (('\u{64}', '\u{307}'), '\u{1e0b}'), (('\u{64}', '\u{30c}'), '\u{10f}'),
+    (('\u{64}', '\u{323}'), '\u{1e0d}'), (('\u{64}', '\u{327}'), '\u{1e11}'),
+    (('\u{64}', '\u{32d}'), '\u{1e13}'), (('\u{64}', '\u{331}'), '\u{1e0f}'),
+    (('\u{65}', '\u{300}'), '\u{e8}'), (('\u{65}', '\u{301}'), '\u{e9}'),
+    (('\u{65}', '\u{302}'), '\u{ea}'), (('\u{65}', '\u{303}'), '\u{1ebd}'),
+    (('\u{65}', '\u{304}'), '\u{113}'), (('\u{65}', '\u{306}'), '\u{115}'),
+    (('\u{65}', '\u{307}'), '\u{117}'), (('\u{65}', '\u{308}'), '\u{eb}'),
+    (('\u{65}', '\u{309}'), '\u{1ebb}'), (('\u{65}', '\u{30c}'), '\u{11b}'),
+    (('\u{65}', '\u{30f}'), '\u{205}'), (('\u{65}', '\u{311}'), '\u{207}'),
+    (('\u{65}', '\u{323}'), '\u{1eb9}'), (('\u{65}', '\u{327}'), '\u{229}'),
+    (('\u{65}', '\u{328}'), '\u{119}'), (('\u{65}', '\u{32d}'), '\u{1e19}'),
+    (('\u{65}', '\u{330}'), '\u{1e1b}'), (('\u{66}', '\u{307}'), '\u{1e1f}'),
+    (('\u{67}', '\u{301}'), '\u{1f5}'), (('\u{67}', '\u{302}'), '\u{11d}'),
+    (('\u{67}', '\u{304}'), '\u{1e21}'), (('\u{67}', '\u{306}'), '\u{11f}'),
+    (('\u{67}', '\u{307}'), '\u{121}'), (('\u{67}', '\u{30c}'), '\u{1e7}'),
+    (('\u{67}', '\u{327}'), '\u{123}'), (('\u{68}', '\u{302}'), '\u{125}'),
+    (('\u{68}', '\u{307}'), '\u{1e23}'), (('\u{68}', '\u{308}'), '\u{1e27}'),
+    (('\u{68}', '\u{30c}'), '\u{21f}'), (('\u{68}', '\u{323}'), '\u{1e25}'),
+    (('\u{68}', '\u{327}'), '\u{1e29}'), (('\u{68}', '\u{32e}'), '\u{1e2b}'),
+    (('\u{68}', '\u{331}'), '\u{1e96}'), (('\u{69}', '\u{300}'), '\u{ec}'),
+    (('\u{69}', '\u{301}'), '\u{ed}'), (('\u{69}', '\u{302}'), '\u{ee}'),
+    (('\u{69}', '\u{303}'), '\u{129}'), (('\u{69}', '\u{304}'), '\u{12b}'),
+    (('\u{69}', '\u{306}'), '\u{12d}'), (('\u{69}', '\u{308}'), '\u{ef}'),
+    (('\u{69}', '\u{309}'), '\u{1ec9}'), (('\u{69}', '\u{30c}'), '\u{1d0}'),
+    (('\u{69}', '\u{30f}'), '\u{209}'), (('\u{69}', '\u{311}'), '\u{20b}'),
+    (('\u{69}', '\u{323}'), '\u{1ecb}'), (('\u{69}', '\u{328}'), '\u{12f}'),
+    (('\u{69}', '\u{330}'), '\u{1e2d}'), (('\u{6a}', '\u{302}'), '\u{135}'),
+    (('\u{6a}', '\u{30c}'), '\u{1f0}'), (('\u{6b}', '\u{301}'), '\u{1e31}'),
+    (('\u{6b}', '\u{30c}'), '\u{1e9}'), (('\u{6b}', '\u{323}'), '\u{1e33}'),
+    (('\u{6b}', '\u{327}'), '\u{137}'), (('\u{6b}', '\u{331}'), '\u{1e35}'),
+    (('\u{6c}', '\u{301}'), '\u{13a}'), (('\u{6c}', '\u{30c}'), '\u{13e}'),
+    (('\u{6c}', '\u{323}'), '\u{1e37}'), (('\u{6c}', '\u{327}'), '\u{13c}'),
+    (('\u{6c}', '\u{32d}'), '\u{1e3d}'), (('\u{6c}', '\u{331}'), '\u{1e3b}'),
+    (('\u{6d}', '\u{301}'), '\u{1e3f}'), (('\u{6d}', '\u{307}'), '\u{1e41}'),
+    (('\u{6d}', '\u{323}'), '\u{1e43}'), (('\u{6e}', '\u{300}'), '\u{1f9}'),
+    (('\u{6e}', '\u{301}'), '\u{144}'), (('\u{6e}', '\u{303}'), '\u{f1}'),
+    (('\u{6e}', '\u{307}'), '\u{1e45}'), (('\u{6e}', '\u{30c}'), '\u{148}'),
+    (('\u{6e}', '\u{323}'), '\u{1e47}'), (('\u{6e}', '\u{327}'), '\u{146}'),
+    (('\u{6e}', '\u{32d}'), '\u{1e4b}'), (('\u{6e}', '\u{331}'), '\u{1e49}'),
+    (('\u{6f}', '\u{300}'), '\u{f2}'), (('\u{6f}', '\u{301}'), '\u{f3}'),
+    (('\u{6f}', '\u{302}'), '\u{f4}'), (('\u{6f}', '\u{303}'), '\u{f5}'),
+    (('\u{6f}', '\u{304}'), '\u{14d}'), (('\u{6f}', '\u{306}'), '\u{14f}'),
+    (('\u{6f}', '\u{307}'), '\u{22f}'), (('\u{6f}', '\u{308}'), '\u{f6}'),
+    (('\u{6f}', '\u{309}'), '\u{1ecf}'), (('\u{6f}', '\u{30b}'), '\u{151}'),
+    (('\u{6f}', '\u{30c}'), '\u{1d2}'), (('\u{6f}', '\u{30f}'), '\u{20d}'),
+    (('\u{6f}', '\u{311}'), '\u{20f}'), (('\u{6f}', '\u{31b}'), '\u{1a1}'),
+    (('\u{6f}', '\u{323}'), '\u{1ecd}'), (('\u{6f}', '\u{328}'), '\u{1eb}'),
+    (('\u{70}', '\u{301}'), '\u{1e55}'), (('\u{70}', '\u{307}'), '\u{1e57}'),
+    (('\u{72}', '\u{301}'), '\u{155}'), (('\u{72}', '\u{307}'), '\u{1e59}'),
+    (('\u{72}', '\u{30c}'), '\u{159}'), (('\u{72}', '\u{30f}'), '\u{211}'),
+    (('\u{72}', '\u{311}'), '\u{213}'), (('\u{72}', '\u{323}'), '\u{1e5b}'),
+    (('\u{72}', '\u{327}'), '\u{157}'), (('\u{72}', '\u{331}'), '\u{1e5f}'),
+    (('\u{73}', '\u{301}'), '\u{15b}'), (('\u{73}', '\u{302}'), '\u{15d}'),
+    (('\u{73}', '\u{307}'), '\u{1e61}'), (('\u{73}', '\u{30c}'), '\u{161}'),
+    (('\u{73}', '\u{323}'), '\u{1e63}'), (('\u{73}', '\u{326}'), '\u{219}'),
+    (('\u{73}', '\u{327}'), '\u{15f}'), (('\u{74}', '\u{307}'), '\u{1e6b}'),
+    (('\u{74}', '\u{308}'), '\u{1e97}'), (('\u{74}', '\u{30c}'), '\u{165}'),
+    (('\u{74}', '\u{323}'), '\u{1e6d}'), (('\u{74}', '\u{326}'), '\u{21b}'),
+    (('\u{74}', '\u{327}'), '\u{163}'), (('\u{74}', '\u{32d}'), '\u{1e71}'),
+    (('\u{74}', '\u{331}'), '\u{1e6f}'), (('\u{75}', '\u{300}'), '\u{f9}'),
+    (('\u{75}', '\u{301}'), '\u{fa}'), (('\u{75}', '\u{302}'), '\u{fb}'),
+    (('\u{75}', '\u{303}'), '\u{169}'), (('\u{75}', '\u{304}'), '\u{16b}'),
+    (('\u{75}', '\u{306}'), '\u{16d}'), (('\u{75}', '\u{308}'), '\u{fc}'),
+    (('\u{75}', '\u{309}'), '\u{1ee7}'), (('\u{75}', '\u{30a}'), '\u{16f}'),
+    (('\u{75}', '\u{30b}'), '\u{171}'), (('\u{75}', '\u{30c}'), '\u{1d4}'),
+    (('\u{75}', '\u{30f}'), '\u{215}'), (('\u{75}', '\u{311}'), '\u{217}'),
+    (('\u{75}', '\u{31b}'), '\u{1b0}'), (('\u{75}', '\u{323}'), '\u{1ee5}'),
+    (('\u{75}', '\u{324}'), '\u{1e73}'), (('\u{75}', '\u{328}'), '\u{173}'),
+    (('\u{75}', '\u{32d}'), '\u{1e77}'), (('\u{75}', '\u{330}'), '\u{1e75}'),
+    (('\u{76}', '\u{303}'), '\u{1e7d}'), (('\u{76}', '\u{323}'), '\u{1e7f}'),
+    (('\u{77}', '\u{300}'), '\u{1e81}'), (('\u{77}', '\u{301}'), '\u{1e83}'),
+    (('\u{77}', '\u{302}'), '\u{175}'), (('\u{77}', '\u{307}'), '\u{1e87}'),
+    (('\u{77}', '\u{308}'), '\u{1e85}'), (('\u{77}', '\u{30a}'), '\u{1e98}'),
+    (('\u{77}', '\u{323}'), '\u{1e89}'), (('\u{78}', '\u{307}'), '\u{1e8b}'),
+    (('\u{78}', '\u{308}'), '\u{1e8d}'), (('\u{79}', '\u{300}'), '\u{1ef3}'),
+    (('\u{79}', '\u{301}'), '\u{fd}'), (('\u{79}', '\u{302}'), '\u{177}'),
+    (('\u{79}', '\u{303}'), '\u{1ef9}'), (('\u{79}', '\u{304}'), '\u{233}'),
+    (('\u{79}', '\u{307}'), '\u{1e8f}'), (('\u{79}', '\u{308}'), '\u{ff}'),
+    (('\u{79}', '\u{309}'), '\u{1ef7}'), (('\u{79}', '\u{30a}'), '\u{1e99}'),
+    (('\u{79}', '\u{323}'), '\u{1ef5}'), (('\u{7a}', '\u{301}'), '\u{17a}'),
+    (('\u{7a}', '\u{302}'), '\u{1e91}'), (('\u{7a}', '\u{307}'), '\u{17c}'),
+    (('\u{7a}', '\u{30c}'), '\u{17e}'), (('\u{7a}', '\u{323}'), '\u{1e93}'),
+    (('\u{7a}', '\u{331}'), '\u{1e95}'), (('\u{a8}', '\u{300}'), '\u{1fed}'),
+    (('\u{a8}', '\u{301}'), '\u{385}'), (('\u{a8}', '\u{342}'), '\u{1fc1}'),
+    (('\u{c2}', '\u{300}'), '\u{1ea6}'), (('\u{c2}', '\u{301}'), '\u{1ea4}'),
+    (('\u{c2}', '\u{303}'), '\u{1eaa}'), (('\u{c2}', '\u{309}'), '\u{1ea8}'),
+    (('\u{c4}', '\u{304}'), '\u{1de}'), (('\u{c5}', '\u{301}'), '\u{1fa}'),
+    (('\u{c6}', '\u{301}'), '\u{1fc}'), (('\u{c6}', '\u{304}'), '\u{1e2}'),
+    (('\u{c7}', '\u{301}'), '\u{1e08}'), (('\u{ca}', '\u{300}'), '\u{1ec0}'),
+    (('\u{ca}', '\u{301}'), '\u{1ebe}'), (('\u{ca}', '\u{303}'), '\u{1ec4}'),
+    (('\u{ca}', '\u{309}'), '\u{1ec2}'), (('\u{cf}', '\u{301}'), '\u{1e2e}'),
+    (('\u{d4}', '\u{300}'), '\u{1ed2}'), (('\u{d4}', '\u{301}'), '\u{1ed0}'),
+    (('\u{d4}', '\u{303}'), '\u{1ed6}'), (('\u{d4}', '\u{309}'), '\u{1ed4}'),
+    (('\u{d5}', '\u{301}'), '\u{1e4c}'), (('\u{d5}', '\u{304}'), '\u{22c}'),
+    (('\u{d5}', '\u{308}'), '\u{1e4e}'), (('\u{d6}', '\u{304}'), '\u{22a}'),
+    (('\u{d8}', '\u{301}'), '\u{1fe}'), (('\u{dc}', '\u{300}'), '\u{1db}'),
+    (('\u{dc}', '\u{301}'), '\u{1d7}'), (('\u{dc}', '\u{304}'), '\u{1d5}'),
+    (('\u{dc}', '\u{30c}'), '\u{1d9}'), (('\u{e2}', '\u{300}'), '\u{1ea7}'),
+    (('\u{e2}', '\u{301}'), '\u{1ea5}'), (('\u{e2}', '\u{303}'), '\u{1eab}'),
+    (('\u{e2}', '\u{309}'), '\u{1ea9}'), (('\u{e4}', '\u{304}'), '\u{1df}'),
+    (('\u{e5}', '\u{301}'), '\u{1fb}'), (('\u{e6}', '\u{301}'), '\u{1fd}'),
+    (('\u{e6}', '\u{304}'), '\u{1e3}'), (('\u{e7}', '\u{301}'), '\u{1e09}'),
+    (('\u{ea}', '\u{300}'), '\u{1ec1}'), (('\u{ea}', '\u{301}'), '\u{1ebf}'),
+    (('\u{ea}', '\u{303}'), '\u{1ec5}'), (('\u{ea}', '\u{309}'), '\u{1ec3}'),
+    (('\u{ef}', '\u{301}'), '\u{1e2f}'), (('\u{f4}', '\u{300}'), '\u{1ed3}'),
+    (('\u{f4}', '\u{301}'), '\u{1ed1}'), (('\u{f4}', '\u{303}'), '\u{1ed7}'),
+    (('\u{f4}', '\u{309}'), '\u{1ed5}'), (('\u{f5}', '\u{301}'), '\u{1e4d}'),
+    (('\u{f5}', '\u{304}'), '\u{22d}'), (('\u{f5}', '\u{308}'), '\u{1e4f}'),
+    (('\u{f6}', '\u{304}'), '\u{22b}'), (('\u{f8}', '\u{301}'), '\u{1ff}'),
+    (('\u{fc}', '\u{300}'), '\u{1dc}'), (('\u{fc}', '\u{301}'), '\u{1d8}'),
+    (('\u{fc}', '\u{304}'), '\u{1d6}'), (('\u{fc}', '\u{30c}'), '\u{1da}'),
+    (('\u{102}', '\u{300}'), '\u{1eb0}'), (('\u{102}', '\u{301}'), '\u{1eae}'),
+    (('\u{102}', '\u{303}'), '\u{1eb4}'), (('\u{102}', '\u{309}'), '\u{1eb2}'),
+    (('\u{103}', '\u{300}'), '\u{1eb1}'), (('\u{103}', '\u{301}'), '\u{1eaf}'),
+    (('\u{103}', '\u{303}'), '\u{1eb5}'), (('\u{103}', '\u{309}'), '\u{1eb3}'),
+    (('\u{112}', '\u{300}'), '\u{1e14}'), (('\u{112}', '\u{301}'), '\u{1e16}'),
+    (('\u{113}', '\u{300}'), '\u{1e15}'), (('\u{113}', '\u{301}'), '\u{1e17}'),
+    (('\u{14c}', '\u{300}'), '\u{1e50}'), (('\u{14c}', '\u{301}'), '\u{1e52}'),
+    (('\u{14d}', '\u{300}'), '\u{1e51}'), (('\u{14d}', '\u{301}'), '\u{1e53}'),
+    (('\u{15a}', '\u{307}'), '\u{1e64}'), (('\u{15b}', '\u{307}'), '\u{1e65}'),
+    (('\u{160}', '\u{307}'), '\u{1e66}'), (('\u{161}', '\u{307}'), '\u{1e67}'),
+    (('\u{168}', '\u{301}'), '\u{1e78}'), (('\u{169}', '\u{301}'), '\u{1e79}'),
+    (('\u{16a}', '\u{308}'), '\u{1e7a}'), (('\u{16b}', '\u{308}'), '\u{1e7b}'),
+    (('\u{17f}', '\u{307}'), '\u{1e9b}'), (('\u{1a0}', '\u{300}'), '\u{1edc}'),
+    (('\u{1a0}', '\u{301}'), '\u{1eda}'), (('\u{1a0}', '\u{303}'), '\u{1ee0}'),
+    (('\u{1a0}', '\u{309}'), '\u{1ede}'), (('\u{1a0}', '\u{323}'), '\u{1ee2}'),
+    (('\u{1a1}', '\u{300}'), '\u{1edd}'), (('\u{1a1}', '\u{301}'), '\u{1edb}'),
+    (('\u{1a1}', '\u{303}'), '\u{1ee1}'), (('\u{1a1}', '\u{309}'), '\u{1edf}'),
+    (('\u{1a1}', '\u{323}'), '\u{1ee3}'), (('\u{1af}', '\u{300}'), '\u{1eea}'),
+    (('\u{1af}', '\u{301}'), '\u{1ee8}'), (('\u{1af}', '\u{303}'), '\u{1eee}'),
+    (('\u{1af}', '\u{309}'), '\u{1eec}'), (('\u{1af}', '\u{323}'), '\u{1ef0}'),
+    (('\u{1b0}', '\u{300}'), '\u{1eeb}'), (('\u{1b0}', '\u{301}'), '\u{1ee9}'),
+    (('\u{1b0}', '\u{303}'), '\u{1eef}'), (('\u{1b0}', '\u{309}'), '\u{1eed}'),
+    (('\u{1b0}', '\u{323}'), '\u{1ef1}'), (('\u{1b7}', '\u{30c}'), '\u{1ee}'),
+    (('\u{1ea}', '\u{304}'), '\u{1ec}'), (('\u{1eb}', '\u{304}'), '\u{1ed}'),
+    (('\u{226}', '\u{304}'), '\u{1e0}'), (('\u{227}', '\u{304}'), '\u{1e1}'),
+    (('\u{228}', '\u{306}'), '\u{1e1c}'), (('\u{229}', '\u{306}'), '\u{1e1d}'),
+    (('\u{22e}', '\u{304}'), '\u{230}'), (('\u{22f}', '\u{304}'), '\u{231}'),
+    (('\u{292}', '\u{30c}'), '\u{1ef}'), (('\u{391}', '\u{300}'), '\u{1fba}'),
+    (('\u{391}', '\u{301}'), '\u{386}'), (('\u{391}', '\u{304}'), '\u{1fb9}'),
+    (('\u{391}', '\u{306}'), '\u{1fb8}'), (('\u{391}', '\u{313}'), '\u{1f08}'),
+    (('\u{391}', '\u{314}'), '\u{1f09}'), (('\u{391}', '\u{345}'), '\u{1fbc}'),
+    (('\u{395}', '\u{300}'), '\u{1fc8}'), (('\u{395}', '\u{301}'), '\u{388}'),
+    (('\u{395}', '\u{313}'), '\u{1f18}'), (('\u{395}', '\u{314}'), '\u{1f19}'),
+    (('\u{397}', '\u{300}'), '\u{1fca}'), (('\u{397}', '\u{301}'), '\u{389}'),
+    (('\u{397}', '\u{313}'), '\u{1f28}'), (('\u{397}', '\u{314}'), '\u{1f29}'),
+    (('\u{397}', '\u{345}'), '\u{1fcc}'), (('\u{399}', '\u{300}'), '\u{1fda}'),
+    (('\u{399}', '\u{301}'), '\u{38a}'), (('\u{399}', '\u{304}'), '\u{1fd9}'),
+    (('\u{399}', '\u{306}'), '\u{1fd8}'), (('\u{399}', '\u{308}'), '\u{3aa}'),
+    (('\u{399}', '\u{313}'), '\u{1f38}'), (('\u{399}', '\u{314}'), '\u{1f39}'),
+    (('\u{39f}', '\u{300}'), '\u{1ff8}'), (('\u{39f}', '\u{301}'), '\u{38c}'),
+    (('\u{39f}', '\u{313}'), '\u{1f48}'), (('\u{39f}', '\u{314}'), '\u{1f49}'),
+    (('\u{3a1}', '\u{314}'), '\u{1fec}'), (('\u{3a5}', '\u{300}'), '\u{1fea}'),
+    (('\u{3a5}', '\u{301}'), '\u{38e}'), (('\u{3a5}', '\u{304}'), '\u{1fe9}'),
+    (('\u{3a5}', '\u{306}'), '\u{1fe8}'), (('\u{3a5}', '\u{308}'), '\u{3ab}'),
+    (('\u{3a5}', '\u{314}'), '\u{1f59}'), (('\u{3a9}', '\u{300}'), '\u{1ffa}'),
+    (('\u{3a9}', '\u{301}'), '\u{38f}'), (('\u{3a9}', '\u{313}'), '\u{1f68}'),
+    (('\u{3a9}', '\u{314}'), '\u{1f69}'), (('\u{3a9}', '\u{345}'), '\u{1ffc}'),
+    (('\u{3ac}', '\u{345}'), '\u{1fb4}'), (('\u{3ae}', '\u{345}'), '\u{1fc4}'),
+    (('\u{3b1}', '\u{300}'), '\u{1f70}'), (('\u{3b1}', '\u{301}'), '\u{3ac}'),
+    (('\u{3b1}', '\u{304}'), '\u{1fb1}'), (('\u{3b1}', '\u{306}'), '\u{1fb0}'),
+    (('\u{3b1}', '\u{313}'), '\u{1f00}'), (('\u{3b1}', '\u{314}'), '\u{1f01}'),
+    (('\u{3b1}', '\u{342}'), '\u{1fb6}'), (('\u{3b1}', '\u{345}'), '\u{1fb3}'),
+    (('\u{3b5}', '\u{300}'), '\u{1f72}'), (('\u{3b5}', '\u{301}'), '\u{3ad}'),
+    (('\u{3b5}', '\u{313}'), '\u{1f10}'), (('\u{3b5}', '\u{314}'), '\u{1f11}'),
+    (('\u{3b7}', '\u{300}'), '\u{1f74}'), (('\u{3b7}', '\u{301}'), '\u{3ae}'),
+    (('\u{3b7}', '\u{313}'), '\u{1f20}'), (('\u{3b7}', '\u{314}'), '\u{1f21}'),
+    (('\u{3b7}', '\u{342}'), '\u{1fc6}'), (('\u{3b7}', '\u{345}'), '\u{1fc3}'),
+    (('\u{3b9}', '\u{300}'), '\u{1f76}'), (('\u{3b9}', '\u{301}'), '\u{3af}'),
+    (('\u{3b9}', '\u{304}'), '\u{1fd1}'), (('\u{3b9}', '\u{306}'), '\u{1fd0}'),
+    (('\u{3b9}', '\u{308}'), '\u{3ca}'), (('\u{3b9}', '\u{313}'), '\u{1f30}'),
+    (('\u{3b9}', '\u{314}'), '\u{1f31}'), (('\u{3b9}', '\u{342}'), '\u{1fd6}'),
+    (('\u{3bf}', '\u{300}'), '\u{1f78}'), (('\u{3bf}', '\u{301}'), '\u{3cc}'),
+    (('\u{3bf}', '\u{313}'), '\u{1f40}'), (('\u{3bf}', '\u{314}'), '\u{1f41}'),
+    (('\u{3c1}', '\u{313}'), '\u{1fe4}'), (('\u{3c1}', '\u{314}'), '\u{1fe5}'),
+    (('\u{3c5}', '\u{300}'), '\u{1f7a}'), (('\u{3c5}', '\u{301}'), '\u{3cd}'),
+    (('\u{3c5}', '\u{304}'), '\u{1fe1}'), (('\u{3c5}', '\u{306}'), '\u{1fe0}'),
+    (('\u{3c5}', '\u{308}'), '\u{3cb}'), (('\u{3c5}', '\u{313}'), '\u{1f50}'),
+    (('\u{3c5}', '\u{314}'), '\u{1f51}'), (('\u{3c5}', '\u{342}'), '\u{1fe6}'),
+    (('\u{3c9}', '\u{300}'), '\u{1f7c}'), (('\u{3c9}', '\u{301}'), '\u{3ce}'),
+    (('\u{3c9}', '\u{313}'), '\u{1f60}'), (('\u{3c9}', '\u{314}'), '\u{1f61}'),
+    (('\u{3c9}', '\u{342}'), '\u{1ff6}'), (('\u{3c9}', '\u{345}'), '\u{1ff3}'),
+    (('\u{3ca}', '\u{300}'), '\u{1fd2}'), (('\u{3ca}', '\u{301}'), '\u{390}'),
+    (('\u{3ca}', '\u{342}'), '\u{1fd7}'), (('\u{3cb}', '\u{300}'), '\u{1fe2}'),
+    (('\u{3cb}', '\u{301}'), '\u{3b0}'), (('\u{3cb}', '\u{342}'), '\u{1fe7}'),
+    (('\u{3ce}', '\u{345}'), '\u{1ff4}'), (('\u{3d2}', '\u{301}'), '\u{3d3}'),
+    (('\u{3d2}', '\u{308}'), '\u{3d4}'), (('\u{406}', '\u{308}'), '\u{407}'),
+    (('\u{410}', '\u{306}'), '\u{4d0}'), (('\u{410}', '\u{308}'), '\u{4d2}'),
+    (('\u{413}', '\u{301}'), '\u{403}'), (('\u{415}', '\u{300}'), '\u{400}'),
+    (('\u{415}', '\u{306}'), '\u{4d6}'), (('\u{415}', '\u{308}'), '\u{401}'),
+    (('\u{416}', '\u{306}'), '\u{4c1}'), (('\u{416}', '\u{308}'), '\u{4dc}'),
+    (('\u{417}', '\u{308}'), '\u{4de}'), (('\u{418}', '\u{300}'), '\u{40d}'),
+    (('\u{418}', '\u{304}'), '\u{4e2}'), (('\u{418}', '\u{306}'), '\u{419}'),
+    (('\u{418}', '\u{308}'), '\u{4e4}'), (('\u{41a}', '\u{301}'), '\u{40c}'),
+    (('\u{41e}', '\u{308}'), '\u{4e6}'), (('\u{423}', '\u{304}'), '\u{4ee}'),
+    (('\u{423}', '\u{306}'), '\u{40e}'), (('\u{423}', '\u{308}'), '\u{4f0}'),
+    (('\u{423}', '\u{30b}'), '\u{4f2}'), (('\u{427}', '\u{308}'), '\u{4f4}'),
+    (('\u{42b}', '\u{308}'), '\u{4f8}'), (('\u{42d}', '\u{308}'), '\u{4ec}'),
+    (('\u{430}', '\u{306}'), '\u{4d1}'), (('\u{430}', '\u{308}'), '\u{4d3}'),
+    (('\u{433}', '\u{301}'), '\u{453}'), (('\u{435}', '\u{300}'), '\u{450}'),
+    (('\u{435}', '\u{306}'), '\u{4d7}'), (('\u{435}', '\u{308}'), '\u{451}'),
+    (('\u{436}', '\u{306}'), '\u{4c2}'), (('\u{436}', '\u{308}'), '\u{4dd}'),
+    (('\u{437}', '\u{308}'), '\u{4df}'), (('\u{438}', '\u{300}'), '\u{45d}'),
+    (('\u{438}', '\u{304}'), '\u{4e3}'), (('\u{438}', '\u{306}'), '\u{439}'),
+    (('\u{438}', '\u{308}'), '\u{4e5}'), (('\u{43a}', '\u{301}'), '\u{45c}'),
+    (('\u{43e}', '\u{308}'), '\u{4e7}'), (('\u{443}', '\u{304}'), '\u{4ef}'),
+    (('\u{443}', '\u{306}'), '\u{45e}'), (('\u{443}', '\u{308}'), '\u{4f1}'),
+    (('\u{443}', '\u{30b}'), '\u{4f3}'), (('\u{447}', '\u{308}'), '\u{4f5}'),
+    (('\u{44b}', '\u{308}'), '\u{4f9}'), (('\u{44d}', '\u{308}'), '\u{4ed}'),
+    (('\u{456}', '\u{308}'), '\u{457}'), (('\u{474}', '\u{30f}'), '\u{476}'),
+    (('\u{475}', '\u{30f}'), '\u{477}'), (('\u{4d8}', '\u{308}'), '\u{4da}'),
+    (('\u{4d9}', '\u{308}'), '\u{4db}'), (('\u{4e8}', '\u{308}'), '\u{4ea}'),
+    (('\u{4e9}', '\u{308}'), '\u{4eb}'), (('\u{627}', '\u{653}'), '\u{622}'),
+    (('\u{627}', '\u{654}'), '\u{623}'), (('\u{627}', '\u{655}'), '\u{625}'),
+    (('\u{648}', '\u{654}'), '\u{624}'), (('\u{64a}', '\u{654}'), '\u{626}'),
+    (('\u{6c1}', '\u{654}'), '\u{6c2}'), (('\u{6d2}', '\u{654}'), '\u{6d3}'),
+    (('\u{6d5}', '\u{654}'), '\u{6c0}'), (('\u{928}', '\u{93c}'), '\u{929}'),
+    (('\u{930}', '\u{93c}'), '\u{931}'), (('\u{933}', '\u{93c}'), '\u{934}'),
+    (('\u{9c7}', '\u{9be}'), '\u{9cb}'), (('\u{9c7}', '\u{9d7}'), '\u{9cc}'),
+    (('\u{b47}', '\u{b3e}'), '\u{b4b}'), (('\u{b47}', '\u{b56}'), '\u{b48}'),
+    (('\u{b47}', '\u{b57}'), '\u{b4c}'), (('\u{b92}', '\u{bd7}'), '\u{b94}'),
+    (('\u{bc6}', '\u{bbe}'), '\u{bca}'), (('\u{bc6}', '\u{bd7}'), '\u{bcc}'),
+    (('\u{bc7}', '\u{bbe}'), '\u{bcb}'), (('\u{c46}', '\u{c56}'), '\u{c48}'),
+    (('\u{cbf}', '\u{cd5}'), '\u{cc0}'), (('\u{cc6}', '\u{cc2}'), '\u{cca}'),
+    (('\u{cc6}', '\u{cd5}'), '\u{cc7}'), (('\u{cc6}', '\u{cd6}'), '\u{cc8}'),
+    (('\u{cca}', '\u{cd5}'), '\u{ccb}'), (('\u{d46}', '\u{d3e}'), '\u{d4a}'),
+    (('\u{d46}', '\u{d57}'), '\u{d4c}'), (('\u{d47}', '\u{d3e}'), '\u{d4b}'),
+    (('\u{dd9}', '\u{dca}'), '\u{dda}'), (('\u{dd9}', '\u{dcf}'), '\u{ddc}'),
+    (('\u{dd9}', '\u{ddf}'), '\u{dde}'), (('\u{ddc}', '\u{dca}'), '\u{ddd}'),
+    (('\u{1025}', '\u{102e}'), '\u{1026}'), (('\u{1b05}', '\u{1b35}'), '\u{1b06}'),
+    (('\u{1b07}', '\u{1b35}'), '\u{1b08}'), (('\u{1b09}', '\u{1b35}'), '\u{1b0a}'),
+    (('\u{1b0b}', '\u{1b35}'), '\u{1b0c}'), (('\u{1b0d}', '\u{1b35}'), '\u{1b0e}'),
+    (('\u{1b11}', '\u{1b35}'), '\u{1b12}'), (('\u{1b3a}', '\u{1b35}'), '\u{1b3b}'),
+    (('\u{1b3c}', '\u{1b35}'), '\u{1b3d}'), (('\u{1b3e}', '\u{1b35}'), '\u{1b40}'),
+    (('\u{1b3f}', '\u{1b35}'), '\u{1b41}'), (('\u{1b42}', '\u{1b35}'), '\u{1b43}'),
+    (('\u{1e36}', '\u{304}'), '\u{1e38}'), (('\u{1e37}', '\u{304}'), '\u{1e39}'),
+    (('\u{1e5a}', '\u{304}'), '\u{1e5c}'), (('\u{1e5b}', '\u{304}'), '\u{1e5d}'),
+    (('\u{1e62}', '\u{307}'), '\u{1e68}'), (('\u{1e63}', '\u{307}'), '\u{1e69}'),
+    (('\u{1ea0}', '\u{302}'), '\u{1eac}'), (('\u{1ea0}', '\u{306}'), '\u{1eb6}'),
+    (('\u{1ea1}', '\u{302}'), '\u{1ead}'), (('\u{1ea1}', '\u{306}'), '\u{1eb7}'),
+    (('\u{1eb8}', '\u{302}'), '\u{1ec6}'), (('\u{1eb9}', '\u{302}'), '\u{1ec7}'),
+    (('\u{1ecc}', '\u{302}'), '\u{1ed8}'), (('\u{1ecd}', '\u{302}'), '\u{1ed9}'),
+    (('\u{1f00}', '\u{300}'), '\u{1f02}'), (('\u{1f00}', '\u{301}'), '\u{1f04}'),
+    (('\u{1f00}', '\u{342}'), '\u{1f06}'), (('\u{1f00}', '\u{345}'), '\u{1f80}'),
+    (('\u{1f01}', '\u{300}'), '\u{1f03}'), (('\u{1f01}', '\u{301}'), '\u{1f05}'),
+    (('\u{1f01}', '\u{342}'), '\u{1f07}'), (('\u{1f01}', '\u{345}'), '\u{1f81}'),
+    (('\u{1f02}', '\u{345}'), '\u{1f82}'), (('\u{1f03}', '\u{345}'), '\u{1f83}'),
+    (('\u{1f04}', '\u{345}'), '\u{1f84}'), (('\u{1f05}', '\u{345}'), '\u{1f85}'),
+    (('\u{1f06}', '\u{345}'), '\u{1f86}'), (('\u{1f07}', '\u{345}'), '\u{1f87}'),
+    (('\u{1f08}', '\u{300}'), '\u{1f0a}'), (('\u{1f08}', '\u{301}'), '\u{1f0c}'),
+    (('\u{1f08}', '\u{342}'), '\u{1f0e}'), (('\u{1f08}', '\u{345}'), '\u{1f88}'),
+    (('\u{1f09}', '\u{300}'), '\u{1f0b}'), (('\u{1f09}', '\u{301}'), '\u{1f0d}'),
+    (('\u{1f09}', '\u{342}'), '\u{1f0f}'), (('\u{1f09}', '\u{345}'), '\u{1f89}'),
+    (('\u{1f0a}', '\u{345}'), '\u{1f8a}'), (('\u{1f0b}', '\u{345}'), '\u{1f8b}'),
+    (('\u{1f0c}', '\u{345}'), '\u{1f8c}'), (('\u{1f0d}', '\u{345}'), '\u{1f8d}'),
+    (('\u{1f0e}', '\u{345}'), '\u{1f8e}'), (('\u{1f0f}', '\u{345}'), '\u{1f8f}'),
+    (('\u{1f10}', '\u{300}'), '\u{1f12}'), (('\u{1f10}', '\u{301}'), '\u{1f14}'),
+    (('\u{1f11}', '\u{300}'), '\u{1f13}'), (('\u{1f11}', '\u{301}'), '\u{1f15}'),
+    (('\u{1f18}', '\u{300}'), '\u{1f1a}'), (('\u{1f18}', '\u{301}'), '\u{1f1c}'),
+    (('\u{1f19}', '\u{300}'), '\u{1f1b}'), (('\u{1f19}', '\u{301}'), '\u{1f1d}'),
+    (('\u{1f20}', '\u{300}'), '\u{1f22}'), (('\u{1f20}', '\u{301}'), '\u{1f24}'),
+    (('\u{1f20}', '\u{342}'), '\u{1f26}'), (('\u{1f20}', '\u{345}'), '\u{1f90}'),
+    (('\u{1f21}', '\u{300}'), '\u{1f23}'), (('\u{1f21}', '\u{301}'), '\u{1f25}'),
+    (('\u{1f21}', '\u{342}'), '\u{1f27}'), (('\u{1f21}', '\u{345}'), '\u{1f91}'),
+    (('\u{1f22}', '\u{345}'), '\u{1f92}'), (('\u{1f23}', '\u{345}'), '\u{1f93}'),
+    (('\u{1f24}', '\u{345}'), '\u{1f94}'), (('\u{1f25}', '\u{345}'), '\u{1f95}'),
+    (('\u{1f26}', '\u{345}'), '\u{1f96}'), (('\u{1f27}', '\u{345}'), '\u{1f97}'),
+    (('\u{1f28}', '\u{300}'), '\u{1f2a}'), (('\u{1f28}', '\u{301}'), '\u{1f2c}'),
+    (('\u{1f28}', '\u{342}'), '\u{1f2e}'), (('\u{1f28}', '\u{345}'), '\u{1f98}'),
+    (('\u{1f29}', '\u{300}'), '\u{1f2b}'), (('\u{1f29}', '\u{301}'), '\u{1f2d}'),
+    (('\u{1f29}', '\u{342}'), '\u{1f2f}'), (('\u{1f29}', '\u{345}'), '\u{1f99}'),
+    (('\u{1f2a}', '\u{345}'), '\u{1f9a}'), (('\u{1f2b}', '\u{345}'), '\u{1f9b}'),
+    (('\u{1f2c}', '\u{345}'), '\u{1f9c}'), (('\u{1f2d}', '\u{345}'), '\u{1f9d}'),
+    (('\u{1f2e}', '\u{345}'), '\u{1f9e}'), (('\u{1f2f}', '\u{345}'), '\u{1f9f}'),
+    (('\u{1f30}', '\u{300}'), '\u{1f32}'), (('\u{1f30}', '\u{301}'), '\u{1f34}'),
+    (('\u{1f30}', '\u{342}'), '\u{1f36}'), (('\u{1f31}', '\u{300}'), '\u{1f33}'),
+    (('\u{1f31}', '\u{301}'), '\u{1f35}'), (('\u{1f31}', '\u{342}'), '\u{1f37}'),
+    (('\u{1f38}', '\u{300}'), '\u{1f3a}'), (('\u{1f38}', '\u{301}'), '\u{1f3c}'),
+    (('\u{1f38}', '\u{342}'), '\u{1f3e}'), (('\u{1f39}', '\u{300}'), '\u{1f3b}'),
+    (('\u{1f39}', '\u{301}'), '\u{1f3d}'), (('\u{1f39}', '\u{342}'), '\u{1f3f}'),
+    (('\u{1f40}', '\u{300}'), '\u{1f42}'), (('\u{1f40}', '\u{301}'), '\u{1f44}'),
+    (('\u{1f41}', '\u{300}'), '\u{1f43}'), (('\u{1f41}', '\u{301}'), '\u{1f45}'),
+    (('\u{1f48}', '\u{300}'), '\u{1f4a}'), (('\u{1f48}', '\u{301}'), '\u{1f4c}'),
+    (('\u{1f49}', '\u{300}'), '\u{1f4b}'), (('\u{1f49}', '\u{301}'), '\u{1f4d}'),
+    (('\u{1f50}', '\u{300}'), '\u{1f52}'), (('\u{1f50}', '\u{301}'), '\u{1f54}'),
+    (('\u{1f50}', '\u{342}'), '\u{1f56}'), (('\u{1f51}', '\u{300}'), '\u{1f53}'),
+    (('\u{1f51}', '\u{301}'), '\u{1f55}'), (('\u{1f51}', '\u{342}'), '\u{1f57}'),
+    (('\u{1f59}', '\u{300}'), '\u{1f5b}'), (('\u{1f59}', '\u{301}'), '\u{1f5d}'),
+    (('\u{1f59}', '\u{342}'), '\u{1f5f}'), (('\u{1f60}', '\u{300}'), '\u{1f62}'),
+    (('\u{1f60}', '\u{301}'), '\u{1f64}'), (('\u{1f60}', '\u{342}'), '\u{1f66}'),
+    (('\u{1f60}', '\u{345}'), '\u{1fa0}'), (('\u{1f61}', '\u{300}'), '\u{1f63}'),
+    (('\u{1f61}', '\u{301}'), '\u{1f65}'), (('\u{1f61}', '\u{342}'), '\u{1f67}'),
+    (('\u{1f61}', '\u{345}'), '\u{1fa1}'), (('\u{1f62}', '\u{345}'), '\u{1fa2}'),
+    (('\u{1f63}', '\u{345}'), '\u{1fa3}'), (('\u{1f64}', '\u{345}'), '\u{1fa4}'),
+    (('\u{1f65}', '\u{345}'), '\u{1fa5}'), (('\u{1f66}', '\u{345}'), '\u{1fa6}'),
+    (('\u{1f67}', '\u{345}'), '\u{1fa7}'), (('\u{1f68}', '\u{300}'), '\u{1f6a}'),
+    (('\u{1f68}', '\u{301}'), '\u{1f6c}'), (('\u{1f68}', '\u{342}'), '\u{1f6e}'),
+    (('\u{1f68}', '\u{345}'), '\u{1fa8}'), (('\u{1f69}', '\u{300}'), '\u{1f6b}'),
+    (('\u{1f69}', '\u{301}'), '\u{1f6d}'), (('\u{1f69}', '\u{342}'), '\u{1f6f}'),
+    (('\u{1f69}', '\u{345}'), '\u{1fa9}'), (('\u{1f6a}', '\u{345}'), '\u{1faa}'),
+    (('\u{1f6b}', '\u{345}'), '\u{1fab}'), (('\u{1f6c}', '\u{345}'), '\u{1fac}'),
+    (('\u{1f6d}', '\u{345}'), '\u{1fad}'), (('\u{1f6e}', '\u{345}'), '\u{1fae}'),
+    (('\u{1f6f}', '\u{345}'), '\u{1faf}'), (('\u{1f70}', '\u{345}'), '\u{1fb2}'),
+    (('\u{1f74}', '\u{345}'), '\u{1fc2}'), (('\u{1f7c}', '\u{345}'), '\u{1ff2}'),
+    (('\u{1fb6}', '\u{345}'), '\u{1fb7}'), (('\u{1fbf}', '\u{300}'), '\u{1fcd}'),
+    (('\u{1fbf}', '\u{301}'), '\u{1fce}'), (('\u{1fbf}', '\u{342}'), '\u{1fcf}'),
+    (('\u{1fc6}', '\u{345}'), '\u{1fc7}'), (('\u{1ff6}', '\u{345}'), '\u{1ff7}'),
+    (('\u{1ffe}', '\u{300}'), '\u{1fdd}'), (('\u{1ffe}', '\u{301}'), '\u{1fde}'),
+    (('\u{1ffe}', '\u{342}'), '\u{1fdf}'), (('\u{2190}', '\u{338}'), '\u{219a}'),
+    (('\u{2192}', '\u{338}'), '\u{219b}'), (('\u{2194}', '\u{338}'), '\u{21ae}'),
+    (('\u{21d0}', '\u{338}'), '\u{21cd}'), (('\u{21d2}', '\u{338}'), '\u{21cf}'),
+    (('\u{21d4}', '\u{338}'), '\u{21ce}'), (('\u{2203}', '\u{338}'), '\u{2204}'),
+    (('\u{2208}', '\u{338}'), '\u{2209}'), (('\u{220b}', '\u{338}'), '\u{220c}'),
+    (('\u{2223}', '\u{338}'), '\u{2224}'), (('\u{2225}', '\u{338}'), '\u{2226}'),
+    (('\u{223c}', '\u{338}'), '\u{2241}'), (('\u{2243}', '\u{338}'), '\u{2244}'),
+    (('\u{2245}', '\u{338}'), '\u{2247}'), (('\u{2248}', '\u{338}'), '\u{2249}'),
+    (('\u{224d}', '\u{338}'), '\u{226d}'), (('\u{2261}', '\u{338}'), '\u{2262}'),
+    (('\u{2264}', '\u{338}'), '\u{2270}'), (('\u{2265}', '\u{338}'), '\u{2271}'),
+    (('\u{2272}', '\u{338}'), '\u{2274}'), (('\u{2273}', '\u{338}'), '\u{2275}'),
+    (('\u{2276}', '\u{338}'), '\u{2278}'), (('\u{2277}', '\u{338}'), '\u{2279}'),
+    (('\u{227a}', '\u{338}'), '\u{2280}'), (('\u{227b}', '\u{338}'), '\u{2281}'),
+    (('\u{227c}', '\u{338}'), '\u{22e0}'), (('\u{227d}', '\u{338}'), '\u{22e1}'),
+    (('\u{2282}', '\u{338}'), '\u{2284}'), (('\u{2283}', '\u{338}'), '\u{2285}'),
+    (('\u{2286}', '\u{338}'), '\u{2288}'), (('\u{2287}', '\u{338}'), '\u{2289}'),
+    (('\u{2291}', '\u{338}'), '\u{22e2}'), (('\u{2292}', '\u{338}'), '\u{22e3}'),
+    (('\u{22a2}', '\u{338}'), '\u{22ac}'), (('\u{22a8}', '\u{338}'), '\u{22ad}'),
+    (('\u{22a9}', '\u{338}'), '\u{22ae}'), (('\u{22ab}', '\u{338}'), '\u{22af}'),
+    (('\u{22b2}', '\u{338}'), '\u{22ea}'), (('\u{22b3}', '\u{338}'), '\u{22eb}'),
+    (('\u{22b4}', '\u{338}'), '\u{22ec}'), (('\u{22b5}', '\u{338}'), '\u{22ed}'),
+    (('\u{3046}', '\u{3099}'), '\u{3094}'), (('\u{304b}', '\u{3099}'), '\u{304c}'),
+    (('\u{304d}', '\u{3099}'), '\u{304e}'), (('\u{304f}', '\u{3099}'), '\u{3050}'),
+    (('\u{3051}', '\u{3099}'), '\u{3052}'), (('\u{3053}', '\u{3099}'), '\u{3054}'),
+    (('\u{3055}', '\u{3099}'), '\u{3056}'), (('\u{3057}', '\u{3099}'), '\u{3058}'),
+    (('\u{3059}', '\u{3099}'), '\u{305a}'), (('\u{305b}', '\u{3099}'), '\u{305c}'),
+    (('\u{305d}', '\u{3099}'), '\u{305e}'), (('\u{305f}', '\u{3099}'), '\u{3060}'),
+    (('\u{3061}', '\u{3099}'), '\u{3062}'), (('\u{3064}', '\u{3099}'), '\u{3065}'),
+    (('\u{3066}', '\u{3099}'), '\u{3067}'), (('\u{3068}', '\u{3099}'), '\u{3069}'),
+    (('\u{306f}', '\u{3099}'), '\u{3070}'), (('\u{306f}', '\u{309a}'), '\u{3071}'),
+    (('\u{3072}', '\u{3099}'), '\u{3073}'), (('\u{3072}', '\u{309a}'), '\u{3074}'),
+    (('\u{3075}', '\u{3099}'), '\u{3076}'), (('\u{3075}', '\u{309a}'), '\u{3077}'),
+    (('\u{3078}', '\u{3099}'), '\u{3079}'), (('\u{3078}', '\u{309a}'), '\u{307a}'),
+    (('\u{307b}', '\u{3099}'), '\u{307c}'), (('\u{307b}', '\u{309a}'), '\u{307d}'),
+    (('\u{309d}', '\u{3099}'), '\u{309e}'), (('\u{30a6}', '\u{3099}'), '\u{30f4}'),
+    (('\u{30ab}', '\u{3099}'), '\u{30ac}'), (('\u{30ad}', '\u{3099}'), '\u{30ae}'),
+    (('\u{30af}', '\u{3099}'), '\u{30b0}'), (('\u{30b1}', '\u{3099}'), '\u{30b2}'),
+    (('\u{30b3}', '\u{3099}'), '\u{30b4}'), (('\u{30b5}', '\u{3099}'), '\u{30b6}'),
+    (('\u{30b7}', '\u{3099}'), '\u{30b8}'), (('\u{30b9}', '\u{3099}'), '\u{30ba}'),
+    (('\u{30bb}', '\u{3099}'), '\u{30bc}'), (('\u{30bd}', '\u{3099}'), '\u{30be}'),
+    (('\u{30bf}', '\u{3099}'), '\u{30c0}'), (('\u{30c1}', '\u{3099}'), '\u{30c2}'),
+    (('\u{30c4}', '\u{3099}'), '\u{30c5}'), (('\u{30c6}', '\u{3099}'), '\u{30c7}'),
+    (('\u{30c8}', '\u{3099}'), '\u{30c9}'), (('\u{30cf}', '\u{3099}'), '\u{30d0}'),
+    (('\u{30cf}', '\u{309a}'), '\u{30d1}'), (('\u{30d2}', '\u{3099}'), '\u{30d3}'),
+    (('\u{30d2}', '\u{309a}'), '\u{30d4}'), (('\u{30d5}', '\u{3099}'), '\u{30d6}'),
+    (('\u{30d5}', '\u{309a}'), '\u{30d7}'), (('\u{30d8}', '\u{3099}'), '\u{30d9}'),
+    (('\u{30d8}', '\u{309a}'), '\u{30da}'), (('\u{30db}', '\u{3099}'), '\u{30dc}'),
+    (('\u{30db}', '\u{309a}'), '\u{30dd}'), (('\u{30ef}', '\u{3099}'), '\u{30f7}'),
+    (('\u{30f0}', '\u{3099}'), '\u{30f8}'), (('\u{30f1}', '\u{3099}'), '\u{30f9}'),
+    (('\u{30f2}', '\u{3099}'), '\u{30fa}'), (('\u{30fd}', '\u{3099}'), '\u{30fe}'),
+    (('\u{105d2}', '\u{307}'), '\u{105c9}'), (('\u{105da}', '\u{307}'), '\u{105e4}'),
+    (('\u{11099}', '\u{110ba}'), '\u{1109a}'), (('\u{1109b}', '\u{110ba}'), '\u{1109c}'),
+    (('\u{110a5}', '\u{110ba}'), '\u{110ab}'), (('\u{11131}', '\u{11127}'), '\u{1112e}'),
+    (('\u{11132}', '\u{11127}'), '\u{1112f}'), (('\u{11347}', '\u{1133e}'), '\u{1134b}'),
+    (('\u{11347}', '\u{11357}'), '\u{1134c}'), (('\u{11382}', '\u{113c9}'), '\u{11383}'),
+    (('\u{11384}', '\u{113bb}'), '\u{11385}'), (('\u{1138b}', '\u{113c2}'), '\u{1138e}'),
+    (('\u{11390}', '\u{113c9}'), '\u{11391}'), (('\u{113c2}', '\u{113b8}'), '\u{113c7}'),
+    (('\u{113c2}', '\u{113c2}'), '\u{113c5}'), (('\u{113c2}', '\u{113c9}'), '\u{113c8}'),
+    (('\u{114b9}', '\u{114b0}'), '\u{114bc}'), (('\u{114b9}', '\u{114ba}'), '\u{114bb}'),
+    (('\u{114b9}', '\u{114bd}'), '\u{114be}'), (('\u{115b8}', '\u{115af}'), '\u{115ba}'),
+    (('\u{115b9}', '\u{115af}'), '\u{115bb}'), (('\u{11935}', '\u{11930}'), '\u{11938}'),
+    (('\u{1611e}', '\u{1611e}'), '\u{16121}'), (('\u{1611e}', '\u{1611f}'), '\u{16123}'),
+    (('\u{1611e}', '\u{16120}'), '\u{16125}'), (('\u{1611e}', '\u{16129}'), '\u{16122}'),
+    (('\u{16121}', '\u{1611f}'), '\u{16126}'), (('\u{16121}', '\u{16120}'), '\u{16128}'),
+    (('\u{16122}', '\u{1611f}'), '\u{16127}'), (('\u{16129}', '\u{1611f}'), '\u{16124}'),
+    (('\u{16d63}', '\u{16d67}'), '\u{16d69}'), (('\u{16d67}', '\u{16d67}'), '\u{16d68}'),
+    (('\u{16d69}', '\u{16d67}'), '\u{16d6a}'),
+];
+
+/// Full case folding (status C and F of `CaseFolding.txt`).
+#[rustfmt::skip]
+pub const CASE_FOLDING: &[(char, &str)] = &[
+    ('\u{41}', "\u{61}"), ('\u{42}', "\u{62}"), ('\u{43}', "\u{63}"), ('\u{44}', "\u{64}"),
+    ('\u{45}', "\u{65}"), ('\u{46}', "\u{66}"), ('\u{47}', "\u{67}"), ('\u{48}', "\u{68}"),
+    ('\u{49}', "\u{69}"), ('\u{4a}', "\u{6a}"), ('\u{4b}', "\u{6b}"), ('\u{4c}', "\u{6c}"),
+    ('\u{4d}', "\u{6d}"), ('\u{4e}', "\u{6e}"), ('\u{4f}', "\u{6f}"), ('\u{50}', "\u{70}"),
+    ('\u{51}', "\u{71}"), ('\u{52}', "\u{72}"), ('\u{53}', "\u{73}"), ('\u{54}', "\u{74}"),
+    ('\u{55}', "\u{75}"), ('\u{56}', "\u{76}"), ('\u{57}', "\u{77}"), ('\u{58}', "\u{78}"),
+    ('\u{59}', "\u{79}"), ('\u{5a}', "\u{7a}"), ('\u{b5}', "\u{3bc}"), ('\u{c0}', "\u{e0}"),
+    ('\u{c1}', "\u{e1}"), ('\u{c2}', "\u{e2}"), ('\u{c3}', "\u{e3}"), ('\u{c4}', "\u{e4}"),
+    ('\u{c5}', "\u{e5}"), ('\u{c6}', "\u{e6}"), ('\u{c7}', "\u{e7}"), ('\u{c8}', "\u{e8}"),
+    ('\u{c9}', "\u{e9}"), ('\u{ca}', "\u{ea}"), ('\u{cb}', "\u{eb}"), ('\u{cc}', "\u{ec}"),
+    ('\u{cd}', "\u{ed}"), ('\u{ce}', "\u{ee}"), ('\u{cf}', "\u{ef}"), ('\u{d0}', "\u{f0}"),
+    ('\u{d1}', "\u{f1}"), ('\u{d2}', "\u{f2}"), ('\u{d3}', "\u{f3}"), ('\u{d4}', "\u{f4}"),
+    ('\u{d5}', "\u{f5}"), ('\u{d6}', "\u{f6}"), ('\u{d8}', "\u{f8}"), ('\u{d9}', "\u{f9}"),
+    ('\u{da}', "\u{fa}"), ('\u{db}', "\u{fb}"), ('\u{dc}', "\u{fc}"), ('\u{dd}', "\u{fd}"),
+    ('\u{de}', "\u{fe}"), ('\u{df}', "\u{73}\u{73}"), ('\u{100}', "\u{101}"),
+    ('\u{102}', "\u{103}"), ('\u{104}', "\u{105}"), ('\u{106}', "\u{107}"),
+    ('\u{108}', "\u{109}"), ('\u{10a}', "\u{10b}"), ('\u{10c}', "\u{10d}"),
+    ('\u{10e}', "\u{10f}"), ('\u{110}', "\u{111}"), ('\u{112}', "\u{113}"),
+    ('\u{114}', "\u{115}"), ('\u{116}', "\u{117}"), ('\u{118}', "\u{119}"),
+    ('\u{11a}', "\u{11b}"), ('\u{11c}', "\u{11d}"), ('\u{11e}', "\u{11f}"),
+    ('\u{120}', "\u{121}"), ('\u{122}', "\u{123}"), ('\u{124}', "\u{125}"),
+    ('\u{126}', "\u{127}"), ('\u{128}', "\u{129}"), ('\u{12a}', "\u{12b}"),
+    ('\u{12c}', "\u{12d}"), ('\u{12e}', "\u{12f}"), ('\u{130}', "\u{69}\u{307}"),
+    ('\u{132}', "\u{133}"), ('\u{134}', "\u{135}"), ('\u{136}', "\u{137}"),
+    ('\u{139}', "\u{13a}"), ('\u{13b}', "\u{13c}"), ('\u{13d}', "\u{13e}"),
+    ('\u{13f}', "\u{140}"), ('\u{141}', "\u{142}"), ('\u{143}', "\u{144}"),
+    ('\u{145}', "\u{146}"), ('\u{147}', "\u{148}"), ('\u{149}', "\u{2bc}\u{6e}"),
+    ('\u{14a}', "\u{14b}"), ('\u{14c}', "\u{14d}"), ('\u{14e}', "\u{14f}"),
+    ('\u{150}', "\u{151}"), ('\u{152}', "\u{153}"), ('\u{154}', "\u{155}"),
+    ('\u{156}', "\u{157}"), ('\u{158}', "\u{159}"), ('\u{15a}', "\u{15b}"),
+    ('\u{15c}', "\u{15d}"), ('\u{15e}', "\u{15f}"), ('\u{160}', "\u{161}"),
+    ('\u{162}', "\u{163}"), ('\u{164}', "\u{165}"), ('\u{166}', "\u{167}"),
+    ('\u{168}', "\u{169}"), ('\u{16a}', "\u{16b}"), ('\u{16c}', "\u{16d}"),
+    ('\u{16e}', "\u{16f}"), ('\u{170}', "\u{171}"), ('\u{172}', "\u{173}"),
+    ('\u{174}', "\u{175}"), ('\u{176}', "\u{177}"), ('\u{178}', "\u{ff}"), ('\u{179}', "\u{17a}"),
+    ('\u{17b}', "\u{17c}"), ('\u{17d}', "\u{17e}"), ('\u{17f}', "\u{73}"), ('\u{181}', "\u{253}"),
+    ('\u{182}', "\u{183}"), ('\u{184}', "\u{185}"), ('\u{186}', "\u{254}"),
+    ('\u{187}', "\u{188}"), ('\u{189}', "\u{256}"), ('\u{18a}', "\u{257}"),
+    ('\u{18b}', "\u{18c}"), ('\u{18e}', "\u{1dd}"), ('\u{18f}', "\u{259}"),
+    ('\u{190}', "\u{25b}"), ('\u{191}', "\u{192}"), ('\u{193}', "\u{260}"),
+    ('\u{194}', "\u{263}"), ('\u{196}', "\u{269}"), ('\u{197}', "\u{268}"),
+    ('\u{198}', "\u{199}"), ('\u{19c}', "\u{26f}"), ('\u{19d}', "\u{272}"),
+    ('\u{19f}', "\u{275}"), ('\u{1a0}', "\u{1a1}"), ('\u{1a2}', "\u{1a3}"),
+    ('\u{1a4}', "\u{1a5}"), ('\u{1a6}', "\u{280}"), ('\u{1a7}', "\u{1a8}"),
+    ('\u{1a9}', "\u{283}"), ('\u{1ac}', "\u{1ad}"), ('\u{1ae}', "\u{288}"),
+    ('\u{1af}', "\u{1b0}"), ('\u{1b1}', "\u{28a}"), ('\u{1b2}', "\u{28b}"),
+    ('\u{1b3}', "\u{1b4}"), ('\u{1b5}', "\u{1b6}"), ('\u{1b7}', "\u{292}"),
+    ('\u{1b8}', "\u{1b9}"), ('\u{1bc}', "\u{1bd}"), ('\u{1c4}', "\u{1c6}"),
+    ('\u{1c5}', "\u{1c6}"), ('\u{1c7}', "\u{1c9}"), ('\u{1c8}', "\u{1c9}"),
+    ('\u{1ca}', "\u{1cc}"), ('\u{1cb}', "\u{1cc}"), ('\u{1cd}', "\u{1ce}"),
+    ('\u{1cf}', "\u{1d0}"), ('\u{1d1}', "\u{1d2}"), ('\u{1d3}', "\u{1d4}"),
+    ('\u{1d5}', "\u{1d6}"), ('\u{1d7}', "\u{1d8}"), ('\u{1d9}', "\u{1da}"),
+    ('\u{1db}', "\u{1dc}"), ('\u{1de}', "\u{1df}"), ('\u{1e0}', "\u{1e1}"),
+    ('\u{1e2}', "\u{1e3}"), ('\u{1e4}', "\u{1e5}"), ('\u{1e6}', "\u{1e7}"),
+    ('\u{1e8}', "\u{1e9}"), ('\u{1ea}', "\u{1eb}"), ('\u{1ec}', "\u{1ed}"),
+    ('\u{1ee}', "\u{1ef}"), ('\u{1f0}', "\u{6a}\u{30c}"), ('\u{1f1}', "\u{1f3}"),
+    ('\u{1f2}', "\u{1f3}"), ('\u{1f4}', "\u{1f5}"), ('\u{1f6}', "\u{195}"),
+    ('\u{1f7}', "\u{1bf}"), ('\u{1f8}', "\u{1f9}"), ('\u{1fa}', "\u{1fb}"),
+    ('\u{1fc}', "\u{1fd}"), ('\u{1fe}', "\u{1ff}"), ('\u{200}', "\u{201}"),
+    ('\u{202}', "\u{203}"), ('\u{204}', "\u{205}"), ('\u{206}', "\u{207}"),
+    ('\u{208}', "\u{209}"), ('\u{20a}', "\u{20b}"), ('\u{20c}', "\u{20d}"),
+    ('\u{20e}', "\u{20f}"), ('\u{210}', "\u{211}"), ('\u{212}', "\u{213}"),
+    ('\u{214}', "\u{215}"), ('\u{216}', "\u{217}"), ('\u{218}', "\u{219}"),
+    ('\u{21a}', "\u{21b}"), ('\u{21c}', "\u{21d}"), ('\u{21e}', "\u{21f}"),
+    ('\u{220}', "\u{19e}"), ('\u{222}', "\u{223}"), ('\u{224}', "\u{225}"),
+    ('\u{226}', "\u{227}"), ('\u{228}', "\u{229}"), ('\u{22a}', "\u{22b}"),
+    ('\u{22c}', "\u{22d}"), ('\u{22e}', "\u{22f}"), ('\u{230}', "\u{231}"),
+    ('\u{232}', "\u{233}"), ('\u{23a}', "\u{2c65}"), ('\u{23b}', "\u{23c}"),
+    ('\u{23d}', "\u{19a}"), ('\u{23e}', "\u{2c66}"), ('\u{241}', "\u{242}"),
+    ('\u{243}', "\u{180}"), ('\u{244}', "\u{289}"), ('\u{245}', "\u{28c}"),
+    ('\u{246}', "\u{247}"), ('\u{248}', "\u{249}"), ('\u{24a}', "\u{24b}"),
+    ('\u{24c}', "\u{24d}"), ('\u{24e}', "\u{24f}"), ('\u{345}', "\u{3b9}"),
+    ('\u{370}', "\u{371}"), ('\u{372}', "\u{373}"), ('\u{376}', "\u{377}"),
+    ('\u{37f}', "\u{3f3}"), ('\u{386}', "\u{3ac}"), ('\u{388}', "\u{3ad}"),
+    ('\u{389}', "\u{3ae}"), ('\u{38a}', "\u{3af}"), ('\u{38c}', "\u{3cc}"),
+    ('\u{38e}', "\u{3cd}"), ('\u{38f}', "\u{3ce}"), ('\u{390}', "\u{3b9}\u{308}\u{301}"),
+    ('\u{391}', "\u{3b1}"), ('\u{392}', "\u{3b2}"), ('\u{393}', "\u{3b3}"),
+    ('\u{394}', "\u{3b4}"), ('\u{395}', "\u{3b5}"), ('\u{396}', "\u{3b6}"),
+    ('\u{397}', "\u{3b7}"), ('\u{398}', "\u{3b8}"), ('\u{399}', "\u{3b9}"),
+    ('\u{39a}', "\u{3ba}"), ('\u{39b}', "\u{3bb}"), ('\u{39c}', "\u{3bc}"),
+    ('\u{39d}', "\u{3bd}"), ('\u{39e}', "\u{3be}"), ('\u{39f}', "\u{3bf}"),
+    ('\u{3a0}', "\u{3c0}"), ('\u{3a1}', "\u{3c1}"), ('\u{3a3}', "\u{3c3}"),
+    ('\u{3a4}', "\u{3c4}"), ('\u{3a5}', "\u{3c5}"), ('\u{3a6}', "\u{3c6}"),
+    ('\u{3a7}', "\u{3c7}"), ('\u{3a8}', "\u{3c8}"), ('\u{3a9}', "\u{3c9}"),
+    ('\u{3aa}', "\u{3ca}"), ('\u{3ab}', "\u{3cb}"), ('\u{3b0}', "\u{3c5}\u{308}\u{301}"),
+    ('\u{3c2}', "\u{3c3}"), ('\u{3cf}', "\u{3d7}"), ('\u{3d0}', "\u{3b2}"),
+    ('\u{3d1}', "\u{3b8}"), ('\u{3d5}', "\u{3c6}"), ('\u{3d6}', "\u{3c0}"),
+    ('\u{3d8}', "\u{3d9}"), ('\u{3da}', "\u{3db}"), ('\u{3dc}', "\u{3dd}"),
+    ('\u{3de}', "\u{3df}"), ('\u{3e0}', "\u{3e1}"), ('\u{3e2}', "\u{3e3}"),
+    ('\u{3e4}', "\u{3e5}"), ('\u{3e6}', "\u{3e7}"), ('\u{3e8}', "\u{3e9}"),
+    ('\u{3ea}', "\u{3eb}"), ('\u{3ec}', "\u{3ed}"), ('\u{3ee}', "\u{3ef}"),
+    ('\u{3f0}', "\u{3ba}"), ('\u{3f1}', "\u{3c1}"), ('\u{3f4}', "\u{3b8}"),
+    ('\u{3f5}', "\u{3b5}"), ('\u{3f7}', "\u{3f8}"), ('\u{3f9}', "\u{3f2}"),
+    ('\u{3fa}', "\u{3fb}"), ('\u{3fd}', "\u{37b}"), ('\u{3fe}', "\u{37c}"),
+    ('\u{3ff}', "\u{37d}"), ('\u{400}', "\u{450}"), ('\u{401}', "\u{451}"),
+    ('\u{402}', "\u{452}"), ('\u{403}', "\u{453}"), ('\u{404}', "\u{454}"),
+    ('\u{405}', "\u{455}"), ('\u{406}', "\u{456}"), ('\u{407}', "\u{457}"),
+    ('\u{408}', "\u{458}"), ('\u{409}', "\u{459}"), ('\u{40a}', "\u{45a}"),
+    ('\u{40b}', "\u{45b}"), ('\u{40c}', "\u{45c}"), ('\u{40d}', "\u{45d}"),
+    ('\u{40e}', "\u{45e}"), ('\u{40f}', "\u{45f}"), ('\u{410}', "\u{430}"),
+    ('\u{411}', "\u{431}"), ('\u{412}', "\u{432}"), ('\u{413}', "\u{433}"),
+    ('\u{414}', "\u{434}"), ('\u{415}', "\u{435}"), ('\u{416}', "\u{436}"),
+    ('\u{417}', "\u{437}"), ('\u{418}', "\u{438}"), ('\u{419}', "\u{439}"),
+    ('\u{41a}', "\u{43a}"), ('\u{41b}', "\u{43b}"), ('\u{41c}', "\u{43c}"),
+    ('\u{41d}', "\u{43d}"), ('\u{41e}', "\u{43e}"), ('\u{41f}', "\u{43f}"),
+    ('\u{420}', "\u{440}"), ('\u{421}', "\u{441}"), ('\u{422}', "\u{442}"),
+    ('\u{423}', "\u{443}"), ('\u{424}', "\u{444}"), ('\u{425}', "\u{445}"),
+    ('\u{426}', "\u{446}"), ('\u{427}', "\u{447}"), ('\u{428}', "\u{448}"),
+    ('\u{429}', "\u{449}"), ('\u{42a}', "\u{44a}"), ('\u{42b}', "\u{44b}"),
+    ('\u{42c}', "\u{44c}"), ('\u{42d}', "\u{44d}"), ('\u{42e}', "\u{44e}"),
+    ('\u{42f}', "\u{44f}"), ('\u{460}', "\u{461}"), ('\u{462}', "\u{463}"),
+    ('\u{464}', "\u{465}"), ('\u{466}', "\u{467}"), ('\u{468}', "\u{469}"),
+    ('\u{46a}', "\u{46b}"), ('\u{46c}', "\u{46d}"), ('\u{46e}', "\u{46f}"),
+    ('\u{470}', "\u{471}"), ('\u{472}', "\u{473}"), ('\u{474}', "\u{475}"),
+    ('\u{476}', "\u{477}"), ('\u{478}', "\u{479}"), ('\u{47a}', "\u{47b}"),
+    ('\u{47c}', "\u{47d}"), ('\u{47e}', "\u{47f}"), ('\u{480}', "\u{481}"),
+    ('\u{48a}', "\u{48b}"), ('\u{48c}', "\u{48d}"), ('\u{48e}', "\u{48f}"),
+    ('\u{490}', "\u{491}"), ('\u{492}', "\u{493}"), ('\u{494}', "\u{495}"),
+    ('\u{496}', "\u{497}"), ('\u{498}', "\u{499}"), ('\u{49a}', "\u{49b}"),
+    ('\u{49c}', "\u{49d}"), ('\u{49e}', "\u{49f}"), ('\u{4a0}', "\u{4a1}"),
+    ('\u{4a2}', "\u{4a3}"), ('\u{4a4}', "\u{4a5}"), ('\u{4a6}', "\u{4a7}"),
+    ('\u{4a8}', "\u{4a9}"), ('\u{4aa}', "\u{4ab}"), ('\u{4ac}', "\u{4ad}"),
+    ('\u{4ae}', "\u{4af}"), ('\u{4b0}', "\u{4b1}"), ('\u{4b2}', "\u{4b3}"),
+    ('\u{4b4}', "\u{4b5}"), ('\u{4b6}', "\u{4b7}"), ('\u{4b8}', "\u{4b9}"),
+    ('\u{4ba}', "\u{4bb}"), ('\u{4bc}', "\u{4bd}"), ('\u{4be}', "\u{4bf}"),
+    ('\u{4c0}', "\u{4cf}"), ('\u{4c1}', "\u{4c2}"), ('\u{4c3}', "\u{4c4}"),
+    ('\u{4c5}', "\u{4c6}"), ('\u{4c7}', "\u{4c8}"), ('\u{4c9}', "\u{4ca}"),
+    ('\u{4cb}', "\u{4cc}"), ('\u{4cd}', "\u{4ce}"), ('\u{4d0}', "\u{4d1}"),
+    ('\u{4d2}', "\u{4d3}"), ('\u{4d4}', "\u{4d5}"), ('\u{4d6}', "\u{4d7}"),
+    ('\u{4d8}', "\u{4d9}"), ('\u{4da}', "\u{4db}"), ('\u{4dc}', "\u{4dd}"),
+    ('\u{4de}', "\u{4df}"), ('\u{4e0}', "\u{4e1}"), ('\u{4e2}', "\u{4e3}"),
+    ('\u{4e4}', "\u{4e5}"), ('\u{4e6}', "\u{4e7}"), ('\u{4e8}', "\u{4e9}"),
+    ('\u{4ea}', "\u{4eb}"), ('\u{4ec}', "\u{4ed}"), ('\u{4ee}', "\u{4ef}"),
+    ('\u{4f0}', "\u{4f1}"), ('\u{4f2}', "\u{4f3}"), ('\u{4f4}', "\u{4f5}"),
+    ('\u{4f6}', "\u{4f7}"), ('\u{4f8}', "\u{4f9}"), ('\u{4fa}', "\u{4fb}"),
+    ('\u{4fc}', "\u{4fd}"), ('\u{4fe}', "\u{4ff}"), ('\u{500}', "\u{501}"),
+    ('\u{502}', "\u{503}"), ('\u{504}', "\u{505}"), ('\u{506}', "\u{507}"),
+    ('\u{508}', "\u{509}"), ('\u{50a}', "\u{50b}"), ('\u{50c}', "\u{50d}"),
+    ('\u{50e}', "\u{50f}"), ('\u{510}', "\u{511}"), ('\u{512}', "\u{513}"),
+    ('\u{514}', "\u{515}"), ('\u{516}', "\u{517}"), ('\u{518}', "\u{519}"),
+    ('\u{51a}', "\u{51b}"), ('\u{51c}', "\u{51d}"), ('\u{51e}', "\u{51f}"),
+    ('\u{520}', "\u{521}"), ('\u{522}', "\u{523}"), ('\u{524}', "\u{525}"),
+    ('\u{526}', "\u{527}"), ('\u{528}', "\u{529}"), ('\u{52a}', "\u{52b}"),
+    ('\u{52c}', "\u{52d}"), ('\u{52e}', "\u{52f}"), ('\u{531}', "\u{561}"),
+    ('\u{532}', "\u{562}"), ('\u{533}', "\u{563}"), ('\u{534}', "\u{564}"),
+    ('\u{535}', "\u{565}"), ('\u{536}', "\u{566}"), ('\u{537}', "\u{567}"),
+    ('\u{538}', "\u{568}"), ('\u{539}', "\u{569}"), ('\u{53a}', "\u{56a}"),
+    ('\u{53b}', "\u{56b}"), ('\u{53c}', "\u{56c}"), ('\u{53d}', "\u{56d}"),
+    ('\u{53e}', "\u{56e}"), ('\u{53f}', "\u{56f}"), ('\u{540}', "\u{570}"),
+    ('\u{541}', "\u{571}"), ('\u{542}', "\u{572}"), ('\u{543}', "\u{573}"),
+    ('\u{544}', "\u{574}"), ('\u{545}', "\u{575}"), ('\u{546}', "\u{576}"),
+    ('\u{547}', "\u{577}"), ('\u{548}', "\u{578}"), ('\u{549}', "\u{579}"),
+    ('\u{54a}', "\u{57a}"), ('\u{54b}', "\u{57b}"), ('\u{54c}', "\u{57c}"),
+    ('\u{54d}', "\u{57d}"), ('\u{54e}', "\u{57e}"), ('\u{54f}', "\u{57f}"),
+    ('\u{550}', "\u{580}"), ('\u{551}', "\u{581}"), ('\u{552}', "\u{582}"),
+    ('\u{553}', "\u{583}"), ('\u{554}', "\u{584}"), ('\u{555}', "\u{585}"),
+    ('\u{556}', "\u{586}"), ('\u{587}', "\u{565}\u{582}"), ('\u{10a0}', "\u{2d00}"),
+    ('\u{10a1}', "\u{2d01}"), ('\u{10a2}', "\u{2d02}"), ('\u{10a3}', "\u{2d03}"),
+    ('\u{10a4}', "\u{2d04}"), ('\u{10a5}', "\u{2d05}"), ('\u{10a6}', "\u{2d06}"),
+    ('\u{10a7}', "\u{2d07}"), ('\u{10a8}', "\u{2d08}"), ('\u{10a9}', "\u{2d09}"),
+    ('\u{10aa}', "\u{2d0a}"), ('\u{10ab}', "\u{2d0b}"), ('\u{10ac}', "\u{2d0c}"),
+    ('\u{10ad}', "\u{2d0d}"), ('\u{10ae}', "\u{2d0e}"), ('\u{10af}', "\u{2d0f}"),
+    ('\u{10b0}', "\u{2d10}"), ('\u{10b1}', "\u{2d11}"), ('\u{10b2}', "\u{2d12}"),
+    ('\u{10b3}', "\u{2d13}"), ('\u{10b4}', "\u{2d14}"), ('\u{10b5}', "\u{2d15}"),
+    ('\u{10b6}', "\u{2d16}"), ('\u{10b7}', "\u{2d17}"), ('\u{10b8}', "\u{2d18}"),
+    ('\u{10b9}', "\u{2d19}"), ('\u{10ba}', "\u{2d1a}"), ('\u{10bb}', "\u{2d1b}"),
+    ('\u{10bc}', "\u{2d1c}"), ('\u{10bd}', "\u{2d1d}"), ('\u{10be}', "\u{2d1e}"),
+    ('\u{10bf}', "\u{2d1f}"), ('\u{10c0}', "\u{2d20}"), ('\u{10c1}', "\u{2d21}"),
+    ('\u{10c2}', "\u{2d22}"), ('\u{10c3}', "\u{2d23}"), ('\u{10c4}', "\u{2d24}"),
+    ('\u{10c5}', "\u{2d25}"), ('\u{10c7}', "\u{2d27}"), ('\u{10cd}', "\u{2d2d}"),
+    ('\u{13f8}', "\u{13f0}"), ('\u{13f9}', "\u{13f1}"), ('\u{13fa}', "\u{13f2}"),
+    ('\u{13fb}', "\u{13f3}"), ('\u{13fc}', "\u{13f4}"), ('\u{13fd}', "\u{13f5}"),
+    ('\u{1c80}', "\u{432}"), ('\u{1c81}', "\u{434}"), ('\u{1c82}', "\u{43e}"),
+    ('\u{1c83}', "\u{441}"), ('\u{1c84}', "\u{442}"), ('\u{1c85}', "\u{442}"),
+    ('\u{1c86}', "\u{44a}"), ('\u{1c87}', "\u{463}"), ('\u{1c88}', "\u{a64b}"),
+    ('\u{1c89}', "\u{1c8a}"), ('\u{1c90}', "\u{10d0}"), ('\u{1c91}', "\u{10d1}"),
+    ('\u{1c92}', "\u{10d2}"), ('\u{1c93}', "\u{10d3}"), ('\u{1c94}', "\u{10d4}"),
+    ('\u{1c95}', "\u{10d5}"), ('\u{1c96}', "\u{10d6}"), ('\u{1c97}', "\u{10d7}"),
+    ('\u{1c98}', "\u{10d8}"), ('\u{1c99}', "\u{10d9}"), ('\u{1c9a}', "\u{10da}"),
+    ('\u{1c9b}', "\u{10db}"), ('\u{1c9c}', "\u{10dc}"), ('\u{1c9d}', "\u{10dd}"),
+    ('\u{1c9e}', "\u{10de}"), ('\u{1c9f}', "\u{10df}"), ('\u{1ca0}', "\u{10e0}"),
+    ('\u{1ca1}', "\u{10e1}"), ('\u{1ca2}', "\u{10e2}"), ('\u{1ca3}', "\u{10e3}"),
+    ('\u{1ca4}', "\u{10e4}"), ('\u{1ca5}', "\u{10e5}"), ('\u{1ca6}', "\u{10e6}"),
+    ('\u{1ca7}', "\u{10e7}"), ('\u{1ca8}', "\u{10e8}"), ('\u{1ca9}', "\u{10e9}"),
+    ('\u{1caa}', "\u{10ea}"), ('\u{1cab}', "\u{10eb}"), ('\u{1cac}', "\u{10ec}"),
+    ('\u{1cad}', "\u{10ed}"), ('\u{1cae}', "\u{10ee}"), ('\u{1caf}', "\u{10ef}"),
+    ('\u{1cb0}', "\u{10f0}"), ('\u{1cb1}', "\u{10f1}"), ('\u{1cb2}', "\u{10f2}"),
+    ('\u{1cb3}', "\u{10f3}"), ('\u{1cb4}', "\u{10f4}"), ('\u{1cb5}', "\u{10f5}"),
+    ('\u{1cb6}', "\u{10f6}"), ('\u{1cb7}', "\u{10f7}"), ('\u{1cb8}', "\u{10f8}"),
+    ('\u{1cb9}', "\u{10f9}"), ('\u{1cba}', "\u{10fa}"), ('\u{1cbd}', "\u{10fd}"),
+    ('\u{1cbe}', "\u{10fe}"), ('\u{1cbf}', "\u{10ff}"), ('\u{1e00}', "\u{1e01}"),
+    ('\u{1e02}', "\u{1e03}"), ('\u{1e04}', "\u{1e05}"), ('\u{1e06}', "\u{1e07}"),
+    ('\u{1e08}', "\u{1e09}"), ('\u{1e0a}', "\u{1e0b}"), ('\u{1e0c}', "\u{1e0d}"),
+    ('\u{1e0e}', "\u{1e0f}"), ('\u{1e10}', "\u{1e11}"), ('\u{1e12}', "\u{1e13}"),
+    ('\u{1e14}', "\u{1e15}"), ('\u{1e16}', "\u{1e17}"), ('\u{1e18}', "\u{1e19}"),
+    ('\u{1e1a}', "\u{1e1b}"), ('\u{1e1c}', "\u{1e1d}"), ('\u{1e1e}', "\u{1e1f}"),
+    ('\u{1e20}', "\u{1e21}"), ('\u{1e22}', "\u{1e23}"), ('\u{1e24}', "\u{1e25}"),
+    ('\u{1e26}', "\u{1e27}"), ('\u{1e28}', "\u{1e29}"), ('\u{1e2a}', "\u{1e2b}"),
+    ('\u{1e2c}', "\u{1e2d}"), ('\u{1e2e}', "\u{1e2f}"), ('\u{1e30}', "\u{1e31}"),
+    ('\u{1e32}', "\u{1e33}"), ('\u{1e34}', "\u{1e35}"), ('\u{1e36}', "\u{1e37}"),
+    ('\u{1e38}', "\u{1e39}"), ('\u{1e3a}', "\u{1e3b}"), ('\u{1e3c}', "\u{1e3d}"),
+    ('\u{1e3e}', "\u{1e3f}"), ('\u{1e40}', "\u{1e41}"), ('\u{1e42}', "\u{1e43}"),
+    ('\u{1e44}', "\u{1e45}"), ('\u{1e46}', "\u{1e47}"), ('\u{1e48}', "\u{1e49}"),
+    ('\u{1e4a}', "\u{1e4b}"), ('\u{1e4c}', "\u{1e4d}"), ('\u{1e4e}', "\u{1e4f}"),
+    ('\u{1e50}', "\u{1e51}"), ('\u{1e52}', "\u{1e53}"), ('\u{1e54}', "\u{1e55}"),
+    ('\u{1e56}', "\u{1e57}"), ('\u{1e58}', "\u{1e59}"), ('\u{1e5a}', "\u{1e5b}"),
+    ('\u{1e5c}', "\u{1e5d}"), ('\u{1e5e}', "\u{1e5f}"), ('\u{1e60}', "\u{1e61}"),
+    ('\u{1e62}', "\u{1e63}"), ('\u{1e64}', "\u{1e65}"), ('\u{1e66}', "\u{1e67}"),
+    ('\u{1e68}', "\u{1e69}"), ('\u{1e6a}', "\u{1e6b}"), ('\u{1e6c}', "\u{1e6d}"),
+    ('\u{1e6e}', "\u{1e6f}"), ('\u{1e70}', "\u{1e71}"), ('\u{1e72}', "\u{1e73}"),
+    ('\u{1e74}', "\u{1e75}"), ('\u{1e76}', "\u{1e77}"), ('\u{1e78}', "\u{1e79}"),
+    ('\u{1e7a}', "\u{1e7b}"), ('\u{1e7c}', "\u{1e7d}"), ('\u{1e7e}', "\u{1e7f}"),
+    ('\u{1e80}', "\u{1e81}"), ('\u{1e82}', "\u{1e83}"), ('\u{1e84}', "\u{1e85}"),
+    ('\u{1e86}', "\u{1e87}"), ('\u{1e88}', "\u{1e89}"), ('\u{1e8a}', "\u{1e8b}"),
+    ('\u{1e8c}', "\u{1e8d}"), ('\u{1e8e}', "\u{1e8f}"), ('\u{1e90}', "\u{1e91}"),
+    ('\u{1e92}', "\u{1e93}"), ('\u{1e94}', "\u{1e95}"), ('\u{1e96}', "\u{68}\u{331}"),
+    ('\u{1e97}', "\u{74}\u{308}"), ('\u{1e98}', "\u{77}\u{30a}"), ('\u{1e99}', "\u{79}\u{30a}"),
+    ('\u{1e9a}', "\u{61}\u{2be}"), ('\u{1e9b}', "\u{1e61}"), ('\u{1e9e}', "\u{73}\u{73}"),
+    ('\u{1ea0}', "\u{1ea1}"), ('\u{1ea2}', "\u{1ea3}"), ('\u{1ea4}', "\u{1ea5}"),
+    ('\u{1ea6}', "\u{1ea7}"), ('\u{1ea8}', "\u{1ea9}"), ('\u{1eaa}', "\u{1eab}"),
+    ('\u{1eac}', "\u{1ead}"), ('\u{1eae}', "\u{1eaf}"), ('\u{1eb0}', "\u{1eb1}"),
+    ('\u{1eb2}', "\u{1eb3}"), ('\u{1eb4}', "\u{1eb5}"), ('\u{1eb6}', "\u{1eb7}"),
+    ('\u{1eb8}', "\u{1eb9}"), ('\u{1eba}', "\u{1ebb}"), ('\u{1ebc}', "\u{1ebd}"),
+    ('\u{1ebe}', "\u{1ebf}"), ('\u{1ec0}', "\u{1ec1}"), ('\u{1ec2}', "\u{1ec3}"),
+    ('\u{1ec4}', "\u{1ec5}"), ('\u{1ec6}', "\u{1ec7}"), ('\u{1ec8}', "\u{1ec9}"),
+    ('\u{1eca}', "\u{1ecb}"), ('\u{1ecc}', "\u{1ecd}"), ('\u{1ece}', "\u{1ecf}"),
+    ('\u{1ed0}', "\u{1ed1}"), ('\u{1ed2}', "\u{1ed3}"), ('\u{1ed4}', "\u{1ed5}"),
+    ('\u{1ed6}', "\u{1ed7}"), ('\u{1ed8}', "\u{1ed9}"), ('\u{1eda}', "\u{1edb}"),
+    ('\u{1edc}', "\u{1edd}"), ('\u{1ede}', "\u{1edf}"), ('\u{1ee0}', "\u{1ee1}"),
+    ('\u{1ee2}', "\u{1ee3}"), ('\u{1ee4}', "\u{1ee5}"), ('\u{1ee6}', "\u{1ee7}"),
+    ('\u{1ee8}', "\u{1ee9}"), ('\u{1eea}', "\u{1eeb}"), ('\u{1eec}', "\u{1eed}"),
+    ('\u{1eee}', "\u{1eef}"), ('\u{1ef0}', "\u{1ef1}"), ('\u{1ef2}', "\u{1ef3}"),
+    ('\u{1ef4}', "\u{1ef5}"), ('\u{1ef6}', "\u{1ef7}"), ('\u{1ef8}', "\u{1ef9}"),
+    ('\u{1efa}', "\u{1efb}"), ('\u{1efc}', "\u{1efd}"), ('\u{1efe}', "\u{1eff}"),
+    ('\u{1f08}', "\u{1f00}"), ('\u{1f09}', "\u{1f01}"), ('\u{1f0a}', "\u{1f02}"),
+    ('\u{1f0b}', "\u{1f03}"), ('\u{1f0c}', "\u{1f04}"), ('\u{1f0d}', "\u{1f05}"),
+    ('\u{1f0e}', "\u{1f06}"), ('\u{1f0f}', "\u{1f07}"), ('\u{1f18}', "\u{1f10}"),
+    ('\u{1f19}', "\u{1f11}"), ('\u{1f1a}', "\u{1f12}"), ('\u{1f1b}', "\u{1f13}"),
+    ('\u{1f1c}', "\u{1f14}"), ('\u{1f1d}', "\u{1f15}"), ('\u{1f28}', "\u{1f20}"),
+    ('\u{1f29}', "\u{1f21}"), ('\u{1f2a}', "\u{1f22}"), ('\u{1f2b}', "\u{1f23}"),
+    ('\u{1f2c}', "\u{1f24}"), ('\u{1f2d}', "\u{1f25}"), ('\u{1f2e}', "\u{1f26}"),
+    ('\u{1f2f}', "\u{1f27}"), ('\u{1f38}', "\u{1f30}"), ('\u{1f39}', "\u{1f31}"),
+    ('\u{1f3a}', "\u{1f32}"), ('\u{1f3b}', "\u{1f33}"), ('\u{1f3c}', "\u{1f34}"),
+    ('\u{1f3d}', "\u{1f35}"), ('\u{1f3e}', "\u{1f36}"), ('\u{1f3f}', "\u{1f37}"),
+    ('\u{1f48}', "\u{1f40}"), ('\u{1f49}', "\u{1f41}"), ('\u{1f4a}', "\u{1f42}"),
+    ('\u{1f4b}', "\u{1f43}"), ('\u{1f4c}', "\u{1f44}"), ('\u{1f4d}', "\u{1f45}"),
+    ('\u{1f50}', "\u{3c5}\u{313}"), ('\u{1f52}', "\u{3c5}\u{313}\u{300}"),
+    ('\u{1f54}', "\u{3c5}\u{313}\u{301}"), ('\u{1f56}', "\u{3c5}\u{313}\u{342}"),
+    ('\u{1f59}', "\u{1f51}"), ('\u{1f5b}', "\u{1f53}"), ('\u{1f5d}', "\u{1f55}"),
+    ('\u{1f5f}', "\u{1f57}"), ('\u{1f68}', "\u{1f60}"), ('\u{1f69}', "\u{1f61}"),
+    ('\u{1f6a}', "\u{1f62}"), ('\u{1f6b}', "\u{1f63}"), ('\u{1f6c}', "\u{1f64}"),
+    ('\u{1f6d}', "\u{1f65}"), ('\u{1f6e}', "\u{1f66}"), ('\u{1f6f}', "\u{1f67}"),
+    ('\u{1f80}', "\u{1f00}\u{3b9}"), ('\u{1f81}', "\u{1f01}\u{3b9}"),
+    ('\u{1f82}', "\u{1f02}\u{3b9}"), ('\u{1f83}', "\u{1f03}\u{3b9}"),
+    ('\u{1f84}', "\u{1f04}\u{3b9}"), ('\u{1f85}', "\u{1f05}\u{3b9}"),
+    ('\u{1f86}', "\u{1f06}\u{3b9}"), ('\u{1f87}', "\u{1f07}\u{3b9}"),
+    ('\u{1f88}', "\u{1f00}\u{3b9}"), ('\u{1f89}', "\u{1f01}\u{3b9}"),
+    ('\u{1f8a}', "\u{1f02}\u{3b9}"), ('\u{1f8b}', "\u{1f03}\u{3b9}"),
+    ('\u{1f8c}', "\u{1f04}\u{3b9}"), ('\u{1f8d}', "\u{1f05}\u{3b9}"),
+    ('\u{1f8e}', "\u{1f06}\u{3b9}"), ('\u{1f8f}', "\u{1f07}\u{3b9}"),
+    ('\u{1f90}', "\u{1f20}\u{3b9}"), ('\u{1f91}', "\u{1f21}\u{3b9}"),
+    ('\u{1f92}', "\u{1f22}\u{3b9}"), ('\u{1f93}', "\u{1f23}\u{3b9}"),
+    ('\u{1f94}', "\u{1f24}\u{3b9}"), ('\u{1f95}', "\u{1f25}\u{3b9}"),
+    ('\u{1f96}', "\u{1f26}\u{3b9}"), ('\u{1f97}', "\u{1f27}\u{3b9}"),
+    ('\u{1f98}', "\u{1f20}\u{3b9}"), ('\u{1f99}', "\u{1f21}\u{3b9}"),
+    ('\u{1f9a}', "\u{1f22}\u{3b9}"), ('\u{1f9b}', "\u{1f23}\u{3b9}"),
+    ('\u{1f9c}', "\u{1f24}\u{3b9}"), ('\u{1f9d}', "\u{1f25}\u{3b9}"),
+    ('\u{1f9e}', "\u{1f26}\u{3b9}"), ('\u{1f9f}', "\u{1f27}\u{3b9}"),
+    ('\u{1fa0}', "\u{1f60}\u{3b9}"), ('\u{1fa1}', "\u{1f61}\u{3b9}"),
+    ('\u{1fa2}', "\u{1f62}\u{3b9}"), ('\u{1fa3}', "\u{1f63}\u{3b9}"),
+    ('\u{1fa4}', "\u{1f64}\u{3b9}"), ('\u{1fa5}', "\u{1f65}\u{3b9}"),
+    ('\u{1fa6}', "\u{1f66}\u{3b9}"), ('\u{1fa7}', "\u{1f67}\u{3b9}"),
+    ('\u{1fa8}', "\u{1f60}\u{3b9}"), ('\u{1fa9}', "\u{1f61}\u{3b9}"),
+    ('\u{1faa}', "\u{1f62}\u{3b9}"), ('\u{1fab}', "\u{1f63}\u{3b9}"),
+    ('\u{1fac}', "\u{1f64}\u{3b9}"), ('\u{1fad}', "\u{1f65}\u{3b9}"),
+    ('\u{1fae}', "\u{1f66}\u{3b9}"), ('\u{1faf}', "\u{1f67}\u{3b9}"),
+    ('\u{1fb2}', "\u{1f70}\u{3b9}"), ('\u{1fb3}', "\u{3b1}\u{3b9}"),
+    ('\u{1fb4}', "\u{3ac}\u{3b9}"), ('\u{1fb6}', "\u{3b1}\u{342}"),
+    ('\u{1fb7}', "\u{3b1}\u{342}\u{3b9}"), ('\u{1fb8}', "\u{1fb0}"), ('\u{1fb9}', "\u{1fb1}"),
+    ('\u{1fba}', "\u{1f70}"), ('\u{1fbb}', "\u{1f71}"), ('\u{1fbc}', "\u{3b1}\u{3b9}"),
+    ('\u{1fbe}', "\u{3b9}"), ('\u{1fc2}', "\u{1f74}\u{3b9}"), ('\u{1fc3}', "\u{3b7}\u{3b9}"),
+    ('\u{1fc4}', "\u{3ae}\u{3b9}"), ('\u{1fc6}', "\u{3b7}\u{342}"),
+    ('\u{1fc7}', "\u{3b7}\u{342}\u{3b9}"), ('\u{1fc8}', "\u{1f72}"), ('\u{1fc9}', "\u{1f73}"),
+    ('\u{1fca}', "\u{1f74}"), ('\u{1fcb}', "\u{1f75}"), ('\u{1fcc}', "\u{3b7}\u{3b9}"),
+    ('\u{1fd2}', "\u{3b9}\u{308}\u{300}"), ('\u{1fd3}', "\u{3b9}\u{308}\u{301}"),
+    ('\u{1fd6}', "\u{3b9}\u{342}"), ('\u{1fd7}', "\u{3b9}\u{308}\u{342}"),
+    ('\u{1fd8}', "\u{1fd0}"), ('\u{1fd9}', "\u{1fd1}"), ('\u{1fda}', "\u{1f76}"),
+    ('\u{1fdb}', "\u{1f77}"), ('\u{1fe2}', "\u{3c5}\u{308}\u{300}"),
+    ('\u{1fe3}', "\u{3c5}\u{308}\u{301}"), ('\u{1fe4}', "\u{3c1}\u{313}"),
+    ('\u{1fe6}', "\u{3c5}\u{342}"), ('\u{1fe7}', "\u{3c5}\u{308}\u{342}"),
+    ('\u{1fe8}', "\u{1fe0}"), ('\u{1fe9}', "\u{1fe1}"), ('\u{1fea}', "\u{1f7a}"),
+    ('\u{1feb}', "\u{1f7b}"), ('\u{1fec}', "\u{1fe5}"), ('\u{1ff2}', "\u{1f7c}\u{3b9}"),
+    ('\u{1ff3}', "\u{3c9}\u{3b9}"), ('\u{1ff4}', "\u{3ce}\u{3b9}"),
+    ('\u{1ff6}', "\u{3c9}\u{342}"), ('\u{1ff7}', "\u{3c9}\u{342}\u{3b9}"),
+    ('\u{1ff8}', "\u{1f78}"), ('\u{1ff9}', "\u{1f79}"), ('\u{1ffa}', "\u{1f7c}"),
+    ('\u{1ffb}', "\u{1f7d}"), ('\u{1ffc}', "\u{3c9}\u{3b9}"), ('\u{2126}', "\u{3c9}"),
+    ('\u{212a}', "\u{6b}"), ('\u{212b}', "\u{e5}"), ('\u{2132}', "\u{214e}"),
+    ('\u{2160}', "\u{2170}"), ('\u{2161}', "\u{2171}"), ('\u{2162}', "\u{2172}"),
+    ('\u{2163}', "\u{2173}"), ('\u{2164}', "\u{2174}"), ('\u{2165}', "\u{2175}"),
+    ('\u{2166}', "\u{2176}"), ('\u{2167}', "\u{2177}"), ('\u{2168}', "\u{2178}"),
+    ('\u{2169}', "\u{2179}"), ('\u{216a}', "\u{217a}"), ('\u{216b}', "\u{217b}"),
+    ('\u{216c}', "\u{217c}"), ('\u{216d}', "\u{217d}"), ('\u{216e}', "\u{217e}"),
+    ('\u{216f}', "\u{217f}"), ('\u{2183}', "\u{2184}"), ('\u{24b6}', "\u{24d0}"),
+    ('\u{24b7}', "\u{24d1}"), ('\u{24b8}', "\u{24d2}"), ('\u{24b9}', "\u{24d3}"),
+    ('\u{24ba}', "\u{24d4}"), ('\u{24bb}', "\u{24d5}"), ('\u{24bc}', "\u{24d6}"),
+    ('\u{24bd}', "\u{24d7}"), ('\u{24be}', "\u{24d8}"), ('\u{24bf}', "\u{24d9}"),
+    ('\u{24c0}', "\u{24da}"), ('\u{24c1}', "\u{24db}"), ('\u{24c2}', "\u{24dc}"),
+    ('\u{24c3}', "\u{24dd}"), ('\u{24c4}', "\u{24de}"), ('\u{24c5}', "\u{24df}"),
+    ('\u{24c6}', "\u{24e0}"), ('\u{24c7}', "\u{24e1}"), ('\u{24c8}', "\u{24e2}"),
+    ('\u{24c9}', "\u{24e3}"), ('\u{24ca}', "\u{24e4}"), ('\u{24cb}', "\u{24e5}"),
+    ('\u{24cc}', "\u{24e6}"), ('\u{24cd}', "\u{24e7}"), ('\u{24ce}', "\u{24e8}"),
+    ('\u{24cf}', "\u{24e9}"), ('\u{2c00}', "\u{2c30}"), ('\u{2c01}', "\u{2c31}"),
+    ('\u{2c02}', "\u{2c32}"), ('\u{2c03}', "\u{2c33}"), ('\u{2c04}', "\u{2c34}"),
+    ('\u{2c05}', "\u{2c35}"), ('\u{2c06}', "\u{2c36}"), ('\u{2c07}', "\u{2c37}"),
+    ('\u{2c08}', "\u{2c38}"), ('\u{2c09}', "\u{2c39}"), ('\u{2c0a}', "\u{2c3a}"),
+    ('\u{2c0b}', "\u{2c3b}"), ('\u{2c0c}', "\u{2c3c}"), ('\u{2c0d}', "\u{2c3d}"),
+    ('\u{2c0e}', "\u{2c3e}"), ('\u{2c0f}', "\u{2c3f}"), ('\u{2c10}', "\u{2c40}"),
+    ('\u{2c11}', "\u{2c41}"), ('\u{2c12}', "\u{2c42}"), ('\u{2c13}', "\u{2c43}"),
+    ('\u{2c14}', "\u{2c44}"), ('\u{2c15}', "\u{2c45}"), ('\u{2c16}', "\u{2c46}"),
+    ('\u{2c17}', "\u{2c47}"), ('\u{2c18}', "\u{2c48}"), ('\u{2c19}', "\u{2c49}"),
+    ('\u{2c1a}', "\u{2c4a}"), ('\u{2c1b}', "\u{2c4b}"), ('\u{2c1c}', "\u{2c4c}"),
+    ('\u{2c1d}', "\u{2c4d}"), ('\u{2c1e}', "\u{2c4e}"), ('\u{2c1f}', "\u{2c4f}"),
+    ('\u{2c20}', "\u{2c50}"), ('\u{2c21}', "\u{2c51}"), ('\u{2c22}', "\u{2c52}"),
+    ('\u{2c23}', "\u{2c53}"), ('\u{2c24}', "\u{2c54}"), ('\u{2c25}', "\u{2c55}"),
+    ('\u{2c26}', "\u{2c56}"), ('\u{2c27}', "\u{2c57}"), ('\u{2c28}', "\u{2c58}"),
+    ('\u{2c29}', "\u{2c59}"), ('\u{2c2a}', "\u{2c5a}"), ('\u{2c2b}', "\u{2c5b}"),
+    ('\u{2c2c}', "\u{2c5c}"), ('\u{2c2d}', "\u{2c5d}"), ('\u{2c2e}', "\u{2c5e}"),
+    ('\u{2c2f}', "\u{2c5f}"), ('\u{2c60}', "\u{2c61}"), ('\u{2c62}', "\u{26b}"),
+    ('\u{2c63}', "\u{1d7d}"), ('\u{2c64}', "\u{27d}"), ('\u{2c67}', "\u{2c68}"),
+    ('\u{2c69}', "\u{2c6a}"), ('\u{2c6b}', "\u{2c6c}"), ('\u{2c6d}', "\u{251}"),
+    ('\u{2c6e}', "\u{271}"), ('\u{2c6f}', "\u{250}"), ('\u{2c70}', "\u{252}"),
+    ('\u{2c72}', "\u{2c73}"), ('\u{2c75}', "\u{2c76}"), ('\u{2c7e}', "\u{23f}"),
+    ('\u{2c7f}', "\u{240}"), ('\u{2c80}', "\u{2c81}"), ('\u{2c82}', "\u{2c83}"),
+    ('\u{2c84}', "\u{2c85}"), ('\u{2c86}', "\u{2c87}"), ('\u{2c88}', "\u{2c89}"),
+    ('\u{2c8a}', "\u{2c8b}"), ('\u{2c8c}', "\u{2c8d}"), ('\u{2c8e}', "\u{2c8f}"),
+    ('\u{2c90}', "\u{2c91}"), ('\u{2c92}', "\u{2c93}"), ('\u{2c94}', "\u{2c95}"),
+    ('\u{2c96}', "\u{2c97}"), ('\u{2c98}', "\u{2c99}"), ('\u{2c9a}', "\u{2c9b}"),
+    ('\u{2c9c}', "\u{2c9d}"), ('\u{2c9e}', "\u{2c9f}"), ('\u{2ca0}', "\u{2ca1}"),
+    ('\u{2ca2}', "\u{2ca3}"), ('\u{2ca4}', "\u{2ca5}"), ('\u{2ca6}', "\u{2ca7}"),
+    ('\u{2ca8}', "\u{2ca9}"), ('\u{2caa}', "\u{2cab}"), ('\u{2cac}', "\u{2cad}"),
+    ('\u{2cae}', "\u{2caf}"), ('\u{2cb0}', "\u{2cb1}"), ('\u{2cb2}', "\u{2cb3}"),
+    ('\u{2cb4}', "\u{2cb5}"), ('\u{2cb6}', "\u{2cb7}"), ('\u{2cb8}', "\u{2cb9}"),
+    ('\u{2cba}', "\u{2cbb}"), ('\u{2cbc}', "\u{2cbd}"), ('\u{2cbe}', "\u{2cbf}"),
+    ('\u{2cc0}', "\u{2cc1}"), ('\u{2cc2}', "\u{2cc3}"), ('\u{2cc4}', "\u{2cc5}"),
+    ('\u{2cc6}', "\u{2cc7}"), ('\u{2cc8}', "\u{2cc9}"), ('\u{2cca}', "\u{2ccb}"),
+    ('\u{2ccc}', "\u{2ccd}"), ('\u{2cce}', "\u{2ccf}"), ('\u{2cd0}', "\u{2cd1}"),
+    ('\u{2cd2}', "\u{2cd3}"), ('\u{2cd4}', "\u{2cd5}"), ('\u{2cd6}', "\u{2cd7}"),
+    ('\u{2cd8}', "\u{2cd9}"), ('\u{2cda}', "\u{2cdb}"), ('\u{2cdc}', "\u{2cdd}"),
+    ('\u{2cde}', "\u{2cdf}"), ('\u{2ce0}', "\u{2ce1}"), ('\u{2ce2}', "\u{2ce3}"),
+    ('\u{2ceb}', "\u{2cec}"), ('\u{2ced}', "\u{2cee}"), ('\u{2cf2}', "\u{2cf3}"),
+    ('\u{a640}', "\u{a641}"), ('\u{a642}', "\u{a643}"), ('\u{a644}', "\u{a645}"),
+    ('\u{a646}', "\u{a647}"), ('\u{a648}', "\u{a649}"), ('\u{a64a}', "\u{a64b}"),
+    ('\u{a64c}', "\u{a64d}"), ('\u{a64e}', "\u{a64f}"), ('\u{a650}', "\u{a651}"),
+    ('\u{a652}', "\u{a653}"), ('\u{a654}', "\u{a655}"), ('\u{a656}', "\u{a657}"),
+    ('\u{a658}', "\u{a659}"), ('\u{a65a}', "\u{a65b}"), ('\u{a65c}', "\u{a65d}"),
+    ('\u{a65e}', "\u{a65f}"), ('\u{a660}', "\u{a661}"), ('\u{a662}', "\u{a663}"),
+    ('\u{a664}', "\u{a665}"), ('\u{a666}', "\u{a667}"), ('\u{a668}', "\u{a669}"),
+    ('\u{a66a}', "\u{a66b}"), ('\u{a66c}', "\u{a66d}"), ('\u{a680}', "\u{a681}"),
+    ('\u{a682}', "\u{a683}"), ('\u{a684}', "\u{a685}"), ('\u{a686}', "\u{a687}"),
+    ('\u{a688}', "\u{a689}"), ('\u{a68a}', "\u{a68b}"), ('\u{a68c}', "\u{a68d}"),
+    ('\u{a68e}', "\u{a68f}"), ('\u{a690}', "\u{a691}"), ('\u{a692}', "\u{a693}"),
+    ('\u{a694}', "\u{a695}"), ('\u{a696}', "\u{a697}"), ('\u{a698}', "\u{a699}"),
+    ('\u{a69a}', "\u{a69b}"), ('\u{a722}', "\u{a723}"), ('\u{a724}', "\u{a725}"),
+    ('\u{a726}', "\u{a727}"), ('\u{a728}', "\u{a729}"), ('\u{a72a}', "\u{a72b}"),
+    ('\u{a72c}', "\u{a72d}"), ('\u{a72e}', "\u{a72f}"), ('\u{a732}', "\u{a733}"),
+    ('\u{a734}', "\u{a735}"), ('\u{a736}', "\u{a737}"), ('\u{a738}', "\u{a739}"),
+    ('\u{a73a}', "\u{a73b}"), ('\u{a73c}', "\u{a73d}"), ('\u{a73e}', "\u{a73f}"),
+    ('\u{a740}', "\u{a741}"), ('\u{a742}', "\u{a743}"), ('\u{a744}', "\u{a745}"),
+    ('\u{a746}', "\u{a747}"), ('\u{a748}', "\u{a749}"), ('\u{a74a}', "\u{a74b}"),
+    ('\u{a74c}', "\u{a74d}"), ('\u{a74e}', "\u{a74f}"), ('\u{a750}', "\u{a751}"),
+    ('\u{a752}', "\u{a753}"), ('\u{a754}', "\u{a755}"), ('\u{a756}', "\u{a757}"),
+    ('\u{a758}', "\u{a759}"), ('\u{a75a}', "\u{a75b}"), ('\u{a75c}', "\u{a75d}"),
+    ('\u{a75e}', "\u{a75f}"), ('\u{a760}', "\u{a761}"), ('\u{a762}', "\u{a763}"),
+    ('\u{a764}', "\u{a765}"), ('\u{a766}', "\u{a767}"), ('\u{a768}', "\u{a769}"),
+    ('\u{a76a}', "\u{a76b}"), ('\u{a76c}', "\u{a76d}"), ('\u{a76e}', "\u{a76f}"),
+    ('\u{a779}', "\u{a77a}"), ('\u{a77b}', "\u{a77c}"), ('\u{a77d}', "\u{1d79}"),
+    ('\u{a77e}', "\u{a77f}"), ('\u{a780}', "\u{a781}"), ('\u{a782}', "\u{a783}"),
+    ('\u{a784}', "\u{a785}"), ('\u{a786}', "\u{a787}"), ('\u{a78b}', "\u{a78c}"),
+    ('\u{a78d}', "\u{265}"), ('\u{a790}', "\u{a791}"), ('\u{a792}', "\u{a793}"),
+    ('\u{a796}', "\u{a797}"), ('\u{a798}', "\u{a799}"), ('\u{a79a}', "\u{a79b}"),
+    ('\u{a79c}', "\u{a79d}"), ('\u{a79e}', "\u{a79f}"), ('\u{a7a0}', "\u{a7a1}"),
+    ('\u{a7a2}', "\u{a7a3}"), ('\u{a7a4}', "\u{a7a5}"), ('\u{a7a6}', "\u{a7a7}"),
+    ('\u{a7a8}', "\u{a7a9}"), ('\u{a7aa}', "\u{266}"), ('\u{a7ab}', "\u{25c}"),
+    ('\u{a7ac}', "\u{261}"), ('\u{a7ad}', "\u{26c}"), ('\u{a7ae}', "\u{26a}"),
+    ('\u{a7b0}', "\u{29e}"), ('\u{a7b1}', "\u{287}"), ('\u{a7b2}', "\u{29d}"),
+    ('\u{a7b3}', "\u{ab53}"), ('\u{a7b4}', "\u{a7b5}"), ('\u{a7b6}', "\u{a7b7}"),
+    ('\u{a7b8}', "\u{a7b9}"), ('\u{a7ba}', "\u{a7bb}"), ('\u{a7bc}', "\u{a7bd}"),
+    ('\u{a7be}', "\u{a7bf}"), ('\u{a7c0}', "\u{a7c1}"), ('\u{a7c2}', "\u{a7c3}"),
+    ('\u{a7c4}', "\u{a794}"), ('\u{a7c5}', "\u{282}"), ('\u{a7c6}', "\u{1d8e}"),
+    ('\u{a7c7}', "\u{a7c8}"), ('\u{a7c9}', "\u{a7ca}"), ('\u{a7cb}', "\u{264}"),
+    ('\u{a7cc}', "\u{a7cd}"), ('\u{a7d0}', "\u{a7d1}"), ('\u{a7d6}', "\u{a7d7}"),
+    ('\u{a7d8}', "\u{a7d9}"), ('\u{a7da}', "\u{a7db}"), ('\u{a7dc}', "\u{19b}"),
+    ('\u{a7f5}', "\u{a7f6}"), ('\u{ab70}', "\u{13a0}"), ('\u{ab71}', "\u{13a1}"),
+    ('\u{ab72}', "\u{13a2}"), ('\u{ab73}', "\u{13a3}"), ('\u{ab74}', "\u{13a4}"),
+    ('\u{ab75}', "\u{13a5}"), ('\u{ab76}', "\u{13a6}"), ('\u{ab77}', "\u{13a7}"),
+    ('\u{ab78}', "\u{13a8}"), ('\u{ab79}', "\u{13a9}"), ('\u{ab7a}', "\u{13aa}"),
+    ('\u{ab7b}', "\u{13ab}"), ('\u{ab7c}', "\u{13ac}"), ('\u{ab7d}', "\u{13ad}"),
+    ('\u{ab7e}', "\u{13ae}"), ('\u{ab7f}', "\u{13af}"), ('\u{ab80}', "\u{13b0}"),
+    ('\u{ab81}', "\u{13b1}"), ('\u{ab82}', "\u{13b2}"), ('\u{ab83}', "\u{13b3}"),
+    ('\u{ab84}', "\u{13b4}"), ('\u{ab85}', "\u{13b5}"), ('\u{ab86}', "\u{13b6}"),
+    ('\u{ab87}', "\u{13b7}"), ('\u{ab88}', "\u{13b8}"), ('\u{ab89}', "\u{13b9}"),
+    ('\u{ab8a}', "\u{13ba}"), ('\u{ab8b}', "\u{13bb}"), ('\u{ab8c}', "\u{13bc}"),
+    ('\u{ab8d}', "\u{13bd}"), ('\u{ab8e}', "\u{13be}"), ('\u{ab8f}', "\u{13bf}"),
+    ('\u{ab90}', "\u{13c0}"), ('\u{ab91}', "\u{13c1}"), ('\u{ab92}', "\u{13c2}"),
+    ('\u{ab93}', "\u{13c3}"), ('\u{ab94}', "\u{13c4}"), ('\u{ab95}', "\u{13c5}"),
+    ('\u{ab96}', "\u{13c6}"), ('\u{ab97}', "\u{13c7}"), ('\u{ab98}', "\u{13c8}"),
+    ('\u{ab99}', "\u{13c9}"), ('\u{ab9a}', "\u{13ca}"), ('\u{ab9b}', "\u{13cb}"),
+    ('\u{ab9c}', "\u{13cc}"), ('\u{ab9d}', "\u{13cd}"), ('\u{ab9e}', "\u{13ce}"),
+    ('\u{ab9f}', "\u{13cf}"), ('\u{aba0}', "\u{13d0}"), ('\u{aba1}', "\u{13d1}"),
+    ('\u{aba2}', "\u{13d2}"), ('\u{aba3}', "\u{13d3}"), ('\u{aba4}', "\u{13d4}"),
+    ('\u{aba5}', "\u{13d5}"), ('\u{aba6}', "\u{13d6}"), ('\u{aba7}', "\u{13d7}"),
+    ('\u{aba8}', "\u{13d8}"), ('\u{aba9}', "\u{13d9}"), ('\u{abaa}', "\u{13da}"),
+    ('\u{abab}', "\u{13db}"), ('\u{abac}', "\u{13dc}"), ('\u{abad}', "\u{13dd}"),
+    ('\u{abae}', "\u{13de}"), ('\u{abaf}', "\u{13df}"), ('\u{abb0}', "\u{13e0}"),
+    ('\u{abb1}', "\u{13e1}"), ('\u{abb2}', "\u{13e2}"), ('\u{abb3}', "\u{13e3}"),
+    ('\u{abb4}', "\u{13e4}"), ('\u{abb5}', "\u{13e5}"), ('\u{abb6}', "\u{13e6}"),
+    ('\u{abb7}', "\u{13e7}"), ('\u{abb8}', "\u{13e8}"), ('\u{abb9}', "\u{13e9}"),
+    ('\u{abba}', "\u{13ea}"), ('\u{abbb}', "\u{13eb}"), ('\u{abbc}', "\u{13ec}"),
+    ('\u{abbd}', "\u{13ed}"), ('\u{abbe}', "\u{13ee}"), ('\u{abbf}', "\u{13ef}"),
+    ('\u{fb00}', "\u{66}\u{66}"), ('\u{fb01}', "\u{66}\u{69}"), ('\u{fb02}', "\u{66}\u{6c}"),
+    ('\u{fb03}', "\u{66}\u{66}\u{69}"), ('\u{fb04}', "\u{66}\u{66}\u{6c}"),
+    ('\u{fb05}', "\u{73}\u{74}"), ('\u{fb06}', "\u{73}\u{74}"), ('\u{fb13}', "\u{574}\u{576}"),
+    ('\u{fb14}', "\u{574}\u{565}"), ('\u{fb15}', "\u{574}\u{56b}"),
+    ('\u{fb16}', "\u{57e}\u{576}"), ('\u{fb17}', "\u{574}\u{56d}"), ('\u{ff21}', "\u{ff41}"),
+    ('\u{ff22}', "\u{ff42}"), ('\u{ff23}', "\u{ff43}"), ('\u{ff24}', "\u{ff44}"),
+    ('\u{ff25}', "\u{ff45}"), ('\u{ff26}', "\u{ff46}"), ('\u{ff27}', "\u{ff47}"),
+    ('\u{ff28}', "\u{ff48}"), ('\u{ff29}', "\u{ff49}"), ('\u{ff2a}', "\u{ff4a}"),
+    ('\u{ff2b}', "\u{ff4b}"), ('\u{ff2c}', "\u{ff4c}"), ('\u{ff2d}', "\u{ff4d}"),
+    ('\u{ff2e}', "\u{ff4e}"), ('\u{ff2f}', "\u{ff4f}"), ('\u{ff30}', "\u{ff50}"),
+    ('\u{ff31}', "\u{ff51}"), ('\u{ff32}', "\u{ff52}"), ('\u{ff33}', "\u{ff53}"),
+    ('\u{ff34}', "\u{ff54}"), ('\u{ff35}', "\u{ff55}"), ('\u{ff36}', "\u{ff56}"),
+    ('\u{ff37}', "\u{ff57}"), ('\u{ff38}', "\u{ff58}"), ('\u{ff39}', "\u{ff59}"),
+    ('\u{ff3a}', "\u{ff5a}"), ('\u{10400}', "\u{10428}"), ('\u{10401}', "\u{10429}"),
+    ('\u{10402}', "\u{1042a}"), ('\u{10403}', "\u{1042b}"), ('\u{10404}', "\u{1042c}"),
+    ('\u{10405}', "\u{1042d}"), ('\u{10406}', "\u{1042e}"), ('\u{10407}', "\u{1042f}"),
+    ('\u{10408}', "\u{10430}"), ('\u{10409}', "\u{10431}"), ('\u{1040a}', "\u{10432}"),
+    ('\u{1040b}', "\u{10433}"), ('\u{1040c}', "\u{10434}"), ('\u{1040d}', "\u{10435}"),
+    ('\u{1040e}', "\u{10436}"), ('\u{1040f}', "\u{10437}"), ('\u{10410}', "\u{10438}"),
+    ('\u{10411}', "\u{10439}"), ('\u{10412}', "\u{1043a}"), ('\u{10413}', "\u{1043b}"),
+    ('\u{10414}', "\u{1043c}"), ('\u{10415}', "\u{1043d}"), ('\u{10416}', "\u{1043e}"),
+    ('\u{10417}', "\u{1043f}"), ('\u{10418}', "\u{10440}"), ('\u{10419}', "\u{10441}"),
+    ('\u{1041a}', "\u{10442}"), ('\u{1041b}', "\u{10443}"), ('\u{1041c}', "\u{10444}"),
+    ('\u{1041d}', "\u{10445}"), ('\u{1041e}', "\u{10446}"), ('\u{1041f}', "\u{10447}"),
+    ('\u{10420}', "\u{10448}"), ('\u{10421}', "\u{10449}"), ('\u{10422}', "\u{1044a}"),
+    ('\u{10423}', "\u{1044b}"), ('\u{10424}', "\u{1044c}"), ('\u{10425}', "\u{1044d}"),
+    ('\u{10426}', "\u{1044e}"), ('\u{10427}', "\u{1044f}"), ('\u{104b0}', "\u{104d8}"),
+    ('\u{104b1}', "\u{104d9}"), ('\u{104b2}', "\u{104da}"), ('\u{104b3}', "\u{104db}"),
+    ('\u{104b4}', "\u{104dc}"), ('\u{104b5}', "\u{104dd}"), ('\u{104b6}', "\u{104de}"),
+    ('\u{104b7}', "\u{104df}"), ('\u{104b8}', "\u{104e0}"), ('\u{104b9}', "\u{104e1}"),
+    ('\u{104ba}', "\u{104e2}"), ('\u{104bb}', "\u{104e3}"), ('\u{104bc}', "\u{104e4}"),
+    ('\u{104bd}', "\u{104e5}"), ('\u{104be}', "\u{104e6}"), ('\u{104bf}', "\u{104e7}"),
+    ('\u{104c0}', "\u{104e8}"), ('\u{104c1}', "\u{104e9}"), ('\u{104c2}', "\u{104ea}"),
+    ('\u{104c3}', "\u{104eb}"), ('\u{104c4}', "\u{104ec}"), ('\u{104c5}', "\u{104ed}"),
+    ('\u{104c6}', "\u{104ee}"), ('\u{104c7}', "\u{104ef}"), ('\u{104c8}', "\u{104f0}"),
+    ('\u{104c9}', "\u{104f1}"), ('\u{104ca}', "\u{104f2}"), ('\u{104cb}', "\u{104f3}"),
+    ('\u{104cc}', "\u{104f4}"), ('\u{104cd}', "\u{104f5}"), ('\u{104ce}', "\u{104f6}"),
+    ('\u{104cf}', "\u{104f7}"), ('\u{104d0}', "\u{104f8}"), ('\u{104d1}', "\u{104f9}"),
+    ('\u{104d2}', "\u{104fa}"), ('\u{104d3}', "\u{104fb}"), ('\u{10570}', "\u{10597}"),
+    ('\u{10571}', "\u{10598}"), ('\u{10572}', "\u{10599}"), ('\u{10573}', "\u{1059a}"),
+    ('\u{10574}', "\u{1059b}"), ('\u{10575}', "\u{1059c}"), ('\u{10576}', "\u{1059d}"),
+    ('\u{10577}', "\u{1059e}"), ('\u{10578}', "\u{1059f}"), ('\u{10579}', "\u{105a0}"),
+    ('\u{1057a}', "\u{105a1}"), ('\u{1057c}', "\u{105a3}"), ('\u{1057d}', "\u{105a4}"),
+    ('\u{1057e}', "\u{105a5}"), ('\u{1057f}', "\u{105a6}"), ('\u{10580}', "\u{105a7}"),
+    ('\u{10581}', "\u{105a8}"), ('\u{10582}', "\u{105a9}"), ('\u{10583}', "\u{105aa}"),
+    ('\u{10584}', "\u{105ab}"), ('\u{10585}', "\u{105ac}"), ('\u{10586}', "\u{105ad}"),
+    ('\u{10587}', "\u{105ae}"), ('\u{10588}', "\u{105af}"), ('\u{10589}', "\u{105b0}"),
+    ('\u{1058a}', "\u{105b1}"), ('\u{1058c}', "\u{105b3}"), ('\u{1058d}', "\u{105b4}"),
+    ('\u{1058e}', "\u{105b5}"), ('\u{1058f}', "\u{105b6}"), ('\u{10590}', "\u{105b7}"),
+    ('\u{10591}', "\u{105b8}"), ('\u{10592}', "\u{105b9}"), ('\u{10594}', "\u{105bb}"),
+    ('\u{10595}', "\u{105bc}"), ('\u{10c80}', "\u{10cc0}"), ('\u{10c81}', "\u{10cc1}"),
+    ('\u{10c82}', "\u{10cc2}"), ('\u{10c83}', "\u{10cc3}"), ('\u{10c84}', "\u{10cc4}"),
+    ('\u{10c85}', "\u{10cc5}"), ('\u{10c86}', "\u{10cc6}"), ('\u{10c87}', "\u{10cc7}"),
+    ('\u{10c88}', "\u{10cc8}"), ('\u{10c89}', "\u{10cc9}"), ('\u{10c8a}', "\u{10cca}"),
+    ('\u{10c8b}', "\u{10ccb}"), ('\u{10c8c}', "\u{10ccc}"), ('\u{10c8d}', "\u{10ccd}"),
+    ('\u{10c8e}', "\u{10cce}"), ('\u{10c8f}', "\u{10ccf}"), ('\u{10c90}', "\u{10cd0}"),
+    ('\u{10c91}', "\u{10cd1}"), ('\u{10c92}', "\u{10cd2}"), ('\u{10c93}', "\u{10cd3}"),
+    ('\u{10c94}', "\u{10cd4}"), ('\u{10c95}', "\u{10cd5}"), ('\u{10c96}', "\u{10cd6}"),
+    ('\u{10c97}', "\u{10cd7}"), ('\u{10c98}', "\u{10cd8}"), ('\u{10c99}', "\u{10cd9}"),
+    ('\u{10c9a}', "\u{10cda}"), ('\u{10c9b}', "\u{10cdb}"), ('\u{10c9c}', "\u{10cdc}"),
+    ('\u{10c9d}', "\u{10cdd}"), ('\u{10c9e}', "\u{10cde}"), ('\u{10c9f}', "\u{10cdf}"),
+    ('\u{10ca0}', "\u{10ce0}"), ('\u{10ca1}', "\u{10ce1}"), ('\u{10ca2}', "\u{10ce2}"),
+    ('\u{10ca3}', "\u{10ce3}"), ('\u{10ca4}', "\u{10ce4}"), ('\u{10ca5}', "\u{10ce5}"),
+    ('\u{10ca6}', "\u{10ce6}"), ('\u{10ca7}', "\u{10ce7}"), ('\u{10ca8}', "\u{10ce8}"),
+    ('\u{10ca9}', "\u{10ce9}"), ('\u{10caa}', "\u{10cea}"), ('\u{10cab}', "\u{10ceb}"),
+    ('\u{10cac}', "\u{10cec}"), ('\u{10cad}', "\u{10ced}"), ('\u{10cae}', "\u{10cee}"),
+    ('\u{10caf}', "\u{10cef}"), ('\u{10cb0}', "\u{10cf0}"), ('\u{10cb1}', "\u{10cf1}"),
+    ('\u{10cb2}', "\u{10cf2}"), ('\u{10d50}', "\u{10d70}"), ('\u{10d51}', "\u{10d71}"),
+    ('\u{10d52}', "\u{10d72}"), ('\u{10d53}', "\u{10d73}"), ('\u{10d54}', "\u{10d74}"),
+    ('\u{10d55}', "\u{10d75}"), ('\u{10d56}', "\u{10d76}"), ('\u{10d57}', "\u{10d77}"),
+    ('\u{10d58}', "\u{10d78}"), ('\u{10d59}', "\u{10d79}"), ('\u{10d5a}', "\u{10d7a}"),
+    ('\u{10d5b}', "\u{10d7b}"), ('\u{10d5c}', "\u{10d7c}"), ('\u{10d5d}', "\u{10d7d}"),
+    ('\u{10d5e}', "\u{10d7e}"), ('\u{10d5f}', "\u{10d7f}"), ('\u{10d60}', "\u{10d80}"),
+    ('\u{10d61}', "\u{10d81}"), ('\u{10d62}', "\u{10d82}"), ('\u{10d63}', "\u{10d83}"),
+    ('\u{10d64}', "\u{10d84}"), ('\u{10d65}', "\u{10d85}"), ('\u{118a0}', "\u{118c0}"),
+    ('\u{118a1}', "\u{118c1}"), ('\u{118a2}', "\u{118c2}"), ('\u{118a3}', "\u{118c3}"),
+    ('\u{118a4}', "\u{118c4}"), ('\u{118a5}', "\u{118c5}"), ('\u{118a6}', "\u{118c6}"),
+    ('\u{118a7}', "\u{118c7}"), ('\u{118a8}', "\u{118c8}"), ('\u{118a9}', "\u{118c9}"),
+    ('\u{118aa}', "\u{118ca}"), ('\u{118ab}', "\u{118cb}"), ('\u{118ac}', "\u{118cc}"),
+    ('\u{118ad}', "\u{118cd}"), ('\u{118ae}', "\u{118ce}"), ('\u{118af}', "\u{118cf}"),
+    ('\u{118b0}', "\u{118d0}"), ('\u{118b1}', "\u{118d1}"), ('\u{118b2}', "\u{118d2}"),
+    ('\u{118b3}', "\u{118d3}"), ('\u{118b4}', "\u{118d4}"), ('\u{118b5}', "\u{118d5}"),
+    ('\u{118b6}', "\u{118d6}"), ('\u{118b7}', "\u{118d7}"), ('\u{118b8}', "\u{118d8}"),
+    ('\u{118b9}', "\u{118d9}"), ('\u{118ba}', "\u{118da}"), ('\u{118bb}', "\u{118db}"),
+    ('\u{118bc}', "\u{118dc}"), ('\u{118bd}', "\u{118dd}"), ('\u{118be}', "\u{118de}"),
+    ('\u{118bf}', "\u{118df}"), ('\u{16e40}', "\u{16e60}"), ('\u{16e41}', "\u{16e61}"),
+    ('\u{16e42}', "\u{16e62}"), ('\u{16e43}', "\u{16e63}"), ('\u{16e44}', "\u{16e64}"),
+    ('\u{16e45}', "\u{16e65}"), ('\u{16e46}', "\u{16e66}"), ('\u{16e47}', "\u{16e67}"),
+    ('\u{16e48}', "\u{16e68}"), ('\u{16e49}', "\u{16e69}"), ('\u{16e4a}', "\u{16e6a}"),
+    ('\u{16e4b}', "\u{16e6b}"), ('\u{16e4c}', "\u{16e6c}"), ('\u{16e4d}', "\u{16e6d}"),
+    ('\u{16e4e}', "\u{16e6e}"), ('\u{16e4f}', "\u{16e6f}"), ('\u{16e50}', "\u{16e70}"),
+    ('\u{16e51}', "\u{16e71}"), ('\u{16e52}', "\u{16e72}"), ('\u{16e53}', "\u{16e73}"),
+    ('\u{16e54}', "\u{16e74}"), ('\u{16e55}', "\u{16e75}"), ('\u{16e56}', "\u{16e76}"),
+    ('\u{16e57}', "\u{16e77}"), ('\u{16e58}', "\u{16e78}"), ('\u{16e59}', "\u{16e79}"),
+    ('\u{16e5a}', "\u{16e7a}"), ('\u{16e5b}', "\u{16e7b}"), ('\u{16e5c}', "\u{16e7c}"),
+    ('\u{16e5d}', "\u{16e7d}"), ('\u{16e5e}', "\u{16e7e}"), ('\u{16e5f}', "\u{16e7f}"),
+    ('\u{1e900}', "\u{1e922}"), ('\u{1e901}', "\u{1e923}"), ('\u{1e902}', "\u{1e924}"),
+    ('\u{1e903}', "\u{1e925}"), ('\u{1e904}', "\u{1e926}"), ('\u{1e905}', "\u{1e927}"),
+    ('\u{1e906}', "\u{1e928}"), ('\u{1e907}', "\u{1e929}"), ('\u{1e908}', "\u{1e92a}"),
+    ('\u{1e909}', "\u{1e92b}"), ('\u{1e90a}', "\u{1e92c}"), ('\u{1e90b}', "\u{1e92d}"),
+    ('\u{1e90c}', "\u{1e92e}"), ('\u{1e90d}', "\u{1e92f}"), ('\u{1e90e}', "\u{1e930}"),
+    ('\u{1e90f}', "\u{1e931}"), ('\u{1e910}', "\u{1e932}"), ('\u{1e911}', "\u{1e933}"),
+    ('\u{1e912}', "\u{1e934}"), ('\u{1e913}', "\u{1e935}"), ('\u{1e914}', "\u{1e936}"),
+    ('\u{1e915}', "\u{1e937}"), ('\u{1e916}', "\u{1e938}"), ('\u{1e917}', "\u{1e939}"),
+    ('\u{1e918}', "\u{1e93a}"), ('\u{1e919}', "\u{1e93b}"), ('\u{1e91a}', "\u{1e93c}"),
+    ('\u{1e91b}', "\u{1e93d}"), ('\u{1e91c}', "\u{1e93e}"), ('\u{1e91d}', "\u{1e93f}"),
+    ('\u{1e91e}', "\u{1e940}"), ('\u{1e91f}', "\u{1e941}"), ('\u{1e920}', "\u{1e942}"),
+    ('\u{1e921}', "\u{1e943}"),
+];
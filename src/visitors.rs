@@ -1,5 +1,6 @@
 use crate::errors::FqError;
-use crate::expr::Expr;
+use crate::expr::{Expr, ExprKind, ValFormat};
+use crate::input::{is_stdin, Format};
 use crate::ops::{Limit, Op, Projection, Scan, Selection};
 use crate::parser::{ColumnNode, LimitNode, QueryNode, SelectNode, Visitor};
 use std::path::Path;
@@ -17,20 +18,71 @@ impl Checker {
 
     pub fn result(&self) -> Result<(), FqError> {
         match &self.msg {
-            Some(msg) => Err(FqError::semantics(msg.to_string())),
+            Some(msg) => Err(FqError::semantics(msg)),
             None => Ok(()),
         }
     }
 
-    fn check_columns(&self, _node: &SelectNode) {
-        // nop
+    // check that aggregate and non-aggregate columns are not mixed
+    fn check_columns(&mut self, node: &SelectNode) {
+        let mut kind = ExprKind::CONST;
+        for column in &node.columns {
+            match column.exp().kind() {
+                ExprKind::NESTED => {
+                    self.msg = Some("Aggregate function calls cannot be nested".to_string());
+                    return;
+                }
+                ExprKind::MIXED => {
+                    self.msg = Some(
+                        "Aggregate and non-aggregate values cannot be mixed in an expression"
+                            .to_string(),
+                    );
+                    return;
+                }
+                other => kind = kind.combine(other),
+            }
+        }
+
+        if kind == ExprKind::MIXED {
+            self.msg =
+                Some("Aggregate and non-aggregate columns cannot be selected together".to_string());
+        }
     }
 
-    // check if file exists
+    // check that where does not use aggregate or window functions
+    fn check_where(&mut self, node: &SelectNode) {
+        if let Some(xwhere) = &node.xwhere {
+            match xwhere.kind() {
+                ExprKind::CONST | ExprKind::SCALAR => {}
+                ExprKind::WINDOW => {
+                    self.msg = Some("Window functions are not allowed in where".to_string());
+                }
+                _ => {
+                    self.msg = Some("Aggregate functions are not allowed in where".to_string());
+                }
+            }
+        }
+    }
+
+    // check the types of function arguments
+    fn check_types(&mut self, node: &SelectNode) {
+        let columns = node.columns.iter().map(|column| column.exp());
+        for exp in columns.chain(node.xwhere.iter().cloned()) {
+            if let Err(msg) = exp.ty() {
+                self.msg = Some(msg);
+                return;
+            }
+        }
+    }
+
+    // check if file exists (unless the standard input is read)
     fn check_file(&mut self, node: &SelectNode) {
+        if is_stdin(node.file_name()) {
+            return;
+        }
         let path = Path::new(node.file_name());
         if !(path.exists() && path.is_file()) {
-            self.msg = Some(format!("File does not exist"));
+            self.msg = Some("File does not exist".to_string());
         }
     }
 }
@@ -46,6 +98,8 @@ impl Visitor for Checker {
 
     fn visit_select(&mut self, node: &SelectNode) {
         self.check_columns(node);
+        self.check_where(node);
+        self.check_types(node);
         self.check_file(node);
     }
 
@@ -68,12 +122,20 @@ impl Visitor for Checker {
 
 pub struct Planner {
     operations: Vec<Box<dyn Op>>,
+    format: Rc<ValFormat>,
+    /// Format of the input file unless the query gives one; if
+    /// neither does, it depends on the extension of the file.
+    input: Option<Format>,
+    header: bool,
 }
 
 impl Planner {
-    pub fn new() -> Planner {
+    pub fn new(format: Rc<ValFormat>, input: Option<Format>, header: bool) -> Planner {
         Planner {
             operations: Vec::new(),
+            format,
+            input,
+            header,
         }
     }
 
@@ -94,12 +156,23 @@ impl Visitor for Planner {
     fn visit_select(&mut self, node: &SelectNode) {
         let mut operations: Vec<Box<dyn Op>> = Vec::new();
 
-        let op = Box::new(Scan::new(node.file_name().to_string()));
+        let input = match node.input().or(self.input.as_ref()) {
+            Some(input) => input.clone(),
+            None => Format::for_path(node.file_name()),
+        };
+        let header = node.header || self.header;
+        // The text of the lines is kept only if the query uses it.
+        let op = Box::new(Scan::new(
+            node.file_name().to_string(),
+            input,
+            header,
+            node.uses_text(),
+        ));
         operations.push(op);
 
         // selections
         if let Some(xwhere) = &node.xwhere {
-            let op = Box::new(Selection::new(Rc::clone(xwhere)));
+            let op = Box::new(Selection::new(Rc::clone(xwhere), Rc::clone(&self.format)));
             operations.push(op);
         }
 
@@ -108,15 +181,12 @@ impl Visitor for Planner {
         for column in &node.columns {
             expressions.push(column.exp())
         }
-        let op = Box::new(Projection::new(expressions));
+        let op = Box::new(Projection::new(expressions, Rc::clone(&self.format)));
         operations.push(op);
 
         // limit as a final selection
         if let Some(limit) = &node.limit {
-            let num = limit.num as usize;
-            let op = Box::new(Limit::from(Box::new(move |_x, ntable| {
-                ntable.nrows() < num
-            })));
+            let op = Box::new(Limit::new(limit.num as usize));
             operations.push(op);
         }
 
@@ -0,0 +1,547 @@
+use crate::aggs::{Accumulator, Distinct};
+use crate::errors::FqError;
+use crate::expr::{Ctx, Expr, ExprKind, Val, ValKey};
+use crate::funcs::{AggregateFunc, Type};
+use crate::table::Table;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+// window functions
+pub const ROW_NUMBER_FUNC: &str = "row_number";
+pub const RANK_FUNC: &str = "rank";
+pub const DENSE_RANK_FUNC: &str = "dense_rank";
+pub const LAG_FUNC: &str = "lag";
+pub const LEAD_FUNC: &str = "lead";
+pub const NTILE_FUNC: &str = "ntile";
+
+pub static WINDOW_FUNCS: [&str; 6] = [
+    ROW_NUMBER_FUNC,
+    RANK_FUNC,
+    DENSE_RANK_FUNC,
+    LAG_FUNC,
+    LEAD_FUNC,
+    NTILE_FUNC,
+];
+
+// Source of unique ids for window function calls.
+static NEXT_WINDOW_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Expression in an `order by` of a window and if the order is
+/// descending.
+pub type OrderBy = (Rc<dyn Expr>, bool);
+
+/// Call of a window function, or of an aggregate function over a
+/// window, e.g., `sum(int(@1)) over (partition by str(@0) order by
+/// int(@2))`.
+///
+/// Rows are split into partitions (rows with the same values of the
+/// `partition by` expressions), and each partition is sorted by the
+/// `order by` expressions. Rows with the same values of the `order
+/// by` expressions are peers. An aggregate function is computed over
+/// the rows from the start of the partition to the last peer of the
+/// current row (a running value), or over the whole partition if
+/// there is no `order by`.
+pub struct WindowCall {
+    // Identifies the values of the call in the context.
+    id: usize,
+    name: String,
+    // the aggregate function if this is not a window function
+    agg: Option<Rc<dyn AggregateFunc>>,
+    args: Vec<Rc<dyn Expr>>,
+    distinct: bool,
+    partition: Vec<Rc<dyn Expr>>,
+    order: Vec<OrderBy>,
+}
+
+impl WindowCall {
+    pub fn new(
+        name: &str,
+        args: Vec<Rc<dyn Expr>>,
+        partition: Vec<Rc<dyn Expr>>,
+        order: Vec<OrderBy>,
+    ) -> WindowCall {
+        WindowCall {
+            id: NEXT_WINDOW_ID.fetch_add(1, AtomicOrdering::Relaxed),
+            name: name.to_string(),
+            agg: None,
+            args,
+            distinct: false,
+            partition,
+            order,
+        }
+    }
+
+    pub fn with_distinct(mut self, distinct: bool) -> WindowCall {
+        self.distinct = distinct;
+        self
+    }
+
+    /// Makes this a call of the given aggregate function over a
+    /// window.
+    pub fn with_aggregate(mut self, agg: Rc<dyn AggregateFunc>) -> WindowCall {
+        self.agg = Some(agg);
+        self
+    }
+
+    fn err_args(&self) -> FqError {
+        FqError::exe(format!("Wrong number of arguments for {}()", self.name))
+    }
+
+    /// Returns the indexes of rows split into partitions (in the
+    /// order of their first rows), with each partition sorted.
+    fn partitions(&self, rows: &[Vec<String>], ctx: &mut Ctx) -> Result<Vec<Vec<usize>>, FqError> {
+        let mut partitions: Vec<Vec<usize>> = Vec::new();
+        let mut index: HashMap<Vec<ValKey>, usize> = HashMap::new();
+        for ix in 0..rows.len() {
+            let mut key = Vec::new();
+            for exp in &self.partition {
+                key.push(eval_at(exp, rows, ix, ctx)?.key());
+            }
+            let next = partitions.len();
+            let pix = *index.entry(key).or_insert(next);
+            if pix == next {
+                partitions.push(Vec::new());
+            }
+            partitions[pix].push(ix);
+        }
+
+        if self.order.is_empty() {
+            return Ok(partitions);
+        }
+
+        let mut keys: Vec<Vec<Val>> = Vec::new();
+        for ix in 0..rows.len() {
+            let mut key = Vec::new();
+            for (exp, _) in &self.order {
+                key.push(eval_at(exp, rows, ix, ctx)?);
+            }
+            keys.push(key);
+        }
+
+        // Sorting cannot fail, so the first error is kept aside.
+        let mut error: Option<FqError> = None;
+        for partition in &mut partitions {
+            partition.sort_by(|a, b| match self.compare(&keys[*a], &keys[*b]) {
+                Ok(ordering) => ordering,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    Ordering::Equal
+                }
+            });
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(partitions),
+        }
+    }
+
+    /// Compares the `order by` values of two rows.
+    fn compare(&self, a: &[Val], b: &[Val]) -> Result<Ordering, FqError> {
+        for ((a, b), (_, desc)) in a.iter().zip(b).zip(&self.order) {
+            let ordering = compare(a, b)?;
+            if ordering != Ordering::Equal {
+                return Ok(if *desc { ordering.reverse() } else { ordering });
+            }
+        }
+        Ok(Ordering::Equal)
+    }
+
+    /// Returns the peer groups of the given sorted partition as
+    /// ranges of positions in the partition.
+    fn peers(
+        &self,
+        partition: &[usize],
+        rows: &[Vec<String>],
+        ctx: &mut Ctx,
+    ) -> Result<Vec<(usize, usize)>, FqError> {
+        let mut keys: Vec<Vec<Val>> = Vec::new();
+        for ix in partition {
+            let mut key = Vec::new();
+            for (exp, _) in &self.order {
+                key.push(eval_at(exp, rows, *ix, ctx)?);
+            }
+            keys.push(key);
+        }
+
+        let mut groups = Vec::new();
+        let mut start = 0;
+        for pos in 1..=keys.len() {
+            if pos == keys.len() || self.compare(&keys[start], &keys[pos])? != Ordering::Equal {
+                groups.push((start, pos));
+                start = pos;
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Creates the state of an aggregate function computed over a
+    /// window. Parameters of the function have to be constant.
+    fn new_accumulator(
+        &self,
+        agg: &Rc<dyn AggregateFunc>,
+        ctx: &Ctx,
+    ) -> Result<Box<dyn Accumulator>, FqError> {
+        let mut params = Vec::new();
+        for arg in self.args.iter().skip(agg.arity()) {
+            if arg.kind() != ExprKind::CONST {
+                return Err(FqError::exe(format!(
+                    "{}() requires constant parameters",
+                    self.name
+                )));
+            }
+            params.push(arg.eval(&[], ctx)?);
+        }
+        let acc = agg.accumulator(&params)?;
+        if self.distinct {
+            return Ok(Box::new(Distinct::new(acc)));
+        }
+        Ok(acc)
+    }
+
+    /// Computes the values of the call for the rows of one sorted
+    /// partition (in the same order).
+    fn eval_partition(
+        &self,
+        partition: &[usize],
+        rows: &[Vec<String>],
+        ctx: &mut Ctx,
+    ) -> Result<Vec<Val>, FqError> {
+        let n = partition.len();
+        let mut vals: Vec<Val> = Vec::with_capacity(n);
+        match (self.name.as_str(), self.args.as_slice()) {
+            (ROW_NUMBER_FUNC, []) => {
+                for pos in 0..n {
+                    vals.push(Val::INT(pos as i32 + 1));
+                }
+            }
+            (RANK_FUNC, []) | (DENSE_RANK_FUNC, []) => {
+                let dense = self.name == DENSE_RANK_FUNC;
+                for (group, (start, end)) in self.peers(partition, rows, ctx)?.iter().enumerate() {
+                    let rank = if dense { group + 1 } else { start + 1 };
+                    for _ in *start..*end {
+                        vals.push(Val::INT(rank as i32));
+                    }
+                }
+            }
+            (LAG_FUNC | LEAD_FUNC, [exp, rest @ ..]) if rest.len() <= 2 => {
+                for (pos, ix) in partition.iter().enumerate() {
+                    let offset = match rest.first() {
+                        Some(offset) => match eval_at(offset, rows, *ix, ctx)? {
+                            Val::INT(offset) if offset >= 0 => offset as usize,
+                            _ => {
+                                return Err(FqError::exe(format!(
+                                    "{}() requires a non-negative int offset",
+                                    self.name
+                                )))
+                            }
+                        },
+                        None => 1,
+                    };
+                    let target = if self.name == LAG_FUNC {
+                        pos.checked_sub(offset)
+                    } else {
+                        pos.checked_add(offset).filter(|target| *target < n)
+                    };
+                    vals.push(match (target, rest.get(1)) {
+                        (Some(target), _) => eval_at(exp, rows, partition[target], ctx)?,
+                        (None, Some(default)) => eval_at(default, rows, *ix, ctx)?,
+                        (None, None) => Val::NULL,
+                    });
+                }
+            }
+            (NTILE_FUNC, [buckets]) => {
+                let buckets = match partition.first() {
+                    Some(ix) => eval_at(buckets, rows, *ix, ctx)?,
+                    None => Val::NULL,
+                };
+                let buckets = match buckets {
+                    Val::INT(buckets) if buckets > 0 => buckets as usize,
+                    Val::NULL if n == 0 => 1,
+                    _ => {
+                        return Err(FqError::exe(
+                            "ntile() requires a positive int number of buckets",
+                        ))
+                    }
+                };
+                // The first (n % buckets) buckets have one extra row.
+                let size = n / buckets;
+                let extra = n % buckets;
+                for pos in 0..n {
+                    let bucket = if pos < extra * (size + 1) {
+                        pos / (size + 1)
+                    } else {
+                        extra + (pos - extra * (size + 1)) / size.max(1)
+                    };
+                    vals.push(Val::INT(bucket as i32 + 1));
+                }
+            }
+            (_, args) if self.agg.is_some() => {
+                let agg = self.agg.as_ref().unwrap();
+                let arity = agg.arity();
+                if args.len() < arity {
+                    return Err(self.err_args());
+                }
+                let mut acc = self.new_accumulator(agg, ctx)?;
+                let groups = if self.order.is_empty() {
+                    vec![(0, n)]
+                } else {
+                    self.peers(partition, rows, ctx)?
+                };
+                for (start, end) in groups {
+                    for ix in &partition[start..end] {
+                        let mut args = Vec::new();
+                        for arg in &self.args[..arity] {
+                            args.push(eval_at(arg, rows, *ix, ctx)?);
+                        }
+                        acc.update(args)?;
+                    }
+                    let val = acc.finish()?;
+                    for _ in start..end {
+                        vals.push(val.clone());
+                    }
+                }
+            }
+            (name, _) if WINDOW_FUNCS.contains(&name) => return Err(self.err_args()),
+            _ => {
+                return Err(FqError::exe(format!(
+                    "Unsupported window function {}",
+                    self.name
+                )))
+            }
+        }
+        Ok(vals)
+    }
+}
+
+/// Evaluates the expression over the row at the given index.
+fn eval_at(
+    exp: &Rc<dyn Expr>,
+    rows: &[Vec<String>],
+    ix: usize,
+    ctx: &mut Ctx,
+) -> Result<Val, FqError> {
+    ctx.set_row(ix);
+    exp.eval(&rows[ix], ctx)
+}
+
+/// Compares two values for sorting; null values come after all other
+/// values.
+fn compare(a: &Val, b: &Val) -> Result<Ordering, FqError> {
+    match (a, b) {
+        (Val::NULL, Val::NULL) => Ok(Ordering::Equal),
+        (Val::NULL, _) => Ok(Ordering::Greater),
+        (_, Val::NULL) => Ok(Ordering::Less),
+        (a, b) if matches!(a.lt(b)?, Val::BOOL(true)) => Ok(Ordering::Less),
+        (a, b) if matches!(b.lt(a)?, Val::BOOL(true)) => Ok(Ordering::Greater),
+        _ => Ok(Ordering::Equal),
+    }
+}
+
+impl Expr for WindowCall {
+    fn eval(&self, _row: &[String], ctx: &Ctx) -> Result<Val, FqError> {
+        ctx.window(self.id)
+    }
+
+    fn kind(&self) -> ExprKind {
+        let kind = self
+            .args
+            .iter()
+            .chain(&self.partition)
+            .chain(self.order.iter().map(|(exp, _)| exp))
+            .fold(ExprKind::CONST, |kind, exp| kind.combine(exp.kind()));
+        match kind {
+            ExprKind::CONST | ExprKind::SCALAR => ExprKind::WINDOW,
+            _ => ExprKind::NESTED,
+        }
+    }
+
+    fn ty(&self) -> Result<Type, String> {
+        let mut args = Vec::new();
+        for arg in &self.args {
+            args.push(arg.ty()?);
+        }
+        for exp in self
+            .partition
+            .iter()
+            .chain(self.order.iter().map(|(exp, _)| exp))
+        {
+            exp.ty()?;
+        }
+        if let Some(agg) = &self.agg {
+            let signature = agg.signature();
+            signature.check(&self.name, &args)?;
+            return Ok(signature.ret);
+        }
+        match self.name.as_str() {
+            // the value of the expression or the default
+            LAG_FUNC | LEAD_FUNC => Ok(Type::ANY),
+            _ => Ok(Type::INT),
+        }
+    }
+
+    fn window(&self, table: &Table, ctx: &mut Ctx) -> Result<(), FqError> {
+        let rows: Vec<Vec<String>> = table.iter().collect();
+        let mut vals: Vec<Val> = vec![Val::NULL; rows.len()];
+        for partition in self.partitions(&rows, ctx)? {
+            let pvals = self.eval_partition(&partition, &rows, ctx)?;
+            for (ix, val) in partition.into_iter().zip(pvals) {
+                vals[ix] = val;
+            }
+        }
+        ctx.set_window(self.id, vals);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggs::SUM_FUNC;
+    use crate::expr::{ColRef, IntCast, IntConst, StrCast, ValFormat};
+    use crate::funcs::{Func, Registry};
+
+    fn sum() -> Rc<dyn AggregateFunc> {
+        match Registry::new().get(SUM_FUNC) {
+            Some(Func::AGG(agg)) => Rc::clone(agg),
+            _ => panic!("sum should be an aggregate function"),
+        }
+    }
+
+    fn int_col(ix: u32) -> Rc<dyn Expr> {
+        Rc::new(IntCast::new(Rc::new(ColRef::new(ix))))
+    }
+
+    fn str_col(ix: u32) -> Rc<dyn Expr> {
+        Rc::new(StrCast::new(Rc::new(ColRef::new(ix))))
+    }
+
+    fn table() -> Table {
+        // group, value
+        let rows = [("a", "3"), ("b", "5"), ("a", "1"), ("a", "3"), ("b", "2")];
+        Table::from(
+            rows.iter()
+                .map(|(group, val)| vec![group.to_string(), val.to_string()])
+                .collect(),
+        )
+    }
+
+    fn eval_window(call: WindowCall) -> Vec<String> {
+        let table = table();
+        let mut ctx = Ctx::new();
+        call.window(&table, &mut ctx).unwrap();
+        (0..table.nrows())
+            .map(|ix| {
+                ctx.set_row(ix);
+                ValFormat::new().show(&call.eval(&[], &ctx).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn eval_ranks() {
+        let by_group = || vec![str_col(0)];
+        let by_val = || vec![(int_col(1), false)];
+        assert_eq!(
+            eval_window(WindowCall::new(
+                ROW_NUMBER_FUNC,
+                vec![],
+                by_group(),
+                by_val()
+            )),
+            vec!["2", "2", "1", "3", "1"]
+        );
+        assert_eq!(
+            eval_window(WindowCall::new(RANK_FUNC, vec![], vec![], by_val())),
+            vec!["3", "5", "1", "3", "2"]
+        );
+        assert_eq!(
+            eval_window(WindowCall::new(DENSE_RANK_FUNC, vec![], vec![], by_val())),
+            vec!["3", "4", "1", "3", "2"]
+        );
+        assert_eq!(
+            eval_window(WindowCall::new(
+                ROW_NUMBER_FUNC,
+                vec![],
+                vec![],
+                vec![(int_col(1), true)]
+            )),
+            vec!["2", "1", "5", "3", "4"]
+        );
+    }
+
+    #[test]
+    fn eval_lag_and_lead() {
+        // input order, no partitions
+        assert_eq!(
+            eval_window(WindowCall::new(LAG_FUNC, vec![int_col(1)], vec![], vec![])),
+            vec![" ", "3", "5", "1", "3"]
+        );
+        assert_eq!(
+            eval_window(WindowCall::new(
+                LEAD_FUNC,
+                vec![
+                    int_col(1),
+                    Rc::new(IntConst::new(2)),
+                    Rc::new(IntConst::new(0))
+                ],
+                vec![str_col(0)],
+                vec![]
+            )),
+            vec!["3", "0", "0", "0", "0"]
+        );
+    }
+
+    #[test]
+    fn eval_ntile() {
+        let ntile = |n| {
+            eval_window(WindowCall::new(
+                NTILE_FUNC,
+                vec![Rc::new(IntConst::new(n))],
+                vec![],
+                vec![],
+            ))
+        };
+        assert_eq!(ntile(2), vec!["1", "1", "1", "2", "2"]);
+        assert_eq!(ntile(10), vec!["1", "2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn eval_running_sum() {
+        // peers (the two 3s in group a) share the running value
+        assert_eq!(
+            eval_window(
+                WindowCall::new(
+                    SUM_FUNC,
+                    vec![int_col(1)],
+                    vec![str_col(0)],
+                    vec![(int_col(1), false)]
+                )
+                .with_aggregate(sum())
+            ),
+            vec!["7", "7", "1", "7", "2"]
+        );
+        assert_eq!(
+            eval_window(
+                WindowCall::new(SUM_FUNC, vec![int_col(1)], vec![str_col(0)], vec![])
+                    .with_aggregate(sum())
+            ),
+            vec!["7", "7", "7", "7", "7"]
+        );
+    }
+
+    #[test]
+    fn eval_window_errors() {
+        let table = table();
+        let mut ctx = Ctx::new();
+        let call = WindowCall::new(LAG_FUNC, vec![], vec![], vec![]);
+        assert!(call.window(&table, &mut ctx).is_err());
+
+        let call = WindowCall::new(NTILE_FUNC, vec![Rc::new(IntConst::new(0))], vec![], vec![]);
+        assert!(call.window(&table, &mut ctx).is_err());
+
+        let call = WindowCall::new(ROW_NUMBER_FUNC, vec![], vec![], vec![]);
+        assert!(call.eval(&[], &ctx).is_err());
+    }
+}
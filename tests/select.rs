@@ -1,5 +1,13 @@
-use fqs;
 use fqs::args::Args;
+use fqs::funcs::{
+    Accumulator, AggregateFunc, Arity, FqError, ScalarFunc, Signature, Type, Val, ValFormat,
+};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn select_all() {
@@ -140,7 +148,7 @@ fn select_with_string_literal() {
 #[test]
 #[should_panic(expected = "Syntax error: Unknown char")]
 fn select_err_incorrect_char() {
-    let args = Args::new(";".to_string());
+    let args = Args::new("#".to_string());
     if let Err(err) = fqs::query(args) {
         panic!("{err}");
     }
@@ -278,7 +286,7 @@ fn select_avg_func() {
     assert_eq!(1, table.ncols());
 
     let col = table.col(0).unwrap();
-    assert_eq!(col, vec!["35"]);
+    assert_eq!(col, vec!["35.333332"]);
 }
 
 #[test]
@@ -394,20 +402,78 @@ fn select_sum_from_empty() {
     let args = Args::new("select sum(int(@1)) from tests/fixtures/empty.txt".to_string());
     let table = fqs::query(args).expect("Failing query");
 
-    assert!(table.empty());
+    assert_eq!(1, table.nrows());
+    assert_eq!(1, table.ncols());
+
+    let col = table.col(0).unwrap();
+    assert_eq!(col, vec![" "]);
+}
+
+#[test]
+fn select_count_from_empty() {
+    let args = Args::new("select count(1) from tests/fixtures/empty.txt".to_string());
+    let table = fqs::query(args).expect("Failing query");
+
+    assert_eq!(1, table.nrows());
+    assert_eq!(1, table.ncols());
+
+    let col = table.col(0).unwrap();
+    assert_eq!(col, vec!["0"]);
+}
+
+#[test]
+fn select_avg_func_with_nulls() {
+    let args = Args::new("select avg(int(@1)) from tests/fixtures/nulls.txt".to_string());
+    let table = fqs::query(args).expect("Failing query");
+
+    assert_eq!(1, table.nrows());
+    assert_eq!(1, table.ncols());
+
+    let col = table.col(0).unwrap();
+    assert_eq!(col, vec!["26.5"]);
+}
+
+#[test]
+fn select_func_and_expr() {
+    let args = Args::new("select sum(int(@0)) * 2 from tests/fixtures/types.txt".to_string());
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(1, table.nrows());
+    assert_eq!(1, table.ncols());
+
+    let col = table.col(0).unwrap();
+    assert_eq!(col, vec!["212"]);
 }
 
-// #[test]
-// fn select_func_and_expr() {
-//     let args = Args::new("select sum(int(@0)) * 2 from tests/fixtures/types.txt".to_string());
-//     let table = fqs::query(args).unwrap();
+#[test]
+fn select_ratio_of_aggregates() {
+    let args = Args::new(
+        "select sum(int(@0)) / count(1), sum(float(@3)) / count(1) from tests/fixtures/types.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
 
-//     assert_eq!(1, table.nrows());
-//     assert_eq!(1, table.ncols());
+    assert_eq!(1, table.nrows());
+    assert_eq!(2, table.ncols());
 
-//     let col = table.col(0).unwrap();
-//     assert_eq!(col, vec!["212"]);
-// }
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["35", "66"]);
+}
+
+#[test]
+fn select_range_of_aggregates() {
+    let args = Args::new(
+        "select max(int(@0)) - min(int(@0)), max(int(@0)) from tests/fixtures/types.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(1, table.nrows());
+    assert_eq!(2, table.ncols());
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["81", "66"]);
+}
 
 #[test]
 fn select_from_weird_file_name() {
@@ -449,3 +515,981 @@ fn select_with_multiple_exprs() {
     let col = table.col(0).unwrap();
     assert_eq!(col, vec!["2", "3", "0"]);
 }
+
+#[test]
+#[should_panic(
+    expected = "Semantics error: Aggregate and non-aggregate columns cannot be selected together"
+)]
+fn select_err_mixed_columns() {
+    let args = Args::new("select int(@0), sum(int(@0)) from tests/fixtures/types.txt".to_string());
+    if let Err(err) = fqs::query(args) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Aggregate and non-aggregate values cannot be mixed")]
+fn select_err_mixed_expr() {
+    let args = Args::new("select int(@0) + sum(int(@0)) from tests/fixtures/types.txt".to_string());
+    if let Err(err) = fqs::query(args) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Aggregate function calls cannot be nested")]
+fn select_err_nested_aggregates() {
+    let args = Args::new("select sum(sum(int(@0))) from tests/fixtures/types.txt".to_string());
+    if let Err(err) = fqs::query(args) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Aggregate functions are not allowed in where")]
+fn select_err_aggregate_in_where() {
+    let args = Args::new(
+        "select int(@0) from tests/fixtures/types.txt where max(int(@0)) > 10".to_string(),
+    );
+    if let Err(err) = fqs::query(args) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+fn select_const_with_aggregate() {
+    let args = Args::new("select 7, count(1) from tests/fixtures/types.txt".to_string());
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(1, table.nrows());
+    assert_eq!(2, table.ncols());
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["7", "3"]);
+}
+
+#[test]
+fn select_number_literals() {
+    let args = Args::new(
+        "select 1.5e3, 0x1F, 1_000, .5 from tests/fixtures/types.txt limit 1".to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["1500", "31", "1000", "0.5"]);
+}
+
+#[test]
+#[should_panic(expected = "Syntax error: Invalid number literal '1.2.3'")]
+fn select_err_invalid_number_literal() {
+    let args = Args::new("select 1.2.3 from tests/fixtures/types.txt".to_string());
+    if let Err(err) = fqs::query(args) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+fn select_lenient_casts() {
+    let args =
+        Args::new("select sum(int(@0)), sum(float(@1)) from tests/fixtures/locale.txt".to_string())
+            .with_thousands_sep('.')
+            .with_decimal_sep(',');
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["1012", "5.75"]);
+}
+
+#[test]
+fn select_custom_tokens() {
+    let args = Args::new("select bool(@0), int(@1) from tests/fixtures/tokens.txt".to_string())
+        .with_true_tokens(vec![String::from("yes")])
+        .with_false_tokens(vec![String::from("no")])
+        .with_null_tokens(vec![String::from("NA")])
+        .with_null_output(String::from("NULL"));
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(table.row(0).unwrap(), vec!["true", "1"]);
+    assert_eq!(table.row(1).unwrap(), vec!["false", "NULL"]);
+    assert_eq!(table.row(2).unwrap(), vec!["NULL", "3"]);
+}
+
+#[test]
+fn select_null_output() {
+    let args = Args::new("select int(@1) from tests/fixtures/tokens.txt".to_string())
+        .with_null_tokens(vec![String::from("NA")])
+        .with_null_output(String::from("NULL"));
+    let table = fqs::query(args).unwrap();
+
+    let col: Vec<String> = (0..table.nrows())
+        .map(|ix| table.row(ix).unwrap()[0].to_string())
+        .collect();
+    assert_eq!(col, vec!["1", "NULL", "3"]);
+}
+
+#[test]
+#[should_panic(expected = "Execution error: Cannot cast yes to bool")]
+fn select_err_default_bool_tokens() {
+    let args = Args::new("select bool(@0) from tests/fixtures/tokens.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_unicode_funcs() {
+    let args = Args::new(
+        "select length(str(@0)), octet_length(str(@0)), rev(str(@0)), substr(str(@0), 2, 3), casefold(str(@1)) from tests/fixtures/unicode.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["3", "4", "\u{eb}oZ", "o\u{eb}", "strasse"]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["4", "6", "e\u{301}soJ", "ose\u{301}", "strasse"]
+    );
+}
+
+#[test]
+fn select_string_funcs() {
+    let args = Args::new(
+        "select concat_ws('-', str(@2), str(@0)), lpad(str(@0), 4, '0'), replace(str(@3), '.0', ''), split_part(str(@3), '.', 1) from tests/fixtures/types.txt limit 1"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["today-55", "0055", "55", "55"]);
+}
+
+#[test]
+fn select_math_funcs() {
+    let args = Args::new(
+        "select sqrt(int(@0) + 26), pow(2, 10), mod(int(@0), 10), greatest(int(@0), 60), round(float(@3) / 7, 2) from tests/fixtures/types.txt limit 1"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["9", "1024", "5", "60", "7.86"]);
+}
+
+#[test]
+#[should_panic(expected = "Execution error: ln() requires a positive argument")]
+fn select_err_math_domain() {
+    let args = Args::new("select ln(int(@0)) from tests/fixtures/types.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_stat_aggregates() {
+    let args = Args::new(
+        "select median(int(@0)), percentile_cont(int(@0), 0.5), percentile_disc(int(@0), 0.9), mode(int(@0)), var_pop(int(@0)) from tests/fixtures/latency.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["13.5", "13.5", "45", "12", "574.09"]);
+}
+
+#[test]
+#[should_panic(expected = "Execution error: percentile_cont() requires constant parameters")]
+fn select_err_percentile_with_column_fraction() {
+    let args = Args::new(
+        "select percentile_cont(int(@0), float(@0)) from tests/fixtures/latency.txt".to_string(),
+    );
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_bivariate_aggregates() {
+    let args = Args::new(
+        "select regr_slope(int(@1), int(@0)), regr_intercept(int(@1), int(@0)), corr(int(@0), int(@1)), count(int(@1)) from tests/fixtures/scaling.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(row, vec!["2", "1", "1", "5"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Wrong number of arguments for corr()")]
+fn select_err_bivariate_one_arg() {
+    let args = Args::new("select corr(int(@0)) from tests/fixtures/scaling.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Wrong number of arguments for sum()")]
+fn select_err_aggregate_no_args() {
+    // The arguments are checked even if there are no rows.
+    let args = Args::new("select sum() from tests/fixtures/empty.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Wrong number of arguments for string_agg()")]
+fn select_err_window_aggregate_args() {
+    let args =
+        Args::new("select string_agg(str(@0)) over () from tests/fixtures/hosts.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Argument 1 of upper() has to be string, not int")]
+fn select_err_arg_type() {
+    // The types are checked even if there are no rows.
+    let args = Args::new("select upper(int(@0)) from tests/fixtures/empty.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(
+    expected = "Semantics error: Argument 2 of percentile_cont() has to be number, not string"
+)]
+fn select_err_aggregate_arg_type() {
+    let args = Args::new(
+        "select percentile_cont(int(@1), 'x') from tests/fixtures/series.txt".to_string(),
+    );
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_string_agg() {
+    let args = Args::new(
+        "select string_agg(str(@0), ','), string_agg(distinct str(@0), ';'), string_agg(str(@1), ',', 10), count(distinct str(@0)) from tests/fixtures/hosts.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(
+        row,
+        vec!["web1,web2,web1,web3", "web1;web2;web3", "timeout,ok", "3"]
+    );
+}
+
+#[test]
+#[should_panic(expected = "Parse error: distinct can only be used in aggregate function calls")]
+fn select_err_distinct_scalar() {
+    let args =
+        Args::new("select upper(distinct str(@0)) from tests/fixtures/hosts.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_arg_max_first_last() {
+    let args = Args::new(
+        "select arg_max(str(@2), int(@0)), arg_min(str(@2), int(@0)), first(str(@2)), last(str(@2)), any_value(int(@0)) from tests/fixtures/types.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    let row = table.row(0).unwrap();
+    assert_eq!(
+        row,
+        vec!["yesterday", "Tomorrow", "today", "Tomorrow", "55"]
+    );
+}
+
+#[test]
+fn select_window_funcs() {
+    let args = Args::new(
+        "select str(@0), int(@1) - lag(int(@1)) over (partition by str(@0)), row_number() over (partition by str(@0) order by int(@1) desc), sum(int(@1)) over (order by int(@1)), ntile(2) over () from tests/fixtures/series.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(table.nrows(), 5);
+    assert_eq!(table.row(0).unwrap(), vec!["a", " ", "3", "10", "1"]);
+    assert_eq!(table.row(1).unwrap(), vec!["b", " ", "2", "37", "1"]);
+    assert_eq!(table.row(2).unwrap(), vec!["a", "2", "2", "22", "1"]);
+    assert_eq!(table.row(3).unwrap(), vec!["b", "6", "1", "78", "2"]);
+    assert_eq!(table.row(4).unwrap(), vec!["a", "8", "1", "57", "2"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: row_number() requires over")]
+fn select_err_window_without_over() {
+    let args = Args::new("select row_number() from tests/fixtures/series.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Window functions are not allowed in where")]
+fn select_err_window_in_where() {
+    let args = Args::new(
+        "select str(@0) from tests/fixtures/series.txt where count(int(@1)) over () > 1"
+            .to_string(),
+    );
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(
+    expected = "Semantics error: Aggregate and non-aggregate columns cannot be selected together"
+)]
+fn select_err_window_with_aggregate() {
+    let args = Args::new(
+        "select sum(int(@1)), rank() over (order by int(@1)) from tests/fixtures/series.txt"
+            .to_string(),
+    );
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_pseudo_columns() {
+    let args = Args::new(
+        "select int(@nr), int(@nf), int(@offset), str(@line), str(@file) from tests/fixtures/ragged.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(table.nrows(), 4);
+    let file = "tests/fixtures/ragged.txt";
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["1", "3", "0", "web1 200 12", file]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["2", "2", "12", "web2 500", file]
+    );
+    assert_eq!(table.row(2).unwrap(), vec!["3", "0", "21", " ", file]);
+    assert_eq!(
+        table.row(3).unwrap(),
+        vec!["4", "4", "22", "web3 200 7 extra", file]
+    );
+}
+
+#[test]
+fn select_where_pseudo_columns() {
+    let args = Args::new(
+        "select int(@nr), str(@0) from tests/fixtures/ragged.txt where int(@nf) != 3 limit 2"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(table.nrows(), 2);
+    assert_eq!(table.row(0).unwrap(), vec!["2", "web2"]);
+    assert_eq!(table.row(1).unwrap(), vec!["3", " "]);
+
+    let args = Args::new(
+        "select max(int(@nf)), count(str(@line)) from tests/fixtures/ragged.txt".to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["4", "3"]);
+
+    // The line is kept when it is used only in a function body.
+    let args = Args::new(
+        "create function text() as str(@line);
+        select text() from tests/fixtures/ragged.txt limit 1"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["web1 200 12"]);
+
+    let args = Args::new(
+        "select int(@nr), lag(int(@nr)) over (order by int(@nf) desc) from tests/fixtures/ragged.txt where int(@nf) > 0"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["1", "4"]);
+    assert_eq!(table.row(1).unwrap(), vec!["2", "1"]);
+    assert_eq!(table.row(2).unwrap(), vec!["4", " "]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Unknown pseudo-column @lines")]
+fn select_err_unknown_pseudo_column() {
+    let args = Args::new("select int(@lines) from tests/fixtures/ragged.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_hash_funcs() {
+    let args = Args::new(
+        "select md5(str(@0)), crc32(str(@0)), hash64(str(@0), 4) from tests/fixtures/hosts.txt"
+            .to_string(),
+    );
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(table.nrows(), 5);
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["e694aa37abf20c91a442da4841aeccdf", "9fa547ed", "0"]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["bbddbd575d47c5d8a629979213e34add", "06ac1657", "1"]
+    );
+    assert_eq!(table.row(2).unwrap(), table.row(0).unwrap());
+    assert_eq!(table.row(3).unwrap(), vec![" ", " ", " "]);
+}
+
+#[test]
+fn select_encoding_funcs() {
+    let args = Args::new(
+        "select url_decode(str(@1)), from_base64(str(@2)), to_base64(str(@0)), html_escape(str(@1)) from tests/fixtures/access.txt"
+            .to_string(),
+    )
+    .with_null_on_decode_error(true);
+    let table = fqs::query(args).unwrap();
+
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec![
+            "/search?q=caf\u{e9}",
+            "hello world",
+            "R0VU",
+            "/search%3Fq%3Dcaf%C3%A9"
+        ]
+    );
+    assert_eq!(table.row(1).unwrap(), vec![" ", " ", "R0VU", "/a%2"]);
+}
+
+#[test]
+#[should_panic(expected = "Execution error: url_decode() cannot decode /a%2")]
+fn select_err_decode() {
+    let args = Args::new("select url_decode(str(@1)) from tests/fixtures/access.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+struct Initials;
+
+impl ScalarFunc for Initials {
+    fn name(&self) -> &str {
+        "initials"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::range(1, 2)
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::new(vec![Type::STR], Type::STR)
+    }
+
+    fn eval(&self, args: &[Val], _format: &ValFormat) -> Result<Val, FqError> {
+        let sep = match args.get(1) {
+            Some(Val::STR(sep)) => sep.as_str(),
+            None => "",
+            _ => return Err(FqError::exe("initials() requires a string separator")),
+        };
+        match &args[0] {
+            Val::STR(val) => Ok(Val::STR(
+                val.chars().take(1).chain(sep.chars()).collect::<String>(),
+            )),
+            Val::NULL => Ok(Val::NULL),
+            _ => Err(FqError::exe("initials() only works for string types")),
+        }
+    }
+}
+
+/// Product of all (non-null) int values.
+struct Product;
+
+struct ProductAcc {
+    product: Option<i32>,
+}
+
+impl Accumulator for ProductAcc {
+    fn update(&mut self, vals: Vec<Val>) -> Result<(), FqError> {
+        match vals.first() {
+            Some(Val::INT(val)) => self.product = Some(self.product.unwrap_or(1) * val),
+            Some(Val::NULL) => {}
+            _ => return Err(FqError::exe("product() only works for int types")),
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Val, FqError> {
+        Ok(self.product.map_or(Val::NULL, Val::INT))
+    }
+}
+
+impl AggregateFunc for Product {
+    fn name(&self) -> &str {
+        "product"
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::new(vec![Type::INT], Type::INT)
+    }
+
+    fn accumulator(&self, _params: &[Val]) -> Result<Box<dyn Accumulator>, FqError> {
+        Ok(Box::new(ProductAcc { product: None }))
+    }
+}
+
+#[test]
+fn select_registered_funcs() {
+    let args = Args::new(
+        "select initials(str(@0), '.'), upper(str(@0)) from tests/fixtures/hosts.txt limit 1"
+            .to_string(),
+    )
+    .with_scalar_func(Rc::new(Initials));
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["w.", "WEB1"]);
+
+    let args = Args::new(
+        "select product(int(@1)), count(int(@1)) from tests/fixtures/series.txt".to_string(),
+    )
+    .with_aggregate_func(Rc::new(Product));
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["756000", "5"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Unknown function initials()")]
+fn select_err_unknown_func() {
+    let args = Args::new("select initials(str(@0)) from tests/fixtures/hosts.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_create_function() {
+    let query = "create function double(x) as x * 2;
+        create function inc(x) as double(x) + 1;
+        select str(@0), inc(int(@1)), 100 / double(int(@1)) from tests/fixtures/series.txt
+        where double(int(@1)) > 30;";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(2, table.nrows());
+    assert_eq!(table.row(0).unwrap(), vec!["b", "43", "2"]);
+    assert_eq!(table.row(1).unwrap(), vec!["a", "41", "2"]);
+
+    let query = "create function total(x, y) as sum(x) - count(y);
+        select total(int(@1), str(@0)) from tests/fixtures/series.txt";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["73"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Wrong number of arguments for upper()")]
+fn select_err_func_args() {
+    // The arguments are checked even if there are no rows.
+    let args = Args::new("select upper(str(@0), 'x') from tests/fixtures/empty.txt".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Function upper() is already defined")]
+fn select_err_create_function_defined() {
+    let query = "create function upper(x) as x; select upper(1) from tests/fixtures/series.txt";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Wrong number of arguments for double()")]
+fn select_err_create_function_args() {
+    let query = "create function double(x) as x * 2;
+        select double(1, 2) from tests/fixtures/series.txt";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: A script can have only one select")]
+fn select_err_create_function_two_selects() {
+    let query = "create function double(x) as x * 2;
+        select double(int(@1)) from tests/fixtures/series.txt;
+        select double(1) from tests/fixtures/series.txt";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Expecting ( for a function call")]
+fn select_err_create_function_body() {
+    let query = "create function double(x) as y * 2;
+        select double(1) from tests/fixtures/series.txt";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_delimiter() {
+    let query =
+        "select str(@0), int(@2) from 'tests/fixtures/passwd.txt' delimiter ':' where int(@2) > 0";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(2, table.nrows());
+    assert_eq!(table.row(1).unwrap(), vec!["alice", "1000"]);
+
+    let query = "select str(@0), int(@2) from tests/fixtures/tabs.tsv delimiter '\\t'";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["web1", "12"]);
+    assert_eq!(table.row(1).unwrap(), vec!["web2", " "]);
+
+    let query = "select str(@2), int(@nf) from tests/fixtures/pipes.txt limit 1";
+    let args = Args::new(query.to_string()).with_delimiter(" || ".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["x y", "3"]);
+
+    // the delimiter in the query takes precedence
+    let query = "select str(@6) from tests/fixtures/passwd.txt delimiter ':' limit 1";
+    let args = Args::new(query.to_string()).with_delimiter("\\t".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["/bin/bash"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Delimiter cannot be empty")]
+fn select_err_empty_delimiter() {
+    let query = "select str(@0) from tests/fixtures/passwd.txt delimiter ''";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_whitespace() {
+    let query = "select int(@0), str(@3), int(@nf) from tests/fixtures/ps.txt whitespace where int(@nr) > 1";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(3, table.nrows());
+    assert_eq!(table.row(0).unwrap(), vec!["1", "init", "4"]);
+    assert_eq!(table.row(2).unwrap(), vec!["12345", "cargo", "4"]);
+
+    let query = "select sum(int(@0)) from tests/fixtures/ps.txt where str(@1) = 'pts/0'";
+    let args = Args::new(query.to_string()).with_whitespace(true);
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["13087"]);
+}
+
+#[test]
+fn select_whitespace_blank_lines() {
+    // No line has a field, so a column reference is always null.
+    let query = "select * from tests/fixtures/blank.txt";
+    let args = Args::new(query.to_string()).with_whitespace(true);
+    let table = fqs::query(args).unwrap();
+    assert_eq!(0, table.ncols());
+
+    let query = "select count(str(@0)), count(int(@nr)) from tests/fixtures/blank.txt";
+    let args = Args::new(query.to_string()).with_whitespace(true);
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["0", "3"]);
+}
+
+#[test]
+fn select_csv() {
+    let query = "select str(@0), str(@1), str(@2), int(@3) from tests/fixtures/people.csv header";
+    let args = Args::new(query.to_string()).with_null_output("NULL".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(3, table.nrows());
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["Smith, John", "Boston", "says \"hi\"", "10"]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["Alice", "New\nYork", "NULL", "20"]
+    );
+
+    // pseudo-columns refer to the first line of a record
+    let query = "select int(@nr), int(@nf), int(@offset) from tests/fixtures/people.csv csv";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(4, table.nrows());
+    assert_eq!(table.row(2).unwrap(), vec!["3", "4", "62"]);
+    assert_eq!(table.row(3).unwrap(), vec!["5", "4", "85"]);
+
+    // the format option takes precedence over the extension
+    let query = "select str(@0) from tests/fixtures/people.csv";
+    let args = Args::new(query.to_string()).with_format("csv".to_string());
+    assert_eq!(fqs::query(args).unwrap().nrows(), 4);
+    let args = Args::new(query.to_string()).with_delimiter(",".to_string());
+    assert_eq!(fqs::query(args).unwrap().nrows(), 5);
+}
+
+#[test]
+fn select_csv_delimiter() {
+    let query = "select str(@0), str(@1), str(@2), int(@nr), int(@nf) from tests/fixtures/semicolons.csv csv delimiter ';' header";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(2, table.nrows());
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["Smith; John", "New\n\nYork", "says \"hi\"", "2", "3"]
+    );
+    assert_eq!(table.row(1).unwrap(), vec!["Alice", "Paris", " ", "5", "3"]);
+
+    // the delimiter option separates CSV fields with the format option
+    let query = "select str(@1) from tests/fixtures/semicolons.csv header";
+    let args = Args::new(query.to_string())
+        .with_format("csv".to_string())
+        .with_delimiter(";".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["New\n\nYork"]);
+}
+
+#[test]
+#[should_panic(
+    expected = "Execution error: Unterminated quoted field in the record starting at line 1"
+)]
+fn select_err_csv_unterminated() {
+    let query = "select str(@0) from tests/fixtures/unterminated.csv";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Semantics error: Unknown format xml")]
+fn select_err_unknown_format() {
+    let query = "select str(@0) from tests/fixtures/people.csv";
+    let args = Args::new(query.to_string()).with_format("xml".to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_jsonl() {
+    let query = "select str($.request.method), str($.request.path), int($.status), int($.bytes), str($.tags), str($.tags[1]) from tests/fixtures/requests.jsonl";
+    let args = Args::new(query.to_string()).with_null_output("NULL".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(3, table.nrows());
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["GET", "/a", "200", "512", "[\"x\",\"y\"]", "y"]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["POST", "/b\u{e9}", "500", "NULL", "NULL", "NULL"]
+    );
+
+    let query = "select count(1), sum(int($.bytes)) from tests/fixtures/requests.jsonl jsonl where str($.request.method) = 'GET'";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["2", "2560"]);
+
+    let query = "select bool($.cached) from tests/fixtures/requests.jsonl where int(@nr) = 3";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["true"]);
+}
+
+#[test]
+#[should_panic(expected = "Execution error: Line 2 is not valid JSON")]
+fn select_err_jsonl_invalid() {
+    let query = "select int($.a) from tests/fixtures/broken.jsonl";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Invalid JSON path $.a..b")]
+fn select_err_json_path() {
+    let query = "select int($.a..b) from tests/fixtures/requests.jsonl";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_widths() {
+    let query = "select str(@0), str(@1), float(@2), str(@3) from 'tests/fixtures/report.txt' widths (1-8, 9-21, 22 - 31, 32-)";
+    let args = Args::new(query.to_string()).with_null_output("NULL".to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(3, table.nrows());
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["ACC00001", "Jane Doe", "1250.5", "EUR"]
+    );
+    assert_eq!(
+        table.row(2).unwrap(),
+        vec!["ACC00003", "NULL", "10000", "NULL"]
+    );
+
+    let query =
+        "select sum(float(@0)) from tests/fixtures/report.txt widths (22-31) where str(@nr) != '3'";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["1350.49"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Range 9-3 ends before it starts")]
+fn select_err_widths() {
+    let query = "select str(@0) from tests/fixtures/report.txt widths (1-8, 9-3)";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Expecting - in a range")]
+fn select_err_widths_space() {
+    let query = "select str(@0) from tests/fixtures/report.txt widths (1 -8)";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn select_regex() {
+    let query = "select str(@ip), str(@path), int(@status), str(@nr) from tests/fixtures/access.log regex '^(?P<ip>\\S+) .*\"(?P<method>[A-Z]+) (?P<path>\\S+) [^\"]*\" (?P<status>\\d{3}) (\\d+|-)$' where int(@4) >= 400";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(2, table.nrows());
+    assert_eq!(
+        table.row(0).unwrap(),
+        vec!["10.0.0.2", "/api/login", "401", "2"]
+    );
+    assert_eq!(
+        table.row(1).unwrap(),
+        vec!["10.0.0.1", "/missing", "404", "4"]
+    );
+
+    // Lines that do not match are skipped.
+    let query =
+        "select count(1), sum(int(@2)) from tests/fixtures/access.log regex '\" (\\d+) (\\d+)$'";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["3", "10552"]);
+
+    let query = "create function status() as int(@status); select str(@0) from tests/fixtures/access.log regex '(?<status>\\d{3}) \\S+$' where status() = 404";
+    let args = Args::new(query.to_string());
+    let table = fqs::query(args).unwrap();
+    assert_eq!(table.row(0).unwrap(), vec!["404 -"]);
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Unknown pseudo-column or group @size")]
+fn select_err_regex_group() {
+    let query =
+        "select str(@size) from tests/fixtures/access.log regex '(?P<status>\\d{3}) (\\d+)$'";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Invalid regex: unmatched )")]
+fn select_err_regex_invalid() {
+    let query = "select str(@0) from tests/fixtures/access.log regex '\\d+)'";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+#[should_panic(expected = "Parse error: Group name line is reserved for a pseudo-column")]
+fn select_err_regex_reserved() {
+    let query = "select str(@0) from tests/fixtures/access.log regex '(?P<line>.*)'";
+    let args = Args::new(query.to_string());
+    fqs::query(args).unwrap_or_else(|err| panic!("{err}"));
+}
+
+/// Runs the binary with the given arguments and text on the standard
+/// input, and returns the standard output.
+fn run_with_stdin(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fqs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run fqs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn select_stdin() {
+    let input = "alice 10\nbob 25\ncarol 40\n";
+    assert_eq!(
+        "bob 26 2 -\ncarol 41 3 -\n",
+        run_with_stdin(
+            &["select str(@0), int(@1) + 1, int(@nr), str(@file) from - where int(@1) > 20"],
+            input
+        )
+    );
+    assert_eq!(
+        "75\n",
+        run_with_stdin(&["select sum(int(@1)) from stdin"], input)
+    );
+}
+
+#[test]
+fn select_stdin_formats() {
+    let input = "name,note\n\"a, b\",\"two\nlines\"\nc,d\n";
+    assert_eq!(
+        "a, b 2\nc 4\n",
+        run_with_stdin(
+            &["--format=csv", "select str(@0), int(@nr) from - header"],
+            input
+        )
+    );
+
+    let input = "{\"level\":\"error\",\"code\":7}\nnot json\n{\"level\":\"info\"}\n";
+    assert_eq!(
+        "7\n",
+        run_with_stdin(
+            &["select int($.code) from stdin regex '^\\{.*' where str($.level) = 'error'"],
+            input
+        )
+    );
+}
+
+/// Runs the binary with the given arguments and writes the given text
+/// on the standard input, which is left open.
+fn spawn_with_open_stdin(args: &[&str], input: &str) -> Child {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fqs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run fqs");
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child
+}
+
+#[test]
+fn select_stdin_streaming() {
+    // Rows are printed before the input ends.
+    let mut child = spawn_with_open_stdin(
+        &["select str(@0), int(@nr) from - where int(@1) > 1"],
+        "a 1\nb 2\n",
+    );
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line).unwrap();
+        sender.send(line).unwrap();
+    });
+    let line = receiver.recv_timeout(Duration::from_secs(10));
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(Ok("b 2\n".to_string()), line);
+
+    // The input is not read any further once the limit is reached.
+    let mut child = spawn_with_open_stdin(&["select str(@0) from - limit 2"], "a\nb\nc\n");
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() && start.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    if child.try_wait().unwrap().is_none() {
+        child.kill().unwrap();
+        panic!("fqs did not stop at the limit");
+    }
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!("a\nb\n", String::from_utf8(output.stdout).unwrap());
+}